@@ -8,17 +8,26 @@ mod modules;
 use crate::activity::{
     Activity, ActivityDB, ActivityError, ActivityFilter, ActivityTags, ClosedChannelDetails,
     DbError, LightningActivity, OnchainActivity, PaymentType, PreActivityMetadata, SortDirection,
-    TransactionDetails,
+    TransactionDetails, Utxo, UtxoBalance,
 };
 use crate::modules::blocktank::{
-    BlocktankDB, BlocktankError, BtOrderState2, CJitStateEnum, ChannelLiquidityOptions,
-    ChannelLiquidityParams, CreateCjitOptions, CreateOrderOptions, DefaultLspBalanceParams,
-    IBt0ConfMinTxFeeWindow, IBtBolt11Invoice, IBtEstimateFeeResponse, IBtEstimateFeeResponse2,
-    IBtInfo, IBtOrder, ICJitEntry, IGift,
+    order_activity_refs, run_order_tracker, BitcoinNetworkEnum, BlocktankDB, BlocktankError,
+    BtChannelQuote, BtDeviceRegistration, BtDeviceRegistrationResult, BtEncryptedNotification,
+    BtLifecycleEvent, BtLifecycleListener, BtLspQuote, BtLspsTransport, BtNodeKeySigner,
+    BtNodeSigner, BtNotification, BtOrderActivityKind, BtOrderActivityLink, BtOrderEvent,
+    BtOrderRefund, BtOrderState2, BtQuoteRequest, BtStatusTransition, BtTrackedItemKind,
+    BtTrackerConfig, CJitStateEnum, ChannelLiquidityOptions, ChannelLiquidityParams,
+    CreateCjitOptions, CreateOrderOptions, DefaultLspBalanceParams, IBt0ConfMinTxFeeWindow,
+    IBtBolt11Invoice, IBtEstimateFeeResponse, IBtEstimateFeeResponse2, IBtInfo, IBtOrder,
+    ICJitEntry, IGift, OrderActivityTarget, ORDER_ACTIVITY_TAG, REFUND_ACTIVITY_TAG,
 };
+use crate::onchain::fees::{FeeEstimatesClient, FeeEstimator, HttpFeeEstimatesClient};
 pub use crate::onchain::WordCount;
 use crate::onchain::{
-    AddressError, GetAddressResponse, GetAddressesResponse, Network, ValidationResult,
+    AddressError, AddressType, FeeBumpError, FeeBumpPlan, FeeEstimates, FeeEstimationError,
+    FeeRateSample, GetAddressResponse, GetAddressesResponse, MessageSignatureFormat,
+    MessageSigningError, Network, Slip39Error, Slip39Group, Slip39GroupShares, Slip39ShareInfo,
+    TransactionFee, TransactionFees, ValidationResult, WalletUtxo,
 };
 pub use modules::activity;
 pub use modules::lnurl;
@@ -40,23 +49,34 @@ use once_cell::sync::OnceCell;
 use crate::modules::paykit::{PaykitCheckoutResult, PaykitError, PaykitSupportedMethods};
 use crate::modules::trezor;
 use crate::modules::trezor::{
-    AccountInfoDetails, AmountUnit, CommonParams, ComposeAccount, ComposeOutput,
+    AccountInfoDetails, AddressResponse, AmountUnit, CommonParams, ComposeAccount, ComposeOutput,
     ComposeTransactionParams, DeepLinkResult, DefaultAccountType, FeeLevel, GetAccountInfoParams,
-    GetAddressParams, MultisigRedeemScriptType, RefTransaction, SignMessageParams,
-    SignTransactionParams, TokenFilter, TrezorConnectError, TrezorEnvironment,
-    TrezorResponsePayload, TxAckPaymentRequest, TxInputType, TxOutputType, UnlockPath,
-    VerifyMessageParams, XrpMarker,
+    GetAddressParams, GetOwnershipIdParams, GetOwnershipProofParams, GetPublicKeyParams,
+    MultisigRedeemScriptType, PrecomposedTransaction, PublicKeyResponse, RefTransaction,
+    ScriptType, SignMessageParams, SignTransactionParams, SignedTransactionResponse, TokenFilter,
+    TrezorAccountAddress, TrezorAccountStore, TrezorBridgeClient, TrezorBridgeDevice,
+    TrezorComposeWarning, TrezorConnectError, TrezorEndpointSettings, TrezorEnvironment,
+    TrezorMultisigAddress, TrezorMultisigCosigner, TrezorMultisigStore, TrezorMultisigWallet,
+    TrezorOwnershipProof, TrezorPendingRequest, TrezorRequestRegistry, TrezorResponsePayload,
+    TrezorSettingsStore, TrezorSignedActivity, TrezorWatchOnlyAccount, TxAckPaymentRequest,
+    TxInputType, TxOutputType, UnlockPath, VerifyMessageParams, XrpMarker,
 };
 use bip39::Mnemonic;
 use bitcoin::bip32::Xpriv;
 use bitcoin::Network as BitcoinNetwork;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::sync::Mutex as StdMutex;
 use tokio::runtime::Runtime;
 use tokio::sync::Mutex as TokioMutex;
+use tokio::task::JoinHandle;
 
 pub struct DatabaseConnections {
     pub(crate) activity_db: Option<ActivityDB>,
+    pub(crate) trezor_requests: Option<Arc<TrezorRequestRegistry>>,
+    pub(crate) trezor_accounts: Option<Arc<TrezorAccountStore>>,
+    pub(crate) trezor_settings: Option<Arc<TrezorSettingsStore>>,
+    pub(crate) trezor_multisig_wallets: Option<Arc<TrezorMultisigStore>>,
 }
 
 pub struct AsyncDatabaseConnections {
@@ -66,6 +86,8 @@ pub struct AsyncDatabaseConnections {
 static DB: OnceCell<StdMutex<DatabaseConnections>> = OnceCell::new();
 static ASYNC_DB: OnceCell<TokioMutex<AsyncDatabaseConnections>> = OnceCell::new();
 static RUNTIME: OnceCell<Runtime> = OnceCell::new();
+static FEE_ESTIMATOR: OnceCell<FeeEstimator> = OnceCell::new();
+static ORDER_TRACKER: StdMutex<Option<JoinHandle<()>>> = StdMutex::new(None);
 
 pub(crate) fn ensure_runtime() -> &'static Runtime {
     RUNTIME.get_or_init(|| Runtime::new().expect("Failed to create Tokio runtime"))
@@ -279,10 +301,194 @@ pub fn mnemonic_to_seed(
     onchain::BitcoinAddressValidator::mnemonic_to_seed(&mnemonic_phrase, passphrase.as_deref())
 }
 
+#[uniffi::export]
+pub fn slip39_generate_shares(
+    master_secret: Vec<u8>,
+    group_threshold: u8,
+    groups: Vec<Slip39Group>,
+    passphrase: Option<String>,
+    iteration_exponent: Option<u8>,
+) -> Result<Vec<Slip39GroupShares>, Slip39Error> {
+    onchain::slip39::generate_shares(
+        &master_secret,
+        group_threshold,
+        &groups,
+        passphrase.as_deref().unwrap_or(""),
+        iteration_exponent.unwrap_or(1),
+    )
+}
+
+#[uniffi::export]
+pub fn slip39_combine_shares(
+    shares: Vec<String>,
+    passphrase: Option<String>,
+) -> Result<Vec<u8>, Slip39Error> {
+    onchain::slip39::combine_shares(&shares, passphrase.as_deref().unwrap_or(""))
+}
+
+#[uniffi::export]
+pub fn slip39_shares_from_mnemonic(
+    mnemonic_phrase: String,
+    group_threshold: u8,
+    groups: Vec<Slip39Group>,
+    passphrase: Option<String>,
+    iteration_exponent: Option<u8>,
+) -> Result<Vec<Slip39GroupShares>, Slip39Error> {
+    onchain::slip39::generate_shares_from_mnemonic(
+        &mnemonic_phrase,
+        group_threshold,
+        &groups,
+        passphrase.as_deref().unwrap_or(""),
+        iteration_exponent.unwrap_or(1),
+    )
+}
+
+#[uniffi::export]
+pub fn slip39_shares_to_mnemonic(
+    shares: Vec<String>,
+    passphrase: Option<String>,
+) -> Result<String, Slip39Error> {
+    onchain::slip39::combine_shares_to_mnemonic(&shares, passphrase.as_deref().unwrap_or(""))
+}
+
+#[uniffi::export]
+pub fn slip39_parse_share(share: String) -> Result<Slip39ShareInfo, Slip39Error> {
+    onchain::slip39::parse_share(&share)
+}
+
+#[uniffi::export]
+pub fn sign_bitcoin_message(
+    message: String,
+    address: String,
+    private_key: String,
+    format: Option<MessageSignatureFormat>,
+) -> Result<String, MessageSigningError> {
+    onchain::message_signing::sign_message(&message, &address, &private_key, format)
+}
+
+#[uniffi::export]
+pub fn verify_bitcoin_message(
+    message: String,
+    address: String,
+    signature: String,
+) -> Result<bool, MessageSigningError> {
+    onchain::message_signing::verify_message(&message, &address, &signature)
+}
+
+#[uniffi::export]
+pub async fn estimate_fee_rates(
+    esplora_url: Option<String>,
+) -> Result<FeeEstimates, FeeEstimationError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let mut samples = Vec::new();
+
+        // Blocktank's stored info is an optional source; the estimator works without init_db
+        if let Some(cell) = ASYNC_DB.get() {
+            let guard = cell.lock().await;
+            if let Some(db) = guard.blocktank_db.as_ref() {
+                if let Ok(Some(info)) = db.get_info().await {
+                    let fee_rates = IBtInfo::from(info).onchain.fee_rates;
+                    samples.push(FeeRateSample {
+                        source: onchain::fees::BLOCKTANK_SOURCE.to_string(),
+                        fast: fee_rates.fast as f64,
+                        normal: fee_rates.mid as f64,
+                        slow: fee_rates.slow as f64,
+                        minimum: None,
+                    });
+                }
+            }
+        }
+
+        let client = esplora_url.map(|url| HttpFeeEstimatesClient::new(&url));
+        FEE_ESTIMATOR
+            .get_or_init(FeeEstimator::default)
+            .estimate(
+                samples,
+                client.as_ref().map(|c| c as &dyn FeeEstimatesClient),
+            )
+            .await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(FeeEstimationError::SourceError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub fn calculate_transaction_vsize(
+    inputs: Vec<AddressType>,
+    outputs: Vec<AddressType>,
+) -> Result<u64, FeeEstimationError> {
+    onchain::vsize::estimate_vsize(&inputs, &outputs)
+}
+
+#[uniffi::export]
+pub fn calculate_transaction_fee(
+    inputs: Vec<AddressType>,
+    outputs: Vec<AddressType>,
+    fee_rate: f64,
+) -> Result<TransactionFee, FeeEstimationError> {
+    onchain::fees::calculate_fee(&inputs, &outputs, fee_rate)
+}
+
+#[uniffi::export]
+pub fn calculate_transaction_fees(
+    inputs: Vec<AddressType>,
+    outputs: Vec<AddressType>,
+    estimates: FeeEstimates,
+) -> Result<TransactionFees, FeeEstimationError> {
+    estimates.fees_for_shape(&inputs, &outputs)
+}
+
+#[uniffi::export]
+pub fn plan_rbf_fee_bump(
+    activity: OnchainActivity,
+    transaction_details: TransactionDetails,
+    utxos: Vec<WalletUtxo>,
+    change_address: String,
+    target_fee_rate: f64,
+) -> Result<FeeBumpPlan, FeeBumpError> {
+    onchain::fee_bump::plan_rbf(
+        &activity,
+        &transaction_details,
+        &utxos,
+        &change_address,
+        target_fee_rate,
+    )
+}
+
+#[uniffi::export]
+pub fn plan_cpfp_fee_bump(
+    activity: OnchainActivity,
+    transaction_details: TransactionDetails,
+    utxos: Vec<WalletUtxo>,
+    change_address: String,
+    target_fee_rate: f64,
+) -> Result<FeeBumpPlan, FeeBumpError> {
+    onchain::fee_bump::plan_cpfp(
+        &activity,
+        &transaction_details,
+        &utxos,
+        &change_address,
+        target_fee_rate,
+    )
+}
+
 #[uniffi::export]
 pub fn init_db(base_path: String) -> Result<String, DbError> {
     // Initialize sync database state
-    DB.get_or_init(|| StdMutex::new(DatabaseConnections { activity_db: None }));
+    DB.get_or_init(|| {
+        StdMutex::new(DatabaseConnections {
+            activity_db: None,
+            trezor_requests: None,
+            trezor_accounts: None,
+            trezor_settings: None,
+            trezor_multisig_wallets: None,
+        })
+    });
 
     // Initialize async database state
     ASYNC_DB.get_or_init(|| TokioMutex::new(AsyncDatabaseConnections { blocktank_db: None }));
@@ -291,6 +497,20 @@ pub fn init_db(base_path: String) -> Result<String, DbError> {
     let rt = ensure_runtime();
     // Create database connections
     let activity_db = ActivityDB::new(&format!("{}/activity.db", base_path))?;
+    let trezor_error = |e: TrezorConnectError| DbError::InitializationError {
+        error_details: e.to_string(),
+    };
+    // The Trezor registry and stores share one connection to trezor.db
+    let trezor_db =
+        trezor::open_trezor_db(&format!("{}/trezor.db", base_path)).map_err(trezor_error)?;
+    let trezor_requests =
+        TrezorRequestRegistry::with_connection(trezor_db.clone()).map_err(trezor_error)?;
+    let trezor_accounts =
+        TrezorAccountStore::with_connection(trezor_db.clone()).map_err(trezor_error)?;
+    let trezor_settings =
+        TrezorSettingsStore::with_connection(trezor_db.clone()).map_err(trezor_error)?;
+    let trezor_multisig_wallets =
+        TrezorMultisigStore::with_connection(trezor_db).map_err(trezor_error)?;
     let blocktank_db = rt
         .block_on(async { BlocktankDB::new(&format!("{}/blocktank.db", base_path), None).await })?;
 
@@ -298,6 +518,10 @@ pub fn init_db(base_path: String) -> Result<String, DbError> {
     {
         let mut guard = DB.get().unwrap().lock().unwrap();
         guard.activity_db = Some(activity_db);
+        guard.trezor_requests = Some(Arc::new(trezor_requests));
+        guard.trezor_accounts = Some(Arc::new(trezor_accounts));
+        guard.trezor_settings = Some(Arc::new(trezor_settings));
+        guard.trezor_multisig_wallets = Some(Arc::new(trezor_multisig_wallets));
     }
 
     // Initialize async database
@@ -423,6 +647,21 @@ pub fn mark_activity_as_seen(activity_id: String, seen_at: u64) -> Result<(), Ac
     db.mark_activity_as_seen(&activity_id, seen_at)
 }
 
+#[uniffi::export]
+pub fn add_activity_boost_tx_id(
+    activity_id: String,
+    boost_tx_id: String,
+) -> Result<(), ActivityError> {
+    let mut guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_mut()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.add_boost_tx_id(&activity_id, &boost_tx_id)
+}
+
 #[uniffi::export]
 pub fn is_address_used(address: String) -> Result<bool, ActivityError> {
     let guard = get_activity_db()?;
@@ -813,27 +1052,23 @@ pub async fn update_blocktank_url(new_url: String) -> Result<(), BlocktankError>
     })
 }
 
+/// Register an LSP implementing the Blocktank API besides the default one. The registration
+/// is stored, so `init_db` registers it again
 #[uniffi::export]
-pub async fn get_info(refresh: Option<bool>) -> Result<Option<IBtInfo>, BlocktankError> {
+pub async fn register_lsp(lsp_url: String) -> Result<(), BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
             error_details: "Database not initialized. Call init_db first.".to_string(),
         })?;
-        let guard = cell.lock().await;
+        let mut guard = cell.lock().await;
         let db = guard
             .blocktank_db
-            .as_ref()
+            .as_mut()
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-
-        if refresh.unwrap_or(false) {
-            Ok(Some(db.fetch_and_store_info().await?.into()))
-        } else {
-            let info = db.get_info().await?;
-            Ok(info.map(|info| info.into()))
-        }
+        db.register_lsp(&lsp_url)
     })
     .await
     .unwrap_or_else(|e| {
@@ -843,32 +1078,27 @@ pub async fn get_info(refresh: Option<bool>) -> Result<Option<IBtInfo>, Blocktan
     })
 }
 
+/// Register an LSP node speaking LSPS1 and LSPS2, reached through `transport`. Returns the
+/// URL it is registered under
 #[uniffi::export]
-pub async fn create_order(
-    lsp_balance_sat: u64,
-    channel_expiry_weeks: u32,
-    options: Option<CreateOrderOptions>,
-) -> Result<IBtOrder, BlocktankError> {
+pub async fn register_lsps_lsp(
+    lsp_node_id: String,
+    network: BitcoinNetworkEnum,
+    transport: Arc<dyn BtLspsTransport>,
+) -> Result<String, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
             error_details: "Database not initialized. Call init_db first.".to_string(),
         })?;
-        let guard = cell.lock().await;
+        let mut guard = cell.lock().await;
         let db = guard
             .blocktank_db
-            .as_ref()
+            .as_mut()
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-
-        // Convert the options to the external type using .into()
-        let external_options = options.map(|opt| opt.into());
-
-        // Convert the result to our local IBtOrder type
-        db.create_and_store_order(lsp_balance_sat, channel_expiry_weeks, external_options)
-            .await
-            .map(|order| order.into())
+        db.register_lsps_lsp(&lsp_node_id, network.into(), transport)
     })
     .await
     .unwrap_or_else(|e| {
@@ -878,27 +1108,22 @@ pub async fn create_order(
     })
 }
 
+/// Unregister an LSP. Returns whether it was registered
 #[uniffi::export]
-pub async fn open_channel(
-    order_id: String,
-    connection_string: String,
-) -> Result<IBtOrder, BlocktankError> {
+pub async fn unregister_lsp(lsp_url: String) -> Result<bool, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
             error_details: "Database not initialized. Call init_db first.".to_string(),
         })?;
-        let guard = cell.lock().await;
+        let mut guard = cell.lock().await;
         let db = guard
             .blocktank_db
-            .as_ref()
+            .as_mut()
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-
-        db.open_channel(order_id, connection_string)
-            .await
-            .map(|order| order.into())
+        db.unregister_lsp(&lsp_url)
     })
     .await
     .unwrap_or_else(|e| {
@@ -908,12 +1133,9 @@ pub async fn open_channel(
     })
 }
 
+/// Get the URLs of the default LSP and the registered LSPs
 #[uniffi::export]
-pub async fn get_orders(
-    order_ids: Option<Vec<String>>,
-    filter: Option<BtOrderState2>,
-    refresh: bool,
-) -> Result<Vec<IBtOrder>, BlocktankError> {
+pub async fn get_lsp_urls() -> Result<Vec<String>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -926,18 +1148,7 @@ pub async fn get_orders(
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-
-        // If refresh is true and we have order_ids, refresh those specific orders
-        if let (true, Some(ids)) = (refresh, &order_ids) {
-            db.refresh_orders(ids)
-                .await
-                .map(|orders| orders.into_iter().map(|order| order.into()).collect())
-        } else {
-            // Otherwise get orders from the database
-            db.get_orders(order_ids.as_deref(), filter.map(|f| f.into()))
-                .await
-                .map(|orders| orders.into_iter().map(|order| order.into()).collect())
-        }
+        Ok(db.lsp_urls())
     })
     .await
     .unwrap_or_else(|e| {
@@ -947,9 +1158,12 @@ pub async fn get_orders(
     })
 }
 
-/// Refresh all active orders in the database with latest data from the LSP
+/// Get the service information of an LSP, fetching it first if `refresh` is set
 #[uniffi::export]
-pub async fn refresh_active_orders() -> Result<Vec<IBtOrder>, BlocktankError> {
+pub async fn get_lsp_info(
+    lsp_url: String,
+    refresh: Option<bool>,
+) -> Result<Option<IBtInfo>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -962,9 +1176,13 @@ pub async fn refresh_active_orders() -> Result<Vec<IBtOrder>, BlocktankError> {
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-        db.refresh_active_orders()
-            .await
-            .map(|orders| orders.into_iter().map(|order| order.into()).collect())
+
+        if refresh.unwrap_or(false) {
+            Ok(Some(db.fetch_and_store_lsp_info(&lsp_url).await?.into()))
+        } else {
+            let info = db.get_lsp_info(&lsp_url).await?;
+            Ok(info.map(|info| info.into()))
+        }
     })
     .await
     .unwrap_or_else(|e| {
@@ -974,10 +1192,13 @@ pub async fn refresh_active_orders() -> Result<Vec<IBtOrder>, BlocktankError> {
     })
 }
 
+/// Quote a channel with every LSP whose limits allow it, cheapest first
 #[uniffi::export]
-pub async fn get_min_zero_conf_tx_fee(
-    order_id: String,
-) -> Result<IBt0ConfMinTxFeeWindow, BlocktankError> {
+pub async fn compare_lsp_quotes(
+    lsp_balance_sat: u64,
+    channel_expiry_weeks: u32,
+    options: Option<CreateOrderOptions>,
+) -> Result<Vec<BtLspQuote>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -991,9 +1212,10 @@ pub async fn get_min_zero_conf_tx_fee(
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.get_min_zero_conf_tx_fee(order_id)
+        let external_options = options.map(|opt| opt.into());
+
+        db.compare_lsp_quotes(lsp_balance_sat, channel_expiry_weeks, external_options)
             .await
-            .map(|fee| fee.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1003,12 +1225,13 @@ pub async fn get_min_zero_conf_tx_fee(
     })
 }
 
+/// Select the cheapest LSP whose limits allow a channel
 #[uniffi::export]
-pub async fn estimate_order_fee(
+pub async fn select_lsp(
     lsp_balance_sat: u64,
     channel_expiry_weeks: u32,
     options: Option<CreateOrderOptions>,
-) -> Result<IBtEstimateFeeResponse, BlocktankError> {
+) -> Result<BtLspQuote, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1024,9 +1247,8 @@ pub async fn estimate_order_fee(
 
         let external_options = options.map(|opt| opt.into());
 
-        db.estimate_order_fee(lsp_balance_sat, channel_expiry_weeks, external_options)
+        db.select_lsp(lsp_balance_sat, channel_expiry_weeks, external_options)
             .await
-            .map(|response| response.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1036,12 +1258,14 @@ pub async fn estimate_order_fee(
     })
 }
 
+/// Create an order with a specific LSP
 #[uniffi::export]
-pub async fn estimate_order_fee_full(
+pub async fn create_lsp_order(
+    lsp_url: String,
     lsp_balance_sat: u64,
     channel_expiry_weeks: u32,
     options: Option<CreateOrderOptions>,
-) -> Result<IBtEstimateFeeResponse2, BlocktankError> {
+) -> Result<IBtOrder, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1057,9 +1281,14 @@ pub async fn estimate_order_fee_full(
 
         let external_options = options.map(|opt| opt.into());
 
-        db.estimate_order_fee_full(lsp_balance_sat, channel_expiry_weeks, external_options)
-            .await
-            .map(|response| response.into())
+        db.create_lsp_order(
+            &lsp_url,
+            lsp_balance_sat,
+            channel_expiry_weeks,
+            external_options,
+        )
+        .await
+        .map(|order| order.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1069,8 +1298,10 @@ pub async fn estimate_order_fee_full(
     })
 }
 
+/// Create a CJIT entry with a specific LSP
 #[uniffi::export]
-pub async fn create_cjit_entry(
+pub async fn create_lsp_cjit_entry(
+    lsp_url: String,
     channel_size_sat: u64,
     invoice_sat: u64,
     invoice_description: String,
@@ -1093,7 +1324,8 @@ pub async fn create_cjit_entry(
 
         let external_options = options.map(|opt| opt.into());
 
-        db.create_cjit_entry(
+        db.create_lsp_cjit_entry(
+            &lsp_url,
             channel_size_sat,
             invoice_sat,
             &invoice_description,
@@ -1112,12 +1344,12 @@ pub async fn create_cjit_entry(
     })
 }
 
+/// Get the URL of the LSP an order or CJIT entry was created with
 #[uniffi::export]
-pub async fn get_cjit_entries(
-    entry_ids: Option<Vec<String>>,
-    filter: Option<CJitStateEnum>,
-    refresh: bool,
-) -> Result<Vec<ICJitEntry>, BlocktankError> {
+pub async fn get_item_lsp(
+    item_id: String,
+    kind: BtTrackedItemKind,
+) -> Result<String, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1130,24 +1362,7 @@ pub async fn get_cjit_entries(
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-
-        // If refresh is true and we have entry_ids, refresh those specific entries
-        if let (true, Some(entries)) = (refresh, &entry_ids) {
-            // Since we don't have a bulk refresh method for CJIT entries,
-            // we'll refresh them one by one
-            let mut results = Vec::new();
-            for entry_id in entries.iter() {
-                if let Ok(entry) = db.refresh_cjit_entry(entry_id).await {
-                    results.push(entry);
-                }
-            }
-            Ok(results.into_iter().map(|entry| entry.into()).collect())
-        } else {
-            // Otherwise get entries from the database
-            db.get_cjit_entries(entry_ids.as_deref(), filter.map(|f| f.into()))
-                .await
-                .map(|entries| entries.into_iter().map(|entry| entry.into()).collect())
-        }
+        db.get_item_lsp(&item_id, kind).await
     })
     .await
     .unwrap_or_else(|e| {
@@ -1157,9 +1372,8 @@ pub async fn get_cjit_entries(
     })
 }
 
-/// Refresh all active CJIT entries in the database with latest data from the LSP
 #[uniffi::export]
-pub async fn refresh_active_cjit_entries() -> Result<Vec<ICJitEntry>, BlocktankError> {
+pub async fn get_info(refresh: Option<bool>) -> Result<Option<IBtInfo>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1172,9 +1386,13 @@ pub async fn refresh_active_cjit_entries() -> Result<Vec<ICJitEntry>, BlocktankE
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-        db.refresh_active_cjit_entries()
-            .await
-            .map(|entries| entries.into_iter().map(|entry| entry.into()).collect())
+
+        if refresh.unwrap_or(false) {
+            Ok(Some(db.fetch_and_store_info().await?.into()))
+        } else {
+            let info = db.get_info().await?;
+            Ok(info.map(|info| info.into()))
+        }
     })
     .await
     .unwrap_or_else(|e| {
@@ -1185,17 +1403,11 @@ pub async fn refresh_active_cjit_entries() -> Result<Vec<ICJitEntry>, BlocktankE
 }
 
 #[uniffi::export]
-#[allow(clippy::too_many_arguments)] // FFI requires flat parameter list for mobile binding compatibility
-pub async fn register_device(
-    device_token: String,
-    public_key: String,
-    features: Vec<String>,
-    node_id: String,
-    iso_timestamp: String,
-    signature: String,
-    is_production: Option<bool>,
-    custom_url: Option<String>,
-) -> Result<String, BlocktankError> {
+pub async fn create_order(
+    lsp_balance_sat: u64,
+    channel_expiry_weeks: u32,
+    options: Option<CreateOrderOptions>,
+) -> Result<IBtOrder, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1209,17 +1421,13 @@ pub async fn register_device(
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.register_device(
-            &device_token,
-            &public_key,
-            &features,
-            &node_id,
-            &iso_timestamp,
-            &signature,
-            is_production,
-            custom_url.as_deref(),
-        )
-        .await
+        // Convert the options to the external type using .into()
+        let external_options = options.map(|opt| opt.into());
+
+        // Convert the result to our local IBtOrder type
+        db.create_and_store_order(lsp_balance_sat, channel_expiry_weeks, external_options)
+            .await
+            .map(|order| order.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1230,12 +1438,10 @@ pub async fn register_device(
 }
 
 #[uniffi::export]
-pub async fn test_notification(
-    device_token: String,
-    secret_message: String,
-    notification_type: Option<String>,
-    custom_url: Option<String>,
-) -> Result<String, BlocktankError> {
+pub async fn open_channel(
+    order_id: String,
+    connection_string: String,
+) -> Result<IBtOrder, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1249,13 +1455,9 @@ pub async fn test_notification(
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.test_notification(
-            &device_token,
-            &secret_message,
-            notification_type.as_deref(),
-            custom_url.as_deref(),
-        )
-        .await
+        db.open_channel(order_id, connection_string)
+            .await
+            .map(|order| order.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1266,7 +1468,11 @@ pub async fn test_notification(
 }
 
 #[uniffi::export]
-pub async fn gift_pay(invoice: String) -> Result<IGift, BlocktankError> {
+pub async fn get_orders(
+    order_ids: Option<Vec<String>>,
+    filter: Option<BtOrderState2>,
+    refresh: bool,
+) -> Result<Vec<IBtOrder>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1280,7 +1486,17 @@ pub async fn gift_pay(invoice: String) -> Result<IGift, BlocktankError> {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.gift_pay(&invoice).await
+        // If refresh is true and we have order_ids, refresh those specific orders
+        if let (true, Some(ids)) = (refresh, &order_ids) {
+            db.refresh_orders(ids)
+                .await
+                .map(|orders| orders.into_iter().map(|order| order.into()).collect())
+        } else {
+            // Otherwise get orders from the database
+            db.get_orders(order_ids.as_deref(), filter.map(|f| f.into()))
+                .await
+                .map(|orders| orders.into_iter().map(|order| order.into()).collect())
+        }
     })
     .await
     .unwrap_or_else(|e| {
@@ -1290,8 +1506,9 @@ pub async fn gift_pay(invoice: String) -> Result<IGift, BlocktankError> {
     })
 }
 
+/// Refresh all active orders in the database with latest data from the LSP
 #[uniffi::export]
-pub async fn gift_order(client_node_id: String, code: String) -> Result<IGift, BlocktankError> {
+pub async fn refresh_active_orders() -> Result<Vec<IBtOrder>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1304,8 +1521,9 @@ pub async fn gift_order(client_node_id: String, code: String) -> Result<IGift, B
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-
-        db.gift_order(&client_node_id, &code).await
+        db.refresh_active_orders()
+            .await
+            .map(|orders| orders.into_iter().map(|order| order.into()).collect())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1316,7 +1534,9 @@ pub async fn gift_order(client_node_id: String, code: String) -> Result<IGift, B
 }
 
 #[uniffi::export]
-pub async fn get_gift(gift_id: String) -> Result<IGift, BlocktankError> {
+pub async fn get_min_zero_conf_tx_fee(
+    order_id: String,
+) -> Result<IBt0ConfMinTxFeeWindow, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1330,7 +1550,9 @@ pub async fn get_gift(gift_id: String) -> Result<IGift, BlocktankError> {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.get_gift(&gift_id).await
+        db.get_min_zero_conf_tx_fee(order_id)
+            .await
+            .map(|fee| fee.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1341,7 +1563,11 @@ pub async fn get_gift(gift_id: String) -> Result<IGift, BlocktankError> {
 }
 
 #[uniffi::export]
-pub async fn get_payment(payment_id: String) -> Result<IBtBolt11Invoice, BlocktankError> {
+pub async fn estimate_order_fee(
+    lsp_balance_sat: u64,
+    channel_expiry_weeks: u32,
+    options: Option<CreateOrderOptions>,
+) -> Result<IBtEstimateFeeResponse, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1355,9 +1581,11 @@ pub async fn get_payment(payment_id: String) -> Result<IBtBolt11Invoice, Blockta
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.get_payment(&payment_id)
+        let external_options = options.map(|opt| opt.into());
+
+        db.estimate_order_fee(lsp_balance_sat, channel_expiry_weeks, external_options)
             .await
-            .map(|payment| payment.into())
+            .map(|response| response.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1368,7 +1596,11 @@ pub async fn get_payment(payment_id: String) -> Result<IBtBolt11Invoice, Blockta
 }
 
 #[uniffi::export]
-pub async fn regtest_mine(count: Option<u32>) -> Result<(), BlocktankError> {
+pub async fn estimate_order_fee_full(
+    lsp_balance_sat: u64,
+    channel_expiry_weeks: u32,
+    options: Option<CreateOrderOptions>,
+) -> Result<IBtEstimateFeeResponse2, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1382,7 +1614,11 @@ pub async fn regtest_mine(count: Option<u32>) -> Result<(), BlocktankError> {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.regtest_mine(count).await
+        let external_options = options.map(|opt| opt.into());
+
+        db.estimate_order_fee_full(lsp_balance_sat, channel_expiry_weeks, external_options)
+            .await
+            .map(|response| response.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1392,11 +1628,12 @@ pub async fn regtest_mine(count: Option<u32>) -> Result<(), BlocktankError> {
     })
 }
 
+/// Quote a grid of channel orders for the target liquidity, cheapest per sat-week first
 #[uniffi::export]
-pub async fn regtest_deposit(
-    address: String,
-    amount_sat: Option<u64>,
-) -> Result<String, BlocktankError> {
+pub async fn compare_channel_quotes(
+    request: BtQuoteRequest,
+    options: Option<CreateOrderOptions>,
+) -> Result<Vec<BtChannelQuote>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1410,7 +1647,9 @@ pub async fn regtest_deposit(
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.regtest_deposit(&address, amount_sat).await
+        let external_options = options.map(|opt| opt.into());
+
+        db.compare_channel_quotes(&request, external_options).await
     })
     .await
     .unwrap_or_else(|e| {
@@ -1421,10 +1660,14 @@ pub async fn regtest_deposit(
 }
 
 #[uniffi::export]
-pub async fn regtest_pay(
-    invoice: String,
-    amount_sat: Option<u64>,
-) -> Result<String, BlocktankError> {
+pub async fn create_cjit_entry(
+    channel_size_sat: u64,
+    invoice_sat: u64,
+    invoice_description: String,
+    node_id: String,
+    channel_expiry_weeks: u32,
+    options: Option<CreateCjitOptions>,
+) -> Result<ICJitEntry, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1438,7 +1681,18 @@ pub async fn regtest_pay(
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.regtest_pay(&invoice, amount_sat).await
+        let external_options = options.map(|opt| opt.into());
+
+        db.create_cjit_entry(
+            channel_size_sat,
+            invoice_sat,
+            &invoice_description,
+            &node_id,
+            channel_expiry_weeks,
+            external_options,
+        )
+        .await
+        .map(|entry| entry.into())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1449,7 +1703,11 @@ pub async fn regtest_pay(
 }
 
 #[uniffi::export]
-pub async fn regtest_get_payment(payment_id: String) -> Result<IBtBolt11Invoice, BlocktankError> {
+pub async fn get_cjit_entries(
+    entry_ids: Option<Vec<String>>,
+    filter: Option<CJitStateEnum>,
+    refresh: bool,
+) -> Result<Vec<ICJitEntry>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1463,9 +1721,23 @@ pub async fn regtest_get_payment(payment_id: String) -> Result<IBtBolt11Invoice,
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
 
-        db.regtest_get_payment(&payment_id)
-            .await
-            .map(|invoice| invoice.into())
+        // If refresh is true and we have entry_ids, refresh those specific entries
+        if let (true, Some(entries)) = (refresh, &entry_ids) {
+            // Since we don't have a bulk refresh method for CJIT entries,
+            // we'll refresh them one by one
+            let mut results = Vec::new();
+            for entry_id in entries.iter() {
+                if let Ok(entry) = db.refresh_cjit_entry(entry_id).await {
+                    results.push(entry);
+                }
+            }
+            Ok(results.into_iter().map(|entry| entry.into()).collect())
+        } else {
+            // Otherwise get entries from the database
+            db.get_cjit_entries(entry_ids.as_deref(), filter.map(|f| f.into()))
+                .await
+                .map(|entries| entries.into_iter().map(|entry| entry.into()).collect())
+        }
     })
     .await
     .unwrap_or_else(|e| {
@@ -1475,12 +1747,9 @@ pub async fn regtest_get_payment(payment_id: String) -> Result<IBtBolt11Invoice,
     })
 }
 
+/// Refresh all active CJIT entries in the database with latest data from the LSP
 #[uniffi::export]
-pub async fn regtest_close_channel(
-    funding_tx_id: String,
-    vout: u32,
-    force_close_after_s: Option<u64>,
-) -> Result<String, BlocktankError> {
+pub async fn refresh_active_cjit_entries() -> Result<Vec<ICJitEntry>, BlocktankError> {
     let rt = ensure_runtime();
     rt.spawn(async move {
         let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
@@ -1493,9 +1762,9 @@ pub async fn regtest_close_channel(
             .ok_or(BlocktankError::ConnectionError {
                 error_details: "Database not initialized. Call init_db first.".to_string(),
             })?;
-
-        db.regtest_close_channel(&funding_tx_id, vout, force_close_after_s)
+        db.refresh_active_cjit_entries()
             .await
+            .map(|entries| entries.into_iter().map(|entry| entry.into()).collect())
     })
     .await
     .unwrap_or_else(|e| {
@@ -1505,96 +1774,1160 @@ pub async fn regtest_close_channel(
     })
 }
 
+async fn poll_tracked_blocktank_items() -> Result<Vec<BtLifecycleEvent>, BlocktankError> {
+    let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+        error_details: "Database not initialized. Call init_db first.".to_string(),
+    })?;
+    let guard = cell.lock().await;
+    let db = guard
+        .blocktank_db
+        .as_ref()
+        .ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.poll_tracked_items().await
+}
+
+/// Start polling active orders and CJIT entries in the background, replacing any running tracker.
+/// Every lifecycle event is delivered to `listener`.
 #[uniffi::export]
-pub fn trezor_get_features(
-    callback_url: String,
-    request_id: Option<String>,
-    trezor_environment: Option<TrezorEnvironment>,
-) -> Result<DeepLinkResult, TrezorConnectError> {
-    let trezor_environment = trezor_environment.unwrap_or(TrezorEnvironment::Production);
-    let trezor_client = match trezor::TrezorConnectClient::new(trezor_environment, callback_url) {
-        Ok(client) => client,
-        Err(e) => {
-            return Err(TrezorConnectError::ClientError {
-                error_details: e.to_string(),
-            })
-        }
-    };
-    match trezor_client.get_features(request_id) {
-        Ok(result) => Ok(result),
-        Err(e) => Err(TrezorConnectError::ClientError {
-            error_details: e.to_string(),
-        }),
+pub fn start_order_tracking(
+    listener: Arc<dyn BtLifecycleListener>,
+    config: Option<BtTrackerConfig>,
+) -> Result<(), BlocktankError> {
+    if ASYNC_DB.get().is_none() {
+        return Err(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        });
+    }
+
+    let rt = ensure_runtime();
+    let handle = rt.spawn(run_order_tracker(
+        config.unwrap_or_default(),
+        listener,
+        poll_tracked_blocktank_items,
+    ));
+    if let Some(previous) = ORDER_TRACKER.lock().unwrap().replace(handle) {
+        previous.abort();
     }
+    Ok(())
 }
 
+/// Stop the background order tracker if it is running
 #[uniffi::export]
-#[allow(non_snake_case)] // Trezor Connect API uses camelCase parameter names
-#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
-pub fn trezor_get_address(
-    path: String,
-    callback_url: String,
-    request_id: Option<String>,
-    trezor_environment: Option<TrezorEnvironment>,
-    address: Option<String>,
-    showOnTrezor: Option<bool>,
-    chunkify: Option<bool>,
-    useEventListener: Option<bool>,
-    coin: Option<String>,
-    crossChain: Option<bool>,
-    multisig: Option<MultisigRedeemScriptType>,
-    scriptType: Option<String>,
-    unlockPath: Option<UnlockPath>,
-    common: Option<CommonParams>,
-) -> Result<DeepLinkResult, TrezorConnectError> {
-    let trezor_environment = trezor_environment.unwrap_or(TrezorEnvironment::Production);
-    let trezor_client = match trezor::TrezorConnectClient::new(trezor_environment, callback_url) {
-        Ok(client) => client,
-        Err(e) => {
-            return Err(TrezorConnectError::ClientError {
-                error_details: e.to_string(),
-            })
-        }
-    };
+pub fn stop_order_tracking() {
+    if let Some(handle) = ORDER_TRACKER.lock().unwrap().take() {
+        handle.abort();
+    }
+}
 
-    let coin = Some(coin.unwrap_or_else(|| "btc".to_string()));
-    let params = GetAddressParams {
-        path,
-        address,
-        showOnTrezor,
-        chunkify,
-        useEventListener,
-        coin,
-        crossChain,
-        multisig,
-        scriptType,
-        unlockPath,
-        common,
-    };
+/// Poll tracked orders and CJIT entries once, e.g. from a background task of the host app
+#[uniffi::export]
+pub async fn poll_tracked_orders() -> Result<Vec<BtLifecycleEvent>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(poll_tracked_blocktank_items())
+        .await
+        .unwrap_or_else(|e| {
+            Err(BlocktankError::ConnectionError {
+                error_details: format!("Runtime error: {}", e),
+            })
+        })
+}
 
-    match trezor_client.get_address(params, request_id) {
-        Ok(result) => Ok(result),
-        Err(e) => Err(TrezorConnectError::ClientError {
-            error_details: e.to_string(),
-        }),
-    }
+/// Get the recorded lifecycle status transitions of an order or CJIT entry, oldest first
+#[uniffi::export]
+pub async fn get_order_status_history(
+    item_id: String,
+) -> Result<Vec<BtStatusTransition>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.get_status_history(&item_id).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
 }
 
+/// Get the logged state, payment state and channel state changes of an order, oldest first
 #[uniffi::export]
-#[allow(non_snake_case)] // Trezor Connect API uses camelCase parameter names
-#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
-pub fn trezor_get_account_info(
-    coin: String,
-    callback_url: String,
-    request_id: Option<String>,
-    trezor_environment: Option<TrezorEnvironment>,
-    path: Option<String>,
-    descriptor: Option<String>,
-    details: Option<AccountInfoDetails>,
-    tokens: Option<TokenFilter>,
-    page: Option<u32>,
-    pageSize: Option<u32>,
-    from: Option<u32>,
+pub async fn get_order_timeline(order_id: String) -> Result<Vec<BtOrderEvent>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.get_order_timeline(&order_id).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Get the stored orders whose payment can be refunded
+#[uniffi::export]
+pub async fn get_refundable_orders() -> Result<Vec<IBtOrder>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.get_refundable_orders()
+            .await
+            .map(|orders| orders.into_iter().map(|order| order.into()).collect())
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Attach or rotate the on-chain address the refund of an order is sent to
+#[uniffi::export]
+pub async fn set_order_refund_address(
+    order_id: String,
+    address: String,
+) -> Result<BtOrderRefund, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.set_refund_address(&order_id, &address).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Refresh refundable orders from the LSP and update their refund state
+#[uniffi::export]
+pub async fn refresh_order_refunds() -> Result<Vec<BtOrderRefund>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.refresh_order_refunds().await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Get all tracked refunds, most recently updated first
+#[uniffi::export]
+pub async fn get_order_refunds() -> Result<Vec<BtOrderRefund>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.get_order_refunds().await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Record the on-chain refund of an order as a received activity and mark the refund reconciled
+///
+/// The refund is validated before anything is recorded, so a rejected reconciliation leaves
+/// the activity list untouched. An existing received activity for `tx_id` is reused, otherwise
+/// one is created for the refund address attached with `set_order_refund_address`. The
+/// activity is tagged with `lsp-refund`.
+#[uniffi::export]
+pub async fn reconcile_order_refund(
+    order_id: String,
+    tx_id: String,
+    value: u64,
+    confirmed: bool,
+    timestamp: u64,
+) -> Result<OnchainActivity, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        let refund = db.get_reconcilable_refund(&order_id, &tx_id).await?;
+        // Blocktank sends manual refunds to the address attached to the refund, and the
+        // `refund_onchain_address` given when creating the order is not returned with it.
+        // The attached address is therefore the only one known to receive the refund.
+        let refund_address = refund.refund_address.ok_or(BlocktankError::OrderState {
+            error_details: format!("Order {} has no refund address", order_id),
+        })?;
+
+        let activity = record_refund_activity(&refund_address, &tx_id, value, confirmed, timestamp)
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to record refund activity: {}", e),
+            })?;
+        db.mark_refund_reconciled(&order_id, &tx_id).await?;
+        db.upsert_order_activity_links(&[BtOrderActivityLink {
+            order_id,
+            activity_id: activity.id.clone(),
+            kind: BtOrderActivityKind::Refund,
+            channel_id: None,
+        }])
+        .await?;
+        Ok(activity)
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+fn record_refund_activity(
+    refund_address: &str,
+    tx_id: &str,
+    value: u64,
+    confirmed: bool,
+    timestamp: u64,
+) -> Result<OnchainActivity, ActivityError> {
+    let mut guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_mut()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+
+    let activity = match db.get_activity_by_tx_id(tx_id)? {
+        Some(Activity::Onchain(activity)) if matches!(activity.tx_type, PaymentType::Received) => {
+            activity
+        }
+        Some(_) => {
+            return Err(ActivityError::DataError {
+                error_details: format!("Transaction {} is not a received payment", tx_id),
+            })
+        }
+        None => {
+            let activity = OnchainActivity {
+                id: tx_id.to_string(),
+                tx_type: PaymentType::Received,
+                tx_id: tx_id.to_string(),
+                value,
+                fee: 0,
+                fee_rate: 0,
+                address: refund_address.to_string(),
+                confirmed,
+                timestamp,
+                is_boosted: false,
+                boost_tx_ids: Vec::new(),
+                is_transfer: false,
+                does_exist: true,
+                confirm_timestamp: confirmed.then_some(timestamp),
+                channel_id: None,
+                transfer_tx_id: None,
+                created_at: None,
+                updated_at: None,
+                seen_at: None,
+            };
+            db.insert_onchain_activity(&activity)?;
+            activity
+        }
+    };
+
+    db.add_tags(&activity.id, &[REFUND_ACTIVITY_TAG.to_string()])?;
+    Ok(activity)
+}
+
+/// Link the stored orders to their payments, channel funding and refunds in the activity list
+///
+/// Matched payments and funding transactions are tagged with `lsp-order`. On-chain ones are
+/// marked as transfers into the channel once the order has one. Returns every link found, and
+/// can be run again after each sync.
+#[uniffi::export]
+pub async fn reconcile_order_activities() -> Result<Vec<BtOrderActivityLink>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        let orders = db.get_orders(None, None).await?;
+        let refunds = db.get_order_refunds().await?;
+
+        let links = link_order_activities(&orders, &refunds).map_err(|e| {
+            BlocktankError::DatabaseError {
+                error_details: format!("Failed to reconcile order activities: {}", e),
+            }
+        })?;
+        db.upsert_order_activity_links(&links).await?;
+        Ok(links)
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Get the activities linked to an order by `reconcile_order_activities`
+#[uniffi::export]
+pub async fn get_order_activities(
+    order_id: String,
+) -> Result<Vec<BtOrderActivityLink>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.get_order_activity_links(&order_id).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Get the order an activity pays for, funds or refunds, if it is linked to one
+#[uniffi::export]
+pub async fn get_order_for_activity(
+    activity_id: String,
+) -> Result<Option<IBtOrder>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.get_order_for_activity(&activity_id)
+            .await
+            .map(|order| order.map(|order| order.into()))
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+fn link_order_activities(
+    orders: &[rust_blocktank_client::IBtOrder],
+    refunds: &[BtOrderRefund],
+) -> Result<Vec<BtOrderActivityLink>, ActivityError> {
+    let mut guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_mut()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+
+    let mut links = Vec::new();
+    for order in orders {
+        for reference in order_activity_refs(order) {
+            let activity = match &reference.target {
+                OrderActivityTarget::Invoice(invoice) => db
+                    .get_lightning_activity_by_invoice(invoice)?
+                    .map(Activity::Lightning),
+                // The lookup by transaction leaves out the timestamps, so reload the activity
+                OrderActivityTarget::TxId(tx_id) => match db.get_activity_by_tx_id(tx_id)? {
+                    Some(activity) => db.get_activity_by_id(activity.get_id())?,
+                    None => None,
+                },
+            };
+            let Some(activity) = activity else {
+                continue;
+            };
+
+            if let (Activity::Onchain(onchain), Some(channel_id)) =
+                (&activity, &reference.channel_id)
+            {
+                let transfer_tx_id = reference
+                    .transfer_tx_id
+                    .clone()
+                    .or_else(|| onchain.transfer_tx_id.clone());
+                if !onchain.is_transfer
+                    || onchain.channel_id.as_ref() != Some(channel_id)
+                    || onchain.transfer_tx_id != transfer_tx_id
+                {
+                    let transfer = OnchainActivity {
+                        is_transfer: true,
+                        channel_id: Some(channel_id.clone()),
+                        transfer_tx_id,
+                        ..onchain.clone()
+                    };
+                    db.update_onchain_activity_by_id(&onchain.id, &transfer)?;
+                }
+            }
+
+            db.add_tags(activity.get_id(), &[ORDER_ACTIVITY_TAG.to_string()])?;
+            links.push(BtOrderActivityLink {
+                order_id: order.id.clone(),
+                activity_id: activity.get_id().to_string(),
+                kind: reference.kind,
+                channel_id: reference.channel_id,
+            });
+        }
+    }
+
+    for refund in refunds {
+        let Some(tx_id) = &refund.tx_id else {
+            continue;
+        };
+        if let Some(activity) = db.get_activity_by_tx_id(tx_id)? {
+            links.push(BtOrderActivityLink {
+                order_id: refund.order_id.clone(),
+                activity_id: activity.get_id().to_string(),
+                kind: BtOrderActivityKind::Refund,
+                channel_id: None,
+            });
+        }
+    }
+
+    Ok(links)
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)] // FFI requires flat parameter list for mobile binding compatibility
+pub async fn register_device(
+    device_token: String,
+    public_key: String,
+    features: Vec<String>,
+    node_id: String,
+    iso_timestamp: String,
+    signature: String,
+    is_production: Option<bool>,
+    custom_url: Option<String>,
+) -> Result<String, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.register_device(
+            &device_token,
+            &public_key,
+            &features,
+            &node_id,
+            &iso_timestamp,
+            &signature,
+            is_production,
+            custom_url.as_deref(),
+        )
+        .await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Register the device for push notifications, signing the registration with `signer`
+///
+/// Does nothing if the stored registration already matches. Empty `features` registers the
+/// default notification features.
+#[uniffi::export]
+pub async fn ensure_device_registered(
+    device_token: String,
+    features: Vec<String>,
+    signer: Arc<dyn BtNodeSigner>,
+    is_production: Option<bool>,
+    custom_url: Option<String>,
+) -> Result<BtDeviceRegistrationResult, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.ensure_device_registered(
+            &device_token,
+            &features,
+            signer,
+            is_production,
+            custom_url.as_deref(),
+        )
+        .await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Register the device for push notifications, signing the registration with the hex encoded
+/// node secret key
+#[uniffi::export]
+pub async fn ensure_device_registered_with_key(
+    device_token: String,
+    features: Vec<String>,
+    node_secret_key: String,
+    is_production: Option<bool>,
+    custom_url: Option<String>,
+) -> Result<BtDeviceRegistrationResult, BlocktankError> {
+    let signer = BtNodeKeySigner::new(&node_secret_key)?;
+    ensure_device_registered(
+        device_token,
+        features,
+        Arc::new(signer),
+        is_production,
+        custom_url,
+    )
+    .await
+}
+
+/// Get the stored push notification registration
+#[uniffi::export]
+pub async fn get_device_registration() -> Result<Option<BtDeviceRegistration>, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.get_device_registration().await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Decrypt and parse a push notification with the secret key of the stored registration
+#[uniffi::export]
+pub async fn decrypt_notification(
+    notification: BtEncryptedNotification,
+) -> Result<BtNotification, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+        db.decrypt_notification(&notification).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Decrypt and parse a push notification with a hex encoded notification secret key, e.g.
+/// from a notification service extension without database access
+#[uniffi::export]
+pub fn decrypt_notification_with_key(
+    notification: BtEncryptedNotification,
+    secret_key: String,
+) -> Result<BtNotification, BlocktankError> {
+    crate::modules::blocktank::decrypt_notification(&notification, &secret_key)
+}
+
+#[uniffi::export]
+pub async fn test_notification(
+    device_token: String,
+    secret_message: String,
+    notification_type: Option<String>,
+    custom_url: Option<String>,
+) -> Result<String, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.test_notification(
+            &device_token,
+            &secret_message,
+            notification_type.as_deref(),
+            custom_url.as_deref(),
+        )
+        .await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn gift_pay(invoice: String) -> Result<IGift, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.gift_pay(&invoice).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn gift_order(client_node_id: String, code: String) -> Result<IGift, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.gift_order(&client_node_id, &code).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn get_gift(gift_id: String) -> Result<IGift, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.get_gift(&gift_id).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn get_payment(payment_id: String) -> Result<IBtBolt11Invoice, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.get_payment(&payment_id)
+            .await
+            .map(|payment| payment.into())
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn regtest_mine(count: Option<u32>) -> Result<(), BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.regtest_mine(count).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn regtest_deposit(
+    address: String,
+    amount_sat: Option<u64>,
+) -> Result<String, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.regtest_deposit(&address, amount_sat).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn regtest_pay(
+    invoice: String,
+    amount_sat: Option<u64>,
+) -> Result<String, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.regtest_pay(&invoice, amount_sat).await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn regtest_get_payment(payment_id: String) -> Result<IBtBolt11Invoice, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.regtest_get_payment(&payment_id)
+            .await
+            .map(|invoice| invoice.into())
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+#[uniffi::export]
+pub async fn regtest_close_channel(
+    funding_tx_id: String,
+    vout: u32,
+    force_close_after_s: Option<u64>,
+) -> Result<String, BlocktankError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        let cell = ASYNC_DB.get().ok_or(BlocktankError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+        let guard = cell.lock().await;
+        let db = guard
+            .blocktank_db
+            .as_ref()
+            .ok_or(BlocktankError::ConnectionError {
+                error_details: "Database not initialized. Call init_db first.".to_string(),
+            })?;
+
+        db.regtest_close_channel(&funding_tx_id, vout, force_close_after_s)
+            .await
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(BlocktankError::ConnectionError {
+            error_details: format!("Runtime error: {}", e),
+        })
+    })
+}
+
+/// Get the Trezor request registry opened by `init_db`
+fn trezor_request_registry() -> Result<Arc<TrezorRequestRegistry>, TrezorConnectError> {
+    DB.get()
+        .and_then(|cell| cell.lock().unwrap().trezor_requests.clone())
+        .ok_or(TrezorConnectError::DatabaseError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })
+}
+
+/// Get the store of watch-only Trezor accounts opened by `init_db`
+fn trezor_account_store() -> Result<Arc<TrezorAccountStore>, TrezorConnectError> {
+    DB.get()
+        .and_then(|cell| cell.lock().unwrap().trezor_accounts.clone())
+        .ok_or(TrezorConnectError::DatabaseError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })
+}
+
+/// Get the store of Trezor Connect settings opened by `init_db`
+fn trezor_settings_store() -> Result<Arc<TrezorSettingsStore>, TrezorConnectError> {
+    DB.get()
+        .and_then(|cell| cell.lock().unwrap().trezor_settings.clone())
+        .ok_or(TrezorConnectError::DatabaseError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })
+}
+
+/// Get the store of Trezor multisig wallets opened by `init_db`
+fn trezor_multisig_store() -> Result<Arc<TrezorMultisigStore>, TrezorConnectError> {
+    DB.get()
+        .and_then(|cell| cell.lock().unwrap().trezor_multisig_wallets.clone())
+        .ok_or(TrezorConnectError::DatabaseError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })
+}
+
+/// Create a Trezor Connect client for `environment`, or the default environment of the
+/// endpoint settings. Once `init_db` was called, it uses the stored endpoint settings and
+/// records its requests in the registry.
+fn trezor_connect_client(
+    environment: Option<TrezorEnvironment>,
+    callback_url: String,
+) -> Result<trezor::TrezorConnectClient, TrezorConnectError> {
+    let endpoints = match trezor_settings_store() {
+        Ok(settings) => settings.endpoints()?,
+        Err(_) => TrezorEndpointSettings::default(),
+    };
+    let environment = environment.unwrap_or(endpoints.default_environment);
+    let client =
+        trezor::TrezorConnectClient::new(environment, callback_url)?.with_endpoints(endpoints);
+    Ok(match trezor_request_registry() {
+        Ok(registry) => client.with_registry(registry),
+        Err(_) => client,
+    })
+}
+
+/// Get the Trezor Connect endpoint settings, the built-in ones until others are saved
+#[uniffi::export]
+pub fn trezor_get_endpoint_settings() -> Result<TrezorEndpointSettings, TrezorConnectError> {
+    trezor_settings_store()?.endpoints()
+}
+
+/// Validate and save the Trezor Connect endpoint settings used by the following calls
+#[uniffi::export]
+pub fn trezor_set_endpoint_settings(
+    settings: TrezorEndpointSettings,
+) -> Result<(), TrezorConnectError> {
+    trezor_settings_store()?.save_endpoints(&settings)
+}
+
+#[uniffi::export]
+pub fn trezor_get_features(
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+    match trezor_client.get_features(request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+#[uniffi::export]
+#[allow(non_snake_case)] // Trezor Connect API uses camelCase parameter names
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_get_address(
+    path: String,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    address: Option<String>,
+    showOnTrezor: Option<bool>,
+    chunkify: Option<bool>,
+    useEventListener: Option<bool>,
+    coin: Option<String>,
+    crossChain: Option<bool>,
+    multisig: Option<MultisigRedeemScriptType>,
+    scriptType: Option<String>,
+    unlockPath: Option<UnlockPath>,
+    common: Option<CommonParams>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    let coin = Some(coin.unwrap_or_else(|| "btc".to_string()));
+    let params = GetAddressParams {
+        path,
+        address,
+        showOnTrezor,
+        chunkify,
+        useEventListener,
+        coin,
+        crossChain,
+        multisig,
+        scriptType,
+        unlockPath,
+        common,
+    };
+
+    match trezor_client.get_address(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+#[uniffi::export]
+#[allow(non_snake_case)] // Trezor Connect API uses camelCase parameter names
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_get_public_key(
+    path: String,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    showOnTrezor: Option<bool>,
+    suppressBackupWarning: Option<bool>,
+    chunkify: Option<bool>,
+    coin: Option<String>,
+    crossChain: Option<bool>,
+    scriptType: Option<String>,
+    ignoreXpubMagic: Option<bool>,
+    ecdsaCurveName: Option<String>,
+    unlockPath: Option<UnlockPath>,
+    common: Option<CommonParams>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    let coin = Some(coin.unwrap_or_else(|| "btc".to_string()));
+    let params = GetPublicKeyParams {
+        path,
+        showOnTrezor,
+        suppressBackupWarning,
+        chunkify,
+        coin,
+        crossChain,
+        scriptType,
+        ignoreXpubMagic,
+        ecdsaCurveName,
+        unlockPath,
+        common,
+    };
+
+    match trezor_client.get_public_key(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+#[uniffi::export]
+#[allow(non_snake_case)] // Trezor Connect API uses camelCase parameter names
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_get_account_info(
+    coin: String,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    path: Option<String>,
+    descriptor: Option<String>,
+    details: Option<AccountInfoDetails>,
+    tokens: Option<TokenFilter>,
+    page: Option<u32>,
+    pageSize: Option<u32>,
+    from: Option<u32>,
     to: Option<u32>,
     gap: Option<u32>,
     contractFilter: Option<String>,
@@ -1603,8 +2936,287 @@ pub fn trezor_get_account_info(
     suppressBackupWarning: Option<bool>,
     common: Option<CommonParams>,
 ) -> Result<DeepLinkResult, TrezorConnectError> {
-    let trezor_environment = trezor_environment.unwrap_or(TrezorEnvironment::Production);
-    let trezor_client = match trezor::TrezorConnectClient::new(trezor_environment, callback_url) {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    let params = GetAccountInfoParams {
+        path,
+        descriptor,
+        coin,
+        details,
+        tokens,
+        page,
+        pageSize,
+        from,
+        to,
+        gap,
+        contractFilter,
+        marker,
+        defaultAccountType,
+        suppressBackupWarning,
+        common,
+    };
+
+    match trezor_client.get_account_info(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+/// Handle a callback URL from Trezor. Once `init_db` was called, the callback must answer a
+/// pending request and is deserialized by its method; otherwise the `method` query parameter
+/// of the callback is used.
+#[uniffi::export]
+pub fn trezor_handle_deep_link(
+    callback_url: String,
+) -> Result<TrezorResponsePayload, TrezorConnectError> {
+    if let Ok(registry) = trezor_request_registry() {
+        return registry.handle_callback(&callback_url);
+    }
+    match trezor::handle_deep_link(callback_url) {
+        Ok(result) => Ok(result),
+        Err(e @ TrezorConnectError::Failure { .. }) => Err(e),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+/// List the Trezor devices connected to Trezor Bridge, at `bridge_url` or at its default
+/// local address
+#[uniffi::export]
+pub async fn trezor_bridge_enumerate(
+    bridge_url: Option<String>,
+) -> Result<Vec<TrezorBridgeDevice>, TrezorConnectError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        TrezorBridgeClient::http(bridge_url.as_deref())
+            .enumerate()
+            .await
+    })
+    .await
+    .unwrap()
+}
+
+/// Run a Trezor Connect method on a device connected to Trezor Bridge, with the parameters of
+/// the method as JSON. The response is the payload the deep link callback would carry.
+#[uniffi::export]
+pub async fn trezor_bridge_call(
+    device_path: String,
+    method: String,
+    params_json: String,
+    bridge_url: Option<String>,
+) -> Result<TrezorResponsePayload, TrezorConnectError> {
+    let rt = ensure_runtime();
+    rt.spawn(async move {
+        TrezorBridgeClient::http(bridge_url.as_deref())
+            .call_method(&device_path, &method, &params_json)
+            .await
+    })
+    .await
+    .unwrap()
+}
+
+/// Get the Trezor Connect requests waiting for their callback, oldest first
+#[uniffi::export]
+pub fn trezor_get_pending_requests() -> Result<Vec<TrezorPendingRequest>, TrezorConnectError> {
+    trezor_request_registry()?.pending_requests()
+}
+
+/// Cancel a pending Trezor Connect request so its callback is rejected. Returns whether it
+/// was pending
+#[uniffi::export]
+pub fn trezor_cancel_request(request_id: String) -> Result<bool, TrezorConnectError> {
+    trezor_request_registry()?.cancel(&request_id)
+}
+
+/// Import a watch-only account from the `getPublicKey` response for its path. The script
+/// type is inferred from the BIP44, BIP49, BIP84 or BIP86 purpose of the path when not set.
+/// Importing an account again replaces it.
+#[uniffi::export]
+pub fn trezor_import_account(
+    public_key: PublicKeyResponse,
+    coin: String,
+    script_type: Option<ScriptType>,
+) -> Result<TrezorWatchOnlyAccount, TrezorConnectError> {
+    let account = TrezorWatchOnlyAccount::from_public_key(&public_key, &coin, script_type)?;
+    trezor_account_store()?.save(&account)?;
+    Ok(account)
+}
+
+/// Get the imported watch-only Trezor accounts, in import order
+#[uniffi::export]
+pub fn trezor_get_accounts() -> Result<Vec<TrezorWatchOnlyAccount>, TrezorConnectError> {
+    trezor_account_store()?.accounts()
+}
+
+/// Remove an imported watch-only Trezor account. Returns whether it was imported
+#[uniffi::export]
+pub fn trezor_remove_account(xpub: String) -> Result<bool, TrezorConnectError> {
+    trezor_account_store()?.remove(&xpub)
+}
+
+/// Derive `count` receive or change addresses of an imported account from `start_index`
+#[uniffi::export]
+pub fn trezor_derive_account_addresses(
+    xpub: String,
+    change: bool,
+    start_index: u32,
+    count: u32,
+) -> Result<Vec<TrezorAccountAddress>, TrezorConnectError> {
+    trezor_account_store()?
+        .account(&xpub)?
+        .derive_addresses(change, start_index, count)
+}
+
+/// Show the receive or change address at `index` of an imported account on the Trezor. The
+/// request fails if the device derives another address than the local one; check the
+/// callback's address with `trezor_account_address_matches`.
+#[uniffi::export]
+pub fn trezor_verify_account_address(
+    xpub: String,
+    change: bool,
+    index: u32,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let params = trezor_account_store()?
+        .account(&xpub)?
+        .verify_address_params(change, index)?;
+
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    match trezor_client.get_address(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+/// Whether the address of a `getAddress` callback is the one an imported account derives at
+/// its path
+#[uniffi::export]
+pub fn trezor_account_address_matches(
+    xpub: String,
+    response: AddressResponse,
+) -> Result<bool, TrezorConnectError> {
+    trezor_account_store()?
+        .account(&xpub)?
+        .address_matches(&response)
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_verify_message(
+    address: String,
+    signature: String,
+    message: String,
+    coin: String,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    hex: Option<bool>,
+    common: Option<CommonParams>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    let params = VerifyMessageParams {
+        address,
+        signature,
+        message,
+        coin,
+        hex,
+        common,
+    };
+
+    match trezor_client.verify_message(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_sign_message(
+    path: String,
+    message: String,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    coin: Option<String>,
+    hex: Option<bool>,
+    no_script_type: Option<bool>,
+    common: Option<CommonParams>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    let params = SignMessageParams {
+        path,
+        coin,
+        message,
+        hex,
+        no_script_type,
+        common,
+    };
+
+    match trezor_client.sign_message(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+/// Get the SLIP-19 ownership id of the script of `path`, which identifies the scripts of the
+/// wallet without revealing its keys
+#[uniffi::export]
+#[allow(non_snake_case)] // Trezor Connect API uses camelCase parameter names
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_get_ownership_id(
+    path: String,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    coin: Option<String>,
+    multisig: Option<MultisigRedeemScriptType>,
+    scriptType: Option<String>,
+    preauthorized: Option<bool>,
+    common: Option<CommonParams>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
         Ok(client) => client,
         Err(e) => {
             return Err(TrezorConnectError::ClientError {
@@ -1613,37 +3225,16 @@ pub fn trezor_get_account_info(
         }
     };
 
-    let params = GetAccountInfoParams {
+    let params = GetOwnershipIdParams {
         path,
-        descriptor,
         coin,
-        details,
-        tokens,
-        page,
-        pageSize,
-        from,
-        to,
-        gap,
-        contractFilter,
-        marker,
-        defaultAccountType,
-        suppressBackupWarning,
+        multisig,
+        scriptType,
+        preauthorized,
         common,
     };
 
-    match trezor_client.get_account_info(params, request_id) {
-        Ok(result) => Ok(result),
-        Err(e) => Err(TrezorConnectError::ClientError {
-            error_details: e.to_string(),
-        }),
-    }
-}
-
-#[uniffi::export]
-pub fn trezor_handle_deep_link(
-    callback_url: String,
-) -> Result<TrezorResponsePayload, TrezorConnectError> {
-    match trezor::handle_deep_link(callback_url) {
+    match trezor_client.get_ownership_id(params, request_id) {
         Ok(result) => Ok(result),
         Err(e) => Err(TrezorConnectError::ClientError {
             error_details: e.to_string(),
@@ -1651,21 +3242,27 @@ pub fn trezor_handle_deep_link(
     }
 }
 
+/// Get a SLIP-19 proof of ownership of the script of `path`, committing to the hex
+/// `commitmentData`, like a payjoin proposal or a withdrawal request. `ownershipIds` are the
+/// ids of the other wallets, followed by the one of the device.
 #[uniffi::export]
+#[allow(non_snake_case)] // Trezor Connect API uses camelCase parameter names
 #[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
-pub fn trezor_verify_message(
-    address: String,
-    signature: String,
-    message: String,
-    coin: String,
+pub fn trezor_get_ownership_proof(
+    path: String,
     callback_url: String,
     request_id: Option<String>,
     trezor_environment: Option<TrezorEnvironment>,
-    hex: Option<bool>,
+    coin: Option<String>,
+    multisig: Option<MultisigRedeemScriptType>,
+    scriptType: Option<String>,
+    userConfirmation: Option<bool>,
+    ownershipIds: Option<Vec<String>>,
+    commitmentData: Option<String>,
+    preauthorized: Option<bool>,
     common: Option<CommonParams>,
 ) -> Result<DeepLinkResult, TrezorConnectError> {
-    let trezor_environment = trezor_environment.unwrap_or(TrezorEnvironment::Production);
-    let trezor_client = match trezor::TrezorConnectClient::new(trezor_environment, callback_url) {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
         Ok(client) => client,
         Err(e) => {
             return Err(TrezorConnectError::ClientError {
@@ -1674,16 +3271,19 @@ pub fn trezor_verify_message(
         }
     };
 
-    let params = VerifyMessageParams {
-        address,
-        signature,
-        message,
+    let params = GetOwnershipProofParams {
+        path,
         coin,
-        hex,
+        multisig,
+        scriptType,
+        userConfirmation,
+        ownershipIds,
+        commitmentData,
+        preauthorized,
         common,
     };
 
-    match trezor_client.verify_message(params, request_id) {
+    match trezor_client.get_ownership_proof(params, request_id) {
         Ok(result) => Ok(result),
         Err(e) => Err(TrezorConnectError::ClientError {
             error_details: e.to_string(),
@@ -1691,9 +3291,14 @@ pub fn trezor_verify_message(
     }
 }
 
+/// Sign `message` with the taproot key of the BIP86 `path`, as a proof of ownership of its
+/// address committing to the message and confirmed by the user.
+///
+/// Trezor signs messages with the legacy format only. Check the `ownership_proof` of the
+/// callback with `trezor_verify_taproot_message`.
 #[uniffi::export]
 #[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
-pub fn trezor_sign_message(
+pub fn trezor_sign_taproot_message(
     path: String,
     message: String,
     callback_url: String,
@@ -1701,11 +3306,12 @@ pub fn trezor_sign_message(
     trezor_environment: Option<TrezorEnvironment>,
     coin: Option<String>,
     hex: Option<bool>,
-    no_script_type: Option<bool>,
     common: Option<CommonParams>,
 ) -> Result<DeepLinkResult, TrezorConnectError> {
-    let trezor_environment = trezor_environment.unwrap_or(TrezorEnvironment::Production);
-    let trezor_client = match trezor::TrezorConnectClient::new(trezor_environment, callback_url) {
+    let mut params = trezor::taproot_message_proof_params(&path, &message, coin, hex)?;
+    params.common = common;
+
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
         Ok(client) => client,
         Err(e) => {
             return Err(TrezorConnectError::ClientError {
@@ -1714,16 +3320,7 @@ pub fn trezor_sign_message(
         }
     };
 
-    let params = SignMessageParams {
-        path,
-        coin,
-        message,
-        hex,
-        no_script_type,
-        common,
-    };
-
-    match trezor_client.sign_message(params, request_id) {
+    match trezor_client.get_ownership_proof(params, request_id) {
         Ok(result) => Ok(result),
         Err(e) => Err(TrezorConnectError::ClientError {
             error_details: e.to_string(),
@@ -1731,6 +3328,43 @@ pub fn trezor_sign_message(
     }
 }
 
+/// Decode a hex encoded SLIP-19 proof of ownership, without verifying it
+#[uniffi::export]
+pub fn trezor_parse_ownership_proof(
+    ownership_proof: String,
+) -> Result<TrezorOwnershipProof, TrezorConnectError> {
+    trezor::parse_ownership_proof(&ownership_proof)
+}
+
+/// Verify a SLIP-19 proof of ownership of `address` committing to the hex `commitment_data`.
+///
+/// Returns false when the proof is not signed by the owner of `address` for
+/// `commitment_data`. P2WPKH, P2SH-P2WPKH and taproot addresses are supported.
+#[uniffi::export]
+pub fn trezor_verify_ownership_proof(
+    ownership_proof: String,
+    address: String,
+    commitment_data: Option<String>,
+) -> Result<bool, TrezorConnectError> {
+    let commitment_data = hex::decode(commitment_data.unwrap_or_default()).map_err(|e| {
+        TrezorConnectError::OwnershipError {
+            error_details: format!("Invalid commitment data: {}", e),
+        }
+    })?;
+    trezor::verify_ownership_proof(&ownership_proof, &address, &commitment_data)
+}
+
+/// Verify a message signed with `trezor_sign_taproot_message` by the taproot `address`
+#[uniffi::export]
+pub fn trezor_verify_taproot_message(
+    address: String,
+    message: String,
+    ownership_proof: String,
+    hex: Option<bool>,
+) -> Result<bool, TrezorConnectError> {
+    trezor::verify_taproot_message(&address, &message, &ownership_proof, hex)
+}
+
 #[uniffi::export]
 #[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
 pub fn trezor_sign_transaction(
@@ -1756,8 +3390,7 @@ pub fn trezor_sign_transaction(
     chunkify: Option<bool>,
     common: Option<CommonParams>,
 ) -> Result<DeepLinkResult, TrezorConnectError> {
-    let trezor_environment = trezor_environment.unwrap_or(TrezorEnvironment::Production);
-    let trezor_client = match trezor::TrezorConnectClient::new(trezor_environment, callback_url) {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
         Ok(client) => client,
         Err(e) => {
             return Err(TrezorConnectError::ClientError {
@@ -1795,6 +3428,261 @@ pub fn trezor_sign_transaction(
     }
 }
 
+/// Sign a PSBT with Trezor.
+///
+/// `prev_txs` are the hex encoded transactions spent by the PSBT, for inputs without a UTXO
+/// and as reference transactions. Inputs and outputs with a BIP32 derivation from
+/// `master_fingerprint`, or any derivation when it is not set, are signed or shown as change.
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_sign_psbt(
+    psbt: String,
+    prev_txs: Vec<String>,
+    coin: String,
+    master_fingerprint: Option<String>,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    push: Option<bool>,
+    common: Option<CommonParams>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let psbt = trezor::parse_psbt(&psbt)?;
+    let prev_txs = prev_txs
+        .iter()
+        .map(|tx| trezor::parse_transaction(tx))
+        .collect::<Result<Vec<_>, _>>()?;
+    let master_fingerprint = master_fingerprint
+        .map(|fingerprint| trezor::parse_fingerprint(&fingerprint))
+        .transpose()?;
+    let mut params =
+        trezor::psbt_to_sign_transaction_params(&psbt, &prev_txs, &coin, master_fingerprint)?;
+    params.push = push;
+    params.common = common;
+
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    match trezor_client.sign_transaction(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+/// Merge the signatures of a Trezor `signTransaction` response into the PSBT it was made
+/// from, with the same `master_fingerprint`, and return the updated PSBT.
+///
+/// Fails if the signed transaction does not spend exactly the inputs and pay exactly the
+/// outputs of the PSBT.
+#[uniffi::export]
+pub fn trezor_merge_signed_psbt(
+    psbt: String,
+    response: SignedTransactionResponse,
+    master_fingerprint: Option<String>,
+) -> Result<String, TrezorConnectError> {
+    let mut psbt = trezor::parse_psbt(&psbt)?;
+    let master_fingerprint = master_fingerprint
+        .map(|fingerprint| trezor::parse_fingerprint(&fingerprint))
+        .transpose()?;
+    trezor::merge_signed_transaction(&mut psbt, &response, master_fingerprint)?;
+    Ok(psbt.to_string())
+}
+
+/// Record a transaction signed by the Trezor of the imported account `xpub` as a pending
+/// send activity, with its transaction details, tagged with `device_label`.
+///
+/// `prev_txs` are the hex encoded transactions spent by the signed transaction. The inputs
+/// and outputs of the account are found among its first `address_count` receive and change
+/// addresses, 1000 by default. An activity already recorded for the transaction is kept
+/// and tagged.
+#[uniffi::export]
+pub fn trezor_record_signed_transaction(
+    response: SignedTransactionResponse,
+    xpub: String,
+    prev_txs: Vec<String>,
+    device_label: String,
+    address_count: Option<u32>,
+) -> Result<OnchainActivity, TrezorConnectError> {
+    let account = trezor_account_store()?.account(&xpub)?;
+    let prev_txs = prev_txs
+        .iter()
+        .map(|tx| trezor::parse_transaction(tx))
+        .collect::<Result<Vec<_>, _>>()?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let TrezorSignedActivity { activity, details } = trezor::signed_transaction_activity(
+        &response,
+        &account,
+        &prev_txs,
+        address_count.unwrap_or(trezor::DEFAULT_ACTIVITY_ADDRESS_COUNT),
+        timestamp,
+    )?;
+
+    let mut guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_mut()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    let activity = match db.get_activity_by_tx_id(&activity.tx_id)? {
+        Some(Activity::Onchain(existing)) => existing,
+        Some(Activity::Lightning(_)) => {
+            return Err(TrezorConnectError::ActivityError {
+                error_details: format!("Transaction {} is not an onchain payment", activity.tx_id),
+            })
+        }
+        None => {
+            db.insert_onchain_activity(&activity)?;
+            activity
+        }
+    };
+    db.upsert_transaction_details(vec![details])?;
+    db.add_tags(&activity.id, &[device_label])?;
+    Ok(activity)
+}
+
+/// Cosigner of a multisig wallet from the `getPublicKey` response of its device, usually for
+/// the BIP48 path `m/48'/0'/0'/2'`. The master fingerprint is taken from the descriptor of
+/// the response when not set.
+#[uniffi::export]
+pub fn trezor_multisig_cosigner_from_public_key(
+    public_key: PublicKeyResponse,
+    master_fingerprint: Option<String>,
+) -> Result<TrezorMultisigCosigner, TrezorConnectError> {
+    TrezorMultisigCosigner::from_public_key(&public_key, master_fingerprint)
+}
+
+/// Register a `threshold` of `cosigners` multisig wallet under `name`. Registering a wallet
+/// again replaces it.
+#[uniffi::export]
+pub fn trezor_create_multisig_wallet(
+    name: String,
+    coin: String,
+    threshold: u32,
+    cosigners: Vec<TrezorMultisigCosigner>,
+) -> Result<TrezorMultisigWallet, TrezorConnectError> {
+    let wallet = TrezorMultisigWallet::new(&name, &coin, threshold, cosigners)?;
+    trezor_multisig_store()?.save(&wallet)?;
+    Ok(wallet)
+}
+
+/// Register the multisig wallet of a `wsh(sortedmulti(...))` descriptor under `name`
+#[uniffi::export]
+pub fn trezor_import_multisig_descriptor(
+    name: String,
+    coin: String,
+    descriptor: String,
+) -> Result<TrezorMultisigWallet, TrezorConnectError> {
+    let wallet = TrezorMultisigWallet::from_descriptor(&name, &coin, &descriptor)?;
+    trezor_multisig_store()?.save(&wallet)?;
+    Ok(wallet)
+}
+
+/// Get the registered multisig wallets, in registration order
+#[uniffi::export]
+pub fn trezor_get_multisig_wallets() -> Result<Vec<TrezorMultisigWallet>, TrezorConnectError> {
+    trezor_multisig_store()?.wallets()
+}
+
+/// Remove a registered multisig wallet. Returns whether it was registered
+#[uniffi::export]
+pub fn trezor_remove_multisig_wallet(name: String) -> Result<bool, TrezorConnectError> {
+    trezor_multisig_store()?.remove(&name)
+}
+
+/// Get the descriptor of a registered multisig wallet, to back it up or share it with the
+/// other cosigners
+#[uniffi::export]
+pub fn trezor_get_multisig_descriptor(name: String) -> Result<String, TrezorConnectError> {
+    Ok(trezor_multisig_store()?.wallet(&name)?.descriptor())
+}
+
+/// Derive `count` receive or change addresses of a registered multisig wallet from
+/// `start_index`
+#[uniffi::export]
+pub fn trezor_derive_multisig_addresses(
+    name: String,
+    change: bool,
+    start_index: u32,
+    count: u32,
+) -> Result<Vec<TrezorMultisigAddress>, TrezorConnectError> {
+    trezor_multisig_store()?
+        .wallet(&name)?
+        .derive_addresses(change, start_index, count)
+}
+
+/// Sign a PSBT of a registered multisig wallet with the cosigner of `master_fingerprint`.
+///
+/// The wallet inputs and change outputs get their `multisig` field, with the signatures of
+/// the other cosigners already in the PSBT. Merge the callback with
+/// `trezor_merge_signed_psbt` and the same `master_fingerprint`, then sign the result with
+/// the next cosigner or finalize it with `trezor_finalize_multisig_psbts`.
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
+pub fn trezor_sign_multisig_psbt(
+    name: String,
+    psbt: String,
+    prev_txs: Vec<String>,
+    master_fingerprint: String,
+    callback_url: String,
+    request_id: Option<String>,
+    trezor_environment: Option<TrezorEnvironment>,
+    common: Option<CommonParams>,
+) -> Result<DeepLinkResult, TrezorConnectError> {
+    let wallet = trezor_multisig_store()?.wallet(&name)?;
+    let psbt = trezor::parse_psbt(&psbt)?;
+    let prev_txs = prev_txs
+        .iter()
+        .map(|tx| trezor::parse_transaction(tx))
+        .collect::<Result<Vec<_>, _>>()?;
+    let master_fingerprint = trezor::parse_fingerprint(&master_fingerprint)?;
+    let mut params = wallet.sign_transaction_params(&psbt, &prev_txs, master_fingerprint)?;
+    params.common = common;
+
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
+        Ok(client) => client,
+        Err(e) => {
+            return Err(TrezorConnectError::ClientError {
+                error_details: e.to_string(),
+            })
+        }
+    };
+
+    match trezor_client.sign_transaction(params, request_id) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(TrezorConnectError::ClientError {
+            error_details: e.to_string(),
+        }),
+    }
+}
+
+/// Combine the PSBTs signed by the cosigners of a registered multisig wallet and return the
+/// hex encoded final transaction. Fails unless every input of the wallet has enough valid
+/// signatures.
+#[uniffi::export]
+pub fn trezor_finalize_multisig_psbts(
+    name: String,
+    psbts: Vec<String>,
+) -> Result<String, TrezorConnectError> {
+    let wallet = trezor_multisig_store()?.wallet(&name)?;
+    let psbts = psbts
+        .iter()
+        .map(|psbt| trezor::parse_psbt(psbt))
+        .collect::<Result<Vec<_>, _>>()?;
+    let tx = wallet.finalize_psbt(&psbts)?;
+    Ok(bitcoin::consensus::encode::serialize_hex(&tx))
+}
+
 #[uniffi::export]
 #[allow(clippy::too_many_arguments)] // Trezor API requires many optional parameters
 pub fn trezor_compose_transaction(
@@ -1810,8 +3698,7 @@ pub fn trezor_compose_transaction(
     skip_permutation: Option<bool>,
     common: Option<CommonParams>,
 ) -> Result<DeepLinkResult, TrezorConnectError> {
-    let trezor_environment = trezor_environment.unwrap_or(TrezorEnvironment::Production);
-    let trezor_client = match trezor::TrezorConnectClient::new(trezor_environment, callback_url) {
+    let trezor_client = match trezor_connect_client(trezor_environment, callback_url) {
         Ok(client) => client,
         Err(e) => {
             return Err(TrezorConnectError::ClientError {
@@ -1839,6 +3726,22 @@ pub fn trezor_compose_transaction(
     }
 }
 
+/// Check a transaction precomposed by `trezor_compose_transaction` against the requested
+/// `outputs`, the `account` and the `fee_level` it was composed for, before signing it.
+///
+/// Returns the problems found, like outputs that were not requested, change outside of the
+/// account or a fee off the fee level, for the user to review. Empty when the transaction
+/// is the requested one.
+#[uniffi::export]
+pub fn trezor_validate_precomposed_transaction(
+    precomposed: PrecomposedTransaction,
+    outputs: Vec<ComposeOutput>,
+    account: ComposeAccount,
+    fee_level: FeeLevel,
+) -> Result<Vec<TrezorComposeWarning>, TrezorConnectError> {
+    trezor::validate_precomposed_transaction(&precomposed, &outputs, &account, &fee_level)
+}
+
 #[uniffi::export]
 pub fn activity_wipe_all() -> Result<(), ActivityError> {
     let mut guard = get_activity_db()?;
@@ -2009,6 +3912,26 @@ pub async fn wipe_all_databases() -> Result<String, DbError> {
         db.wipe_all().map_err(|e| DbError::InitializationError {
             error_details: format!("Failed to wipe activity database: {}", e),
         })?;
+
+        // Wipe trezor database: requests, watch-only accounts, settings and multisig wallets
+        let not_initialized = || DbError::InitializationError {
+            error_details: "Trezor database not initialized. Call init_db first.".to_string(),
+        };
+        let trezor_requests = guard.trezor_requests.as_ref().ok_or_else(not_initialized)?;
+        let trezor_accounts = guard.trezor_accounts.as_ref().ok_or_else(not_initialized)?;
+        let trezor_settings = guard.trezor_settings.as_ref().ok_or_else(not_initialized)?;
+        let trezor_multisig_wallets = guard
+            .trezor_multisig_wallets
+            .as_ref()
+            .ok_or_else(not_initialized)?;
+        trezor_requests
+            .wipe_all()
+            .and_then(|_| trezor_accounts.wipe_all())
+            .and_then(|_| trezor_settings.wipe_all())
+            .and_then(|_| trezor_multisig_wallets.wipe_all())
+            .map_err(|e| DbError::InitializationError {
+                error_details: format!("Failed to wipe trezor database: {}", e),
+            })?;
     }
 
     // Wipe blocktank database - require it to be initialized
@@ -2143,3 +4066,98 @@ pub fn get_transaction_details(tx_id: String) -> Result<Option<TransactionDetail
         })?;
     db.get_transaction_details(&tx_id)
 }
+
+/// Rebuild the UTXO set from stored transaction details and the wallet's owned addresses
+#[uniffi::export]
+pub fn sync_utxos(owned_addresses: Vec<String>) -> Result<(), ActivityError> {
+    let mut guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_mut()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.sync_utxos(&owned_addresses)
+}
+
+/// List owned outputs for coin control, largest first
+#[uniffi::export]
+pub fn get_utxos(include_spent: bool) -> Result<Vec<Utxo>, ActivityError> {
+    let guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_ref()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.get_utxos(include_spent)
+}
+
+/// List unspent, unfrozen outputs available for coin selection
+#[uniffi::export]
+pub fn get_spendable_utxos() -> Result<Vec<Utxo>, ActivityError> {
+    let guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_ref()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.get_spendable_utxos()
+}
+
+/// Get the confirmed, unconfirmed and frozen balance of the UTXO set
+#[uniffi::export]
+pub fn get_utxo_balance() -> Result<UtxoBalance, ActivityError> {
+    let guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_ref()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.get_utxo_balance()
+}
+
+/// Set or clear the label of an owned output
+#[uniffi::export]
+pub fn set_utxo_label(
+    tx_id: String,
+    vout: u32,
+    label: Option<String>,
+) -> Result<(), ActivityError> {
+    let mut guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_mut()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.set_utxo_label(&tx_id, vout, label.as_deref())
+}
+
+/// Freeze or unfreeze an owned output
+#[uniffi::export]
+pub fn set_utxo_frozen(tx_id: String, vout: u32, is_frozen: bool) -> Result<(), ActivityError> {
+    let mut guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_mut()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.set_utxo_frozen(&tx_id, vout, is_frozen)
+}
+
+/// Get the onchain activity that created an owned output
+#[uniffi::export]
+pub fn get_utxo_activity(tx_id: String, vout: u32) -> Result<Option<Activity>, ActivityError> {
+    let guard = get_activity_db()?;
+    let db = guard
+        .activity_db
+        .as_ref()
+        .ok_or(ActivityError::ConnectionError {
+            error_details: "Database not initialized. Call init_db first.".to_string(),
+        })?;
+    db.get_utxo_activity(&tx_id, vout)
+}
@@ -1,10 +1,11 @@
 use crate::activity::{
     Activity, ActivityError, ActivityFilter, ActivityTags, ClosedChannelDetails, LightningActivity,
     OnchainActivity, PaymentState, PaymentType, PreActivityMetadata, SortDirection,
-    TransactionDetails, TxInput, TxOutput,
+    TransactionDetails, TxInput, TxOutput, Utxo, UtxoBalance,
 };
 use rusqlite::{Connection, OptionalExtension};
 use serde_json;
+use std::collections::{HashMap, HashSet};
 
 pub struct ActivityDB {
     pub conn: Connection,
@@ -114,6 +115,33 @@ const CREATE_TRANSACTION_DETAILS_TABLE: &str = "
         stored_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
     )";
 
+const CREATE_UTXOS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS utxos (
+        tx_id TEXT NOT NULL,
+        vout INTEGER NOT NULL CHECK (vout >= 0),
+        address TEXT NOT NULL CHECK (length(address) > 0),
+        script_pubkey TEXT NOT NULL,
+        value INTEGER NOT NULL CHECK (value >= 0),
+        spent_by_tx_id TEXT,
+        label TEXT,
+        is_frozen BOOLEAN NOT NULL DEFAULT FALSE,
+        created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+        PRIMARY KEY (tx_id, vout)
+    )";
+
+/// Joins each output with the activity of its creating transaction, preferring one that still exists
+const SELECT_UTXOS_SQL: &str = "
+    SELECT
+        u.tx_id, u.vout, u.address, u.script_pubkey, u.value, u.spent_by_tx_id,
+        u.label, u.is_frozen, o.id, COALESCE(o.confirmed, 0)
+    FROM utxos u
+    LEFT JOIN onchain_activity o ON o.id = (
+        SELECT id FROM onchain_activity
+        WHERE tx_id = u.tx_id
+        ORDER BY does_exist DESC
+        LIMIT 1
+    )";
+
 const UPSERT_CLOSED_CHANNEL_SQL: &str = "
     INSERT OR REPLACE INTO closed_channels (
         channel_id, counterparty_node_id, funding_txo_txid, funding_txo_index,
@@ -148,7 +176,10 @@ const INDEX_STATEMENTS: &[&str] = &[
     "CREATE INDEX IF NOT EXISTS idx_pre_activity_metadata_tx_id ON pre_activity_metadata(tx_id)",
 
     // Closed channels indexes
-    "CREATE INDEX IF NOT EXISTS idx_closed_channels_funding_txo ON closed_channels(funding_txo_txid)"
+    "CREATE INDEX IF NOT EXISTS idx_closed_channels_funding_txo ON closed_channels(funding_txo_txid)",
+
+    // UTXO indexes
+    "CREATE INDEX IF NOT EXISTS idx_utxos_spent_value ON utxos(spent_by_tx_id, value DESC)"
 ];
 
 const TRIGGER_STATEMENTS: &[&str] = &[
@@ -273,6 +304,13 @@ impl ActivityDB {
             });
         }
 
+        // Create UTXO table
+        if let Err(e) = self.conn.execute(CREATE_UTXOS_TABLE, []) {
+            return Err(ActivityError::InitializationError {
+                error_details: format!("Error creating utxos table: {}", e),
+            });
+        }
+
         // Create indexes
         for statement in INDEX_STATEMENTS {
             if let Err(e) = self.conn.execute(statement, []) {
@@ -2301,6 +2339,37 @@ impl ActivityDB {
         Ok(exists)
     }
 
+    /// Get the lightning activity paying or receiving `invoice`, preferring a succeeded one
+    pub fn get_lightning_activity_by_invoice(
+        &self,
+        invoice: &str,
+    ) -> Result<Option<LightningActivity>, ActivityError> {
+        let sql = "
+            SELECT l.id
+            FROM lightning_activity l
+            JOIN activities a ON l.id = a.id
+            WHERE l.invoice = ?1
+            ORDER BY l.status = 'succeeded' DESC, a.timestamp DESC
+            LIMIT 1
+        ";
+
+        let activity_id: Option<String> = self
+            .conn
+            .query_row(sql, [invoice], |row| row.get(0))
+            .optional()
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to query lightning activity: {}", e),
+            })?;
+
+        match activity_id {
+            Some(id) => match self.get_activity_by_id(&id)? {
+                Some(Activity::Lightning(activity)) => Ok(Some(activity)),
+                _ => Ok(None),
+            },
+            None => Ok(None),
+        }
+    }
+
     /// Wipes all activity data from the database
     /// This deletes all activities, which cascades to delete all activity_tags due to foreign key constraints.
     /// Also deletes all pre_activity_metadata and closed_channels.
@@ -2330,6 +2399,12 @@ impl ActivityDB {
                 error_details: format!("Failed to delete all closed channels: {}", e),
             })?;
 
+        // Delete the UTXO set, including labels and freeze flags
+        tx.execute("DELETE FROM utxos", [])
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to delete all utxos: {}", e),
+            })?;
+
         tx.commit().map_err(|e| ActivityError::DataError {
             error_details: format!("Failed to commit transaction: {}", e),
         })?;
@@ -2431,6 +2506,48 @@ impl ActivityDB {
         Ok(())
     }
 
+    /// Marks an onchain activity as boosted by the RBF replacement or CPFP child `boost_tx_id`.
+    pub fn add_boost_tx_id(
+        &mut self,
+        activity_id: &str,
+        boost_tx_id: &str,
+    ) -> Result<(), ActivityError> {
+        let boost_tx_ids_str: String = self
+            .conn
+            .query_row(
+                "SELECT boost_tx_ids FROM onchain_activity WHERE id = ?1",
+                [activity_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to get boost transaction ids: {}", e),
+            })?
+            .ok_or(ActivityError::DataError {
+                error_details: "No onchain activity found with given ID".to_string(),
+            })?;
+
+        let mut boost_tx_ids: Vec<&str> = if boost_tx_ids_str.is_empty() {
+            Vec::new()
+        } else {
+            boost_tx_ids_str.split(',').collect()
+        };
+        if !boost_tx_ids.contains(&boost_tx_id) {
+            boost_tx_ids.push(boost_tx_id);
+        }
+
+        self.conn
+            .execute(
+                "UPDATE onchain_activity SET is_boosted = 1, boost_tx_ids = ?1 WHERE id = ?2",
+                rusqlite::params![boost_tx_ids.join(","), activity_id],
+            )
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to record boost transaction: {}", e),
+            })?;
+
+        Ok(())
+    }
+
     /// Get activity by transaction ID
     pub fn get_activity_by_tx_id(&self, tx_id: &str) -> Result<Option<Activity>, ActivityError> {
         // Try to find onchain activity with matching tx_id
@@ -2492,4 +2609,296 @@ impl ActivityDB {
     pub fn is_address_used(&self, address: &str) -> Result<bool, ActivityError> {
         self.has_onchain_received(address)
     }
+
+    /// Rebuilds the UTXO set from the stored transaction details and the wallet's owned
+    /// addresses. Labels and freeze flags of outputs that are still owned are kept.
+    /// Transactions that were evicted, i.e. all of whose onchain activities no longer exist
+    /// (e.g. replaced by RBF), neither create nor spend outputs.
+    pub fn sync_utxos(&mut self, owned_addresses: &[String]) -> Result<(), ActivityError> {
+        let owned: HashSet<&str> = owned_addresses.iter().map(String::as_str).collect();
+
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to start transaction: {}", e),
+            })?;
+
+        let evicted_tx_ids: HashSet<String> = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT tx_id FROM onchain_activity
+                     GROUP BY tx_id HAVING MAX(does_exist) = 0",
+                )
+                .map_err(|e| ActivityError::RetrievalError {
+                    error_details: format!("Failed to prepare statement: {}", e),
+                })?;
+            let tx_ids = stmt
+                .query_map([], |row| row.get(0))
+                .map_err(|e| ActivityError::RetrievalError {
+                    error_details: format!("Failed to execute query: {}", e),
+                })?
+                .collect::<Result<HashSet<String>, _>>()
+                .map_err(|e| ActivityError::DataError {
+                    error_details: format!("Failed to process rows: {}", e),
+                })?;
+            tx_ids
+        };
+
+        let details: Vec<(String, String, String)> = {
+            let mut stmt = tx
+                .prepare("SELECT tx_id, inputs, outputs FROM transaction_details")
+                .map_err(|e| ActivityError::RetrievalError {
+                    error_details: format!("Failed to prepare statement: {}", e),
+                })?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .map_err(|e| ActivityError::RetrievalError {
+                    error_details: format!("Failed to execute query: {}", e),
+                })?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| ActivityError::DataError {
+                    error_details: format!("Failed to process rows: {}", e),
+                })?;
+            rows
+        };
+
+        let mut created: Vec<(String, u32, String, String, u64)> = Vec::new();
+        let mut spent_by: HashMap<(String, u32), String> = HashMap::new();
+        for (tx_id, inputs_json, outputs_json) in details {
+            if evicted_tx_ids.contains(&tx_id) {
+                continue;
+            }
+
+            let inputs: Vec<TxInput> = serde_json::from_str(&inputs_json).unwrap_or_default();
+            let outputs: Vec<TxOutput> = serde_json::from_str(&outputs_json).unwrap_or_default();
+
+            for input in inputs {
+                spent_by.insert((input.txid, input.vout), tx_id.clone());
+            }
+            for output in outputs {
+                if let Some(address) = output
+                    .scriptpubkey_address
+                    .filter(|address| owned.contains(address.as_str()))
+                {
+                    created.push((
+                        tx_id.clone(),
+                        output.n,
+                        address,
+                        output.scriptpubkey,
+                        output.value,
+                    ));
+                }
+            }
+        }
+
+        let created_keys: HashSet<(String, u32)> = created
+            .iter()
+            .map(|(tx_id, vout, ..)| (tx_id.clone(), *vout))
+            .collect();
+        let existing_keys: Vec<(String, u32)> = {
+            let mut stmt = tx.prepare("SELECT tx_id, vout FROM utxos").map_err(|e| {
+                ActivityError::RetrievalError {
+                    error_details: format!("Failed to prepare statement: {}", e),
+                }
+            })?;
+            let keys = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| ActivityError::RetrievalError {
+                    error_details: format!("Failed to execute query: {}", e),
+                })?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| ActivityError::DataError {
+                    error_details: format!("Failed to process rows: {}", e),
+                })?;
+            keys
+        };
+
+        for (tx_id, vout) in existing_keys {
+            if !created_keys.contains(&(tx_id.clone(), vout)) {
+                tx.execute(
+                    "DELETE FROM utxos WHERE tx_id = ?1 AND vout = ?2",
+                    rusqlite::params![tx_id, vout],
+                )
+                .map_err(|e| ActivityError::DataError {
+                    error_details: format!("Failed to delete utxo: {}", e),
+                })?;
+            }
+        }
+
+        for (tx_id, vout, address, script_pubkey, value) in created {
+            let spent_by_tx_id = spent_by.get(&(tx_id.clone(), vout));
+            tx.execute(
+                "INSERT INTO utxos (tx_id, vout, address, script_pubkey, value, spent_by_tx_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(tx_id, vout) DO UPDATE SET
+                    address = excluded.address,
+                    script_pubkey = excluded.script_pubkey,
+                    value = excluded.value,
+                    spent_by_tx_id = excluded.spent_by_tx_id",
+                rusqlite::params![tx_id, vout, address, script_pubkey, value, spent_by_tx_id],
+            )
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to upsert utxo: {}", e),
+            })?;
+        }
+
+        tx.commit().map_err(|e| ActivityError::DataError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        Ok(())
+    }
+
+    fn query_utxos(
+        &self,
+        condition: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<Utxo>, ActivityError> {
+        let sql = format!(
+            "{} WHERE {} ORDER BY u.value DESC, u.tx_id, u.vout",
+            SELECT_UTXOS_SQL, condition
+        );
+
+        let mut stmt = self
+            .conn
+            .prepare(&sql)
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let utxos = stmt
+            .query_map(params, |row| {
+                Ok(Utxo {
+                    tx_id: row.get(0)?,
+                    vout: row.get(1)?,
+                    address: row.get(2)?,
+                    script_pubkey: row.get(3)?,
+                    value: row.get(4)?,
+                    spent_by_tx_id: row.get(5)?,
+                    label: row.get(6)?,
+                    is_frozen: row.get(7)?,
+                    activity_id: row.get(8)?,
+                    confirmed: row.get(9)?,
+                })
+            })
+            .map_err(|e| ActivityError::RetrievalError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<Utxo>, _>>()
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to process rows: {}", e),
+            })?;
+
+        Ok(utxos)
+    }
+
+    /// Lists owned outputs for coin control, largest first. Spent outputs are only
+    /// included when `include_spent` is set.
+    pub fn get_utxos(&self, include_spent: bool) -> Result<Vec<Utxo>, ActivityError> {
+        if include_spent {
+            self.query_utxos("1 = 1", [])
+        } else {
+            self.query_utxos("u.spent_by_tx_id IS NULL", [])
+        }
+    }
+
+    /// Lists unspent, unfrozen outputs available for coin selection, largest first.
+    pub fn get_spendable_utxos(&self) -> Result<Vec<Utxo>, ActivityError> {
+        self.query_utxos("u.spent_by_tx_id IS NULL AND u.is_frozen = 0", [])
+    }
+
+    /// Gets a single owned output.
+    pub fn get_utxo(&self, tx_id: &str, vout: u32) -> Result<Option<Utxo>, ActivityError> {
+        Ok(self
+            .query_utxos(
+                "u.tx_id = ?1 AND u.vout = ?2",
+                rusqlite::params![tx_id, vout],
+            )?
+            .pop())
+    }
+
+    /// Sums the unspent outputs by confirmation and freeze status.
+    pub fn get_utxo_balance(&self) -> Result<UtxoBalance, ActivityError> {
+        let mut balance = UtxoBalance {
+            confirmed_sats: 0,
+            unconfirmed_sats: 0,
+            frozen_sats: 0,
+        };
+        for utxo in self.get_utxos(false)? {
+            if utxo.is_frozen {
+                balance.frozen_sats += utxo.value;
+            } else if utxo.confirmed {
+                balance.confirmed_sats += utxo.value;
+            } else {
+                balance.unconfirmed_sats += utxo.value;
+            }
+        }
+        Ok(balance)
+    }
+
+    /// Sets or clears the label of an owned output.
+    pub fn set_utxo_label(
+        &mut self,
+        tx_id: &str,
+        vout: u32,
+        label: Option<&str>,
+    ) -> Result<(), ActivityError> {
+        let rows = self
+            .conn
+            .execute(
+                "UPDATE utxos SET label = ?1 WHERE tx_id = ?2 AND vout = ?3",
+                rusqlite::params![label.filter(|label| !label.is_empty()), tx_id, vout],
+            )
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to update utxo label: {}", e),
+            })?;
+
+        if rows == 0 {
+            return Err(ActivityError::DataError {
+                error_details: format!("No utxo found for {}:{}", tx_id, vout),
+            });
+        }
+        Ok(())
+    }
+
+    /// Freezes or unfreezes an owned output. Frozen outputs are excluded from coin selection.
+    pub fn set_utxo_frozen(
+        &mut self,
+        tx_id: &str,
+        vout: u32,
+        is_frozen: bool,
+    ) -> Result<(), ActivityError> {
+        let rows = self
+            .conn
+            .execute(
+                "UPDATE utxos SET is_frozen = ?1 WHERE tx_id = ?2 AND vout = ?3",
+                rusqlite::params![is_frozen, tx_id, vout],
+            )
+            .map_err(|e| ActivityError::DataError {
+                error_details: format!("Failed to update utxo freeze flag: {}", e),
+            })?;
+
+        if rows == 0 {
+            return Err(ActivityError::DataError {
+                error_details: format!("No utxo found for {}:{}", tx_id, vout),
+            });
+        }
+        Ok(())
+    }
+
+    /// Gets the onchain activity whose transaction created an owned output.
+    pub fn get_utxo_activity(
+        &self,
+        tx_id: &str,
+        vout: u32,
+    ) -> Result<Option<Activity>, ActivityError> {
+        match self
+            .get_utxo(tx_id, vout)?
+            .and_then(|utxo| utxo.activity_id)
+        {
+            Some(activity_id) => self.get_activity_by_id(&activity_id),
+            None => Ok(None),
+        }
+    }
 }
@@ -3,7 +3,7 @@ mod tests {
     use crate::activity::{
         Activity, ActivityDB, ActivityFilter, ActivityTags, ActivityType, ClosedChannelDetails,
         LightningActivity, OnchainActivity, PaymentState, PaymentType, PreActivityMetadata,
-        SortDirection,
+        SortDirection, TransactionDetails, TxInput, TxOutput, UtxoBalance,
     };
     use rand::random;
     use std::fs;
@@ -171,6 +171,33 @@ mod tests {
         cleanup(&db_path);
     }
 
+    #[test]
+    fn test_get_lightning_activity_by_invoice() {
+        let (mut db, db_path) = setup();
+        let succeeded = create_test_lightning_activity();
+        let failed_retry = LightningActivity {
+            id: "test_lightning_2".to_string(),
+            status: PaymentState::Failed,
+            timestamp: succeeded.timestamp + 60,
+            ..succeeded.clone()
+        };
+        db.insert_lightning_activity(&succeeded).unwrap();
+        db.insert_lightning_activity(&failed_retry).unwrap();
+
+        let found = db
+            .get_lightning_activity_by_invoice(&succeeded.invoice)
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, succeeded.id);
+        assert_eq!(found.timestamp, succeeded.timestamp);
+        assert!(db
+            .get_lightning_activity_by_invoice("lightning:unknown")
+            .unwrap()
+            .is_none());
+
+        cleanup(&db_path);
+    }
+
     #[test]
     fn test_get_all_activities() {
         let (mut db, db_path) = setup();
@@ -3911,4 +3938,224 @@ mod tests {
 
         cleanup(&db_path);
     }
+
+    #[test]
+    fn test_add_boost_tx_id() {
+        let (mut db, db_path) = setup();
+        let activity = create_test_onchain_activity();
+        db.insert_onchain_activity(&activity).unwrap();
+
+        db.add_boost_tx_id(&activity.id, "boost_tx_1").unwrap();
+        db.add_boost_tx_id(&activity.id, "boost_tx_2").unwrap();
+        // Recording the same boost twice is a no-op
+        db.add_boost_tx_id(&activity.id, "boost_tx_1").unwrap();
+
+        if let Some(Activity::Onchain(boosted)) = db.get_activity_by_id(&activity.id).unwrap() {
+            assert!(boosted.is_boosted);
+            assert_eq!(boosted.boost_tx_ids, vec!["boost_tx_1", "boost_tx_2"]);
+        } else {
+            panic!("Expected onchain activity");
+        }
+
+        assert!(db.add_boost_tx_id("missing", "boost_tx_1").is_err());
+
+        cleanup(&db_path);
+    }
+
+    fn create_test_tx_output(address: &str, value: u64, n: u32) -> TxOutput {
+        TxOutput {
+            scriptpubkey: format!("0014{:040}", n),
+            scriptpubkey_type: "v0_p2wpkh".to_string(),
+            scriptpubkey_address: Some(address.to_string()),
+            value,
+            n,
+        }
+    }
+
+    fn create_test_tx_input(txid: &str, vout: u32) -> TxInput {
+        TxInput {
+            txid: txid.to_string(),
+            vout,
+            scriptsig: String::new(),
+            witness: vec![],
+            sequence: 0xfffffffd,
+        }
+    }
+
+    /// Receives 100_000 sats in `tx_a`, spends them in `tx_b` with 39_000 sats of change
+    /// and receives 20_000 sats in `tx_c`, which has no activity yet.
+    fn setup_utxo_history(db: &mut ActivityDB) -> Vec<String> {
+        let owned = vec!["bc1qowned1".to_string(), "bc1qowned2".to_string()];
+
+        let mut received = create_test_onchain_activity();
+        received.id = "received_a".to_string();
+        received.tx_id = "tx_a".to_string();
+        received.tx_type = PaymentType::Received;
+        db.insert_onchain_activity(&received).unwrap();
+
+        let mut sent = create_test_onchain_activity();
+        sent.id = "sent_b".to_string();
+        sent.tx_id = "tx_b".to_string();
+        sent.confirmed = false;
+        sent.confirm_timestamp = None;
+        db.insert_onchain_activity(&sent).unwrap();
+
+        db.upsert_transaction_details(vec![
+            TransactionDetails {
+                tx_id: "tx_a".to_string(),
+                amount_sats: 100_000,
+                inputs: vec![create_test_tx_input("tx_external", 0)],
+                outputs: vec![
+                    create_test_tx_output("bc1qowned1", 100_000, 0),
+                    create_test_tx_output("bc1qsomeoneelse", 5_000, 1),
+                ],
+            },
+            TransactionDetails {
+                tx_id: "tx_b".to_string(),
+                amount_sats: -61_000,
+                inputs: vec![create_test_tx_input("tx_a", 0)],
+                outputs: vec![
+                    create_test_tx_output("bc1qsomeoneelse", 60_000, 0),
+                    create_test_tx_output("bc1qowned2", 39_000, 1),
+                ],
+            },
+            TransactionDetails {
+                tx_id: "tx_c".to_string(),
+                amount_sats: 20_000,
+                inputs: vec![create_test_tx_input("tx_external", 1)],
+                outputs: vec![create_test_tx_output("bc1qowned1", 20_000, 0)],
+            },
+        ])
+        .unwrap();
+
+        owned
+    }
+
+    #[test]
+    fn test_sync_utxos() {
+        let (mut db, db_path) = setup();
+        let owned = setup_utxo_history(&mut db);
+        db.sync_utxos(&owned).unwrap();
+
+        let all = db.get_utxos(true).unwrap();
+        assert_eq!(all.len(), 3);
+        let spent = all.iter().find(|utxo| utxo.tx_id == "tx_a").unwrap();
+        assert_eq!(spent.spent_by_tx_id, Some("tx_b".to_string()));
+        assert!(spent.confirmed);
+
+        let unspent = db.get_utxos(false).unwrap();
+        assert_eq!(unspent.len(), 2);
+        // Largest first
+        assert_eq!(unspent[0].tx_id, "tx_b");
+        assert_eq!(unspent[0].vout, 1);
+        assert_eq!(unspent[0].value, 39_000);
+        assert_eq!(unspent[0].address, "bc1qowned2");
+        assert_eq!(unspent[0].activity_id, Some("sent_b".to_string()));
+        assert!(!unspent[0].confirmed);
+        assert_eq!(unspent[1].tx_id, "tx_c");
+        assert_eq!(unspent[1].activity_id, None);
+
+        assert_eq!(
+            db.get_utxo_balance().unwrap(),
+            UtxoBalance {
+                confirmed_sats: 0,
+                unconfirmed_sats: 59_000,
+                frozen_sats: 0,
+            }
+        );
+
+        // Syncing again is idempotent
+        db.sync_utxos(&owned).unwrap();
+        assert_eq!(db.get_utxos(true).unwrap(), all);
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_utxo_labels_and_freezing() {
+        let (mut db, db_path) = setup();
+        let owned = setup_utxo_history(&mut db);
+        db.sync_utxos(&owned).unwrap();
+
+        db.set_utxo_label("tx_b", 1, Some("change")).unwrap();
+        db.set_utxo_frozen("tx_c", 0, true).unwrap();
+
+        let spendable = db.get_spendable_utxos().unwrap();
+        assert_eq!(spendable.len(), 1);
+        assert_eq!(spendable[0].tx_id, "tx_b");
+        assert_eq!(spendable[0].label, Some("change".to_string()));
+
+        let balance = db.get_utxo_balance().unwrap();
+        assert_eq!(balance.unconfirmed_sats, 39_000);
+        assert_eq!(balance.frozen_sats, 20_000);
+
+        // Labels and freeze flags survive a resync
+        db.sync_utxos(&owned).unwrap();
+        let frozen = db.get_utxo("tx_c", 0).unwrap().unwrap();
+        assert!(frozen.is_frozen);
+        assert_eq!(
+            db.get_utxo("tx_b", 1).unwrap().unwrap().label,
+            Some("change".to_string())
+        );
+
+        db.set_utxo_label("tx_b", 1, None).unwrap();
+        db.set_utxo_frozen("tx_c", 0, false).unwrap();
+        assert_eq!(db.get_spendable_utxos().unwrap().len(), 2);
+        assert_eq!(db.get_utxo("tx_b", 1).unwrap().unwrap().label, None);
+
+        assert!(db.set_utxo_label("tx_missing", 0, Some("label")).is_err());
+        assert!(db.set_utxo_frozen("tx_missing", 0, true).is_err());
+
+        cleanup(&db_path);
+    }
+
+    #[test]
+    fn test_utxo_activity_and_replacement() {
+        let (mut db, db_path) = setup();
+        let owned = setup_utxo_history(&mut db);
+        db.sync_utxos(&owned).unwrap();
+
+        match db.get_utxo_activity("tx_b", 1).unwrap() {
+            Some(Activity::Onchain(activity)) => assert_eq!(activity.id, "sent_b"),
+            _ => panic!("Expected onchain activity"),
+        }
+        assert!(db.get_utxo_activity("tx_c", 0).unwrap().is_none());
+        assert!(db.get_utxo_activity("tx_missing", 0).unwrap().is_none());
+
+        // A transaction is only evicted once none of its activities exist
+        let mut stale = create_test_onchain_activity();
+        stale.id = "stale_c".to_string();
+        stale.tx_id = "tx_c".to_string();
+        stale.tx_type = PaymentType::Received;
+        stale.does_exist = false;
+        db.insert_onchain_activity(&stale).unwrap();
+        let mut received = stale.clone();
+        received.id = "received_c".to_string();
+        received.does_exist = true;
+        db.insert_onchain_activity(&received).unwrap();
+        db.sync_utxos(&owned).unwrap();
+        assert!(db.get_utxo("tx_c", 0).unwrap().is_some());
+        assert_eq!(
+            db.get_utxo("tx_a", 0).unwrap().unwrap().spent_by_tx_id,
+            Some("tx_b".to_string())
+        );
+
+        // A replaced transaction neither spends nor creates outputs
+        if let Some(Activity::Onchain(mut sent)) = db.get_activity_by_id("sent_b").unwrap() {
+            sent.does_exist = false;
+            db.update_onchain_activity_by_id("sent_b", &sent).unwrap();
+        }
+        db.sync_utxos(&owned).unwrap();
+
+        assert!(db.get_utxo("tx_b", 1).unwrap().is_none());
+        let restored = db.get_utxo("tx_a", 0).unwrap().unwrap();
+        assert_eq!(restored.spent_by_tx_id, None);
+        assert_eq!(db.get_utxo_balance().unwrap().confirmed_sats, 120_000);
+
+        // Outputs to addresses that are no longer owned are dropped
+        db.sync_utxos(&["bc1qowned1".to_string()]).unwrap();
+        assert_eq!(db.get_utxos(true).unwrap().len(), 2);
+
+        cleanup(&db_path);
+    }
 }
@@ -241,3 +241,39 @@ pub struct TransactionDetails {
     /// Outputs
     pub outputs: Vec<TxOutput>,
 }
+
+/// A wallet-owned transaction output, derived from stored transaction details
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record, Serialize, Deserialize)]
+pub struct Utxo {
+    /// Transaction ID that created the output
+    pub tx_id: String,
+    /// Output index in that transaction
+    pub vout: u32,
+    /// Owned address the output pays to
+    pub address: String,
+    /// Scriptpubkey as hex
+    pub script_pubkey: String,
+    /// Value in satoshis
+    pub value: u64,
+    /// Whether the creating transaction is confirmed
+    pub confirmed: bool,
+    /// Transaction ID that spends the output, if any
+    pub spent_by_tx_id: Option<String>,
+    /// User label
+    pub label: Option<String>,
+    /// Frozen outputs are excluded from coin selection
+    pub is_frozen: bool,
+    /// ID of the onchain activity that created the output, if known
+    pub activity_id: Option<String>,
+}
+
+/// Balance of the unspent outputs in the UTXO set
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record, Serialize, Deserialize)]
+pub struct UtxoBalance {
+    /// Confirmed, unfrozen outputs
+    pub confirmed_sats: u64,
+    /// Unconfirmed, unfrozen outputs
+    pub unconfirmed_sats: u64,
+    /// Frozen outputs, not spendable until unfrozen
+    pub frozen_sats: u64,
+}
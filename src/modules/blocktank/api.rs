@@ -1,4 +1,6 @@
-use crate::modules::blocktank::{BlocktankDB, BlocktankError, IGift};
+use crate::modules::blocktank::{
+    BlocktankDB, BlocktankError, BtTrackedItemKind, IGift, LSPS_URL_PREFIX,
+};
 use rust_blocktank_client::{
     CreateCjitOptions, CreateOrderOptions, IBt0ConfMinTxFeeWindow, IBtBolt11Invoice,
     IBtEstimateFeeResponse, IBtEstimateFeeResponse2, IBtInfo, IBtOrder, ICJitEntry,
@@ -48,7 +50,8 @@ impl BlocktankDB {
         connection_string: String,
     ) -> Result<IBtOrder, BlocktankError> {
         let response = self
-            .client
+            .item_client(&order_id, BtTrackedItemKind::Order)
+            .await?
             .open_channel(&order_id, &connection_string)
             .await
             .map_err(|e| BlocktankError::DataError {
@@ -59,18 +62,38 @@ impl BlocktankDB {
         Ok(response)
     }
 
-    /// Fetches and updates multiple orders in the database
+    /// Fetches and updates multiple orders in the database, each from the LSP it was
+    /// created with. Orders of an LSP that is not registered are skipped.
     pub async fn refresh_orders(
         &self,
         order_ids: &[String],
     ) -> Result<Vec<IBtOrder>, BlocktankError> {
-        let orders =
-            self.client
-                .get_orders(order_ids)
-                .await
-                .map_err(|e| BlocktankError::DataError {
-                    error_details: format!("Failed to fetch orders: {}", e),
-                })?;
+        let mut orders = Vec::new();
+        for (lsp_url, order_ids) in self
+            .group_by_lsp(order_ids, BtTrackedItemKind::Order)
+            .await?
+        {
+            // An LSP that is not registered anymore must not fail the orders of the others
+            let client = match self.lsp_client(&lsp_url) {
+                Ok(client) => client,
+                Err(e) => {
+                    println!(
+                        "Warning: Skipping refresh of orders {}: {}",
+                        order_ids.join(", "),
+                        e
+                    );
+                    continue;
+                }
+            };
+            let fetched =
+                client
+                    .get_orders(&order_ids)
+                    .await
+                    .map_err(|e| BlocktankError::DataError {
+                        error_details: format!("Failed to fetch orders: {}", e),
+                    })?;
+            orders.extend(fetched);
+        }
 
         for order in &orders {
             self.upsert_order(order).await?;
@@ -97,7 +120,8 @@ impl BlocktankDB {
         order_id: String,
     ) -> Result<IBt0ConfMinTxFeeWindow, BlocktankError> {
         let response = self
-            .client
+            .item_client(&order_id, BtTrackedItemKind::Order)
+            .await?
             .get_min_zero_conf_tx_fee(&order_id)
             .await
             .map_err(|e| BlocktankError::DataError {
@@ -169,16 +193,24 @@ impl BlocktankDB {
         Ok(response)
     }
 
-    /// Fetches a CJIT entry by ID from Blocktank and stores it in the database.
+    /// Fetches a CJIT entry by ID from the LSP it was created with and stores it in the
+    /// database. JIT channels bought over LSPS2 are refreshed from the database.
     /// Returns the fetched CJIT entry if successful.
     pub async fn refresh_cjit_entry(&self, entry_id: &str) -> Result<ICJitEntry, BlocktankError> {
-        let response =
-            self.client
-                .get_cjit_entry(entry_id)
-                .await
-                .map_err(|e| BlocktankError::DataError {
-                    error_details: format!("Failed to fetch CJIT entry from Blocktank: {}", e),
-                })?;
+        let lsp_url = self
+            .get_item_lsp(entry_id, BtTrackedItemKind::CjitEntry)
+            .await?;
+        if lsp_url.starts_with(LSPS_URL_PREFIX) {
+            return self.refresh_lsps2_cjit_entry(entry_id).await;
+        }
+
+        let response = self
+            .lsp_client(&lsp_url)?
+            .get_cjit_entry(entry_id)
+            .await
+            .map_err(|e| BlocktankError::DataError {
+                error_details: format!("Failed to fetch CJIT entry from Blocktank: {}", e),
+            })?;
 
         self.upsert_cjit_entry(&response).await?;
         Ok(response)
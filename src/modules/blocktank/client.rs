@@ -0,0 +1,332 @@
+use crate::modules::blocktank::BlocktankError;
+use async_trait::async_trait;
+use rust_blocktank_client::{
+    BlocktankClient, CreateCjitOptions, CreateOrderOptions, IBt0ConfMinTxFeeWindow,
+    IBtBolt11Invoice, IBtEstimateFeeResponse, IBtEstimateFeeResponse2, IBtInfo, IBtOrder,
+    ICJitEntry, IGift,
+};
+
+/// The Blocktank API used by `BlocktankDB`, injectable for testing.
+#[async_trait]
+pub trait BlocktankApi: Send + Sync {
+    async fn get_info(&self) -> Result<IBtInfo, BlocktankError>;
+
+    async fn create_order(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtOrder, BlocktankError>;
+
+    async fn open_channel(
+        &self,
+        order_id: &str,
+        connection_string: &str,
+    ) -> Result<IBtOrder, BlocktankError>;
+
+    async fn get_orders(&self, order_ids: &[String]) -> Result<Vec<IBtOrder>, BlocktankError>;
+
+    async fn get_min_zero_conf_tx_fee(
+        &self,
+        order_id: &str,
+    ) -> Result<IBt0ConfMinTxFeeWindow, BlocktankError>;
+
+    async fn estimate_order_fee(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse, BlocktankError>;
+
+    async fn estimate_order_fee_full(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse2, BlocktankError>;
+
+    async fn create_cjit_entry(
+        &self,
+        channel_size_sat: u64,
+        invoice_sat: u64,
+        invoice_description: &str,
+        node_id: &str,
+        channel_expiry_weeks: u32,
+        options: Option<CreateCjitOptions>,
+    ) -> Result<ICJitEntry, BlocktankError>;
+
+    async fn get_cjit_entry(&self, entry_id: &str) -> Result<ICJitEntry, BlocktankError>;
+
+    async fn regtest_mine(&self, count: Option<u32>) -> Result<(), BlocktankError>;
+
+    async fn regtest_deposit(
+        &self,
+        address: &str,
+        amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError>;
+
+    async fn regtest_pay(
+        &self,
+        invoice: &str,
+        amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError>;
+
+    async fn regtest_get_payment(
+        &self,
+        payment_id: &str,
+    ) -> Result<IBtBolt11Invoice, BlocktankError>;
+
+    async fn regtest_close_channel(
+        &self,
+        funding_tx_id: &str,
+        vout: u32,
+        force_close_after_s: Option<u64>,
+    ) -> Result<String, BlocktankError>;
+
+    #[allow(clippy::too_many_arguments)] // Mirrors the Blocktank device registration request
+    async fn register_device(
+        &self,
+        device_token: &str,
+        public_key: &str,
+        features: &[String],
+        node_id: &str,
+        iso_timestamp: &str,
+        signature: &str,
+        is_production: Option<bool>,
+        custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError>;
+
+    async fn test_notification(
+        &self,
+        device_token: &str,
+        secret_message: &str,
+        notification_type: Option<&str>,
+        custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError>;
+
+    async fn gift_pay(&self, invoice: &str) -> Result<IGift, BlocktankError>;
+
+    async fn gift_order(&self, client_node_id: &str, code: &str) -> Result<IGift, BlocktankError>;
+
+    async fn get_gift(&self, gift_id: &str) -> Result<IGift, BlocktankError>;
+
+    async fn get_payment(&self, payment_id: &str) -> Result<IBtBolt11Invoice, BlocktankError>;
+}
+
+fn client_error(e: impl std::fmt::Display) -> BlocktankError {
+    BlocktankError::BlocktankClient {
+        error_details: e.to_string(),
+    }
+}
+
+#[async_trait]
+impl BlocktankApi for BlocktankClient {
+    async fn get_info(&self) -> Result<IBtInfo, BlocktankError> {
+        BlocktankClient::get_info(self).await.map_err(client_error)
+    }
+
+    async fn create_order(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtOrder, BlocktankError> {
+        BlocktankClient::create_order(self, lsp_balance_sat, channel_expiry_weeks, options)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn open_channel(
+        &self,
+        order_id: &str,
+        connection_string: &str,
+    ) -> Result<IBtOrder, BlocktankError> {
+        BlocktankClient::open_channel(self, order_id, connection_string)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn get_orders(&self, order_ids: &[String]) -> Result<Vec<IBtOrder>, BlocktankError> {
+        BlocktankClient::get_orders(self, order_ids)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn get_min_zero_conf_tx_fee(
+        &self,
+        order_id: &str,
+    ) -> Result<IBt0ConfMinTxFeeWindow, BlocktankError> {
+        BlocktankClient::get_min_zero_conf_tx_fee(self, order_id)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn estimate_order_fee(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse, BlocktankError> {
+        BlocktankClient::estimate_order_fee(self, lsp_balance_sat, channel_expiry_weeks, options)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn estimate_order_fee_full(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse2, BlocktankError> {
+        BlocktankClient::estimate_order_fee_full(
+            self,
+            lsp_balance_sat,
+            channel_expiry_weeks,
+            options,
+        )
+        .await
+        .map_err(client_error)
+    }
+
+    async fn create_cjit_entry(
+        &self,
+        channel_size_sat: u64,
+        invoice_sat: u64,
+        invoice_description: &str,
+        node_id: &str,
+        channel_expiry_weeks: u32,
+        options: Option<CreateCjitOptions>,
+    ) -> Result<ICJitEntry, BlocktankError> {
+        BlocktankClient::create_cjit_entry(
+            self,
+            channel_size_sat,
+            invoice_sat,
+            invoice_description,
+            node_id,
+            channel_expiry_weeks,
+            options,
+        )
+        .await
+        .map_err(client_error)
+    }
+
+    async fn get_cjit_entry(&self, entry_id: &str) -> Result<ICJitEntry, BlocktankError> {
+        BlocktankClient::get_cjit_entry(self, entry_id)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn regtest_mine(&self, count: Option<u32>) -> Result<(), BlocktankError> {
+        BlocktankClient::regtest_mine(self, count)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn regtest_deposit(
+        &self,
+        address: &str,
+        amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        BlocktankClient::regtest_deposit(self, address, amount_sat)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn regtest_pay(
+        &self,
+        invoice: &str,
+        amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        BlocktankClient::regtest_pay(self, invoice, amount_sat)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn regtest_get_payment(
+        &self,
+        payment_id: &str,
+    ) -> Result<IBtBolt11Invoice, BlocktankError> {
+        BlocktankClient::regtest_get_payment(self, payment_id)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn regtest_close_channel(
+        &self,
+        funding_tx_id: &str,
+        vout: u32,
+        force_close_after_s: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        BlocktankClient::regtest_close_channel(self, funding_tx_id, vout, force_close_after_s)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn register_device(
+        &self,
+        device_token: &str,
+        public_key: &str,
+        features: &[String],
+        node_id: &str,
+        iso_timestamp: &str,
+        signature: &str,
+        is_production: Option<bool>,
+        custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError> {
+        BlocktankClient::register_device(
+            self,
+            device_token,
+            public_key,
+            features,
+            node_id,
+            iso_timestamp,
+            signature,
+            is_production,
+            custom_url,
+        )
+        .await
+        .map_err(client_error)
+    }
+
+    async fn test_notification(
+        &self,
+        device_token: &str,
+        secret_message: &str,
+        notification_type: Option<&str>,
+        custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError> {
+        BlocktankClient::test_notification(
+            self,
+            device_token,
+            secret_message,
+            notification_type,
+            custom_url,
+        )
+        .await
+        .map_err(client_error)
+    }
+
+    async fn gift_pay(&self, invoice: &str) -> Result<IGift, BlocktankError> {
+        BlocktankClient::gift_pay(self, invoice)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn gift_order(&self, client_node_id: &str, code: &str) -> Result<IGift, BlocktankError> {
+        BlocktankClient::gift_order(self, client_node_id, code)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn get_gift(&self, gift_id: &str) -> Result<IGift, BlocktankError> {
+        BlocktankClient::get_gift(self, gift_id)
+            .await
+            .map_err(client_error)
+    }
+
+    async fn get_payment(&self, payment_id: &str) -> Result<IBtBolt11Invoice, BlocktankError> {
+        BlocktankClient::get_payment(self, payment_id)
+            .await
+            .map_err(client_error)
+    }
+}
@@ -1,8 +1,16 @@
+use crate::modules::blocktank::history::order_events;
 use crate::modules::blocktank::models::*;
-use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+use crate::modules::blocktank::{
+    BlocktankApi, BlocktankDB, BlocktankError, BtDeviceRegistration, BtLifecycleStatus,
+    BtOrderActivityLink, BtOrderEvent, BtOrderEventField, BtOrderRefund, BtRefundState,
+    BtStatusTransition, BtTrackedItemKind,
+};
 use rusqlite::{Connection, OptionalExtension};
 use rust_blocktank_client::*;
+use std::collections::HashMap;
 use std::result::Result;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 pub const DEFAULT_BLOCKTANK_URL: &str = "https://api1.blocktank.to/api";
 
@@ -11,22 +19,40 @@ impl BlocktankDB {
         db_path: &str,
         blocktank_url: Option<&str>,
     ) -> Result<BlocktankDB, BlocktankError> {
-        let conn = Connection::open(db_path).map_err(|e| BlocktankError::InitializationError {
-            error_details: format!("Error opening database: {}", e),
-        })?;
-
         let url = blocktank_url.unwrap_or(DEFAULT_BLOCKTANK_URL);
         let client =
             BlocktankClient::new(Some(url)).map_err(|e| BlocktankError::InitializationError {
                 error_details: format!("Failed to initialize Blocktank client: {}", e),
             })?;
 
-        let db = BlocktankDB {
+        Self::open(db_path, Arc::new(client), url).await
+    }
+
+    /// Creates a database that talks to Blocktank through `api`, e.g. a `MockLsp` in tests.
+    pub async fn with_api(
+        db_path: &str,
+        api: Arc<dyn BlocktankApi>,
+    ) -> Result<BlocktankDB, BlocktankError> {
+        Self::open(db_path, api, "").await
+    }
+
+    async fn open(
+        db_path: &str,
+        client: Arc<dyn BlocktankApi>,
+        blocktank_url: &str,
+    ) -> Result<BlocktankDB, BlocktankError> {
+        let conn = Connection::open(db_path).map_err(|e| BlocktankError::InitializationError {
+            error_details: format!("Error opening database: {}", e),
+        })?;
+
+        let mut db = BlocktankDB {
             conn: Mutex::new(conn),
             client,
-            blocktank_url: url.to_string(),
+            blocktank_url: blocktank_url.to_string(),
+            lsps: Vec::new(),
         };
         db.initialize().await?;
+        db.restore_registered_lsps().await?;
         Ok(db)
     }
 
@@ -58,6 +84,68 @@ impl BlocktankDB {
             }
         })?;
 
+        conn.execute(CREATE_STATUS_TRANSITIONS_TABLE, [])
+            .map_err(|e| BlocktankError::InitializationError {
+                error_details: format!("Failed to create status transitions table: {}", e),
+            })?;
+
+        conn.execute(CREATE_ORDER_EVENTS_TABLE, []).map_err(|e| {
+            BlocktankError::InitializationError {
+                error_details: format!("Failed to create order events table: {}", e),
+            }
+        })?;
+
+        conn.execute(CREATE_ORDER_REFUNDS_TABLE, []).map_err(|e| {
+            BlocktankError::InitializationError {
+                error_details: format!("Failed to create order refunds table: {}", e),
+            }
+        })?;
+
+        conn.execute(CREATE_ORDER_ACTIVITIES_TABLE, [])
+            .map_err(|e| BlocktankError::InitializationError {
+                error_details: format!("Failed to create order activities table: {}", e),
+            })?;
+
+        conn.execute(CREATE_DEVICE_REGISTRATION_TABLE, [])
+            .map_err(|e| BlocktankError::InitializationError {
+                error_details: format!("Failed to create device registration table: {}", e),
+            })?;
+
+        conn.execute(CREATE_LSP_INFO_TABLE, []).map_err(|e| {
+            BlocktankError::InitializationError {
+                error_details: format!("Failed to create LSP info table: {}", e),
+            }
+        })?;
+
+        conn.execute(CREATE_LSP_ITEMS_TABLE, []).map_err(|e| {
+            BlocktankError::InitializationError {
+                error_details: format!("Failed to create LSP items table: {}", e),
+            }
+        })?;
+
+        conn.execute(CREATE_REGISTERED_LSPS_TABLE, [])
+            .map_err(|e| BlocktankError::InitializationError {
+                error_details: format!("Failed to create registered LSPs table: {}", e),
+            })?;
+
+        // Orders and CJIT entries stored before their LSP was recorded are the default LSP's
+        for (table, kind) in [
+            ("orders", BtTrackedItemKind::Order),
+            ("cjit_entries", BtTrackedItemKind::CjitEntry),
+        ] {
+            conn.execute(
+                &format!(
+                    "INSERT OR IGNORE INTO lsp_items (item_id, item_kind, lsp_url)
+                     SELECT id, ?1, ?2 FROM {}",
+                    table
+                ),
+                rusqlite::params![format!("{:?}", kind), self.blocktank_url],
+            )
+            .map_err(|e| BlocktankError::InitializationError {
+                error_details: format!("Failed to record the LSP of stored {}: {}", table, e),
+            })?;
+        }
+
         // Populate enum tables
         // Order states
         for state in ["Created", "Expired", "Open", "Closed"] {
@@ -137,7 +225,7 @@ impl BlocktankDB {
         })?;
 
         // Update both the client and URL
-        self.client = new_client;
+        self.client = Arc::new(new_client);
         self.blocktank_url = new_url.to_string();
 
         Ok(())
@@ -146,28 +234,7 @@ impl BlocktankDB {
     pub async fn upsert_info(&self, info: &IBtInfo) -> Result<(), BlocktankError> {
         let conn = self.conn.lock().await;
 
-        let nodes_json =
-            serde_json::to_string(&info.nodes).map_err(|e| BlocktankError::SerializationError {
-                error_details: format!("Failed to serialize nodes: {}", e),
-            })?;
-
-        let options_json = serde_json::to_string(&info.options).map_err(|e| {
-            BlocktankError::SerializationError {
-                error_details: format!("Failed to serialize options: {}", e),
-            }
-        })?;
-
-        let versions_json = serde_json::to_string(&info.versions).map_err(|e| {
-            BlocktankError::SerializationError {
-                error_details: format!("Failed to serialize versions: {}", e),
-            }
-        })?;
-
-        let onchain_json = serde_json::to_string(&info.onchain).map_err(|e| {
-            BlocktankError::SerializationError {
-                error_details: format!("Failed to serialize onchain: {}", e),
-            }
-        })?;
+        let (nodes_json, options_json, versions_json, onchain_json) = serialize_info(info)?;
 
         conn.execute("UPDATE info SET is_current = 0 WHERE is_current = 1", [])
             .map_err(|e| BlocktankError::DatabaseError {
@@ -205,56 +272,7 @@ impl BlocktankDB {
              FROM info
              WHERE is_current = 1",
                 [],
-                |row| {
-                    let version: u32 = row.get(0)?;
-                    let nodes_json: String = row.get(1)?;
-                    let options_json: String = row.get(2)?;
-                    let versions_json: String = row.get(3)?;
-                    let onchain_json: String = row.get(4)?;
-
-                    let nodes: Vec<ILspNode> = serde_json::from_str(&nodes_json).map_err(|e| {
-                        rusqlite::Error::FromSqlConversionFailure(
-                            0,
-                            rusqlite::types::Type::Text,
-                            Box::new(e),
-                        )
-                    })?;
-
-                    let options: IBtInfoOptions =
-                        serde_json::from_str(&options_json).map_err(|e| {
-                            rusqlite::Error::FromSqlConversionFailure(
-                                0,
-                                rusqlite::types::Type::Text,
-                                Box::new(e),
-                            )
-                        })?;
-
-                    let versions: IBtInfoVersions =
-                        serde_json::from_str(&versions_json).map_err(|e| {
-                            rusqlite::Error::FromSqlConversionFailure(
-                                0,
-                                rusqlite::types::Type::Text,
-                                Box::new(e),
-                            )
-                        })?;
-
-                    let onchain: IBtInfoOnchain =
-                        serde_json::from_str(&onchain_json).map_err(|e| {
-                            rusqlite::Error::FromSqlConversionFailure(
-                                0,
-                                rusqlite::types::Type::Text,
-                                Box::new(e),
-                            )
-                        })?;
-
-                    Ok(IBtInfo {
-                        version,
-                        nodes,
-                        options,
-                        versions,
-                        onchain,
-                    })
-                },
+                info_from_row,
             )
             .optional()
             .map_err(|e| BlocktankError::DataError {
@@ -265,41 +283,80 @@ impl BlocktankDB {
     }
 
     pub async fn upsert_order(&self, order: &IBtOrder) -> Result<(), BlocktankError> {
-        let conn = self.conn.lock().await;
+        let mut conn = self.conn.lock().await;
+        let tx = conn
+            .transaction()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to start transaction: {}", e),
+            })?;
 
-        let params = Self::build_order_params(order)?;
+        Self::insert_order(&tx, order)?;
+        self.insert_default_item_lsp(&tx, &order.id, BtTrackedItemKind::Order)?;
 
-        let mut stmt =
-            conn.prepare(INSERT_ORDER_SQL)
-                .map_err(|e| BlocktankError::DatabaseError {
-                    error_details: format!("Failed to prepare statement: {}", e),
-                })?;
+        tx.commit().map_err(|e| BlocktankError::DatabaseError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
 
-        stmt.execute(rusqlite::params![
-            params.id,
-            params.state,
-            params.state2,
-            params.fee_sat,
-            params.network_fee_sat,
-            params.service_fee_sat,
-            params.lsp_balance_sat,
-            params.client_balance_sat,
-            params.zero_conf,
-            params.zero_reserve,
-            params.client_node_id,
-            params.channel_expiry_weeks,
-            params.channel_expires_at,
-            params.order_expires_at,
-            params.lnurl,
-            params.coupon_code,
-            params.source,
-            params.channel_json,
-            params.lsp_node_json,
-            params.payment_json,
-            params.discount_json,
-            params.updated_at,
-            params.created_at,
-        ])
+        Ok(())
+    }
+
+    /// Stores an order created with `lsp_url` together with its LSP, so it is never stored
+    /// without it
+    pub(crate) async fn upsert_order_with_lsp(
+        &self,
+        order: &IBtOrder,
+        lsp_url: &str,
+    ) -> Result<(), BlocktankError> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn
+            .transaction()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to start transaction: {}", e),
+            })?;
+
+        Self::insert_order(&tx, order)?;
+        Self::store_item_lsp(&tx, &order.id, BtTrackedItemKind::Order, lsp_url)?;
+
+        tx.commit().map_err(|e| BlocktankError::DatabaseError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Inserts or replaces `order` and logs its state changes
+    fn insert_order(conn: &Connection, order: &IBtOrder) -> Result<(), BlocktankError> {
+        let params = Self::build_order_params(order)?;
+        Self::append_order_events(conn, order, unix_now())?;
+
+        conn.execute(
+            INSERT_ORDER_SQL,
+            rusqlite::params![
+                params.id,
+                params.state,
+                params.state2,
+                params.fee_sat,
+                params.network_fee_sat,
+                params.service_fee_sat,
+                params.lsp_balance_sat,
+                params.client_balance_sat,
+                params.zero_conf,
+                params.zero_reserve,
+                params.client_node_id,
+                params.channel_expiry_weeks,
+                params.channel_expires_at,
+                params.order_expires_at,
+                params.lnurl,
+                params.coupon_code,
+                params.source,
+                params.channel_json,
+                params.lsp_node_json,
+                params.payment_json,
+                params.discount_json,
+                params.updated_at,
+                params.created_at,
+            ],
+        )
         .map_err(|e| BlocktankError::InsertError {
             error_details: format!("Failed to insert order: {}", e),
         })?;
@@ -322,8 +379,10 @@ impl BlocktankDB {
                         error_details: format!("Failed to prepare statement: {}", e),
                     })?;
 
+            let recorded_at = unix_now();
             for order in orders {
                 let params = Self::build_order_params(order)?;
+                Self::append_order_events(&tx, order, recorded_at)?;
 
                 stmt.execute(rusqlite::params![
                     params.id,
@@ -353,6 +412,7 @@ impl BlocktankDB {
                 .map_err(|e| BlocktankError::InsertError {
                     error_details: format!("Failed to insert order {}: {}", params.id, e),
                 })?;
+                self.insert_default_item_lsp(&tx, &params.id, BtTrackedItemKind::Order)?;
             }
         }
 
@@ -363,6 +423,80 @@ impl BlocktankDB {
         Ok(())
     }
 
+    /// Records the default LSP as the LSP of a newly stored order or CJIT entry. Items
+    /// created with another LSP are stored with it by `upsert_order_with_lsp` and
+    /// `upsert_cjit_entry_with_lsp`.
+    fn insert_default_item_lsp(
+        &self,
+        conn: &Connection,
+        item_id: &str,
+        kind: BtTrackedItemKind,
+    ) -> Result<(), BlocktankError> {
+        conn.execute(
+            INSERT_DEFAULT_ITEM_LSP_SQL,
+            rusqlite::params![item_id, format!("{:?}", kind), self.blocktank_url],
+        )
+        .map_err(|e| BlocktankError::InsertError {
+            error_details: format!("Failed to store LSP of {}: {}", item_id, e),
+        })?;
+        Ok(())
+    }
+
+    /// Logs the tracked fields of `order` that changed since they were last logged
+    fn append_order_events(
+        conn: &Connection,
+        order: &IBtOrder,
+        recorded_at: u64,
+    ) -> Result<(), BlocktankError> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT field, new_value FROM order_events
+                 WHERE id IN (
+                     SELECT MAX(id) FROM order_events WHERE order_id = ?1 GROUP BY field
+                 )",
+            )
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let previous = stmt
+            .query_map([&order.id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to process results: {}", e),
+            })?
+            .into_iter()
+            .map(|(field, value)| Ok((field.parse::<BtOrderEventField>()?, value)))
+            .collect::<Result<Vec<_>, BlocktankError>>()?;
+
+        for event in order_events(&previous, order, recorded_at) {
+            conn.execute(
+                INSERT_ORDER_EVENT_SQL,
+                rusqlite::params![
+                    event.order_id,
+                    format!("{:?}", event.field),
+                    event.old_value,
+                    event.new_value,
+                    event.fee_sat,
+                    event.network_fee_sat,
+                    event.service_fee_sat,
+                    event.order_updated_at,
+                    event.recorded_at,
+                ],
+            )
+            .map_err(|e| BlocktankError::InsertError {
+                error_details: format!("Failed to insert order event: {}", e),
+            })?;
+        }
+
+        Ok(())
+    }
+
     fn build_order_params(order: &IBtOrder) -> Result<OrderInsertParams, BlocktankError> {
         let channel_json = if let Some(channel) = &order.channel {
             Some(serde_json::to_string(channel).map_err(|e| {
@@ -732,6 +866,38 @@ impl BlocktankDB {
     pub async fn upsert_cjit_entry(&self, entry: &ICJitEntry) -> Result<(), BlocktankError> {
         let conn = self.conn.lock().await;
 
+        Self::insert_cjit_entry(&conn, entry)?;
+        self.insert_default_item_lsp(&conn, &entry.id, BtTrackedItemKind::CjitEntry)?;
+
+        Ok(())
+    }
+
+    /// Stores a CJIT entry created with `lsp_url` together with its LSP, so it is never
+    /// stored without it
+    pub(crate) async fn upsert_cjit_entry_with_lsp(
+        &self,
+        entry: &ICJitEntry,
+        lsp_url: &str,
+    ) -> Result<(), BlocktankError> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn
+            .transaction()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to start transaction: {}", e),
+            })?;
+
+        Self::insert_cjit_entry(&tx, entry)?;
+        Self::store_item_lsp(&tx, &entry.id, BtTrackedItemKind::CjitEntry, lsp_url)?;
+
+        tx.commit().map_err(|e| BlocktankError::DatabaseError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Inserts or replaces `entry`
+    fn insert_cjit_entry(conn: &Connection, entry: &ICJitEntry) -> Result<(), BlocktankError> {
         let params = Self::build_cjit_params(entry)?;
 
         let mut stmt =
@@ -808,6 +974,7 @@ impl BlocktankDB {
                 .map_err(|e| BlocktankError::InsertError {
                     error_details: format!("Failed to insert CJIT entry {}: {}", params.id, e),
                 })?;
+                self.insert_default_item_lsp(&tx, &params.id, BtTrackedItemKind::CjitEntry)?;
             }
         }
 
@@ -1061,63 +1228,866 @@ impl BlocktankDB {
         Ok(entries)
     }
 
-    /// Removes all orders from the database
-    pub async fn remove_all_orders(&self) -> Result<(), BlocktankError> {
+    /// Appends a status transition of a tracked order or CJIT entry
+    pub async fn record_status_transition(
+        &self,
+        item_id: &str,
+        kind: BtTrackedItemKind,
+        from_status: Option<BtLifecycleStatus>,
+        to_status: BtLifecycleStatus,
+    ) -> Result<BtStatusTransition, BlocktankError> {
         let conn = self.conn.lock().await;
 
-        conn.execute("DELETE FROM orders", [])
-            .map_err(|e| BlocktankError::DatabaseError {
-                error_details: format!("Failed to delete all orders: {}", e),
-            })?;
+        let recorded_at = unix_now();
 
-        Ok(())
+        conn.execute(
+            "INSERT INTO status_transitions (
+                item_id, item_kind, from_status, to_status, recorded_at
+             ) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                item_id,
+                format!("{:?}", kind),
+                from_status.map(|status| format!("{:?}", status)),
+                format!("{:?}", to_status),
+                recorded_at,
+            ],
+        )
+        .map_err(|e| BlocktankError::InsertError {
+            error_details: format!("Failed to insert status transition: {}", e),
+        })?;
+
+        Ok(BtStatusTransition {
+            item_id: item_id.to_string(),
+            kind,
+            from_status,
+            to_status,
+            recorded_at,
+        })
     }
 
-    /// Removes all CJIT entries from the database
-    pub async fn remove_all_cjit_entries(&self) -> Result<(), BlocktankError> {
+    /// Returns the most recently recorded status of a tracked item
+    pub async fn get_last_status(
+        &self,
+        item_id: &str,
+        kind: BtTrackedItemKind,
+    ) -> Result<Option<BtLifecycleStatus>, BlocktankError> {
         let conn = self.conn.lock().await;
 
-        conn.execute("DELETE FROM cjit_entries", []).map_err(|e| {
-            BlocktankError::DatabaseError {
-                error_details: format!("Failed to delete all CJIT entries: {}", e),
-            }
-        })?;
+        let status: Option<String> = conn
+            .query_row(
+                "SELECT to_status FROM status_transitions
+                 WHERE item_id = ?1 AND item_kind = ?2
+                 ORDER BY id DESC LIMIT 1",
+                rusqlite::params![item_id, format!("{:?}", kind)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to fetch last status: {}", e),
+            })?;
 
-        Ok(())
+        status.map(|status| status.parse()).transpose()
     }
 
-    /// Removes all data from all Blocktank tables
-    ///
-    /// This wipes:
-    /// - All orders
-    /// - All CJIT entries
-    /// - All info entries
-    ///
-    /// Note: This does NOT delete the enum state tables (order_states, payment_states, cjit_states)
-    /// as these contain static reference data that should persist across wipes.
-    pub async fn wipe_all(&self) -> Result<(), BlocktankError> {
-        let mut conn = self.conn.lock().await;
+    /// Returns the latest status of every tracked item of `kind` that is still stored
+    pub async fn get_latest_statuses(
+        &self,
+        kind: BtTrackedItemKind,
+    ) -> Result<HashMap<String, BtLifecycleStatus>, BlocktankError> {
+        let conn = self.conn.lock().await;
 
-        let tx = conn
-            .transaction()
+        let mut stmt = conn
+            .prepare(
+                "SELECT t.item_id, t.to_status FROM status_transitions t
+                 WHERE t.item_kind = ?1
+                   AND t.id = (
+                       SELECT MAX(id) FROM status_transitions
+                       WHERE item_kind = t.item_kind AND item_id = t.item_id
+                   )
+                   AND (
+                       (t.item_kind = 'Order'
+                           AND EXISTS (SELECT 1 FROM orders WHERE id = t.item_id))
+                       OR (t.item_kind = 'CjitEntry'
+                           AND EXISTS (SELECT 1 FROM cjit_entries WHERE id = t.item_id))
+                   )",
+            )
             .map_err(|e| BlocktankError::DatabaseError {
-                error_details: format!("Failed to start transaction: {}", e),
+                error_details: format!("Failed to prepare statement: {}", e),
             })?;
 
-        tx.execute("DELETE FROM orders", [])
+        let rows = stmt
+            .query_map([format!("{:?}", kind)], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
             .map_err(|e| BlocktankError::DatabaseError {
-                error_details: format!("Failed to delete orders: {}", e),
-            })?;
-
-        tx.execute("DELETE FROM cjit_entries", [])
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
             .map_err(|e| BlocktankError::DatabaseError {
-                error_details: format!("Failed to delete CJIT entries: {}", e),
+                error_details: format!("Failed to process results: {}", e),
             })?;
 
-        tx.execute("DELETE FROM info", [])
-            .map_err(|e| BlocktankError::DatabaseError {
-                error_details: format!("Failed to delete info entries: {}", e),
-            })?;
+        rows.into_iter()
+            .map(|(item_id, status)| Ok((item_id, status.parse()?)))
+            .collect()
+    }
+
+    /// Returns the recorded status transitions of an order or CJIT entry, oldest first
+    pub async fn get_status_history(
+        &self,
+        item_id: &str,
+    ) -> Result<Vec<BtStatusTransition>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT item_id, item_kind, from_status, to_status, recorded_at
+                 FROM status_transitions
+                 WHERE item_id = ?1
+                 ORDER BY id ASC",
+            )
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let rows = stmt
+            .query_map([item_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, u64>(4)?,
+                ))
+            })
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to process results: {}", e),
+            })?;
+
+        rows.into_iter()
+            .map(|(item_id, kind, from_status, to_status, recorded_at)| {
+                Ok(BtStatusTransition {
+                    item_id,
+                    kind: kind.parse()?,
+                    from_status: from_status.map(|status| status.parse()).transpose()?,
+                    to_status: to_status.parse()?,
+                    recorded_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the logged changes of an order, oldest first
+    pub async fn get_order_timeline(
+        &self,
+        order_id: &str,
+    ) -> Result<Vec<BtOrderEvent>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT order_id, field, old_value, new_value, fee_sat, network_fee_sat,
+                        service_fee_sat, order_updated_at, recorded_at
+                 FROM order_events
+                 WHERE order_id = ?1
+                 ORDER BY id ASC",
+            )
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let rows = stmt
+            .query_map([order_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, u64>(4)?,
+                    row.get::<_, u64>(5)?,
+                    row.get::<_, u64>(6)?,
+                    row.get::<_, String>(7)?,
+                    row.get::<_, u64>(8)?,
+                ))
+            })
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to process results: {}", e),
+            })?;
+
+        rows.into_iter()
+            .map(
+                |(
+                    order_id,
+                    field,
+                    old_value,
+                    new_value,
+                    fee_sat,
+                    network_fee_sat,
+                    service_fee_sat,
+                    order_updated_at,
+                    recorded_at,
+                )| {
+                    Ok(BtOrderEvent {
+                        order_id,
+                        field: field.parse()?,
+                        old_value,
+                        new_value,
+                        fee_sat,
+                        network_fee_sat,
+                        service_fee_sat,
+                        order_updated_at,
+                        recorded_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Stores `refund` and logs a `RefundState` event if its state differs from `previous_state`
+    pub async fn upsert_order_refund(
+        &self,
+        refund: &BtOrderRefund,
+        order: &IBtOrder,
+        previous_state: Option<BtRefundState>,
+    ) -> Result<BtOrderRefund, BlocktankError> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn
+            .transaction()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to start transaction: {}", e),
+            })?;
+
+        tx.execute(
+            "INSERT OR REPLACE INTO order_refunds (
+                order_id, refund_address, state, amount_sat, tx_id, created_at, updated_at
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                refund.order_id,
+                refund.refund_address,
+                format!("{:?}", refund.state),
+                refund.amount_sat,
+                refund.tx_id,
+                refund.created_at,
+                refund.updated_at,
+            ],
+        )
+        .map_err(|e| BlocktankError::InsertError {
+            error_details: format!("Failed to insert order refund: {}", e),
+        })?;
+
+        if previous_state != Some(refund.state) {
+            tx.execute(
+                INSERT_ORDER_EVENT_SQL,
+                rusqlite::params![
+                    order.id,
+                    format!("{:?}", BtOrderEventField::RefundState),
+                    previous_state.map(|state| format!("{:?}", state)),
+                    format!("{:?}", refund.state),
+                    order.fee_sat,
+                    order.network_fee_sat,
+                    order.service_fee_sat,
+                    order.updated_at,
+                    refund.updated_at,
+                ],
+            )
+            .map_err(|e| BlocktankError::InsertError {
+                error_details: format!("Failed to insert order event: {}", e),
+            })?;
+        }
+
+        tx.commit().map_err(|e| BlocktankError::DatabaseError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        Ok(refund.clone())
+    }
+
+    /// Returns the tracked refund of an order
+    pub async fn get_order_refund(
+        &self,
+        order_id: &str,
+    ) -> Result<Option<BtOrderRefund>, BlocktankError> {
+        Ok(self
+            .query_order_refunds("WHERE order_id = ?1", &[order_id])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Returns all tracked refunds, most recently updated first
+    pub async fn get_order_refunds(&self) -> Result<Vec<BtOrderRefund>, BlocktankError> {
+        self.query_order_refunds("", &[]).await
+    }
+
+    async fn query_order_refunds(
+        &self,
+        condition: &str,
+        params: &[&str],
+    ) -> Result<Vec<BtOrderRefund>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT order_id, refund_address, state, amount_sat, tx_id, created_at, updated_at
+                 FROM order_refunds {}
+                 ORDER BY updated_at DESC, order_id ASC",
+                condition
+            ))
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, u64>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, u64>(5)?,
+                    row.get::<_, u64>(6)?,
+                ))
+            })
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to process results: {}", e),
+            })?;
+
+        rows.into_iter()
+            .map(
+                |(order_id, refund_address, state, amount_sat, tx_id, created_at, updated_at)| {
+                    Ok(BtOrderRefund {
+                        order_id,
+                        refund_address,
+                        state: state.parse()?,
+                        amount_sat,
+                        tx_id,
+                        created_at,
+                        updated_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Stores `links`, keeping the time an activity was first linked to an order
+    pub async fn upsert_order_activity_links(
+        &self,
+        links: &[BtOrderActivityLink],
+    ) -> Result<(), BlocktankError> {
+        let mut conn = self.conn.lock().await;
+        let tx = conn
+            .transaction()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to start transaction: {}", e),
+            })?;
+
+        let linked_at = unix_now();
+        for link in links {
+            tx.execute(
+                "INSERT INTO order_activities (order_id, activity_id, kind, channel_id, linked_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (order_id, activity_id) DO UPDATE SET
+                    kind = excluded.kind,
+                    channel_id = excluded.channel_id",
+                rusqlite::params![
+                    link.order_id,
+                    link.activity_id,
+                    format!("{:?}", link.kind),
+                    link.channel_id,
+                    linked_at,
+                ],
+            )
+            .map_err(|e| BlocktankError::InsertError {
+                error_details: format!("Failed to insert order activity: {}", e),
+            })?;
+        }
+
+        tx.commit().map_err(|e| BlocktankError::DatabaseError {
+            error_details: format!("Failed to commit transaction: {}", e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Returns the activities linked to an order, oldest link first
+    pub async fn get_order_activity_links(
+        &self,
+        order_id: &str,
+    ) -> Result<Vec<BtOrderActivityLink>, BlocktankError> {
+        self.query_order_activity_links("order_id", order_id).await
+    }
+
+    /// Returns the orders linked to an activity, oldest link first
+    pub async fn get_activity_order_links(
+        &self,
+        activity_id: &str,
+    ) -> Result<Vec<BtOrderActivityLink>, BlocktankError> {
+        self.query_order_activity_links("activity_id", activity_id)
+            .await
+    }
+
+    async fn query_order_activity_links(
+        &self,
+        column: &str,
+        value: &str,
+    ) -> Result<Vec<BtOrderActivityLink>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT order_id, activity_id, kind, channel_id
+                 FROM order_activities
+                 WHERE {} = ?1
+                 ORDER BY linked_at ASC, rowid ASC",
+                column
+            ))
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let rows = stmt
+            .query_map([value], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to execute query: {}", e),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to process results: {}", e),
+            })?;
+
+        rows.into_iter()
+            .map(|(order_id, activity_id, kind, channel_id)| {
+                Ok(BtOrderActivityLink {
+                    order_id,
+                    activity_id,
+                    kind: kind.parse()?,
+                    channel_id,
+                })
+            })
+            .collect()
+    }
+
+    /// Stores the push notification registration, replacing the previous one
+    pub async fn upsert_device_registration(
+        &self,
+        registration: &BtDeviceRegistration,
+        secret_key: &str,
+    ) -> Result<(), BlocktankError> {
+        let features = serde_json::to_string(&registration.features).map_err(|e| {
+            BlocktankError::SerializationError {
+                error_details: format!("Failed to serialize notification features: {}", e),
+            }
+        })?;
+        let conn = self.conn.lock().await;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO device_registration (
+                id, device_token, node_id, public_key, secret_key, features, is_production,
+                custom_url, iso_timestamp, signature, registered_at
+             ) VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                registration.device_token,
+                registration.node_id,
+                registration.public_key,
+                secret_key,
+                features,
+                registration.is_production,
+                registration.custom_url,
+                registration.iso_timestamp,
+                registration.signature,
+                registration.registered_at,
+            ],
+        )
+        .map_err(|e| BlocktankError::InsertError {
+            error_details: format!("Failed to insert device registration: {}", e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Returns the stored push notification registration
+    pub async fn get_device_registration(
+        &self,
+    ) -> Result<Option<BtDeviceRegistration>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let row = conn
+            .query_row(
+                "SELECT device_token, node_id, public_key, features, is_production, custom_url,
+                        iso_timestamp, signature, registered_at
+                 FROM device_registration WHERE id = 1",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, Option<bool>>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, String>(7)?,
+                        row.get::<_, u64>(8)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(|e| BlocktankError::RetrievalError {
+                error_details: format!("Failed to get device registration: {}", e),
+            })?;
+
+        row.map(
+            |(
+                device_token,
+                node_id,
+                public_key,
+                features,
+                is_production,
+                custom_url,
+                iso_timestamp,
+                signature,
+                registered_at,
+            )| {
+                Ok(BtDeviceRegistration {
+                    device_token,
+                    node_id,
+                    public_key,
+                    features: serde_json::from_str(&features).map_err(|e| {
+                        BlocktankError::SerializationError {
+                            error_details: format!(
+                                "Failed to deserialize notification features: {}",
+                                e
+                            ),
+                        }
+                    })?,
+                    is_production,
+                    custom_url,
+                    iso_timestamp,
+                    signature,
+                    registered_at,
+                })
+            },
+        )
+        .transpose()
+    }
+
+    /// Returns the hex encoded secret key of the registered notification keypair
+    pub async fn get_notification_secret_key(&self) -> Result<Option<String>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        conn.query_row(
+            "SELECT secret_key FROM device_registration WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| BlocktankError::RetrievalError {
+            error_details: format!("Failed to get notification secret key: {}", e),
+        })
+    }
+
+    /// Stores the service information of a registered LSP other than the default one
+    pub async fn upsert_lsp_info(
+        &self,
+        lsp_url: &str,
+        info: &IBtInfo,
+    ) -> Result<(), BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let (nodes_json, options_json, versions_json, onchain_json) = serialize_info(info)?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO lsp_info (
+                lsp_url, version, nodes, options, versions, onchain, updated_at
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                lsp_url,
+                info.version,
+                nodes_json,
+                options_json,
+                versions_json,
+                onchain_json,
+                unix_now(),
+            ],
+        )
+        .map_err(|e| BlocktankError::InsertError {
+            error_details: format!("Failed to insert LSP info: {}", e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Retrieves the stored service information of `lsp_url`, which is the current info for
+    /// the default LSP
+    pub async fn get_lsp_info(&self, lsp_url: &str) -> Result<Option<IBtInfo>, BlocktankError> {
+        if lsp_url == self.blocktank_url {
+            return self.get_info().await;
+        }
+
+        let conn = self.conn.lock().await;
+
+        conn.query_row(
+            "SELECT version, nodes, options, versions, onchain
+             FROM lsp_info
+             WHERE lsp_url = ?1",
+            [lsp_url],
+            info_from_row,
+        )
+        .optional()
+        .map_err(|e| BlocktankError::DataError {
+            error_details: format!("Failed to fetch LSP info from database: {}", e),
+        })
+    }
+
+    /// Records that an order or CJIT entry was created with `lsp_url`
+    pub async fn set_item_lsp(
+        &self,
+        item_id: &str,
+        kind: BtTrackedItemKind,
+        lsp_url: &str,
+    ) -> Result<(), BlocktankError> {
+        let conn = self.conn.lock().await;
+        Self::store_item_lsp(&conn, item_id, kind, lsp_url)
+    }
+
+    fn store_item_lsp(
+        conn: &Connection,
+        item_id: &str,
+        kind: BtTrackedItemKind,
+        lsp_url: &str,
+    ) -> Result<(), BlocktankError> {
+        conn.execute(
+            SET_ITEM_LSP_SQL,
+            rusqlite::params![item_id, format!("{:?}", kind), lsp_url],
+        )
+        .map_err(|e| BlocktankError::InsertError {
+            error_details: format!("Failed to store LSP of {}: {}", item_id, e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Returns the URL of the LSP an order or CJIT entry was created with, which is the
+    /// default LSP for items that are not stored
+    pub async fn get_item_lsp(
+        &self,
+        item_id: &str,
+        kind: BtTrackedItemKind,
+    ) -> Result<String, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let lsp_url: Option<String> = conn
+            .query_row(
+                "SELECT lsp_url FROM lsp_items WHERE item_kind = ?1 AND item_id = ?2",
+                rusqlite::params![format!("{:?}", kind), item_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| BlocktankError::RetrievalError {
+                error_details: format!("Failed to get LSP of {}: {}", item_id, e),
+            })?;
+
+        Ok(lsp_url.unwrap_or_else(|| self.blocktank_url.clone()))
+    }
+
+    /// Returns the LSP URL of every stored item of `kind`
+    pub(crate) async fn get_item_lsps(
+        &self,
+        kind: BtTrackedItemKind,
+    ) -> Result<HashMap<String, String>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn
+            .prepare("SELECT item_id, lsp_url FROM lsp_items WHERE item_kind = ?1")
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let rows = stmt
+            .query_map([format!("{:?}", kind)], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(|e| BlocktankError::RetrievalError {
+                error_details: format!("Failed to get item LSPs: {}", e),
+            })?;
+
+        rows.collect::<Result<HashMap<String, String>, _>>()
+            .map_err(|e| BlocktankError::RetrievalError {
+                error_details: format!("Failed to read item LSPs: {}", e),
+            })
+    }
+
+    /// Records that the LSP at `lsp_url` is registered, to register it again when the
+    /// database is opened
+    pub(crate) fn store_registered_lsp(&mut self, lsp_url: &str) -> Result<(), BlocktankError> {
+        self.conn
+            .get_mut()
+            .execute(
+                "INSERT OR IGNORE INTO registered_lsps (lsp_url, registered_at) VALUES (?1, ?2)",
+                rusqlite::params![lsp_url, unix_now()],
+            )
+            .map_err(|e| BlocktankError::InsertError {
+                error_details: format!("Failed to store registered LSP {}: {}", lsp_url, e),
+            })?;
+        Ok(())
+    }
+
+    /// Forgets the registration of the LSP at `lsp_url`. Returns whether it was stored.
+    pub(crate) fn remove_registered_lsp(&mut self, lsp_url: &str) -> Result<bool, BlocktankError> {
+        let deleted = self
+            .conn
+            .get_mut()
+            .execute("DELETE FROM registered_lsps WHERE lsp_url = ?1", [lsp_url])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete registered LSP {}: {}", lsp_url, e),
+            })?;
+        Ok(deleted > 0)
+    }
+
+    /// Returns the URLs of the stored LSP registrations, in registration order
+    pub(crate) async fn get_registered_lsps(&self) -> Result<Vec<String>, BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        let mut stmt = conn
+            .prepare("SELECT lsp_url FROM registered_lsps ORDER BY registered_at, rowid")
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to prepare statement: {}", e),
+            })?;
+
+        let rows =
+            stmt.query_map([], |row| row.get(0))
+                .map_err(|e| BlocktankError::RetrievalError {
+                    error_details: format!("Failed to get registered LSPs: {}", e),
+                })?;
+
+        rows.collect::<Result<Vec<String>, _>>()
+            .map_err(|e| BlocktankError::RetrievalError {
+                error_details: format!("Failed to read registered LSPs: {}", e),
+            })
+    }
+
+    /// Removes all orders from the database
+    pub async fn remove_all_orders(&self) -> Result<(), BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        conn.execute("DELETE FROM orders", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete all orders: {}", e),
+            })?;
+
+        Ok(())
+    }
+
+    /// Removes all CJIT entries from the database
+    pub async fn remove_all_cjit_entries(&self) -> Result<(), BlocktankError> {
+        let conn = self.conn.lock().await;
+
+        conn.execute("DELETE FROM cjit_entries", []).map_err(|e| {
+            BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete all CJIT entries: {}", e),
+            }
+        })?;
+
+        Ok(())
+    }
+
+    /// Removes all data from all Blocktank tables
+    ///
+    /// This wipes:
+    /// - All orders
+    /// - All CJIT entries
+    /// - All info entries
+    /// - All status transitions
+    /// - All order events
+    /// - All order refunds
+    /// - All LSP info, the LSPs of orders and CJIT entries and the stored LSP registrations
+    ///
+    /// Note: This does NOT delete the enum state tables (order_states, payment_states, cjit_states)
+    /// as these contain static reference data that should persist across wipes.
+    pub async fn wipe_all(&self) -> Result<(), BlocktankError> {
+        let mut conn = self.conn.lock().await;
+
+        let tx = conn
+            .transaction()
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to start transaction: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM orders", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete orders: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM cjit_entries", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete CJIT entries: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM info", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete info entries: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM status_transitions", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete status transitions: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM order_events", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete order events: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM order_refunds", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete order refunds: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM order_activities", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete order activities: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM device_registration", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete device registration: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM lsp_info", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete LSP info: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM lsp_items", [])
+            .map_err(|e| BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete LSP items: {}", e),
+            })?;
+
+        tx.execute("DELETE FROM registered_lsps", []).map_err(|e| {
+            BlocktankError::DatabaseError {
+                error_details: format!("Failed to delete registered LSPs: {}", e),
+            }
+        })?;
 
         tx.commit().map_err(|e| BlocktankError::DatabaseError {
             error_details: format!("Failed to commit transaction: {}", e),
@@ -1127,6 +2097,71 @@ impl BlocktankDB {
     }
 }
 
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Serializes the nodes, options, versions and onchain fields of `info`
+fn serialize_info(info: &IBtInfo) -> Result<(String, String, String, String), BlocktankError> {
+    let nodes_json =
+        serde_json::to_string(&info.nodes).map_err(|e| BlocktankError::SerializationError {
+            error_details: format!("Failed to serialize nodes: {}", e),
+        })?;
+
+    let options_json =
+        serde_json::to_string(&info.options).map_err(|e| BlocktankError::SerializationError {
+            error_details: format!("Failed to serialize options: {}", e),
+        })?;
+
+    let versions_json =
+        serde_json::to_string(&info.versions).map_err(|e| BlocktankError::SerializationError {
+            error_details: format!("Failed to serialize versions: {}", e),
+        })?;
+
+    let onchain_json =
+        serde_json::to_string(&info.onchain).map_err(|e| BlocktankError::SerializationError {
+            error_details: format!("Failed to serialize onchain: {}", e),
+        })?;
+
+    Ok((nodes_json, options_json, versions_json, onchain_json))
+}
+
+/// Reads info from a row of version, nodes, options, versions and onchain columns
+fn info_from_row(row: &rusqlite::Row) -> rusqlite::Result<IBtInfo> {
+    let version: u32 = row.get(0)?;
+    let nodes_json: String = row.get(1)?;
+    let options_json: String = row.get(2)?;
+    let versions_json: String = row.get(3)?;
+    let onchain_json: String = row.get(4)?;
+
+    let nodes: Vec<ILspNode> = serde_json::from_str(&nodes_json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+
+    let options: IBtInfoOptions = serde_json::from_str(&options_json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+
+    let versions: IBtInfoVersions = serde_json::from_str(&versions_json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+
+    let onchain: IBtInfoOnchain = serde_json::from_str(&onchain_json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+
+    Ok(IBtInfo {
+        version,
+        nodes,
+        options,
+        versions,
+        onchain,
+    })
+}
+
 struct OrderInsertParams {
     id: String,
     state: String,
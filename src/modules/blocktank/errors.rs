@@ -59,6 +59,14 @@ impl From<url::ParseError> for BlocktankError {
     }
 }
 
+impl From<uniffi::UnexpectedUniFFICallbackError> for BlocktankError {
+    fn from(err: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        BlocktankError::DataError {
+            error_details: format!("Unexpected callback error: {}", err.reason),
+        }
+    }
+}
+
 #[derive(uniffi::Record, Debug)]
 pub struct ErrorData {
     pub error_details: String,
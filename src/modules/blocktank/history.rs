@@ -0,0 +1,119 @@
+//! Audit log of Blocktank order changes.
+//!
+//! Orders are overwritten on every upsert, so each upsert first compares the tracked fields
+//! with the last values logged for the order and appends one [`BtOrderEvent`] per change to
+//! the append-only `order_events` table. Refund state changes are logged to the same table
+//! by the refund workflow.
+
+use crate::modules::blocktank::BlocktankError;
+use rust_blocktank_client::IBtOrder;
+use std::str::FromStr;
+
+/// Order field whose changes are logged
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtOrderEventField {
+    State,
+    State2,
+    PaymentState,
+    ChannelState,
+    RefundState,
+}
+
+impl BtOrderEventField {
+    /// Fields derived from the order itself
+    pub(crate) const ORDER_FIELDS: [BtOrderEventField; 4] = [
+        Self::State,
+        Self::State2,
+        Self::PaymentState,
+        Self::ChannelState,
+    ];
+
+    /// Current value of the field, formatted the way it is stored
+    pub(crate) fn value_of(&self, order: &IBtOrder) -> Option<String> {
+        match self {
+            Self::State => Some(format!("{:?}", order.state)),
+            Self::State2 => order.state2.as_ref().map(|state| format!("{:?}", state)),
+            Self::PaymentState => order
+                .payment
+                .as_ref()
+                .and_then(|payment| payment.state2.as_ref())
+                .map(|state| format!("{:?}", state)),
+            Self::ChannelState => order
+                .channel
+                .as_ref()
+                .map(|channel| format!("{:?}", channel.state)),
+            // Depends on the refund address attached locally, see `refunds`
+            Self::RefundState => None,
+        }
+    }
+}
+
+impl FromStr for BtOrderEventField {
+    type Err = BlocktankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "State" => Ok(Self::State),
+            "State2" => Ok(Self::State2),
+            "PaymentState" => Ok(Self::PaymentState),
+            "ChannelState" => Ok(Self::ChannelState),
+            "RefundState" => Ok(Self::RefundState),
+            _ => Err(BlocktankError::DataError {
+                error_details: format!("Unknown order event field: {}", s),
+            }),
+        }
+    }
+}
+
+/// A single observed change of an order field
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtOrderEvent {
+    pub order_id: String,
+    pub field: BtOrderEventField,
+    /// `None` when the order is logged for the first time or the field was unset
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    /// Fees of the order when the change was observed
+    pub fee_sat: u64,
+    pub network_fee_sat: u64,
+    pub service_fee_sat: u64,
+    /// `updated_at` of the order as reported by Blocktank
+    pub order_updated_at: String,
+    /// Unix timestamp in seconds
+    pub recorded_at: u64,
+}
+
+/// Returns the events for `order` given the last logged value of each field.
+///
+/// Fields missing from `previous` have not been logged yet and count as unset.
+pub(crate) fn order_events(
+    previous: &[(BtOrderEventField, Option<String>)],
+    order: &IBtOrder,
+    recorded_at: u64,
+) -> Vec<BtOrderEvent> {
+    BtOrderEventField::ORDER_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let new_value = field.value_of(order);
+            let old_value = previous
+                .iter()
+                .find(|(logged, _)| logged == field)
+                .and_then(|(_, value)| value.clone());
+            if old_value == new_value {
+                return None;
+            }
+
+            Some(BtOrderEvent {
+                order_id: order.id.clone(),
+                field: *field,
+                old_value,
+                new_value,
+                fee_sat: order.fee_sat,
+                network_fee_sat: order.network_fee_sat,
+                service_fee_sat: order.service_fee_sat,
+                order_updated_at: order.updated_at.clone(),
+                recorded_at,
+            })
+        })
+        .collect()
+}
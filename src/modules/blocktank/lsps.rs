@@ -0,0 +1,376 @@
+//! Several LSPs implementing the Blocktank API.
+//!
+//! The LSP at `blocktank_url` is the default one. Other LSPs are registered by URL, and are
+//! registered again when the database is opened. Every order and CJIT entry is stored with
+//! the URL of its LSP, so refreshing them and opening their channels goes back to the LSP
+//! they came from, also after the default LSP changes. `select_lsp` picks the cheapest LSP
+//! whose limits allow a requested channel.
+//!
+//! LSPs speaking LSPS1 and LSPS2 through the node are registered with `register_lsps_lsp`.
+//! Their transport lives in the app, so they are registered again by the app after a
+//! restart. They can not estimate fees, so `select_lsp` leaves them out.
+
+use crate::modules::blocktank::lsps2::lsps2_expire_entry;
+use crate::modules::blocktank::{
+    BlocktankApi, BlocktankDB, BlocktankError, BtLspsTransport, BtTrackedItemKind, LspsClient,
+    LSPS_URL_PREFIX,
+};
+use bitcoin::secp256k1::PublicKey;
+use rust_blocktank_client::{
+    BitcoinNetworkEnum, BlocktankClient, CreateCjitOptions, CreateOrderOptions, IBtInfo,
+    IBtInfoOptions, IBtOrder, ICJitEntry,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtLspQuote {
+    pub lsp_url: String,
+    /// Total to pay, including the client balance
+    pub fee_sat: u64,
+    pub network_fee_sat: u64,
+    pub service_fee_sat: u64,
+}
+
+/// Returns whether a channel is within the limits an LSP advertises
+fn channel_within_limits(
+    limits: &IBtInfoOptions,
+    lsp_balance_sat: u64,
+    channel_expiry_weeks: u32,
+    options: &CreateOrderOptions,
+) -> bool {
+    let channel_size_sat = lsp_balance_sat.saturating_add(options.client_balance_sat);
+    (limits.min_channel_size_sat..=limits.max_channel_size_sat).contains(&channel_size_sat)
+        && (limits.min_expiry_weeks..=limits.max_expiry_weeks).contains(&channel_expiry_weeks)
+        && options.client_balance_sat <= limits.max_client_balance_sat
+        && (!options.zero_conf
+            || options.client_balance_sat <= limits.max_0_conf_client_balance_sat)
+}
+
+fn blocktank_client(lsp_url: &str) -> Result<BlocktankClient, BlocktankError> {
+    BlocktankClient::new(Some(lsp_url)).map_err(|e| BlocktankError::InitializationError {
+        error_details: format!("Failed to initialize client for LSP {}: {}", lsp_url, e),
+    })
+}
+
+impl BlocktankDB {
+    /// Registers the LSP at `lsp_url` besides the default one. The registration is stored,
+    /// so the LSP is registered again when the database is opened.
+    pub fn register_lsp(&mut self, lsp_url: &str) -> Result<(), BlocktankError> {
+        self.register_lsp_api(lsp_url, Arc::new(blocktank_client(lsp_url)?))?;
+        self.store_registered_lsp(lsp_url)
+    }
+
+    /// Registers again the LSPs whose registration is stored. An LSP whose client can not be
+    /// created is left unregistered, and its orders and CJIT entries are skipped by refreshes.
+    pub(crate) async fn restore_registered_lsps(&mut self) -> Result<(), BlocktankError> {
+        for lsp_url in self.get_registered_lsps().await? {
+            let restored = blocktank_client(&lsp_url)
+                .and_then(|client| self.register_lsp_api(&lsp_url, Arc::new(client)));
+            if let Err(e) = restored {
+                println!("Warning: Failed to register LSP {} again: {}", lsp_url, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers `api` as the LSP at `lsp_url`, e.g. a `MockLsp` in tests. Registering a URL
+    /// again replaces its client.
+    pub fn register_lsp_api(
+        &mut self,
+        lsp_url: &str,
+        api: Arc<dyn BlocktankApi>,
+    ) -> Result<(), BlocktankError> {
+        if lsp_url.is_empty() {
+            return Err(BlocktankError::InvalidParameter {
+                error_details: "The LSP URL cannot be empty".to_string(),
+            });
+        }
+        if lsp_url == self.blocktank_url {
+            return Err(BlocktankError::InvalidParameter {
+                error_details: format!("{} is the default LSP", lsp_url),
+            });
+        }
+
+        match self.lsps.iter_mut().find(|(url, _)| url == lsp_url) {
+            Some((_, client)) => *client = api,
+            None => self.lsps.push((lsp_url.to_string(), api)),
+        }
+        Ok(())
+    }
+
+    /// Registers the LSP node `lsp_node_id`, which speaks LSPS1 and LSPS2 through
+    /// `transport`. Returns the URL it is registered under, `lsps:<lsp_node_id>`.
+    pub fn register_lsps_lsp(
+        &mut self,
+        lsp_node_id: &str,
+        network: BitcoinNetworkEnum,
+        transport: Arc<dyn BtLspsTransport>,
+    ) -> Result<String, BlocktankError> {
+        PublicKey::from_str(lsp_node_id).map_err(|_| BlocktankError::InvalidParameter {
+            error_details: format!("Invalid LSP node id: {}", lsp_node_id),
+        })?;
+
+        let client = LspsClient::new(lsp_node_id, network, transport);
+        let lsp_url = client.url();
+        self.register_lsp_api(&lsp_url, Arc::new(client))?;
+        Ok(lsp_url)
+    }
+
+    /// Unregisters the LSP at `lsp_url` and forgets its stored registration. Returns whether
+    /// it was registered.
+    ///
+    /// Its stored orders and CJIT entries are kept but are skipped by refreshes until it is
+    /// registered again.
+    pub fn unregister_lsp(&mut self, lsp_url: &str) -> Result<bool, BlocktankError> {
+        let count = self.lsps.len();
+        self.lsps.retain(|(url, _)| url != lsp_url);
+        let stored = self.remove_registered_lsp(lsp_url)?;
+        Ok(self.lsps.len() != count || stored)
+    }
+
+    /// Returns the URLs of the default LSP and the registered LSPs, in registration order
+    pub fn lsp_urls(&self) -> Vec<String> {
+        std::iter::once(self.blocktank_url.clone())
+            .chain(self.lsps.iter().map(|(url, _)| url.clone()))
+            .collect()
+    }
+
+    /// Returns the client of the LSP at `lsp_url`
+    pub(crate) fn lsp_client(
+        &self,
+        lsp_url: &str,
+    ) -> Result<Arc<dyn BlocktankApi>, BlocktankError> {
+        if lsp_url == self.blocktank_url {
+            return Ok(self.client.clone());
+        }
+        self.lsps
+            .iter()
+            .find(|(url, _)| url == lsp_url)
+            .map(|(_, client)| client.clone())
+            .ok_or(BlocktankError::InvalidParameter {
+                error_details: format!("LSP {} is not registered", lsp_url),
+            })
+    }
+
+    /// Returns the client of the LSP an order or CJIT entry was created with
+    pub(crate) async fn item_client(
+        &self,
+        item_id: &str,
+        kind: BtTrackedItemKind,
+    ) -> Result<Arc<dyn BlocktankApi>, BlocktankError> {
+        self.lsp_client(&self.get_item_lsp(item_id, kind).await?)
+    }
+
+    /// Groups `item_ids` by the LSP they were created with, in order of first appearance.
+    /// Items that are not stored are grouped with the default LSP.
+    pub(crate) async fn group_by_lsp(
+        &self,
+        item_ids: &[String],
+        kind: BtTrackedItemKind,
+    ) -> Result<Vec<(String, Vec<String>)>, BlocktankError> {
+        let item_lsps = self.get_item_lsps(kind).await?;
+        let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+        for item_id in item_ids {
+            let lsp_url = item_lsps.get(item_id).unwrap_or(&self.blocktank_url);
+            match groups.iter_mut().find(|(url, _)| url == lsp_url) {
+                Some((_, ids)) => ids.push(item_id.clone()),
+                None => groups.push((lsp_url.clone(), vec![item_id.clone()])),
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Refreshes a JIT channel bought over LSPS2 from the database, as the LSP can not look
+    /// it up. It is expired once its fee parameters are no longer valid, unless the app
+    /// recorded its payment or channel with `upsert_cjit_entry`.
+    pub(crate) async fn refresh_lsps2_cjit_entry(
+        &self,
+        entry_id: &str,
+    ) -> Result<ICJitEntry, BlocktankError> {
+        let mut entry = self
+            .get_cjit_entries(Some(&[entry_id.to_string()]), None)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(BlocktankError::DataError {
+                error_details: format!("Unknown LSPS2 JIT channel {}", entry_id),
+            })?;
+
+        if lsps2_expire_entry(&mut entry, chrono::Utc::now()) {
+            self.upsert_cjit_entry(&entry).await?;
+        }
+        Ok(entry)
+    }
+
+    /// Fetches service information from the LSP at `lsp_url` and stores it in the database
+    pub async fn fetch_and_store_lsp_info(&self, lsp_url: &str) -> Result<IBtInfo, BlocktankError> {
+        if lsp_url == self.blocktank_url {
+            return self.fetch_and_store_info().await;
+        }
+
+        let info =
+            self.lsp_client(lsp_url)?
+                .get_info()
+                .await
+                .map_err(|e| BlocktankError::DataError {
+                    error_details: format!("Failed to fetch info from LSP {}: {}", lsp_url, e),
+                })?;
+
+        self.upsert_lsp_info(lsp_url, &info).await?;
+        Ok(info)
+    }
+
+    /// Creates a new order with the LSP at `lsp_url` and stores it in the database
+    pub async fn create_lsp_order(
+        &self,
+        lsp_url: &str,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtOrder, BlocktankError> {
+        let order = self
+            .lsp_client(lsp_url)?
+            .create_order(lsp_balance_sat, channel_expiry_weeks, options)
+            .await
+            .map_err(|e| BlocktankError::DataError {
+                error_details: format!("Failed to create order with LSP {}: {}", lsp_url, e),
+            })?;
+
+        self.upsert_order_with_lsp(&order, lsp_url).await?;
+        Ok(order)
+    }
+
+    /// Creates a new CJIT entry with the LSP at `lsp_url` and stores it in the database
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_lsp_cjit_entry(
+        &self,
+        lsp_url: &str,
+        channel_size_sat: u64,
+        invoice_sat: u64,
+        invoice_description: &str,
+        node_id: &str,
+        channel_expiry_weeks: u32,
+        options: Option<CreateCjitOptions>,
+    ) -> Result<ICJitEntry, BlocktankError> {
+        let entry = self
+            .lsp_client(lsp_url)?
+            .create_cjit_entry(
+                channel_size_sat,
+                invoice_sat,
+                invoice_description,
+                node_id,
+                channel_expiry_weeks,
+                options,
+            )
+            .await
+            .map_err(|e| BlocktankError::DataError {
+                error_details: format!("Failed to create CJIT entry with LSP {}: {}", lsp_url, e),
+            })?;
+
+        self.upsert_cjit_entry_with_lsp(&entry, lsp_url).await?;
+        Ok(entry)
+    }
+
+    /// Quotes a channel with every LSP whose limits allow it, cheapest first.
+    ///
+    /// Limits come from the stored info of each LSP, which is fetched if missing. LSPS LSPs,
+    /// which can not estimate fees, and LSPs whose info or estimate fails are left out, and
+    /// the first error is returned if none can quote the channel. Ties keep the registration
+    /// order, the default LSP first.
+    pub async fn compare_lsp_quotes(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<Vec<BtLspQuote>, BlocktankError> {
+        let options = options.unwrap_or_default();
+        let mut first_error = None;
+        let mut estimates = JoinSet::new();
+        for (index, lsp_url) in self.lsp_urls().into_iter().enumerate() {
+            if lsp_url.starts_with(LSPS_URL_PREFIX) {
+                continue;
+            }
+            let info = match self.get_lsp_info(&lsp_url).await? {
+                Some(info) => info,
+                None => match self.fetch_and_store_lsp_info(&lsp_url).await {
+                    Ok(info) => info,
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                        continue;
+                    }
+                },
+            };
+            if !channel_within_limits(
+                &info.options,
+                lsp_balance_sat,
+                channel_expiry_weeks,
+                &options,
+            ) {
+                continue;
+            }
+
+            let client = self.lsp_client(&lsp_url)?;
+            let options = options.clone();
+            estimates.spawn(async move {
+                let estimate = client
+                    .estimate_order_fee_full(lsp_balance_sat, channel_expiry_weeks, Some(options))
+                    .await;
+                (index, lsp_url, estimate)
+            });
+        }
+
+        let mut quotes = Vec::new();
+        while let Some(joined) = estimates.join_next().await {
+            let (index, lsp_url, estimate) =
+                joined.map_err(|e| BlocktankError::ConnectionError {
+                    error_details: format!("Runtime error: {}", e),
+                })?;
+            match estimate {
+                Ok(estimate) => quotes.push((
+                    index,
+                    BtLspQuote {
+                        lsp_url,
+                        fee_sat: estimate.fee_sat,
+                        network_fee_sat: estimate.network_fee_sat,
+                        service_fee_sat: estimate.service_fee_sat,
+                    },
+                )),
+                Err(e) => {
+                    first_error.get_or_insert(BlocktankError::DataError {
+                        error_details: format!(
+                            "Failed to estimate fee with LSP {}: {}",
+                            lsp_url, e
+                        ),
+                    });
+                }
+            }
+        }
+
+        if quotes.is_empty() {
+            return Err(first_error.unwrap_or(BlocktankError::InvalidParameter {
+                error_details: "No LSP accepts the requested channel".to_string(),
+            }));
+        }
+
+        quotes.sort_by_key(|(index, quote)| (quote.fee_sat, *index));
+        Ok(quotes.into_iter().map(|(_, quote)| quote).collect())
+    }
+
+    /// Returns the cheapest quote for a channel among the LSPs whose limits allow it
+    pub async fn select_lsp(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<BtLspQuote, BlocktankError> {
+        self.compare_lsp_quotes(lsp_balance_sat, channel_expiry_weeks, options)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(BlocktankError::InvalidParameter {
+                error_details: "No LSP accepts the requested channel".to_string(),
+            })
+    }
+}
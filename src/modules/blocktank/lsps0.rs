@@ -0,0 +1,171 @@
+//! LSPS0 transport of the LSPS1 and LSPS2 protocols.
+//!
+//! LSPS messages are JSON-RPC 2.0 requests and responses that travel between the nodes as
+//! lightning custom messages. The node is owned by the app, so it relays them through
+//! `BtLspsTransport`, and also creates the invoices of JIT channels bought over LSPS2.
+
+use crate::modules::blocktank::BlocktankError;
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::sync::Arc;
+
+/// JSON-RPC error code of a request the LSP does not know
+#[cfg(test)]
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Parameters of an invoice that makes the LSP open a JIT channel when it is paid
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtJitInvoiceParams {
+    pub lsp_node_id: String,
+    /// Intercept short channel id to use in the route hint through the LSP, as `BxTxO`
+    pub jit_channel_scid: String,
+    /// CLTV expiry delta of the route hint
+    pub cltv_expiry_delta: u32,
+    pub amount_msat: u64,
+    pub description: String,
+    /// Seconds until the invoice, and the fee parameters it was bought with, expire
+    pub expiry_secs: u64,
+}
+
+/// Connection to an LSP through the node of the app
+#[uniffi::export(with_foreign)]
+#[async_trait::async_trait]
+pub trait BtLspsTransport: Send + Sync {
+    /// Sends a JSON-RPC `request` to the LSP node `lsp_node_id` as LSPS0 custom messages and
+    /// returns the response with the same id
+    async fn send_request(
+        &self,
+        lsp_node_id: String,
+        request: String,
+    ) -> Result<String, BlocktankError>;
+
+    /// Creates an invoice with a route hint through the LSP for `params`, as LDK's
+    /// `LSPS2ClientHandler` does, and returns it
+    async fn create_jit_invoice(
+        &self,
+        params: BtJitInvoiceParams,
+    ) -> Result<String, BlocktankError>;
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct JsonRpcRequest<P> {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: P,
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct JsonRpcResponse<R> {
+    pub jsonrpc: String,
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<R>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+#[cfg(test)]
+impl<R> JsonRpcResponse<R> {
+    pub(crate) fn result(id: String, result: R) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub(crate) fn error(id: Option<String>, code: i64, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message: message.into(),
+                data: None,
+            }),
+        }
+    }
+
+    pub(crate) fn method_not_found(id: String, method: &str) -> Self {
+        Self::error(
+            Some(id),
+            METHOD_NOT_FOUND,
+            format!("Method not found: {}", method),
+        )
+    }
+}
+
+/// (De)serializes `LSPS0.sat` and `LSPS0.msat` amounts, which are JSON strings
+pub(crate) mod amount_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(amount: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Sends JSON-RPC requests to one LSP through a `BtLspsTransport`
+pub(crate) struct LspsRpc {
+    pub lsp_node_id: String,
+    pub transport: Arc<dyn BtLspsTransport>,
+}
+
+impl LspsRpc {
+    /// Calls `method` on the LSP and returns its result
+    pub async fn call<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> Result<R, BlocktankError> {
+        let mut id = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut id);
+        let id = hex::encode(id);
+        let request = serde_json::to_string(&JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: id.clone(),
+        })?;
+
+        let response = self
+            .transport
+            .send_request(self.lsp_node_id.clone(), request)
+            .await?;
+        let response: JsonRpcResponse<R> =
+            serde_json::from_str(&response).map_err(|e| BlocktankError::DataError {
+                error_details: format!("Invalid {} response: {}", method, e),
+            })?;
+
+        if response.id.as_deref() != Some(id.as_str()) {
+            return Err(BlocktankError::DataError {
+                error_details: format!("Response to {} has a different id", method),
+            });
+        }
+        if let Some(error) = response.error {
+            return Err(BlocktankError::BlocktankClient {
+                error_details: format!("{} failed with {}: {}", method, error.code, error.message),
+            });
+        }
+        response.result.ok_or(BlocktankError::DataError {
+            error_details: format!("Response to {} has no result", method),
+        })
+    }
+}
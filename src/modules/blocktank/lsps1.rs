@@ -0,0 +1,395 @@
+//! LSPS1 (bLIP-51) channel requests.
+//!
+//! LSPS1 orders are converted to `IBtOrder`, so they are stored and tracked like Blocktank
+//! orders. LSPS1 has no fee breakdown, so the whole fee is reported as the service fee, and
+//! channels are opened by the LSP once the order is paid.
+
+use crate::modules::blocktank::lsps0::amount_string;
+use crate::modules::blocktank::BlocktankError;
+use rust_blocktank_client::{
+    BitcoinNetworkEnum, BtBolt11InvoiceState, BtOpenChannelState, BtOrderState, BtOrderState2,
+    BtPaymentState, BtPaymentState2, CreateOrderOptions, FeeRates, FundingTx, IBtBolt11Invoice,
+    IBtChannel, IBtInfo, IBtInfoOnchain, IBtInfoOptions, IBtInfoVersions, IBtOnchainTransactions,
+    IBtOrder, IBtPayment, ILspNode,
+};
+use serde::{Deserialize, Serialize};
+
+/// Blocks per week of channel lifetime
+pub(crate) const BLOCKS_PER_WEEK: u32 = 1_008;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1Options {
+    pub min_required_channel_confirmations: u16,
+    pub min_funding_confirms_within_blocks: u16,
+    pub supports_zero_channel_reserve: bool,
+    pub max_channel_expiry_blocks: u32,
+    #[serde(with = "amount_string")]
+    pub min_initial_client_balance_sat: u64,
+    #[serde(with = "amount_string")]
+    pub max_initial_client_balance_sat: u64,
+    #[serde(with = "amount_string")]
+    pub min_initial_lsp_balance_sat: u64,
+    #[serde(with = "amount_string")]
+    pub max_initial_lsp_balance_sat: u64,
+    #[serde(with = "amount_string")]
+    pub min_channel_balance_sat: u64,
+    #[serde(with = "amount_string")]
+    pub max_channel_balance_sat: u64,
+}
+
+/// Result of `lsps1.get_info`. Older LSPs return the options directly instead of nested
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Lsps1GetInfoResponse {
+    Nested { options: Lsps1Options },
+    Flat(Lsps1Options),
+}
+
+impl Lsps1GetInfoResponse {
+    pub fn into_options(self) -> Lsps1Options {
+        match self {
+            Self::Nested { options } | Self::Flat(options) => options,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1CreateOrderRequest {
+    #[serde(with = "amount_string")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "amount_string")]
+    pub client_balance_sat: u64,
+    pub required_channel_confirmations: u16,
+    pub funding_confirms_within_blocks: u16,
+    pub channel_expiry_blocks: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_onchain_address: Option<String>,
+    pub announce_channel: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1GetOrderRequest {
+    pub order_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum Lsps1OrderState {
+    Created,
+    Completed,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum Lsps1PaymentState {
+    ExpectPayment,
+    Hold,
+    Paid,
+    Refunded,
+}
+
+impl Lsps1PaymentState {
+    fn is_paid(self) -> bool {
+        matches!(self, Self::Hold | Self::Paid)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1Bolt11Payment {
+    pub state: Lsps1PaymentState,
+    pub expires_at: String,
+    #[serde(with = "amount_string")]
+    pub fee_total_sat: u64,
+    #[serde(with = "amount_string")]
+    pub order_total_sat: u64,
+    pub invoice: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1OnchainPayment {
+    pub state: Lsps1PaymentState,
+    pub expires_at: String,
+    #[serde(with = "amount_string")]
+    pub fee_total_sat: u64,
+    #[serde(with = "amount_string")]
+    pub order_total_sat: u64,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_onchain_payment_confirmations: Option<u16>,
+    /// Fee rate in sat/vbyte a payment needs to be accepted without confirmations
+    pub min_fee_for_0conf: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_onchain_address: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1Payment {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bolt11: Option<Lsps1Bolt11Payment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub onchain: Option<Lsps1OnchainPayment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1Channel {
+    pub funded_at: String,
+    /// `txid:vout`
+    pub funding_outpoint: String,
+    pub expires_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps1Order {
+    pub order_id: String,
+    #[serde(with = "amount_string")]
+    pub lsp_balance_sat: u64,
+    #[serde(with = "amount_string")]
+    pub client_balance_sat: u64,
+    pub required_channel_confirmations: u16,
+    pub funding_confirms_within_blocks: u16,
+    pub channel_expiry_blocks: u32,
+    #[serde(default)]
+    pub token: String,
+    pub created_at: String,
+    pub announce_channel: bool,
+    pub order_state: Lsps1OrderState,
+    pub payment: Lsps1Payment,
+    #[serde(default)]
+    pub channel: Option<Lsps1Channel>,
+}
+
+/// Returns the service information of an LSPS1 LSP, as far as LSPS1 advertises it.
+///
+/// There are no on-chain payment confirmation limits, so they are 0, and zero-conf
+/// channels are only allowed if the LSP requires no channel confirmations.
+pub(crate) fn lsps1_info(
+    options: &Lsps1Options,
+    lsp_node_id: &str,
+    network: BitcoinNetworkEnum,
+) -> IBtInfo {
+    let max_0_conf_client_balance_sat = if options.min_required_channel_confirmations == 0 {
+        options.max_initial_client_balance_sat
+    } else {
+        0
+    };
+
+    IBtInfo {
+        version: 0,
+        nodes: vec![lsps_node(lsp_node_id)],
+        options: IBtInfoOptions {
+            min_channel_size_sat: options.min_channel_balance_sat,
+            max_channel_size_sat: options.max_channel_balance_sat,
+            min_expiry_weeks: 1,
+            max_expiry_weeks: options.max_channel_expiry_blocks / BLOCKS_PER_WEEK,
+            min_payment_confirmations: 0,
+            min_high_risk_payment_confirmations: 0,
+            max_0_conf_client_balance_sat,
+            max_client_balance_sat: options.max_initial_client_balance_sat,
+        },
+        versions: IBtInfoVersions {
+            http: String::new(),
+            btc: String::new(),
+            ln2: String::new(),
+        },
+        onchain: IBtInfoOnchain {
+            network,
+            fee_rates: FeeRates {
+                fast: 0,
+                mid: 0,
+                slow: 0,
+            },
+        },
+    }
+}
+
+/// Returns the `lsps1.create_order` request for a Blocktank order.
+///
+/// Zero-conf orders ask for as few channel confirmations as the LSP allows, other orders
+/// for at least one. The coupon code is sent as the LSPS1 token.
+pub(crate) fn lsps1_order_request(
+    lsp_balance_sat: u64,
+    channel_expiry_weeks: u32,
+    options: &CreateOrderOptions,
+    lsp_options: &Lsps1Options,
+) -> Lsps1CreateOrderRequest {
+    let required_channel_confirmations = if options.zero_conf {
+        lsp_options.min_required_channel_confirmations
+    } else {
+        lsp_options.min_required_channel_confirmations.max(1)
+    };
+
+    Lsps1CreateOrderRequest {
+        lsp_balance_sat,
+        client_balance_sat: options.client_balance_sat,
+        required_channel_confirmations,
+        funding_confirms_within_blocks: lsp_options.min_funding_confirms_within_blocks,
+        channel_expiry_blocks: channel_expiry_weeks.saturating_mul(BLOCKS_PER_WEEK),
+        token: Some(options.coupon_code.clone()).filter(|code| !code.is_empty()),
+        refund_onchain_address: options.refund_onchain_address.clone(),
+        announce_channel: options.announce_channel,
+    }
+}
+
+/// Converts an LSPS1 order of the LSP `lsp_node_id` to a Blocktank order
+pub(crate) fn lsps1_order_to_bt(
+    order: Lsps1Order,
+    lsp_node_id: &str,
+) -> Result<IBtOrder, BlocktankError> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let bolt11 = order.payment.bolt11.as_ref();
+    let onchain = order.payment.onchain.as_ref();
+    let states: Vec<Lsps1PaymentState> = bolt11
+        .map(|payment| payment.state)
+        .into_iter()
+        .chain(onchain.map(|payment| payment.state))
+        .collect();
+    let (fee_total_sat, order_total_sat, payment_expires_at) = match (bolt11, onchain) {
+        (Some(payment), _) => (
+            payment.fee_total_sat,
+            payment.order_total_sat,
+            payment.expires_at.clone(),
+        ),
+        (None, Some(payment)) => (
+            payment.fee_total_sat,
+            payment.order_total_sat,
+            payment.expires_at.clone(),
+        ),
+        (None, None) => {
+            return Err(BlocktankError::DataError {
+                error_details: format!("LSPS1 order {} has no payment", order.order_id),
+            })
+        }
+    };
+    let refunded = states.contains(&Lsps1PaymentState::Refunded);
+    let paid = states.iter().any(|state| state.is_paid());
+
+    let (payment_state, payment_state2) = if refunded {
+        (BtPaymentState::Refunded, BtPaymentState2::Refunded)
+    } else if paid {
+        (BtPaymentState::Paid, BtPaymentState2::Paid)
+    } else {
+        (BtPaymentState::Created, BtPaymentState2::Created)
+    };
+
+    let channel = order
+        .channel
+        .as_ref()
+        .map(|channel| {
+            let (tx_id, vout) = channel
+                .funding_outpoint
+                .split_once(':')
+                .and_then(|(tx_id, vout)| Some((tx_id, vout.parse().ok()?)))
+                .ok_or(BlocktankError::DataError {
+                    error_details: format!(
+                        "Invalid funding outpoint {} of LSPS1 order {}",
+                        channel.funding_outpoint, order.order_id
+                    ),
+                })?;
+            Ok::<_, BlocktankError>(IBtChannel {
+                state: match order.order_state {
+                    Lsps1OrderState::Created => BtOpenChannelState::Opening,
+                    Lsps1OrderState::Completed => BtOpenChannelState::Open,
+                    Lsps1OrderState::Failed => BtOpenChannelState::Closed,
+                },
+                lsp_node_pubkey: lsp_node_id.to_string(),
+                client_node_pubkey: String::new(),
+                announce_channel: order.announce_channel,
+                funding_tx: FundingTx {
+                    id: tx_id.to_string(),
+                    vout,
+                },
+                closing_tx_id: None,
+                close: None,
+                short_channel_id: None,
+            })
+        })
+        .transpose()?;
+
+    let (state, state2) = match order.order_state {
+        Lsps1OrderState::Failed => (BtOrderState::Expired, BtOrderState2::Expired),
+        Lsps1OrderState::Completed => (BtOrderState::Open, BtOrderState2::Executed),
+        Lsps1OrderState::Created if channel.is_some() => {
+            (BtOrderState::Created, BtOrderState2::Executed)
+        }
+        Lsps1OrderState::Created if paid => (BtOrderState::Created, BtOrderState2::Paid),
+        Lsps1OrderState::Created => (BtOrderState::Created, BtOrderState2::Created),
+    };
+
+    let channel_lifetime_secs = i64::from(order.channel_expiry_blocks) * 600;
+    let channel_expires_at = match &order.channel {
+        Some(channel) => channel.expires_at.clone(),
+        None => chrono::DateTime::parse_from_rfc3339(&order.created_at)
+            .map(|created_at| {
+                (created_at + chrono::Duration::seconds(channel_lifetime_secs)).to_rfc3339()
+            })
+            .unwrap_or_else(|_| order.created_at.clone()),
+    };
+
+    Ok(IBtOrder {
+        id: order.order_id,
+        state,
+        state2: Some(state2),
+        fee_sat: order_total_sat,
+        network_fee_sat: 0,
+        service_fee_sat: fee_total_sat,
+        lsp_balance_sat: order.lsp_balance_sat,
+        client_balance_sat: order.client_balance_sat,
+        zero_conf: order.required_channel_confirmations == 0,
+        zero_reserve: false,
+        client_node_id: None,
+        channel_expiry_weeks: order.channel_expiry_blocks.div_ceil(BLOCKS_PER_WEEK),
+        channel_expires_at,
+        order_expires_at: payment_expires_at,
+        channel,
+        lsp_node: Some(lsps_node(lsp_node_id)),
+        lnurl: None,
+        payment: Some(IBtPayment {
+            state: payment_state,
+            state2: Some(payment_state2),
+            paid_sat: if paid { order_total_sat } else { 0 },
+            bolt11_invoice: bolt11.map(|payment| IBtBolt11Invoice {
+                request: payment.invoice.clone(),
+                state: match payment.state {
+                    Lsps1PaymentState::ExpectPayment => BtBolt11InvoiceState::Pending,
+                    Lsps1PaymentState::Hold => BtBolt11InvoiceState::Holding,
+                    Lsps1PaymentState::Paid => BtBolt11InvoiceState::Paid,
+                    Lsps1PaymentState::Refunded => BtBolt11InvoiceState::Canceled,
+                },
+                expires_at: payment.expires_at.clone(),
+                updated_at: now.clone(),
+            }),
+            onchain: onchain.map(|payment| IBtOnchainTransactions {
+                address: payment.address.clone(),
+                confirmed_sat: if payment.state.is_paid() {
+                    payment.order_total_sat
+                } else {
+                    0
+                },
+                required_confirmations: payment
+                    .min_onchain_payment_confirmations
+                    .map_or(0, u32::from),
+                transactions: Vec::new(),
+            }),
+            is_manually_paid: None,
+            manual_refunds: None,
+        }),
+        coupon_code: Some(order.token).filter(|token| !token.is_empty()),
+        source: Some("lsps1".to_string()),
+        discount: None,
+        updated_at: now,
+        created_at: order.created_at,
+    })
+}
+
+/// Returns the node of an LSPS LSP, which is only known by its id
+pub(crate) fn lsps_node(lsp_node_id: &str) -> ILspNode {
+    ILspNode {
+        alias: String::new(),
+        pubkey: lsp_node_id.to_string(),
+        connection_strings: Vec::new(),
+        readonly: None,
+    }
+}
@@ -0,0 +1,158 @@
+//! LSPS2 (bLIP-52) JIT channels.
+//!
+//! A JIT channel is bought for one payment: the LSP returns an intercept short channel id,
+//! the node puts it in the route hint of an invoice, and the LSP opens the channel when the
+//! invoice is paid, keeping its opening fee from the payment. This maps onto CJIT entries,
+//! with the fee taken from the first fee menu entry that fits the payment.
+
+use crate::modules::blocktank::lsps0::amount_string;
+use crate::modules::blocktank::lsps1::{lsps_node, BLOCKS_PER_WEEK};
+use crate::modules::blocktank::BlocktankError;
+use chrono::{DateTime, Utc};
+use rust_blocktank_client::{BtBolt11InvoiceState, CJitStateEnum, IBtBolt11Invoice, ICJitEntry};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps2GetInfoRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps2OpeningFeeParams {
+    #[serde(with = "amount_string")]
+    pub min_fee_msat: u64,
+    /// Parts per million of the payment
+    pub proportional: u32,
+    pub valid_until: String,
+    /// Blocks the LSP keeps the channel open at least
+    pub min_lifetime: u32,
+    pub max_client_to_self_delay: u32,
+    #[serde(with = "amount_string")]
+    pub min_payment_size_msat: u64,
+    #[serde(with = "amount_string")]
+    pub max_payment_size_msat: u64,
+    /// Signature of the LSP over the other fields, returned unchanged in `lsps2.buy`
+    pub promise: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps2GetInfoResponse {
+    pub opening_fee_params_menu: Vec<Lsps2OpeningFeeParams>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps2BuyRequest {
+    pub opening_fee_params: Lsps2OpeningFeeParams,
+    #[serde(with = "amount_string")]
+    pub payment_size_msat: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Lsps2BuyResponse {
+    pub jit_channel_scid: String,
+    pub lsp_cltv_expiry_delta: u32,
+    #[serde(default)]
+    pub client_trusts_lsp: bool,
+}
+
+/// Returns the opening fee of a payment, `None` on overflow
+pub(crate) fn lsps2_opening_fee_msat(
+    params: &Lsps2OpeningFeeParams,
+    payment_size_msat: u64,
+) -> Option<u64> {
+    let proportional_fee_msat = u128::from(payment_size_msat)
+        .checked_mul(u128::from(params.proportional))?
+        .div_ceil(1_000_000);
+    u64::try_from(proportional_fee_msat)
+        .ok()
+        .map(|fee_msat| fee_msat.max(params.min_fee_msat))
+}
+
+/// Returns the first entry of the fee menu that is still valid at `now`, fits the payment
+/// and keeps the channel open for `channel_expiry_weeks`, with its opening fee
+pub(crate) fn lsps2_select_fee_params(
+    menu: &[Lsps2OpeningFeeParams],
+    payment_size_msat: u64,
+    channel_expiry_weeks: u32,
+    now: DateTime<Utc>,
+) -> Result<(Lsps2OpeningFeeParams, u64), BlocktankError> {
+    let min_lifetime = channel_expiry_weeks.saturating_mul(BLOCKS_PER_WEEK);
+    menu.iter()
+        .filter(|params| {
+            DateTime::parse_from_rfc3339(&params.valid_until)
+                .is_ok_and(|valid_until| valid_until > now)
+                && (params.min_payment_size_msat..=params.max_payment_size_msat)
+                    .contains(&payment_size_msat)
+                && params.min_lifetime >= min_lifetime
+        })
+        .find_map(|params| {
+            let fee_msat = lsps2_opening_fee_msat(params, payment_size_msat)?;
+            (fee_msat < payment_size_msat).then(|| (params.clone(), fee_msat))
+        })
+        .ok_or(BlocktankError::InvalidParameter {
+            error_details: format!(
+                "No LSPS2 fee parameters allow a payment of {} msat for {} weeks",
+                payment_size_msat, channel_expiry_weeks
+            ),
+        })
+}
+
+/// Returns the CJIT entry of a bought JIT channel, identified by its intercept scid
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn lsps2_cjit_entry(
+    lsp_node_id: &str,
+    params: &Lsps2OpeningFeeParams,
+    fee_msat: u64,
+    jit_channel_scid: String,
+    invoice: String,
+    channel_size_sat: u64,
+    channel_expiry_weeks: u32,
+    node_id: &str,
+    token: Option<String>,
+) -> ICJitEntry {
+    let now = Utc::now().to_rfc3339();
+    let fee_sat = fee_msat.div_ceil(1_000);
+
+    ICJitEntry {
+        id: jit_channel_scid,
+        state: CJitStateEnum::Created,
+        fee_sat,
+        network_fee_sat: 0,
+        service_fee_sat: fee_sat,
+        channel_size_sat,
+        channel_expiry_weeks,
+        channel_open_error: None,
+        node_id: node_id.to_string(),
+        invoice: IBtBolt11Invoice {
+            request: invoice,
+            state: BtBolt11InvoiceState::Pending,
+            expires_at: params.valid_until.clone(),
+            updated_at: now.clone(),
+        },
+        channel: None,
+        lsp_node: lsps_node(lsp_node_id),
+        coupon_code: token.unwrap_or_default(),
+        source: Some("lsps2".to_string()),
+        discount: None,
+        expires_at: params.valid_until.clone(),
+        updated_at: now.clone(),
+        created_at: now,
+    }
+}
+
+/// Expires a JIT channel whose fee parameters are no longer valid at `now`, unless it was
+/// paid or its channel opened. Returns whether the entry changed.
+pub(crate) fn lsps2_expire_entry(entry: &mut ICJitEntry, now: DateTime<Utc>) -> bool {
+    let expired =
+        DateTime::parse_from_rfc3339(&entry.expires_at).is_ok_and(|expires_at| expires_at <= now);
+    let pending = entry.state == CJitStateEnum::Created
+        && entry.invoice.state == BtBolt11InvoiceState::Pending
+        && entry.channel.is_none();
+    if !(expired && pending) {
+        return false;
+    }
+    entry.state = CJitStateEnum::Expired;
+    entry.updated_at = now.to_rfc3339();
+    true
+}
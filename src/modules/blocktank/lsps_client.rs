@@ -0,0 +1,328 @@
+//! An LSPS1 and LSPS2 LSP behind the Blocktank API.
+//!
+//! `LspsClient` implements `BlocktankApi` over `BtLspsTransport`, so an LSP reached through
+//! the node is registered like any other LSP and its orders and CJIT entries are stored and
+//! tracked like Blocktank ones. Calls with no LSPS counterpart, like fee estimates, JIT
+//! channel lookups, regtest helpers, push notifications and gifts, fail. `BlocktankDB`
+//! refreshes the JIT channels from the database instead.
+
+use crate::modules::blocktank::lsps0::{BtJitInvoiceParams, BtLspsTransport, LspsRpc};
+use crate::modules::blocktank::lsps1::{
+    lsps1_info, lsps1_order_request, lsps1_order_to_bt, Lsps1GetInfoResponse, Lsps1GetOrderRequest,
+    Lsps1Options, Lsps1Order,
+};
+use crate::modules::blocktank::lsps2::{
+    lsps2_cjit_entry, lsps2_select_fee_params, Lsps2BuyRequest, Lsps2BuyResponse,
+    Lsps2GetInfoRequest, Lsps2GetInfoResponse,
+};
+use crate::modules::blocktank::{BlocktankApi, BlocktankError};
+use async_trait::async_trait;
+use rust_blocktank_client::{
+    BitcoinNetworkEnum, CreateCjitOptions, CreateOrderOptions, IBt0ConfMinTxFeeWindow,
+    IBtBolt11Invoice, IBtEstimateFeeResponse, IBtEstimateFeeResponse2, IBtInfo, IBtOrder,
+    ICJitEntry, IGift,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// Prefix of the URL an LSPS LSP is registered under, followed by its node id
+pub const LSPS_URL_PREFIX: &str = "lsps:";
+
+fn unsupported(method: &str) -> BlocktankError {
+    BlocktankError::BlocktankClient {
+        error_details: format!("{} is not supported by LSPS LSPs", method),
+    }
+}
+
+/// Client of an LSP speaking LSPS1 and LSPS2 through the node of the app
+pub struct LspsClient {
+    rpc: LspsRpc,
+    network: BitcoinNetworkEnum,
+}
+
+impl LspsClient {
+    pub fn new(
+        lsp_node_id: &str,
+        network: BitcoinNetworkEnum,
+        transport: Arc<dyn BtLspsTransport>,
+    ) -> Self {
+        Self {
+            rpc: LspsRpc {
+                lsp_node_id: lsp_node_id.to_string(),
+                transport,
+            },
+            network,
+        }
+    }
+
+    /// Returns the URL the LSP is registered under
+    pub fn url(&self) -> String {
+        format!("{}{}", LSPS_URL_PREFIX, self.rpc.lsp_node_id)
+    }
+
+    async fn lsps1_options(&self) -> Result<Lsps1Options, BlocktankError> {
+        let response: Lsps1GetInfoResponse = self.rpc.call("lsps1.get_info", json!({})).await?;
+        Ok(response.into_options())
+    }
+
+    async fn lsps1_order(&self, order_id: &str) -> Result<Lsps1Order, BlocktankError> {
+        self.rpc
+            .call(
+                "lsps1.get_order",
+                Lsps1GetOrderRequest {
+                    order_id: order_id.to_string(),
+                },
+            )
+            .await
+    }
+}
+
+#[async_trait]
+impl BlocktankApi for LspsClient {
+    async fn get_info(&self) -> Result<IBtInfo, BlocktankError> {
+        let options = self.lsps1_options().await?;
+        Ok(lsps1_info(
+            &options,
+            &self.rpc.lsp_node_id,
+            self.network.clone(),
+        ))
+    }
+
+    async fn create_order(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtOrder, BlocktankError> {
+        let lsp_options = self.lsps1_options().await?;
+        let request = lsps1_order_request(
+            lsp_balance_sat,
+            channel_expiry_weeks,
+            &options.unwrap_or_default(),
+            &lsp_options,
+        );
+        let order: Lsps1Order = self.rpc.call("lsps1.create_order", request).await?;
+        lsps1_order_to_bt(order, &self.rpc.lsp_node_id)
+    }
+
+    /// The LSP opens the channel by itself once the order is paid, so this only returns the
+    /// current order
+    async fn open_channel(
+        &self,
+        order_id: &str,
+        _connection_string: &str,
+    ) -> Result<IBtOrder, BlocktankError> {
+        lsps1_order_to_bt(self.lsps1_order(order_id).await?, &self.rpc.lsp_node_id)
+    }
+
+    async fn get_orders(&self, order_ids: &[String]) -> Result<Vec<IBtOrder>, BlocktankError> {
+        let mut orders = Vec::with_capacity(order_ids.len());
+        for order_id in order_ids {
+            orders.push(lsps1_order_to_bt(
+                self.lsps1_order(order_id).await?,
+                &self.rpc.lsp_node_id,
+            )?);
+        }
+        Ok(orders)
+    }
+
+    async fn get_min_zero_conf_tx_fee(
+        &self,
+        order_id: &str,
+    ) -> Result<IBt0ConfMinTxFeeWindow, BlocktankError> {
+        let onchain =
+            self.lsps1_order(order_id)
+                .await?
+                .payment
+                .onchain
+                .ok_or(BlocktankError::DataError {
+                    error_details: format!("LSPS1 order {} has no on-chain payment", order_id),
+                })?;
+        Ok(IBt0ConfMinTxFeeWindow {
+            sat_per_vbyte: f64::from(onchain.min_fee_for_0conf),
+            validity_ends_at: onchain.expires_at,
+        })
+    }
+
+    async fn estimate_order_fee(
+        &self,
+        _lsp_balance_sat: u64,
+        _channel_expiry_weeks: u32,
+        _options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse, BlocktankError> {
+        Err(unsupported("Estimating order fees"))
+    }
+
+    async fn estimate_order_fee_full(
+        &self,
+        _lsp_balance_sat: u64,
+        _channel_expiry_weeks: u32,
+        _options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse2, BlocktankError> {
+        Err(unsupported("Estimating order fees"))
+    }
+
+    /// Buys a JIT channel over LSPS2 and has the node create its invoice. The LSP picks the
+    /// channel size, so `channel_size_sat` is only recorded.
+    async fn create_cjit_entry(
+        &self,
+        channel_size_sat: u64,
+        invoice_sat: u64,
+        invoice_description: &str,
+        node_id: &str,
+        channel_expiry_weeks: u32,
+        options: Option<CreateCjitOptions>,
+    ) -> Result<ICJitEntry, BlocktankError> {
+        let token = options
+            .and_then(|options| options.discount_code)
+            .filter(|code| !code.is_empty());
+        let menu: Lsps2GetInfoResponse = self
+            .rpc
+            .call(
+                "lsps2.get_info",
+                Lsps2GetInfoRequest {
+                    token: token.clone(),
+                },
+            )
+            .await?;
+
+        let payment_size_msat =
+            invoice_sat
+                .checked_mul(1_000)
+                .ok_or(BlocktankError::InvalidParameter {
+                    error_details: format!("Invoice amount {} sat is too large", invoice_sat),
+                })?;
+        let now = chrono::Utc::now();
+        let (params, fee_msat) = lsps2_select_fee_params(
+            &menu.opening_fee_params_menu,
+            payment_size_msat,
+            channel_expiry_weeks,
+            now,
+        )?;
+        let expiry_secs = chrono::DateTime::parse_from_rfc3339(&params.valid_until)
+            .map(|valid_until| (valid_until.with_timezone(&chrono::Utc) - now).num_seconds())
+            .unwrap_or_default()
+            .max(0) as u64;
+
+        let bought: Lsps2BuyResponse = self
+            .rpc
+            .call(
+                "lsps2.buy",
+                Lsps2BuyRequest {
+                    opening_fee_params: params.clone(),
+                    payment_size_msat,
+                },
+            )
+            .await?;
+        let invoice = self
+            .rpc
+            .transport
+            .create_jit_invoice(BtJitInvoiceParams {
+                lsp_node_id: self.rpc.lsp_node_id.clone(),
+                jit_channel_scid: bought.jit_channel_scid.clone(),
+                cltv_expiry_delta: bought.lsp_cltv_expiry_delta,
+                amount_msat: payment_size_msat,
+                description: invoice_description.to_string(),
+                expiry_secs,
+            })
+            .await?;
+
+        Ok(lsps2_cjit_entry(
+            &self.rpc.lsp_node_id,
+            &params,
+            fee_msat,
+            bought.jit_channel_scid,
+            invoice,
+            channel_size_sat,
+            channel_expiry_weeks,
+            node_id,
+            token,
+        ))
+    }
+
+    /// LSPS2 has no way to look a JIT channel up again, `BlocktankDB` refreshes them from
+    /// the database
+    async fn get_cjit_entry(&self, _entry_id: &str) -> Result<ICJitEntry, BlocktankError> {
+        Err(unsupported("Looking up JIT channels"))
+    }
+
+    async fn regtest_mine(&self, _count: Option<u32>) -> Result<(), BlocktankError> {
+        Err(unsupported("Regtest mining"))
+    }
+
+    async fn regtest_deposit(
+        &self,
+        _address: &str,
+        _amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        Err(unsupported("Regtest deposits"))
+    }
+
+    async fn regtest_pay(
+        &self,
+        _invoice: &str,
+        _amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        Err(unsupported("Regtest payments"))
+    }
+
+    async fn regtest_get_payment(
+        &self,
+        _payment_id: &str,
+    ) -> Result<IBtBolt11Invoice, BlocktankError> {
+        Err(unsupported("Regtest payments"))
+    }
+
+    async fn regtest_close_channel(
+        &self,
+        _funding_tx_id: &str,
+        _vout: u32,
+        _force_close_after_s: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        Err(unsupported("Regtest channel closes"))
+    }
+
+    async fn register_device(
+        &self,
+        _device_token: &str,
+        _public_key: &str,
+        _features: &[String],
+        _node_id: &str,
+        _iso_timestamp: &str,
+        _signature: &str,
+        _is_production: Option<bool>,
+        _custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError> {
+        Err(unsupported("Push notifications"))
+    }
+
+    async fn test_notification(
+        &self,
+        _device_token: &str,
+        _secret_message: &str,
+        _notification_type: Option<&str>,
+        _custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError> {
+        Err(unsupported("Push notifications"))
+    }
+
+    async fn gift_pay(&self, _invoice: &str) -> Result<IGift, BlocktankError> {
+        Err(unsupported("Gifts"))
+    }
+
+    async fn gift_order(
+        &self,
+        _client_node_id: &str,
+        _code: &str,
+    ) -> Result<IGift, BlocktankError> {
+        Err(unsupported("Gifts"))
+    }
+
+    async fn get_gift(&self, _gift_id: &str) -> Result<IGift, BlocktankError> {
+        Err(unsupported("Gifts"))
+    }
+
+    async fn get_payment(&self, _payment_id: &str) -> Result<IBtBolt11Invoice, BlocktankError> {
+        Err(unsupported("Payment lookups"))
+    }
+}
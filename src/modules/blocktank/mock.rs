@@ -0,0 +1,1028 @@
+//! Deterministic in-memory LSP implementing [`BlocktankApi`].
+//!
+//! Time only moves through [`MockLsp::advance`] and `regtest_mine`, so order payment, channel
+//! opening and expiry can be driven step by step without network access.
+
+use crate::modules::blocktank::{
+    encrypt_notification, BlocktankApi, BlocktankError, BtChannelOrderErrorType,
+    BtEncryptedNotification,
+};
+use async_trait::async_trait;
+use rust_blocktank_client::{
+    BitcoinNetworkEnum, BtBolt11InvoiceState, BtOpenChannelState, BtOrderState, BtOrderState2,
+    BtPaymentState, BtPaymentState2, CJitStateEnum, CreateCjitOptions, CreateOrderOptions,
+    FeeRates, FundingTx, IBt0ConfMinTxFeeWindow, IBtBolt11Invoice, IBtChannel, IBtChannelClose,
+    IBtEstimateFeeResponse, IBtEstimateFeeResponse2, IBtInfo, IBtInfoOnchain, IBtInfoOptions,
+    IBtInfoVersions, IBtOnchainTransactions, IBtOrder, IBtPayment, ICJitEntry, IGift, ILspNode,
+    IManualRefund, ManualRefundStateEnum,
+};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const MOCK_LSP_PUBKEY: &str =
+    "0296b2db342fcf87ea94d981757fdf4d3e545bd5cef4919f58b5d38dfdd73bf5c9";
+pub const MOCK_LSP_CONNECTION_STRING: &str =
+    "0296b2db342fcf87ea94d981757fdf4d3e545bd5cef4919f58b5d38dfdd73bf5c9@127.0.0.1:9735";
+/// 2024-01-01T00:00:00Z
+pub const MOCK_LSP_START_TIME: i64 = 1_704_067_200;
+pub const MOCK_LSP_START_HEIGHT: u32 = 800_000;
+
+const BLOCK_INTERVAL_SECS: i64 = 600;
+const ZERO_CONF_FEE_WINDOW_SECS: i64 = 600;
+const GIFT_LSP_BALANCE_SAT: u64 = 100_000;
+const GIFT_CHANNEL_EXPIRY_WEEKS: u32 = 6;
+
+#[derive(Debug, Clone)]
+pub struct MockLspConfig {
+    /// Time an unpaid order or CJIT entry stays payable
+    pub order_expiry_secs: i64,
+    /// Time between the channel funding broadcast and the channel being usable
+    pub channel_open_delay_secs: i64,
+    pub network_fee_sat: u64,
+    /// Service fee in parts per million of the channel size, per week of channel lifetime
+    pub service_fee_ppm_per_week: u64,
+    pub min_0_conf_fee_rate: f64,
+    pub options: IBtInfoOptions,
+    /// Prefix of order, CJIT entry and gift ids, to keep ids apart when several mocks
+    /// stand in for different LSPs
+    pub id_prefix: String,
+}
+
+impl Default for MockLspConfig {
+    fn default() -> Self {
+        Self {
+            order_expiry_secs: 3_600,
+            channel_open_delay_secs: BLOCK_INTERVAL_SECS,
+            network_fee_sat: 2_000,
+            service_fee_ppm_per_week: 1_000,
+            min_0_conf_fee_rate: 2.0,
+            options: IBtInfoOptions {
+                min_channel_size_sat: 20_000,
+                max_channel_size_sat: 10_000_000,
+                min_expiry_weeks: 2,
+                max_expiry_weeks: 52,
+                min_payment_confirmations: 1,
+                min_high_risk_payment_confirmations: 6,
+                max_0_conf_client_balance_sat: 100_000,
+                max_client_balance_sat: 1_000_000,
+            },
+            id_prefix: "mock".to_string(),
+        }
+    }
+}
+
+struct MockChannelRequest {
+    opened_at: i64,
+    funding_tx_id: String,
+}
+
+struct MockOrder {
+    order: IBtOrder,
+    expires_at: i64,
+    channel_request: Option<MockChannelRequest>,
+}
+
+struct MockCjitEntry {
+    entry: ICJitEntry,
+    expires_at: i64,
+    channel_request: Option<MockChannelRequest>,
+}
+
+/// Latest registration of a device for push notifications
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockDevice {
+    pub public_key: String,
+    pub features: Vec<String>,
+    pub node_id: String,
+    pub iso_timestamp: String,
+    pub signature: String,
+    /// Number of times the device was registered
+    pub registrations: u32,
+}
+
+struct MockState {
+    now: i64,
+    block_height: u32,
+    next_id: u64,
+    unreachable: bool,
+    orders: BTreeMap<String, MockOrder>,
+    cjit_entries: BTreeMap<String, MockCjitEntry>,
+    /// Payment id to invoice
+    payments: BTreeMap<String, IBtBolt11Invoice>,
+    gifts: BTreeMap<String, IGift>,
+    devices: BTreeMap<String, MockDevice>,
+}
+
+/// In-memory LSP simulating the Blocktank order and CJIT lifecycle.
+pub struct MockLsp {
+    config: MockLspConfig,
+    state: Mutex<MockState>,
+}
+
+fn timestamp(secs: i64) -> String {
+    chrono::DateTime::from_timestamp(secs, 0)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
+fn mock_error(error_details: impl Into<String>) -> BlocktankError {
+    BlocktankError::BlocktankClient {
+        error_details: error_details.into(),
+    }
+}
+
+fn lsp_node() -> ILspNode {
+    ILspNode {
+        alias: "mock-lsp".to_string(),
+        pubkey: MOCK_LSP_PUBKEY.to_string(),
+        connection_strings: vec![MOCK_LSP_CONNECTION_STRING.to_string()],
+        readonly: None,
+    }
+}
+
+impl Default for MockLsp {
+    fn default() -> Self {
+        Self::new(MockLspConfig::default())
+    }
+}
+
+impl MockLsp {
+    pub fn new(config: MockLspConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(MockState {
+                now: MOCK_LSP_START_TIME,
+                block_height: MOCK_LSP_START_HEIGHT,
+                next_id: 1,
+                unreachable: false,
+                orders: BTreeMap::new(),
+                cjit_entries: BTreeMap::new(),
+                payments: BTreeMap::new(),
+                gifts: BTreeMap::new(),
+                devices: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Current simulated time as a unix timestamp in seconds
+    pub fn now(&self) -> i64 {
+        self.state.lock().unwrap().now
+    }
+
+    pub fn block_height(&self) -> u32 {
+        self.state.lock().unwrap().block_height
+    }
+
+    /// Moves simulated time forward, opening pending channels and expiring orders
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += duration.as_secs() as i64;
+        self.process(&mut state);
+    }
+
+    /// Makes every API call fail until reset, to simulate the LSP being offline
+    pub fn set_unreachable(&self, unreachable: bool) {
+        self.state.lock().unwrap().unreachable = unreachable;
+    }
+
+    /// Returns the latest registration of `device_token`
+    pub fn registered_device(&self, device_token: &str) -> Option<MockDevice> {
+        self.state
+            .lock()
+            .unwrap()
+            .devices
+            .get(device_token)
+            .cloned()
+    }
+
+    /// Sends a notification to `device_token`, encrypted to its registered public key.
+    /// `payload` is the JSON payload of the notification type.
+    pub fn notify(
+        &self,
+        device_token: &str,
+        notification_type: &str,
+        payload: serde_json::Value,
+    ) -> Result<BtEncryptedNotification, BlocktankError> {
+        let device =
+            self.registered_device(device_token)
+                .ok_or(BlocktankError::InvalidParameter {
+                    error_details: format!("Device {} is not registered", device_token),
+                })?;
+        let message = serde_json::json!({ "type": notification_type, "payload": payload });
+        encrypt_notification(&message.to_string(), &device.public_key)
+    }
+
+    /// Pays an order in full as if its invoice was settled
+    pub fn pay_order(&self, order_id: &str) -> Result<(), BlocktankError> {
+        let mut state = self.state.lock().unwrap();
+        Self::settle_order(&mut state, order_id)
+    }
+
+    /// Pays the invoice of a CJIT entry, which makes the LSP open the channel
+    pub fn pay_cjit_entry(&self, entry_id: &str) -> Result<(), BlocktankError> {
+        let mut state = self.state.lock().unwrap();
+        Self::settle_cjit_entry(&mut state, entry_id)
+    }
+
+    /// Fails the channel open of a pending CJIT entry
+    pub fn fail_cjit_entry(&self, entry_id: &str, reason: &str) -> Result<(), BlocktankError> {
+        let mut state = self.state.lock().unwrap();
+        let now = state.now;
+        let mock = state
+            .cjit_entries
+            .get_mut(entry_id)
+            .ok_or_else(|| mock_error(format!("CJIT entry {} not found", entry_id)))?;
+        if !matches!(mock.entry.state, CJitStateEnum::Created) {
+            return Err(mock_error(format!(
+                "CJIT entry {} is not pending",
+                entry_id
+            )));
+        }
+        mock.entry.state = CJitStateEnum::Failed;
+        mock.entry.channel_open_error = Some(reason.to_string());
+        mock.entry.channel = None;
+        mock.channel_request = None;
+        mock.entry.updated_at = timestamp(now);
+        Ok(())
+    }
+
+    /// Creates or updates an on-chain manual refund of the paid amount of an order, as
+    /// Blocktank support would. A sent refund marks the payment as refunded.
+    pub fn manual_refund(
+        &self,
+        order_id: &str,
+        target: &str,
+        refund_state: ManualRefundStateEnum,
+    ) -> Result<(), BlocktankError> {
+        let mut state = self.state.lock().unwrap();
+        let now = state.now;
+        let order = &mut state
+            .orders
+            .get_mut(order_id)
+            .ok_or_else(|| mock_error(format!("Order {} not found", order_id)))?
+            .order;
+        let payment = order
+            .payment
+            .as_mut()
+            .filter(|payment| payment.paid_sat > 0)
+            .ok_or_else(|| mock_error(format!("Order {} is not paid", order_id)))?;
+
+        if matches!(refund_state, ManualRefundStateEnum::Sent) {
+            payment.state2 = Some(BtPaymentState2::Refunded);
+        }
+        let refund = IManualRefund {
+            amount_sat: payment.paid_sat,
+            target: target.to_string(),
+            state: refund_state,
+            created_by_name: "mock".to_string(),
+            voted_by_name: None,
+            reason: None,
+            target_type: "onchain".to_string(),
+        };
+        let refunds = payment.manual_refunds.get_or_insert_with(Vec::new);
+        match refunds
+            .iter_mut()
+            .find(|existing| existing.target == target)
+        {
+            Some(existing) => *existing = refund,
+            None => refunds.push(refund),
+        }
+        order.updated_at = timestamp(now);
+        Ok(())
+    }
+
+    fn check_reachable(&self) -> Result<(), BlocktankError> {
+        if self.state.lock().unwrap().unreachable {
+            return Err(mock_error("Mock LSP is unreachable"));
+        }
+        Ok(())
+    }
+
+    fn next_id(state: &mut MockState) -> u64 {
+        let id = state.next_id;
+        state.next_id += 1;
+        id
+    }
+
+    fn fees(&self, channel_size_sat: u64, channel_expiry_weeks: u32) -> (u64, u64) {
+        let service_fee_sat = channel_size_sat
+            * self.config.service_fee_ppm_per_week
+            * u64::from(channel_expiry_weeks)
+            / 1_000_000;
+        (self.config.network_fee_sat, service_fee_sat)
+    }
+
+    fn validate_channel(
+        &self,
+        lsp_balance_sat: u64,
+        client_balance_sat: u64,
+        channel_expiry_weeks: u32,
+    ) -> Result<(), BlocktankError> {
+        let options = &self.config.options;
+        let channel_size_sat = lsp_balance_sat + client_balance_sat;
+        if channel_size_sat < options.min_channel_size_sat
+            || channel_size_sat > options.max_channel_size_sat
+        {
+            return Err(mock_error(format!(
+                "Channel size {} is outside of {}..={}",
+                channel_size_sat, options.min_channel_size_sat, options.max_channel_size_sat
+            )));
+        }
+        if client_balance_sat > options.max_client_balance_sat {
+            return Err(mock_error(format!(
+                "Client balance {} exceeds {}",
+                client_balance_sat, options.max_client_balance_sat
+            )));
+        }
+        if channel_expiry_weeks < options.min_expiry_weeks
+            || channel_expiry_weeks > options.max_expiry_weeks
+        {
+            return Err(mock_error(format!(
+                "Channel expiry of {} weeks is outside of {}..={}",
+                channel_expiry_weeks, options.min_expiry_weeks, options.max_expiry_weeks
+            )));
+        }
+        Ok(())
+    }
+
+    fn new_invoice(
+        state: &mut MockState,
+        id: &str,
+        amount_sat: u64,
+        expires_at: i64,
+    ) -> IBtBolt11Invoice {
+        let invoice = IBtBolt11Invoice {
+            request: format!("lnbcrt{}n1mock{}", amount_sat, id),
+            state: BtBolt11InvoiceState::Pending,
+            expires_at: timestamp(expires_at),
+            updated_at: timestamp(state.now),
+        };
+        state.payments.insert(id.to_string(), invoice.clone());
+        invoice
+    }
+
+    fn mark_invoice_paid(state: &mut MockState, payment_id: &str) {
+        let now = state.now;
+        if let Some(invoice) = state.payments.get_mut(payment_id) {
+            invoice.state = BtBolt11InvoiceState::Paid;
+            invoice.updated_at = timestamp(now);
+        }
+    }
+
+    fn settle_order(state: &mut MockState, order_id: &str) -> Result<(), BlocktankError> {
+        let now = state.now;
+        let mock = state
+            .orders
+            .get_mut(order_id)
+            .ok_or_else(|| mock_error(format!("Order {} not found", order_id)))?;
+        if !matches!(mock.order.state2, Some(BtOrderState2::Created)) {
+            return Err(mock_error(format!(
+                "Order {} is not awaiting payment",
+                order_id
+            )));
+        }
+
+        let order = &mut mock.order;
+        order.state2 = Some(BtOrderState2::Paid);
+        if let Some(payment) = order.payment.as_mut() {
+            payment.state = BtPaymentState::Paid;
+            payment.state2 = Some(BtPaymentState2::Paid);
+            payment.paid_sat = order.fee_sat;
+            if let Some(invoice) = payment.bolt11_invoice.as_mut() {
+                invoice.state = BtBolt11InvoiceState::Paid;
+                invoice.updated_at = timestamp(now);
+            }
+        }
+        order.updated_at = timestamp(now);
+        Self::mark_invoice_paid(state, order_id);
+        Ok(())
+    }
+
+    fn settle_cjit_entry(state: &mut MockState, entry_id: &str) -> Result<(), BlocktankError> {
+        let now = state.now;
+        let funding_tx_id = format!("{:064x}", Self::next_id(state));
+        let mock = state
+            .cjit_entries
+            .get_mut(entry_id)
+            .ok_or_else(|| mock_error(format!("CJIT entry {} not found", entry_id)))?;
+        if !matches!(mock.entry.state, CJitStateEnum::Created) || mock.channel_request.is_some() {
+            return Err(mock_error(format!(
+                "CJIT entry {} is not awaiting payment",
+                entry_id
+            )));
+        }
+
+        mock.entry.invoice.state = BtBolt11InvoiceState::Paid;
+        mock.entry.invoice.updated_at = timestamp(now);
+        mock.entry.channel = Some(Self::opening_channel(&mock.entry.node_id, &funding_tx_id));
+        mock.entry.updated_at = timestamp(now);
+        mock.channel_request = Some(MockChannelRequest {
+            opened_at: now,
+            funding_tx_id,
+        });
+        Self::mark_invoice_paid(state, entry_id);
+        Ok(())
+    }
+
+    fn opening_channel(client_node_pubkey: &str, funding_tx_id: &str) -> IBtChannel {
+        IBtChannel {
+            state: BtOpenChannelState::Opening,
+            lsp_node_pubkey: MOCK_LSP_PUBKEY.to_string(),
+            client_node_pubkey: client_node_pubkey.to_string(),
+            announce_channel: false,
+            funding_tx: FundingTx {
+                id: funding_tx_id.to_string(),
+                vout: 0,
+            },
+            closing_tx_id: None,
+            close: None,
+            short_channel_id: None,
+        }
+    }
+
+    /// Applies everything that became due at the current simulated time
+    fn process(&self, state: &mut MockState) {
+        let now = state.now;
+        let open_after = self.config.channel_open_delay_secs;
+        let block_height = state.block_height;
+
+        for mock in state.orders.values_mut() {
+            let order = &mut mock.order;
+            if let Some(request) = &mock.channel_request {
+                if let Some(channel) = order.channel.as_mut() {
+                    if matches!(channel.state, BtOpenChannelState::Opening)
+                        && now >= request.opened_at + open_after
+                    {
+                        channel.state = BtOpenChannelState::Open;
+                        channel.short_channel_id = Some(format!("{}x1x0", block_height));
+                        order.state = BtOrderState::Open;
+                        order.updated_at = timestamp(now);
+                    }
+                }
+                continue;
+            }
+
+            if now < mock.expires_at {
+                continue;
+            }
+            match order.state2 {
+                Some(BtOrderState2::Created) => {
+                    if let Some(payment) = order.payment.as_mut() {
+                        payment.state2 = Some(BtPaymentState2::Canceled);
+                        if let Some(invoice) = payment.bolt11_invoice.as_mut() {
+                            invoice.state = BtBolt11InvoiceState::Canceled;
+                        }
+                    }
+                }
+                Some(BtOrderState2::Paid) => {
+                    // The LSP keeps the funds of a paid order that never got a channel
+                    // until the client claims a refund
+                    if let Some(payment) = order.payment.as_mut() {
+                        payment.state = BtPaymentState::RefundAvailable;
+                        payment.state2 = Some(BtPaymentState2::RefundAvailable);
+                    }
+                }
+                _ => continue,
+            }
+            order.state = BtOrderState::Expired;
+            order.state2 = Some(BtOrderState2::Expired);
+            order.updated_at = timestamp(now);
+        }
+
+        for mock in state.cjit_entries.values_mut() {
+            let entry = &mut mock.entry;
+            if !matches!(entry.state, CJitStateEnum::Created) {
+                continue;
+            }
+            if let Some(request) = &mock.channel_request {
+                if now >= request.opened_at + open_after {
+                    if let Some(channel) = entry.channel.as_mut() {
+                        channel.state = BtOpenChannelState::Open;
+                        channel.short_channel_id = Some(format!("{}x1x0", block_height));
+                    }
+                    entry.state = CJitStateEnum::Completed;
+                    entry.updated_at = timestamp(now);
+                }
+            } else if now >= mock.expires_at {
+                entry.state = CJitStateEnum::Expired;
+                entry.invoice.state = BtBolt11InvoiceState::Canceled;
+                entry.updated_at = timestamp(now);
+            }
+        }
+    }
+
+    fn create_order_locked(
+        &self,
+        state: &mut MockState,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtOrder, BlocktankError> {
+        let client_balance_sat = options.as_ref().map_or(0, |o| o.client_balance_sat);
+        self.validate_channel(lsp_balance_sat, client_balance_sat, channel_expiry_weeks)?;
+        let (network_fee_sat, service_fee_sat) =
+            self.fees(lsp_balance_sat + client_balance_sat, channel_expiry_weeks);
+        // The client pays the fees plus the balance it wants on its side of the channel
+        let fee_sat = network_fee_sat + service_fee_sat + client_balance_sat;
+
+        let n = Self::next_id(state);
+        let id = format!("{}-order-{}", self.config.id_prefix, n);
+        let now = state.now;
+        let expires_at = now + self.config.order_expiry_secs;
+        let channel_expires_at = now + i64::from(channel_expiry_weeks) * 7 * 24 * 3_600;
+        let invoice = Self::new_invoice(state, &id, fee_sat, expires_at);
+
+        let order = IBtOrder {
+            id: id.clone(),
+            state: BtOrderState::Created,
+            state2: Some(BtOrderState2::Created),
+            fee_sat,
+            network_fee_sat,
+            service_fee_sat,
+            lsp_balance_sat,
+            client_balance_sat,
+            zero_conf: options.as_ref().is_some_and(|o| o.zero_conf),
+            zero_reserve: options.as_ref().is_some_and(|o| o.zero_reserve),
+            client_node_id: options.as_ref().and_then(|o| o.client_node_id.clone()),
+            channel_expiry_weeks,
+            channel_expires_at: timestamp(channel_expires_at),
+            order_expires_at: timestamp(expires_at),
+            channel: None,
+            lsp_node: Some(lsp_node()),
+            lnurl: None,
+            payment: Some(IBtPayment {
+                state: BtPaymentState::Created,
+                state2: Some(BtPaymentState2::Created),
+                paid_sat: 0,
+                bolt11_invoice: Some(invoice),
+                onchain: Some(IBtOnchainTransactions {
+                    address: format!("bcrt1qmockorder{}", n),
+                    confirmed_sat: 0,
+                    required_confirmations: self.config.options.min_payment_confirmations,
+                    transactions: vec![],
+                }),
+                is_manually_paid: None,
+                manual_refunds: None,
+            }),
+            coupon_code: options
+                .as_ref()
+                .map(|o| o.coupon_code.clone())
+                .filter(|code| !code.is_empty()),
+            source: options.as_ref().and_then(|o| o.source.clone()),
+            discount: None,
+            updated_at: timestamp(now),
+            created_at: timestamp(now),
+        };
+
+        state.orders.insert(
+            id,
+            MockOrder {
+                order: order.clone(),
+                expires_at,
+                channel_request: None,
+            },
+        );
+        Ok(order)
+    }
+}
+
+#[async_trait]
+impl BlocktankApi for MockLsp {
+    async fn get_info(&self) -> Result<IBtInfo, BlocktankError> {
+        self.check_reachable()?;
+        Ok(IBtInfo {
+            version: 2,
+            nodes: vec![lsp_node()],
+            options: self.config.options.clone(),
+            versions: IBtInfoVersions {
+                http: "mock".to_string(),
+                btc: "mock".to_string(),
+                ln2: "mock".to_string(),
+            },
+            onchain: IBtInfoOnchain {
+                network: BitcoinNetworkEnum::Regtest,
+                fee_rates: FeeRates {
+                    fast: 20,
+                    mid: 10,
+                    slow: 2,
+                },
+            },
+        })
+    }
+
+    async fn create_order(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtOrder, BlocktankError> {
+        self.check_reachable()?;
+        let mut state = self.state.lock().unwrap();
+        self.create_order_locked(&mut state, lsp_balance_sat, channel_expiry_weeks, options)
+    }
+
+    async fn open_channel(
+        &self,
+        order_id: &str,
+        connection_string: &str,
+    ) -> Result<IBtOrder, BlocktankError> {
+        self.check_reachable()?;
+        let mut state = self.state.lock().unwrap();
+        let now = state.now;
+        let funding_tx_id = format!("{:064x}", Self::next_id(&mut state));
+        let mock = state
+            .orders
+            .get_mut(order_id)
+            .ok_or_else(|| mock_error(format!("Order {} not found", order_id)))?;
+
+        if !matches!(mock.order.state2, Some(BtOrderState2::Paid)) {
+            return Err(BlocktankError::ChannelOpen {
+                error_type: BtChannelOrderErrorType::WrongOrderState,
+                error_details: format!("Order {} is not paid", order_id),
+            });
+        }
+        let Some((client_node_pubkey, _)) = connection_string.split_once('@') else {
+            return Err(BlocktankError::ChannelOpen {
+                error_type: BtChannelOrderErrorType::PeerNotReachable,
+                error_details: format!("Invalid connection string: {}", connection_string),
+            });
+        };
+
+        mock.order.state2 = Some(BtOrderState2::Executed);
+        mock.order.channel = Some(Self::opening_channel(client_node_pubkey, &funding_tx_id));
+        mock.order.updated_at = timestamp(now);
+        mock.channel_request = Some(MockChannelRequest {
+            opened_at: now,
+            funding_tx_id,
+        });
+        Ok(mock.order.clone())
+    }
+
+    async fn get_orders(&self, order_ids: &[String]) -> Result<Vec<IBtOrder>, BlocktankError> {
+        self.check_reachable()?;
+        let state = self.state.lock().unwrap();
+        Ok(order_ids
+            .iter()
+            .filter_map(|id| state.orders.get(id))
+            .map(|mock| mock.order.clone())
+            .collect())
+    }
+
+    async fn get_min_zero_conf_tx_fee(
+        &self,
+        order_id: &str,
+    ) -> Result<IBt0ConfMinTxFeeWindow, BlocktankError> {
+        self.check_reachable()?;
+        let state = self.state.lock().unwrap();
+        if !state.orders.contains_key(order_id) {
+            return Err(mock_error(format!("Order {} not found", order_id)));
+        }
+        Ok(IBt0ConfMinTxFeeWindow {
+            sat_per_vbyte: self.config.min_0_conf_fee_rate,
+            validity_ends_at: timestamp(state.now + ZERO_CONF_FEE_WINDOW_SECS),
+        })
+    }
+
+    async fn estimate_order_fee(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse, BlocktankError> {
+        let estimate = self
+            .estimate_order_fee_full(lsp_balance_sat, channel_expiry_weeks, options)
+            .await?;
+        Ok(IBtEstimateFeeResponse {
+            fee_sat: estimate.fee_sat,
+            min_0_conf_tx_fee: estimate.min_0_conf_tx_fee,
+        })
+    }
+
+    async fn estimate_order_fee_full(
+        &self,
+        lsp_balance_sat: u64,
+        channel_expiry_weeks: u32,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<IBtEstimateFeeResponse2, BlocktankError> {
+        self.check_reachable()?;
+        let client_balance_sat = options.as_ref().map_or(0, |o| o.client_balance_sat);
+        self.validate_channel(lsp_balance_sat, client_balance_sat, channel_expiry_weeks)?;
+        let (network_fee_sat, service_fee_sat) =
+            self.fees(lsp_balance_sat + client_balance_sat, channel_expiry_weeks);
+        let now = self.now();
+        Ok(IBtEstimateFeeResponse2 {
+            fee_sat: network_fee_sat + service_fee_sat + client_balance_sat,
+            network_fee_sat,
+            service_fee_sat,
+            min_0_conf_tx_fee: IBt0ConfMinTxFeeWindow {
+                sat_per_vbyte: self.config.min_0_conf_fee_rate,
+                validity_ends_at: timestamp(now + ZERO_CONF_FEE_WINDOW_SECS),
+            },
+        })
+    }
+
+    async fn create_cjit_entry(
+        &self,
+        channel_size_sat: u64,
+        invoice_sat: u64,
+        _invoice_description: &str,
+        node_id: &str,
+        channel_expiry_weeks: u32,
+        options: Option<CreateCjitOptions>,
+    ) -> Result<ICJitEntry, BlocktankError> {
+        self.check_reachable()?;
+        self.validate_channel(channel_size_sat, 0, channel_expiry_weeks)?;
+        let (network_fee_sat, service_fee_sat) = self.fees(channel_size_sat, channel_expiry_weeks);
+        let fee_sat = network_fee_sat + service_fee_sat;
+        if invoice_sat <= fee_sat {
+            return Err(mock_error(format!(
+                "Invoice amount {} does not cover the fee of {}",
+                invoice_sat, fee_sat
+            )));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let id = format!(
+            "{}-cjit-{}",
+            self.config.id_prefix,
+            Self::next_id(&mut state)
+        );
+        let now = state.now;
+        let expires_at = now + self.config.order_expiry_secs;
+        let invoice = Self::new_invoice(&mut state, &id, invoice_sat, expires_at);
+
+        let entry = ICJitEntry {
+            id: id.clone(),
+            state: CJitStateEnum::Created,
+            fee_sat,
+            network_fee_sat,
+            service_fee_sat,
+            channel_size_sat,
+            channel_expiry_weeks,
+            channel_open_error: None,
+            node_id: node_id.to_string(),
+            invoice,
+            channel: None,
+            lsp_node: lsp_node(),
+            coupon_code: String::new(),
+            source: options.and_then(|o| o.source),
+            discount: None,
+            expires_at: timestamp(expires_at),
+            updated_at: timestamp(now),
+            created_at: timestamp(now),
+        };
+
+        state.cjit_entries.insert(
+            id,
+            MockCjitEntry {
+                entry: entry.clone(),
+                expires_at,
+                channel_request: None,
+            },
+        );
+        Ok(entry)
+    }
+
+    async fn get_cjit_entry(&self, entry_id: &str) -> Result<ICJitEntry, BlocktankError> {
+        self.check_reachable()?;
+        let state = self.state.lock().unwrap();
+        state
+            .cjit_entries
+            .get(entry_id)
+            .map(|mock| mock.entry.clone())
+            .ok_or_else(|| mock_error(format!("CJIT entry {} not found", entry_id)))
+    }
+
+    async fn regtest_mine(&self, count: Option<u32>) -> Result<(), BlocktankError> {
+        self.check_reachable()?;
+        let blocks = count.unwrap_or(1);
+        let mut state = self.state.lock().unwrap();
+        state.block_height += blocks;
+        state.now += i64::from(blocks) * BLOCK_INTERVAL_SECS;
+        self.process(&mut state);
+        Ok(())
+    }
+
+    async fn regtest_deposit(
+        &self,
+        _address: &str,
+        _amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        self.check_reachable()?;
+        let mut state = self.state.lock().unwrap();
+        Ok(format!("{:064x}", Self::next_id(&mut state)))
+    }
+
+    async fn regtest_pay(
+        &self,
+        invoice: &str,
+        _amount_sat: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        self.check_reachable()?;
+        let mut state = self.state.lock().unwrap();
+        let payment_id = state
+            .payments
+            .iter()
+            .find(|(_, payment)| payment.request == invoice)
+            .map(|(id, _)| id.clone())
+            .ok_or_else(|| mock_error(format!("Unknown invoice: {}", invoice)))?;
+
+        if state.orders.contains_key(&payment_id) {
+            Self::settle_order(&mut state, &payment_id)?;
+        } else if state.cjit_entries.contains_key(&payment_id) {
+            Self::settle_cjit_entry(&mut state, &payment_id)?;
+        } else {
+            Self::mark_invoice_paid(&mut state, &payment_id);
+        }
+        Ok(payment_id)
+    }
+
+    async fn regtest_get_payment(
+        &self,
+        payment_id: &str,
+    ) -> Result<IBtBolt11Invoice, BlocktankError> {
+        self.get_payment(payment_id).await
+    }
+
+    async fn regtest_close_channel(
+        &self,
+        funding_tx_id: &str,
+        vout: u32,
+        force_close_after_s: Option<u64>,
+    ) -> Result<String, BlocktankError> {
+        self.check_reachable()?;
+        let mut state = self.state.lock().unwrap();
+        let now = state.now;
+        let closing_tx_id = format!("{:064x}", Self::next_id(&mut state));
+        let mock = state
+            .orders
+            .values_mut()
+            .find(|mock| {
+                mock.channel_request
+                    .as_ref()
+                    .is_some_and(|request| request.funding_tx_id == funding_tx_id)
+                    && vout == 0
+            })
+            .ok_or_else(|| mock_error(format!("Channel {}:{} not found", funding_tx_id, vout)))?;
+
+        let channel = mock
+            .order
+            .channel
+            .as_mut()
+            .ok_or_else(|| mock_error("Order has no channel"))?;
+        channel.state = BtOpenChannelState::Closed;
+        channel.closing_tx_id = Some(closing_tx_id.clone());
+        channel.close = Some(IBtChannelClose {
+            tx_id: closing_tx_id.clone(),
+            close_type: if force_close_after_s.is_some() {
+                "force".to_string()
+            } else {
+                "cooperative".to_string()
+            },
+            initiator: "lsp".to_string(),
+            registered_at: timestamp(now),
+        });
+        mock.order.state = BtOrderState::Closed;
+        mock.order.updated_at = timestamp(now);
+        Ok(closing_tx_id)
+    }
+
+    async fn register_device(
+        &self,
+        device_token: &str,
+        public_key: &str,
+        features: &[String],
+        node_id: &str,
+        iso_timestamp: &str,
+        signature: &str,
+        _is_production: Option<bool>,
+        _custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError> {
+        self.check_reachable()?;
+        let mut state = self.state.lock().unwrap();
+        let registrations = state
+            .devices
+            .get(device_token)
+            .map_or(0, |device| device.registrations);
+        state.devices.insert(
+            device_token.to_string(),
+            MockDevice {
+                public_key: public_key.to_string(),
+                features: features.to_vec(),
+                node_id: node_id.to_string(),
+                iso_timestamp: iso_timestamp.to_string(),
+                signature: signature.to_string(),
+                registrations: registrations + 1,
+            },
+        );
+        Ok(device_token.to_string())
+    }
+
+    async fn test_notification(
+        &self,
+        device_token: &str,
+        _secret_message: &str,
+        _notification_type: Option<&str>,
+        _custom_url: Option<&str>,
+    ) -> Result<String, BlocktankError> {
+        self.check_reachable()?;
+        let registered = self
+            .state
+            .lock()
+            .unwrap()
+            .devices
+            .contains_key(device_token);
+        if !registered {
+            return Err(mock_error(format!(
+                "Device {} is not registered",
+                device_token
+            )));
+        }
+        Ok("sent".to_string())
+    }
+
+    async fn gift_pay(&self, invoice: &str) -> Result<IGift, BlocktankError> {
+        let payment_id = self.regtest_pay(invoice, None).await?;
+        let mut state = self.state.lock().unwrap();
+        let now = state.now;
+        let id = format!(
+            "{}-gift-{}",
+            self.config.id_prefix,
+            Self::next_id(&mut state)
+        );
+        let gift = IGift {
+            id: id.clone(),
+            node_id: String::new(),
+            order_id: None,
+            order: None,
+            bolt11_payment_id: Some(payment_id),
+            bolt11_payment: None,
+            applied_gift_code_id: None,
+            applied_gift_code: None,
+            created_at: Some(timestamp(now)),
+            updated_at: Some(timestamp(now)),
+        };
+        state.gifts.insert(id, gift.clone());
+        Ok(gift)
+    }
+
+    async fn gift_order(&self, client_node_id: &str, code: &str) -> Result<IGift, BlocktankError> {
+        self.check_reachable()?;
+        let mut state = self.state.lock().unwrap();
+        let options = CreateOrderOptions {
+            client_node_id: Some(client_node_id.to_string()),
+            coupon_code: code.to_string(),
+            ..Default::default()
+        };
+        let order = self.create_order_locked(
+            &mut state,
+            GIFT_LSP_BALANCE_SAT,
+            GIFT_CHANNEL_EXPIRY_WEEKS,
+            Some(options),
+        )?;
+        // Gift codes pay for the whole order
+        Self::settle_order(&mut state, &order.id)?;
+
+        let now = state.now;
+        let id = format!(
+            "{}-gift-{}",
+            self.config.id_prefix,
+            Self::next_id(&mut state)
+        );
+        let gift = IGift {
+            id: id.clone(),
+            node_id: client_node_id.to_string(),
+            order_id: Some(order.id),
+            order: None,
+            bolt11_payment_id: None,
+            bolt11_payment: None,
+            applied_gift_code_id: Some(code.to_string()),
+            applied_gift_code: None,
+            created_at: Some(timestamp(now)),
+            updated_at: Some(timestamp(now)),
+        };
+        state.gifts.insert(id, gift.clone());
+        Ok(gift)
+    }
+
+    async fn get_gift(&self, gift_id: &str) -> Result<IGift, BlocktankError> {
+        self.check_reachable()?;
+        let state = self.state.lock().unwrap();
+        state
+            .gifts
+            .get(gift_id)
+            .cloned()
+            .ok_or_else(|| mock_error(format!("Gift {} not found", gift_id)))
+    }
+
+    async fn get_payment(&self, payment_id: &str) -> Result<IBtBolt11Invoice, BlocktankError> {
+        self.check_reachable()?;
+        let state = self.state.lock().unwrap();
+        state
+            .payments
+            .get(payment_id)
+            .cloned()
+            .ok_or_else(|| mock_error(format!("Payment {} not found", payment_id)))
+    }
+}
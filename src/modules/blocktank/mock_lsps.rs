@@ -0,0 +1,423 @@
+//! In-memory LSPS1 and LSPS2 server for tests, on top of [`MockLsp`].
+//!
+//! [`MockLspsServer`] is a `BtLspsTransport` that answers the requests itself instead of
+//! relaying them to a node. LSPS1 orders are `MockLsp` orders, so they are paid and moved
+//! forward through the wrapped `MockLsp`. LSPS2 fee parameters are valid for a while from the
+//! real clock, as clients check them against it.
+
+use crate::modules::blocktank::lsps0::{
+    BtJitInvoiceParams, BtLspsTransport, JsonRpcRequest, JsonRpcResponse,
+};
+use crate::modules::blocktank::lsps1::{
+    Lsps1Bolt11Payment, Lsps1Channel, Lsps1CreateOrderRequest, Lsps1GetOrderRequest,
+    Lsps1OnchainPayment, Lsps1Options, Lsps1Order, Lsps1OrderState, Lsps1Payment,
+    Lsps1PaymentState, BLOCKS_PER_WEEK,
+};
+use crate::modules::blocktank::lsps2::{
+    Lsps2BuyRequest, Lsps2BuyResponse, Lsps2GetInfoRequest, Lsps2GetInfoResponse,
+    Lsps2OpeningFeeParams,
+};
+use crate::modules::blocktank::{BlocktankApi, BlocktankError, MockLsp, MOCK_LSP_PUBKEY};
+use async_trait::async_trait;
+use rust_blocktank_client::{
+    BtOrderState, BtOrderState2, BtPaymentState2, CreateOrderOptions, IBtOrder,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+const LSPS1_OPTION_MISMATCH: i64 = 100;
+const LSPS2_INVALID_OPENING_FEE_PARAMS: i64 = 201;
+const LSPS2_PAYMENT_SIZE_TOO_SMALL: i64 = 202;
+const LSPS2_PAYMENT_SIZE_TOO_LARGE: i64 = 203;
+const MOCK_PROMISE: &str = "mock-promise";
+
+#[derive(Debug, Clone)]
+pub struct MockLspsConfig {
+    /// Node the client has to address its requests to
+    pub lsp_node_id: String,
+    /// Node the LSP opens LSPS1 channels to
+    pub client_node_id: String,
+    pub min_required_channel_confirmations: u16,
+    pub min_funding_confirms_within_blocks: u16,
+    /// LSPS2 opening fee, at least `min_fee_msat` and `proportional` ppm of the payment
+    pub min_fee_msat: u64,
+    pub proportional: u32,
+    /// Blocks LSPS2 channels stay open at least
+    pub min_lifetime: u32,
+    pub min_payment_size_msat: u64,
+    pub max_payment_size_msat: u64,
+    /// Time the LSPS2 fee parameters stay valid
+    pub fee_params_validity_secs: i64,
+    pub cltv_expiry_delta: u32,
+}
+
+impl Default for MockLspsConfig {
+    fn default() -> Self {
+        Self {
+            lsp_node_id: MOCK_LSP_PUBKEY.to_string(),
+            client_node_id: "03c0d6e0bd8b1ac5d6d8b5e5ad2d0f4c1e4c3dc3e5b2e3f0c7f8b5a9d1e2f3a4b5"
+                .to_string(),
+            min_required_channel_confirmations: 0,
+            min_funding_confirms_within_blocks: 6,
+            min_fee_msat: 2_000_000,
+            proportional: 10_000,
+            min_lifetime: 12 * BLOCKS_PER_WEEK,
+            min_payment_size_msat: 10_000_000,
+            max_payment_size_msat: 1_000_000_000,
+            fee_params_validity_secs: 3_600,
+            cltv_expiry_delta: 144,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockLspsState {
+    /// Methods of the requests received, in order
+    requests: Vec<String>,
+    /// LSPS1 requests of the orders created, by order id
+    order_requests: BTreeMap<String, Lsps1CreateOrderRequest>,
+    /// JIT channels bought, by intercept scid
+    jit_channels: BTreeMap<String, Lsps2BuyRequest>,
+    jit_invoices: Vec<BtJitInvoiceParams>,
+}
+
+/// LSPS server answering through the `BtLspsTransport` interface
+pub struct MockLspsServer {
+    lsp: Arc<MockLsp>,
+    config: MockLspsConfig,
+    state: Mutex<MockLspsState>,
+}
+
+type RpcResult = Result<Value, (i64, String)>;
+
+fn params<P: DeserializeOwned>(params: Value) -> Result<P, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+fn result<R: Serialize>(result: R) -> RpcResult {
+    serde_json::to_value(result).map_err(|e| (INTERNAL_ERROR, e.to_string()))
+}
+
+fn lsp_error(e: BlocktankError) -> (i64, String) {
+    (INTERNAL_ERROR, e.to_string())
+}
+
+fn payment_state(order: &IBtOrder) -> Lsps1PaymentState {
+    match order
+        .payment
+        .as_ref()
+        .and_then(|payment| payment.state2.as_ref())
+    {
+        Some(BtPaymentState2::Paid) | Some(BtPaymentState2::RefundAvailable) => {
+            Lsps1PaymentState::Paid
+        }
+        Some(BtPaymentState2::Refunded) => Lsps1PaymentState::Refunded,
+        _ => Lsps1PaymentState::ExpectPayment,
+    }
+}
+
+impl MockLspsServer {
+    pub fn new(lsp: Arc<MockLsp>, config: MockLspsConfig) -> Self {
+        Self {
+            lsp,
+            config,
+            state: Mutex::new(MockLspsState::default()),
+        }
+    }
+
+    /// The `MockLsp` the LSPS1 orders live in
+    pub fn lsp(&self) -> &Arc<MockLsp> {
+        &self.lsp
+    }
+
+    /// Methods of the requests received so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Invoices created for JIT channels so far, in order
+    pub fn jit_invoices(&self) -> Vec<BtJitInvoiceParams> {
+        self.state.lock().unwrap().jit_invoices.clone()
+    }
+
+    /// Answers a request, `None` if the method is unknown
+    async fn handle(&self, method: &str, request_params: Value) -> Option<RpcResult> {
+        Some(match method {
+            "lsps1.get_info" => self.lsps1_get_info().await,
+            "lsps1.create_order" => self.lsps1_create_order(request_params).await,
+            "lsps1.get_order" => self.lsps1_get_order(request_params).await,
+            "lsps2.get_info" => self.lsps2_get_info(request_params),
+            "lsps2.buy" => self.lsps2_buy(request_params),
+            _ => return None,
+        })
+    }
+
+    async fn lsps1_get_info(&self) -> RpcResult {
+        result(json!({ "options": self.lsps1_options().await? }))
+    }
+
+    async fn lsps1_get_order(&self, request_params: Value) -> RpcResult {
+        let request: Lsps1GetOrderRequest = params(request_params)?;
+        result(self.lsps1_order(&request.order_id).await?)
+    }
+
+    fn lsps2_get_info(&self, request_params: Value) -> RpcResult {
+        let _: Lsps2GetInfoRequest = params(request_params)?;
+        result(Lsps2GetInfoResponse {
+            opening_fee_params_menu: vec![self.opening_fee_params()],
+        })
+    }
+
+    async fn lsps1_options(&self) -> Result<Lsps1Options, (i64, String)> {
+        let limits = self.lsp.get_info().await.map_err(lsp_error)?.options;
+        Ok(Lsps1Options {
+            min_required_channel_confirmations: self.config.min_required_channel_confirmations,
+            min_funding_confirms_within_blocks: self.config.min_funding_confirms_within_blocks,
+            supports_zero_channel_reserve: false,
+            max_channel_expiry_blocks: limits.max_expiry_weeks * BLOCKS_PER_WEEK,
+            min_initial_client_balance_sat: 0,
+            max_initial_client_balance_sat: limits.max_client_balance_sat,
+            min_initial_lsp_balance_sat: 0,
+            max_initial_lsp_balance_sat: limits.max_channel_size_sat,
+            min_channel_balance_sat: limits.min_channel_size_sat,
+            max_channel_balance_sat: limits.max_channel_size_sat,
+        })
+    }
+
+    async fn lsps1_create_order(&self, request_params: Value) -> RpcResult {
+        let request: Lsps1CreateOrderRequest = params(request_params)?;
+        if request.required_channel_confirmations < self.config.min_required_channel_confirmations {
+            return Err((
+                LSPS1_OPTION_MISMATCH,
+                "required_channel_confirmations is too low".to_string(),
+            ));
+        }
+
+        let options = CreateOrderOptions {
+            client_balance_sat: request.client_balance_sat,
+            coupon_code: request.token.clone().unwrap_or_default(),
+            zero_conf: request.required_channel_confirmations == 0,
+            refund_onchain_address: request.refund_onchain_address.clone(),
+            announce_channel: request.announce_channel,
+            ..Default::default()
+        };
+        let order = self
+            .lsp
+            .create_order(
+                request.lsp_balance_sat,
+                request.channel_expiry_blocks.div_ceil(BLOCKS_PER_WEEK),
+                Some(options),
+            )
+            .await
+            .map_err(lsp_error)?;
+        self.state
+            .lock()
+            .unwrap()
+            .order_requests
+            .insert(order.id.clone(), request);
+        result(self.lsps1_order(&order.id).await?)
+    }
+
+    /// Returns an order, opening its channel first if it was paid
+    async fn lsps1_order(&self, order_id: &str) -> Result<Lsps1Order, (i64, String)> {
+        let request = self
+            .state
+            .lock()
+            .unwrap()
+            .order_requests
+            .get(order_id)
+            .cloned()
+            .ok_or((INVALID_PARAMS, format!("Order {} not found", order_id)))?;
+        let mut order = self.get_order(order_id).await?;
+        if order.state2 == Some(BtOrderState2::Paid) && order.channel.is_none() {
+            let connection_string = format!("{}@127.0.0.1:9735", self.config.client_node_id);
+            order = self
+                .lsp
+                .open_channel(order_id, &connection_string)
+                .await
+                .map_err(lsp_error)?;
+        }
+
+        let state = payment_state(&order);
+        let payment = order
+            .payment
+            .clone()
+            .ok_or((INTERNAL_ERROR, format!("Order {} has no payment", order_id)))?;
+        let fee_total_sat = order.fee_sat.saturating_sub(order.client_balance_sat);
+        let min_fee_for_0conf = self
+            .lsp
+            .get_min_zero_conf_tx_fee(order_id)
+            .await
+            .map_err(lsp_error)?
+            .sat_per_vbyte
+            .ceil() as u8;
+
+        Ok(Lsps1Order {
+            order_id: order.id.clone(),
+            lsp_balance_sat: order.lsp_balance_sat,
+            client_balance_sat: order.client_balance_sat,
+            required_channel_confirmations: request.required_channel_confirmations,
+            funding_confirms_within_blocks: request.funding_confirms_within_blocks,
+            channel_expiry_blocks: request.channel_expiry_blocks,
+            token: request.token.unwrap_or_default(),
+            created_at: order.created_at.clone(),
+            announce_channel: order
+                .channel
+                .as_ref()
+                .map_or(request.announce_channel, |c| c.announce_channel),
+            order_state: match (&order.state, &order.channel) {
+                (BtOrderState::Expired, None) => Lsps1OrderState::Failed,
+                (BtOrderState::Open | BtOrderState::Closed, _) => Lsps1OrderState::Completed,
+                _ => Lsps1OrderState::Created,
+            },
+            payment: Lsps1Payment {
+                bolt11: payment.bolt11_invoice.map(|invoice| Lsps1Bolt11Payment {
+                    state,
+                    expires_at: invoice.expires_at,
+                    fee_total_sat,
+                    order_total_sat: order.fee_sat,
+                    invoice: invoice.request,
+                }),
+                onchain: payment.onchain.map(|onchain| Lsps1OnchainPayment {
+                    state,
+                    expires_at: order.order_expires_at.clone(),
+                    fee_total_sat,
+                    order_total_sat: order.fee_sat,
+                    address: onchain.address,
+                    min_onchain_payment_confirmations: u16::try_from(
+                        onchain.required_confirmations,
+                    )
+                    .ok(),
+                    min_fee_for_0conf,
+                    refund_onchain_address: request.refund_onchain_address,
+                }),
+            },
+            channel: order.channel.as_ref().map(|channel| Lsps1Channel {
+                funded_at: order.updated_at.clone(),
+                funding_outpoint: format!("{}:{}", channel.funding_tx.id, channel.funding_tx.vout),
+                expires_at: order.channel_expires_at.clone(),
+            }),
+        })
+    }
+
+    async fn get_order(&self, order_id: &str) -> Result<IBtOrder, (i64, String)> {
+        self.lsp
+            .get_orders(&[order_id.to_string()])
+            .await
+            .map_err(lsp_error)?
+            .into_iter()
+            .next()
+            .ok_or((INVALID_PARAMS, format!("Order {} not found", order_id)))
+    }
+
+    fn opening_fee_params(&self) -> Lsps2OpeningFeeParams {
+        let valid_until =
+            chrono::Utc::now() + chrono::Duration::seconds(self.config.fee_params_validity_secs);
+        Lsps2OpeningFeeParams {
+            min_fee_msat: self.config.min_fee_msat,
+            proportional: self.config.proportional,
+            valid_until: valid_until.to_rfc3339(),
+            min_lifetime: self.config.min_lifetime,
+            max_client_to_self_delay: 2_016,
+            min_payment_size_msat: self.config.min_payment_size_msat,
+            max_payment_size_msat: self.config.max_payment_size_msat,
+            promise: MOCK_PROMISE.to_string(),
+        }
+    }
+
+    fn lsps2_buy(&self, request_params: Value) -> RpcResult {
+        let request: Lsps2BuyRequest = params(request_params)?;
+        let params = &request.opening_fee_params;
+        let expired = chrono::DateTime::parse_from_rfc3339(&params.valid_until)
+            .map_or(true, |valid_until| valid_until <= chrono::Utc::now());
+        if params.promise != MOCK_PROMISE
+            || params.min_fee_msat != self.config.min_fee_msat
+            || params.proportional != self.config.proportional
+            || expired
+        {
+            return Err((
+                LSPS2_INVALID_OPENING_FEE_PARAMS,
+                "Invalid opening fee parameters".to_string(),
+            ));
+        }
+        if request.payment_size_msat < params.min_payment_size_msat {
+            return Err((
+                LSPS2_PAYMENT_SIZE_TOO_SMALL,
+                "Payment size is too small".to_string(),
+            ));
+        }
+        if request.payment_size_msat > params.max_payment_size_msat {
+            return Err((
+                LSPS2_PAYMENT_SIZE_TOO_LARGE,
+                "Payment size is too large".to_string(),
+            ));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let jit_channel_scid = format!(
+            "{}x{}x0",
+            self.lsp.block_height(),
+            state.jit_channels.len() + 1
+        );
+        state.jit_channels.insert(jit_channel_scid.clone(), request);
+        result(Lsps2BuyResponse {
+            jit_channel_scid,
+            lsp_cltv_expiry_delta: self.config.cltv_expiry_delta,
+            client_trusts_lsp: false,
+        })
+    }
+}
+
+#[async_trait]
+impl BtLspsTransport for MockLspsServer {
+    async fn send_request(
+        &self,
+        lsp_node_id: String,
+        request: String,
+    ) -> Result<String, BlocktankError> {
+        if lsp_node_id != self.config.lsp_node_id {
+            return Err(BlocktankError::ConnectionError {
+                error_details: format!("Not connected to {}", lsp_node_id),
+            });
+        }
+
+        let request: JsonRpcRequest<Value> = match serde_json::from_str(&request) {
+            Ok(request) => request,
+            Err(e) => {
+                let response = JsonRpcResponse::<Value>::error(None, PARSE_ERROR, e.to_string());
+                return Ok(serde_json::to_string(&response)?);
+            }
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .push(request.method.clone());
+
+        let response = match self.handle(&request.method, request.params).await {
+            Some(Ok(result)) => JsonRpcResponse::result(request.id, result),
+            Some(Err((code, message))) => JsonRpcResponse::error(Some(request.id), code, message),
+            None => JsonRpcResponse::method_not_found(request.id, &request.method),
+        };
+        Ok(serde_json::to_string(&response)?)
+    }
+
+    async fn create_jit_invoice(
+        &self,
+        params: BtJitInvoiceParams,
+    ) -> Result<String, BlocktankError> {
+        let invoice = format!(
+            "lnbcrt{}n1mockjit{}",
+            params.amount_msat / 100,
+            params.jit_channel_scid
+        );
+        self.state.lock().unwrap().jit_invoices.push(params);
+        Ok(invoice)
+    }
+}
@@ -1,13 +1,43 @@
 mod api;
+mod client;
 mod db;
 mod errors;
+mod history;
 mod liquidity;
+mod lsps;
+mod lsps0;
+mod lsps1;
+mod lsps2;
+mod lsps_client;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod mock_lsps;
 mod models;
+mod notifications;
+mod quotes;
+mod reconcile;
+mod refunds;
 #[cfg(test)]
 mod tests;
+mod tracker;
 mod types;
 
+pub use client::BlocktankApi;
 pub use errors::BlocktankError;
+pub use history::*;
 pub use liquidity::*;
+pub use lsps::*;
+pub use lsps0::{BtJitInvoiceParams, BtLspsTransport};
+pub use lsps_client::*;
+#[cfg(test)]
+pub use mock::*;
+#[cfg(test)]
+pub use mock_lsps::*;
 pub use models::BlocktankDB;
+pub use notifications::*;
+pub use quotes::*;
+pub use reconcile::*;
+pub use refunds::*;
+pub use tracker::*;
 pub use types::*;
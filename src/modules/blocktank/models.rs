@@ -1,11 +1,14 @@
+use crate::modules::blocktank::BlocktankApi;
 use rusqlite::Connection;
-use rust_blocktank_client::BlocktankClient;
+use std::sync::Arc;
 use tokio::sync::Mutex;
 
 pub struct BlocktankDB {
     pub(crate) conn: Mutex<Connection>,
-    pub(crate) client: BlocktankClient,
+    pub(crate) client: Arc<dyn BlocktankApi>,
     pub(crate) blocktank_url: String,
+    /// LSPs registered besides the default one, in registration order
+    pub(crate) lsps: Vec<(String, Arc<dyn BlocktankApi>)>,
 }
 
 pub const CREATE_ENUM_TABLES: &[&str] = &[
@@ -83,6 +86,109 @@ pub const CREATE_CJIT_ENTRIES_TABLE: &str = "
         discount_data TEXT  -- JSON for IDiscount
     )";
 
+pub const CREATE_STATUS_TRANSITIONS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS status_transitions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        item_id TEXT NOT NULL,
+        item_kind TEXT NOT NULL,  -- 'Order' | 'CjitEntry'
+        from_status TEXT,
+        to_status TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL
+    )";
+
+pub const CREATE_ORDER_EVENTS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS order_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        order_id TEXT NOT NULL,
+        field TEXT NOT NULL,  -- 'State' | 'State2' | 'PaymentState' | 'ChannelState' | 'RefundState'
+        old_value TEXT,
+        new_value TEXT,
+        fee_sat INTEGER NOT NULL,
+        network_fee_sat INTEGER NOT NULL,
+        service_fee_sat INTEGER NOT NULL,
+        order_updated_at TEXT NOT NULL,
+        recorded_at INTEGER NOT NULL
+    )";
+
+pub const INSERT_ORDER_EVENT_SQL: &str = "
+    INSERT INTO order_events (
+        order_id, field, old_value, new_value, fee_sat, network_fee_sat,
+        service_fee_sat, order_updated_at, recorded_at
+    ) VALUES (
+        ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9
+    )";
+
+pub const CREATE_ORDER_REFUNDS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS order_refunds (
+        order_id TEXT PRIMARY KEY,
+        refund_address TEXT,
+        state TEXT NOT NULL,
+        amount_sat INTEGER NOT NULL CHECK (amount_sat >= 0),
+        tx_id TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    )";
+
+pub const CREATE_ORDER_ACTIVITIES_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS order_activities (
+        order_id TEXT NOT NULL,
+        activity_id TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        channel_id TEXT,
+        linked_at INTEGER NOT NULL,
+        PRIMARY KEY (order_id, activity_id)
+    )";
+
+pub const CREATE_DEVICE_REGISTRATION_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS device_registration (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        device_token TEXT NOT NULL,
+        node_id TEXT NOT NULL,
+        public_key TEXT NOT NULL,
+        secret_key TEXT NOT NULL,
+        features TEXT NOT NULL,
+        is_production INTEGER,
+        custom_url TEXT,
+        iso_timestamp TEXT NOT NULL,
+        signature TEXT NOT NULL,
+        registered_at INTEGER NOT NULL
+    )";
+
+pub const CREATE_LSP_INFO_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS lsp_info (
+        lsp_url TEXT PRIMARY KEY,
+        version INTEGER NOT NULL,
+        nodes TEXT NOT NULL,  -- JSON array of ILspNode
+        options TEXT NOT NULL,  -- JSON of IBtInfoOptions
+        versions TEXT NOT NULL,  -- JSON of IBtInfoVersions
+        onchain TEXT NOT NULL,  -- JSON of IBtInfoOnchain
+        updated_at INTEGER NOT NULL
+    )";
+
+/// LSP of every stored order and CJIT entry, including the ones of the default LSP
+pub const CREATE_LSP_ITEMS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS lsp_items (
+        item_id TEXT NOT NULL,
+        item_kind TEXT NOT NULL,  -- 'Order' | 'CjitEntry'
+        lsp_url TEXT NOT NULL,
+        PRIMARY KEY (item_kind, item_id)
+    )";
+
+/// LSPs registered with `register_lsp`, registered again when the database is opened
+pub const CREATE_REGISTERED_LSPS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS registered_lsps (
+        lsp_url TEXT PRIMARY KEY,
+        registered_at INTEGER NOT NULL
+    )";
+
+/// Attributes a stored order or CJIT entry without an LSP to the default LSP
+pub const INSERT_DEFAULT_ITEM_LSP_SQL: &str = "
+    INSERT OR IGNORE INTO lsp_items (item_id, item_kind, lsp_url) VALUES (?1, ?2, ?3)";
+
+/// Attributes a stored order or CJIT entry to the LSP it was created with
+pub const SET_ITEM_LSP_SQL: &str = "
+    INSERT OR REPLACE INTO lsp_items (item_id, item_kind, lsp_url) VALUES (?1, ?2, ?3)";
+
 pub const INSERT_ORDER_SQL: &str = "
     INSERT OR REPLACE INTO orders (
         id, state, state2, fee_sat, network_fee_sat, service_fee_sat,
@@ -145,4 +251,9 @@ pub const INDEX_STATEMENTS: &[&str] = &[
     "CREATE INDEX IF NOT EXISTS idx_cjit_node_state ON cjit_entries(node_id, state)",
     "CREATE INDEX IF NOT EXISTS idx_cjit_expires_at ON cjit_entries(expires_at DESC)",
     "CREATE INDEX IF NOT EXISTS idx_cjit_created_at ON cjit_entries(created_at DESC)",
+    // Status transitions indexes
+    "CREATE INDEX IF NOT EXISTS idx_status_transitions_item
+     ON status_transitions(item_kind, item_id, id)",
+    // Order events indexes
+    "CREATE INDEX IF NOT EXISTS idx_order_events_order ON order_events(order_id, id)",
 ];
@@ -0,0 +1,487 @@
+//! Push notification registration with Blocktank.
+//!
+//! Blocktank only sends notifications to a device registered by the node they are about. The
+//! registration is signed by the node key over the device token, the registration time and the
+//! public key notifications are encrypted to, the same way `lnd` and LDK sign messages. The
+//! registration and the notification secret key are stored, and the device is only registered
+//! again when the device token, the features or the node change.
+//!
+//! Notifications are encrypted with AES-256-GCM under a key derived from an ECDH between a
+//! sender key and the registered public key, so decrypting one also authenticates it.
+
+use crate::modules::blocktank::db::unix_now;
+use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+use aes_gcm::aead::consts::U16;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::aes::Aes256;
+use aes_gcm::{Aes256Gcm, AesGcm, Nonce};
+use bitcoin::base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bitcoin::hashes::{sha256, sha256d, Hash, HashEngine};
+use bitcoin::secp256k1::ecdh::shared_secret_point;
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use rand::RngCore;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Prepended to the device token, timestamp and public key of a registration before signing,
+/// and appended to the ECDH shared point to derive the notification encryption key
+pub const NOTIFICATION_SIGN_PREFIX: &str = "bitkit-notifications";
+
+/// Notification features registered when none are requested
+pub const DEFAULT_NOTIFICATION_FEATURES: [&str; 5] = [
+    "blocktank.incomingHtlc",
+    "blocktank.mutualClose",
+    "blocktank.orderPaymentConfirmed",
+    "blocktank.cjitPaymentArrived",
+    "blocktank.wakeToTimeout",
+];
+
+const LIGHTNING_MESSAGE_PREFIX: &str = "Lightning Signed Message:";
+const ZBASE32_ALPHABET: &[u8; 32] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+/// Signs device registrations with the node key, e.g. backed by LDK's `sign_message`
+#[uniffi::export(with_foreign)]
+pub trait BtNodeSigner: Send + Sync {
+    /// Hex encoded public key of the node
+    fn node_id(&self) -> String;
+    /// Signs `message` as a zbase32 encoded lightning message signature
+    fn sign_message(&self, message: String) -> Result<String, BlocktankError>;
+}
+
+/// [`BtNodeSigner`] holding the node secret key in memory
+pub struct BtNodeKeySigner {
+    secret_key: SecretKey,
+}
+
+impl BtNodeKeySigner {
+    pub fn new(secret_key_hex: &str) -> Result<Self, BlocktankError> {
+        let secret_key = hex::decode(secret_key_hex)
+            .ok()
+            .and_then(|bytes| SecretKey::from_slice(&bytes).ok())
+            .ok_or(BlocktankError::InvalidParameter {
+                error_details: "Invalid node secret key".to_string(),
+            })?;
+        Ok(Self { secret_key })
+    }
+}
+
+impl BtNodeSigner for BtNodeKeySigner {
+    fn node_id(&self) -> String {
+        self.secret_key.public_key(&Secp256k1::new()).to_string()
+    }
+
+    fn sign_message(&self, message: String) -> Result<String, BlocktankError> {
+        Ok(sign_node_message(&self.secret_key, &message))
+    }
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtDeviceRegistration {
+    pub device_token: String,
+    pub node_id: String,
+    /// Hex encoded public key notifications are encrypted to
+    pub public_key: String,
+    pub features: Vec<String>,
+    pub is_production: Option<bool>,
+    pub custom_url: Option<String>,
+    /// Signed registration time
+    pub iso_timestamp: String,
+    pub signature: String,
+    /// Unix timestamp in seconds
+    pub registered_at: u64,
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtDeviceRegistrationResult {
+    pub registration: BtDeviceRegistration,
+    /// Whether the device was registered with Blocktank by this call
+    pub registered: bool,
+    /// Hex encoded secret key notifications are decrypted with
+    pub encryption_secret_key: String,
+}
+
+/// Encrypted notification as found in the `payload` of the push notification
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtEncryptedNotification {
+    /// Base64 encoded ciphertext
+    pub cipher: String,
+    /// Hex encoded AES-GCM nonce, 12 or 16 bytes
+    pub iv: String,
+    /// Hex encoded AES-GCM authentication tag
+    pub tag: String,
+    /// Hex encoded public key of the sender
+    pub public_key: String,
+}
+
+/// Decrypted notification
+#[derive(uniffi::Enum, Debug, Clone, PartialEq, Eq)]
+pub enum BtNotification {
+    /// A payment is held by the LSP until the node comes online
+    IncomingHtlc { payment_hash: Option<String> },
+    /// A CJIT invoice was paid and its channel is being opened
+    CjitChannelOpen { cjit_entry_id: Option<String> },
+    /// The payment of an order is confirmed and its channel can be opened
+    OrderPaymentConfirmed { order_id: String },
+    /// A channel or an HTLC is about to time out and the node has to come online
+    ChannelExpiryWarning { channel_id: Option<String> },
+    /// The LSP wants to cooperatively close a channel
+    MutualClose { channel_id: Option<String> },
+}
+
+/// Decrypted notification JSON
+#[derive(Deserialize)]
+struct NotificationMessage {
+    #[serde(rename = "type")]
+    notification_type: String,
+    #[serde(default)]
+    payload: NotificationPayload,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct NotificationPayload {
+    order_id: Option<String>,
+    payment_hash: Option<String>,
+    cjit_entry_id: Option<String>,
+    channel_id: Option<String>,
+}
+
+/// Returns the message signed for a device registration
+pub fn notification_sign_message(
+    device_token: &str,
+    iso_timestamp: &str,
+    public_key: &str,
+) -> String {
+    format!(
+        "{}{}{}{}",
+        NOTIFICATION_SIGN_PREFIX, device_token, iso_timestamp, public_key
+    )
+}
+
+fn lightning_message_hash(message: &str) -> Message {
+    let hash = sha256d::Hash::hash(format!("{}{}", LIGHTNING_MESSAGE_PREFIX, message).as_bytes());
+    Message::from_digest(hash.to_byte_array())
+}
+
+/// Signs `message` with a node key as a zbase32 encoded lightning message signature
+pub fn sign_node_message(secret_key: &SecretKey, message: &str) -> String {
+    let signature =
+        Secp256k1::new().sign_ecdsa_recoverable(&lightning_message_hash(message), secret_key);
+    let (recovery_id, compact) = signature.serialize_compact();
+
+    let mut bytes = Vec::with_capacity(65);
+    bytes.push(recovery_id.to_i32() as u8 + 31);
+    bytes.extend_from_slice(&compact);
+    zbase32_encode(&bytes)
+}
+
+/// Whether `signature` is a lightning message signature of `message` by `node_id`
+pub fn verify_node_message(message: &str, signature: &str, node_id: &str) -> bool {
+    let Some(bytes) = zbase32_decode(signature).filter(|bytes| bytes.len() == 65) else {
+        return false;
+    };
+    let Some(recovery_id) = bytes[0]
+        .checked_sub(31)
+        .and_then(|id| RecoveryId::from_i32(i32::from(id)).ok())
+    else {
+        return false;
+    };
+    let Ok(signature) = RecoverableSignature::from_compact(&bytes[1..], recovery_id) else {
+        return false;
+    };
+
+    Secp256k1::new()
+        .recover_ecdsa(&lightning_message_hash(message), &signature)
+        .is_ok_and(|public_key| public_key.to_string() == node_id)
+}
+
+fn zbase32_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() * 8).div_ceil(5));
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ZBASE32_ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ZBASE32_ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    encoded
+}
+
+fn zbase32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = ZBASE32_ALPHABET.iter().position(|a| *a == c)? as u16;
+        buffer = ((buffer << 5) | value) & 0x1fff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Generates a notification keypair, returned as hex encoded (public key, secret key)
+fn generate_notification_keypair() -> (String, String) {
+    let secp = Secp256k1::new();
+    let mut bytes = [0u8; 32];
+    loop {
+        rand::thread_rng().fill_bytes(&mut bytes);
+        if let Ok(secret_key) = SecretKey::from_slice(&bytes) {
+            let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+            return (
+                public_key.to_string(),
+                hex::encode(secret_key.secret_bytes()),
+            );
+        }
+    }
+}
+
+/// Derives the AES key shared by `secret_key` and `public_key`
+fn notification_key(secret_key: &SecretKey, public_key: &PublicKey) -> [u8; 32] {
+    let point = shared_secret_point(public_key, secret_key);
+    let mut engine = sha256::Hash::engine();
+    // Compressed encoding of the shared point
+    engine.input(&[0x02 | (point[63] & 1)]);
+    engine.input(&point[..32]);
+    engine.input(NOTIFICATION_SIGN_PREFIX.as_bytes());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn parse_secret_key(secret_key_hex: &str) -> Result<SecretKey, BlocktankError> {
+    hex::decode(secret_key_hex)
+        .ok()
+        .and_then(|bytes| SecretKey::from_slice(&bytes).ok())
+        .ok_or(BlocktankError::InvalidParameter {
+            error_details: "Invalid notification secret key".to_string(),
+        })
+}
+
+fn decode_field(name: &str, decoded: Option<Vec<u8>>) -> Result<Vec<u8>, BlocktankError> {
+    decoded.ok_or(BlocktankError::InvalidParameter {
+        error_details: format!("Invalid notification {}", name),
+    })
+}
+
+/// Decrypts `notification` with the hex encoded notification secret key and parses it.
+///
+/// Fails if the notification was not encrypted to the key, was tampered with or has an
+/// unknown type.
+pub fn decrypt_notification(
+    notification: &BtEncryptedNotification,
+    secret_key_hex: &str,
+) -> Result<BtNotification, BlocktankError> {
+    let secret_key = parse_secret_key(secret_key_hex)?;
+    let sender = PublicKey::from_str(&notification.public_key).map_err(|_| {
+        BlocktankError::InvalidParameter {
+            error_details: "Invalid notification public key".to_string(),
+        }
+    })?;
+    let mut data = decode_field("cipher", BASE64.decode(&notification.cipher).ok())?;
+    let iv = decode_field("iv", hex::decode(&notification.iv).ok())?;
+    let tag = decode_field("tag", hex::decode(&notification.tag).ok())?;
+    if tag.len() != 16 {
+        return Err(BlocktankError::InvalidParameter {
+            error_details: "Invalid notification tag".to_string(),
+        });
+    }
+    data.extend_from_slice(&tag);
+
+    let key = notification_key(&secret_key, &sender);
+    let decrypted = match iv.len() {
+        12 => Aes256Gcm::new(&key.into()).decrypt(Nonce::from_slice(&iv), data.as_slice()),
+        16 => {
+            AesGcm::<Aes256, U16>::new(&key.into()).decrypt(Nonce::from_slice(&iv), data.as_slice())
+        }
+        _ => {
+            return Err(BlocktankError::InvalidParameter {
+                error_details: "Invalid notification iv".to_string(),
+            })
+        }
+    }
+    .map_err(|_| BlocktankError::DataError {
+        error_details: "Failed to decrypt notification".to_string(),
+    })?;
+
+    let message: NotificationMessage = serde_json::from_slice(&decrypted)?;
+    let payload = message.payload;
+    match message.notification_type.as_str() {
+        "incomingHtlc" => Ok(BtNotification::IncomingHtlc {
+            payment_hash: payload.payment_hash,
+        }),
+        "cjitPaymentArrived" => Ok(BtNotification::CjitChannelOpen {
+            cjit_entry_id: payload.cjit_entry_id,
+        }),
+        "orderPaymentConfirmed" => Ok(BtNotification::OrderPaymentConfirmed {
+            order_id: payload.order_id.ok_or(BlocktankError::DataError {
+                error_details: "Order payment notification without order id".to_string(),
+            })?,
+        }),
+        "wakeToTimeout" => Ok(BtNotification::ChannelExpiryWarning {
+            channel_id: payload.channel_id,
+        }),
+        "mutualClose" => Ok(BtNotification::MutualClose {
+            channel_id: payload.channel_id,
+        }),
+        other => Err(BlocktankError::DataError {
+            error_details: format!("Unknown notification type: {}", other),
+        }),
+    }
+}
+
+/// Encrypts the notification JSON `message` to the hex encoded `public_key`, as Blocktank does
+#[cfg(test)]
+pub(crate) fn encrypt_notification(
+    message: &str,
+    public_key: &str,
+) -> Result<BtEncryptedNotification, BlocktankError> {
+    let recipient =
+        PublicKey::from_str(public_key).map_err(|_| BlocktankError::InvalidParameter {
+            error_details: "Invalid notification public key".to_string(),
+        })?;
+    let (sender_public_key, sender_secret_key) = generate_notification_keypair();
+    let sender_secret_key = parse_secret_key(&sender_secret_key)?;
+    let key = notification_key(&sender_secret_key, &recipient);
+
+    let mut iv = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut iv);
+    let mut data = Aes256Gcm::new(&key.into())
+        .encrypt(Nonce::from_slice(&iv), message.as_bytes())
+        .map_err(|_| BlocktankError::DataError {
+            error_details: "Failed to encrypt notification".to_string(),
+        })?;
+    let tag = data.split_off(data.len() - 16);
+
+    Ok(BtEncryptedNotification {
+        cipher: BASE64.encode(&data),
+        iv: hex::encode(iv),
+        tag: hex::encode(tag),
+        public_key: sender_public_key,
+    })
+}
+
+/// Returns `features` sorted and deduplicated, or the default features if empty
+fn normalize_features(features: &[String]) -> Vec<String> {
+    let mut features = if features.is_empty() {
+        DEFAULT_NOTIFICATION_FEATURES
+            .iter()
+            .map(|feature| feature.to_string())
+            .collect()
+    } else {
+        features.to_vec()
+    };
+    features.sort();
+    features.dedup();
+    features
+}
+
+impl BlocktankDB {
+    /// Registers the device for push notifications unless the stored registration matches.
+    ///
+    /// The device is registered again when the device token, the features, the node or the
+    /// endpoint changed. The notification keypair is generated on the first registration of a
+    /// node and kept for later ones. Empty `features` registers `DEFAULT_NOTIFICATION_FEATURES`.
+    pub async fn ensure_device_registered(
+        &self,
+        device_token: &str,
+        features: &[String],
+        signer: Arc<dyn BtNodeSigner>,
+        is_production: Option<bool>,
+        custom_url: Option<&str>,
+    ) -> Result<BtDeviceRegistrationResult, BlocktankError> {
+        if device_token.is_empty() {
+            return Err(BlocktankError::InvalidParameter {
+                error_details: "Device token must not be empty".to_string(),
+            });
+        }
+        let features = normalize_features(features);
+        let node_id = signer.node_id();
+        let existing = self.get_device_registration().await?;
+        let secret_key = self.get_notification_secret_key().await?;
+
+        if let (Some(registration), Some(secret_key)) = (&existing, &secret_key) {
+            if registration.device_token == device_token
+                && registration.node_id == node_id
+                && registration.features == features
+                && registration.is_production == is_production
+                && registration.custom_url.as_deref() == custom_url
+            {
+                return Ok(BtDeviceRegistrationResult {
+                    registration: registration.clone(),
+                    registered: false,
+                    encryption_secret_key: secret_key.clone(),
+                });
+            }
+        }
+
+        // A new node gets a new keypair
+        let (public_key, encryption_secret_key) = match (existing, secret_key) {
+            (Some(registration), Some(secret_key)) if registration.node_id == node_id => {
+                (registration.public_key, secret_key)
+            }
+            _ => generate_notification_keypair(),
+        };
+
+        let iso_timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let signature = signer.sign_message(notification_sign_message(
+            device_token,
+            &iso_timestamp,
+            &public_key,
+        ))?;
+        self.register_device(
+            device_token,
+            &public_key,
+            &features,
+            &node_id,
+            &iso_timestamp,
+            &signature,
+            is_production,
+            custom_url,
+        )
+        .await?;
+
+        let registration = BtDeviceRegistration {
+            device_token: device_token.to_string(),
+            node_id,
+            public_key,
+            features,
+            is_production,
+            custom_url: custom_url.map(str::to_string),
+            iso_timestamp,
+            signature,
+            registered_at: unix_now(),
+        };
+        self.upsert_device_registration(&registration, &encryption_secret_key)
+            .await?;
+
+        Ok(BtDeviceRegistrationResult {
+            registration,
+            registered: true,
+            encryption_secret_key,
+        })
+    }
+
+    /// Decrypts `notification` with the secret key of the stored registration and parses it
+    pub async fn decrypt_notification(
+        &self,
+        notification: &BtEncryptedNotification,
+    ) -> Result<BtNotification, BlocktankError> {
+        let secret_key =
+            self.get_notification_secret_key()
+                .await?
+                .ok_or(BlocktankError::InvalidParameter {
+                    error_details: "No device is registered for notifications".to_string(),
+                })?;
+        decrypt_notification(notification, &secret_key)
+    }
+}
@@ -0,0 +1,229 @@
+//! Channel purchase quotes.
+//!
+//! Evaluates a grid of order options against Blocktank's fee estimates and ranks the
+//! resulting quotes by what a sat of channel capacity costs per week.
+
+use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+use rust_blocktank_client::{CreateOrderOptions, IBtEstimateFeeResponse2, IBtInfoOptions};
+use tokio::task::JoinSet;
+
+pub const DEFAULT_QUOTE_EXPIRY_WEEKS: [u32; 5] = [4, 6, 12, 26, 52];
+
+/// Upper bound on fee estimates in flight at once
+const MAX_CONCURRENT_ESTIMATES: usize = 8;
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct BtQuoteRequest {
+    /// Receiving capacity wanted, bought as the LSP balance
+    pub target_inbound_sat: u64,
+    /// Sending capacity wanted, bought as the client balance
+    pub target_outbound_sat: u64,
+    /// Highest total fee to quote, including the client balance. `None` for no limit
+    pub budget_sat: Option<u64>,
+    /// Channel durations to compare. Empty for `DEFAULT_QUOTE_EXPIRY_WEEKS`
+    pub expiry_weeks: Vec<u32>,
+    /// Number of even steps from no client balance up to `target_outbound_sat`.
+    /// 0 only quotes the target
+    pub client_balance_steps: u32,
+    /// Also quote zero-conf channels where the client balance allows it
+    pub include_zero_conf: bool,
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct BtChannelQuote {
+    pub lsp_balance_sat: u64,
+    pub client_balance_sat: u64,
+    pub channel_expiry_weeks: u32,
+    pub zero_conf: bool,
+    /// Total to pay, including the client balance
+    pub fee_sat: u64,
+    pub network_fee_sat: u64,
+    pub service_fee_sat: u64,
+    /// Fee excluding the client balance, which ends up on the client's side of the channel
+    pub cost_sat: u64,
+    /// `cost_sat` per sat of channel capacity per week of channel lifetime
+    pub cost_per_sat_week: f64,
+}
+
+/// A single point of the quote grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct QuoteCandidate {
+    client_balance_sat: u64,
+    channel_expiry_weeks: u32,
+    zero_conf: bool,
+}
+
+/// Returns the grid points of `request` that are within the LSP `limits`
+fn quote_candidates(
+    request: &BtQuoteRequest,
+    limits: &IBtInfoOptions,
+) -> Result<Vec<QuoteCandidate>, BlocktankError> {
+    if request.target_inbound_sat == 0 {
+        return Err(BlocktankError::InvalidParameter {
+            error_details: "Target inbound liquidity must be greater than 0".to_string(),
+        });
+    }
+
+    let mut expiry_weeks = if request.expiry_weeks.is_empty() {
+        DEFAULT_QUOTE_EXPIRY_WEEKS.to_vec()
+    } else {
+        request.expiry_weeks.clone()
+    };
+    expiry_weeks
+        .retain(|weeks| (limits.min_expiry_weeks..=limits.max_expiry_weeks).contains(weeks));
+    expiry_weeks.sort_unstable();
+    expiry_weeks.dedup();
+
+    let steps = u64::from(request.client_balance_steps);
+    let mut client_balances: Vec<u64> = if steps == 0 {
+        vec![request.target_outbound_sat]
+    } else {
+        (0..=steps)
+            .map(|step| request.target_outbound_sat * step / steps)
+            .collect()
+    };
+    client_balances.dedup();
+    client_balances.retain(|client_balance| {
+        let channel_size = request.target_inbound_sat.saturating_add(*client_balance);
+        *client_balance <= limits.max_client_balance_sat
+            && (limits.min_channel_size_sat..=limits.max_channel_size_sat).contains(&channel_size)
+    });
+
+    let mut candidates = Vec::new();
+    for &channel_expiry_weeks in &expiry_weeks {
+        for &client_balance_sat in &client_balances {
+            candidates.push(QuoteCandidate {
+                client_balance_sat,
+                channel_expiry_weeks,
+                zero_conf: false,
+            });
+            if request.include_zero_conf
+                && client_balance_sat <= limits.max_0_conf_client_balance_sat
+            {
+                candidates.push(QuoteCandidate {
+                    client_balance_sat,
+                    channel_expiry_weeks,
+                    zero_conf: true,
+                });
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        return Err(BlocktankError::InvalidParameter {
+            error_details: "No order options within the LSP limits".to_string(),
+        });
+    }
+    Ok(candidates)
+}
+
+fn channel_quote(
+    lsp_balance_sat: u64,
+    candidate: QuoteCandidate,
+    estimate: IBtEstimateFeeResponse2,
+) -> BtChannelQuote {
+    let cost_sat = estimate
+        .fee_sat
+        .saturating_sub(candidate.client_balance_sat);
+    let sat_weeks = (lsp_balance_sat + candidate.client_balance_sat) as f64
+        * f64::from(candidate.channel_expiry_weeks);
+
+    BtChannelQuote {
+        lsp_balance_sat,
+        client_balance_sat: candidate.client_balance_sat,
+        channel_expiry_weeks: candidate.channel_expiry_weeks,
+        zero_conf: candidate.zero_conf,
+        fee_sat: estimate.fee_sat,
+        network_fee_sat: estimate.network_fee_sat,
+        service_fee_sat: estimate.service_fee_sat,
+        cost_sat,
+        cost_per_sat_week: cost_sat as f64 / sat_weeks,
+    }
+}
+
+impl BlocktankDB {
+    /// Quotes every grid point of `request` within the LSP limits, cheapest per sat-week first.
+    ///
+    /// `options` is the base for every estimate; its client balance and zero-conf flag are
+    /// overwritten by the grid. Grid points whose estimate fails or exceeds the budget are
+    /// left out, and the first error is returned if no estimate succeeds.
+    pub async fn compare_channel_quotes(
+        &self,
+        request: &BtQuoteRequest,
+        options: Option<CreateOrderOptions>,
+    ) -> Result<Vec<BtChannelQuote>, BlocktankError> {
+        let limits = match self.get_info().await? {
+            Some(info) => info.options,
+            None => self.fetch_and_store_info().await?.options,
+        };
+        let candidates = quote_candidates(request, &limits)?;
+        let base_options = options.unwrap_or_default();
+        let lsp_balance_sat = request.target_inbound_sat;
+
+        let mut estimates = JoinSet::new();
+        let mut pending = candidates.into_iter().enumerate();
+        let mut results = Vec::new();
+        let mut first_error = None;
+        loop {
+            while estimates.len() < MAX_CONCURRENT_ESTIMATES {
+                let Some((index, candidate)) = pending.next() else {
+                    break;
+                };
+                let client = self.client.clone();
+                let options = CreateOrderOptions {
+                    client_balance_sat: candidate.client_balance_sat,
+                    zero_conf: candidate.zero_conf,
+                    ..base_options.clone()
+                };
+                estimates.spawn(async move {
+                    let estimate = client
+                        .estimate_order_fee_full(
+                            lsp_balance_sat,
+                            candidate.channel_expiry_weeks,
+                            Some(options),
+                        )
+                        .await;
+                    (index, candidate, estimate)
+                });
+            }
+
+            let Some(joined) = estimates.join_next().await else {
+                break;
+            };
+            let (index, candidate, estimate) =
+                joined.map_err(|e| BlocktankError::ConnectionError {
+                    error_details: format!("Runtime error: {}", e),
+                })?;
+            match estimate {
+                Ok(estimate) => {
+                    results.push((index, channel_quote(lsp_balance_sat, candidate, estimate)))
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        if results.is_empty() {
+            if let Some(e) = first_error {
+                return Err(BlocktankError::DataError {
+                    error_details: format!("Failed to estimate order fees: {}", e),
+                });
+            }
+        }
+
+        // Ties keep the grid order, so results do not depend on which estimate finished first
+        results.sort_by_key(|(index, _)| *index);
+        let mut quotes: Vec<BtChannelQuote> = results
+            .into_iter()
+            .map(|(_, quote)| quote)
+            .filter(|quote| {
+                request
+                    .budget_sat
+                    .is_none_or(|budget| quote.fee_sat <= budget)
+            })
+            .collect();
+        quotes.sort_by(|a, b| a.cost_per_sat_week.total_cmp(&b.cost_per_sat_week));
+        Ok(quotes)
+    }
+}
@@ -0,0 +1,136 @@
+//! Links between Blocktank orders and the activity list.
+//!
+//! An order is paid with a bolt11 invoice or on-chain transactions, and the channel it buys
+//! is opened with a funding transaction. Each of those is looked up in the activity list
+//! and the matches are stored in the `order_activities` table, so the order behind an
+//! activity can be looked up later.
+
+use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+use rust_blocktank_client::{FundingTx, IBtOrder};
+use std::str::FromStr;
+
+/// Tag of the activities that pay for or fund an order
+pub const ORDER_ACTIVITY_TAG: &str = "lsp-order";
+
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtOrderActivityKind {
+    /// Lightning or on-chain payment of the order
+    Payment,
+    /// Funding transaction of the channel bought with the order
+    ChannelFunding,
+    /// Refund of the order payment
+    Refund,
+}
+
+impl FromStr for BtOrderActivityKind {
+    type Err = BlocktankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Payment" => Ok(Self::Payment),
+            "ChannelFunding" => Ok(Self::ChannelFunding),
+            "Refund" => Ok(Self::Refund),
+            _ => Err(BlocktankError::DataError {
+                error_details: format!("Unknown order activity kind: {}", s),
+            }),
+        }
+    }
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtOrderActivityLink {
+    pub order_id: String,
+    pub activity_id: String,
+    pub kind: BtOrderActivityKind,
+    /// Channel of the order, once it is opened
+    pub channel_id: Option<String>,
+}
+
+/// What an activity of an order is looked up by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OrderActivityTarget {
+    Invoice(String),
+    TxId(String),
+}
+
+/// An activity an order is expected to have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OrderActivityRef {
+    pub target: OrderActivityTarget,
+    pub kind: BtOrderActivityKind,
+    pub channel_id: Option<String>,
+    /// Funding transaction the activity transfers funds into, if it is on-chain
+    pub transfer_tx_id: Option<String>,
+}
+
+/// Returns the channel id LDK derives from a channel funding outpoint
+pub fn funding_channel_id(funding_tx: &FundingTx) -> Option<String> {
+    let vout = u16::try_from(funding_tx.vout).ok()?;
+    let mut channel_id: [u8; 32] = hex::decode(&funding_tx.id).ok()?.try_into().ok()?;
+    // Txids are displayed in reverse byte order
+    channel_id.reverse();
+    channel_id[30] ^= (vout >> 8) as u8;
+    channel_id[31] ^= (vout & 0xff) as u8;
+    Some(hex::encode(channel_id))
+}
+
+/// Returns the activities `order` is expected to have, payments first
+pub(crate) fn order_activity_refs(order: &IBtOrder) -> Vec<OrderActivityRef> {
+    let funding_tx = order.channel.as_ref().map(|channel| &channel.funding_tx);
+    let channel_id = funding_tx.and_then(funding_channel_id);
+    let mut refs = Vec::new();
+
+    if let Some(payment) = &order.payment {
+        if let Some(invoice) = &payment.bolt11_invoice {
+            if !invoice.request.is_empty() {
+                refs.push(OrderActivityRef {
+                    target: OrderActivityTarget::Invoice(invoice.request.clone()),
+                    kind: BtOrderActivityKind::Payment,
+                    channel_id: channel_id.clone(),
+                    transfer_tx_id: None,
+                });
+            }
+        }
+        if let Some(onchain) = &payment.onchain {
+            for transaction in &onchain.transactions {
+                refs.push(OrderActivityRef {
+                    target: OrderActivityTarget::TxId(transaction.tx_id.clone()),
+                    kind: BtOrderActivityKind::Payment,
+                    channel_id: channel_id.clone(),
+                    transfer_tx_id: funding_tx.map(|funding_tx| funding_tx.id.clone()),
+                });
+            }
+        }
+    }
+
+    if let Some(funding_tx) = funding_tx {
+        if !funding_tx.id.is_empty() {
+            refs.push(OrderActivityRef {
+                target: OrderActivityTarget::TxId(funding_tx.id.clone()),
+                kind: BtOrderActivityKind::ChannelFunding,
+                channel_id,
+                transfer_tx_id: None,
+            });
+        }
+    }
+
+    refs
+}
+
+impl BlocktankDB {
+    /// Returns the stored order `activity_id` belongs to, the most recently linked one if
+    /// there are several
+    pub async fn get_order_for_activity(
+        &self,
+        activity_id: &str,
+    ) -> Result<Option<IBtOrder>, BlocktankError> {
+        let Some(link) = self.get_activity_order_links(activity_id).await?.pop() else {
+            return Ok(None);
+        };
+        Ok(self
+            .get_orders(Some(&[link.order_id]), None)
+            .await?
+            .into_iter()
+            .next())
+    }
+}
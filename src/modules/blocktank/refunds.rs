@@ -0,0 +1,340 @@
+//! Refunds of failed and expired Blocktank orders.
+//!
+//! Refundable orders are tracked in the `order_refunds` table together with the on-chain
+//! address the refund should go to. The refund state is derived from the order payment and
+//! its manual refunds, and every change is logged to the order timeline as a `RefundState`
+//! event. A refund is reconciled once its transaction is recorded in the activity list.
+
+use crate::modules::blocktank::db::unix_now;
+use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+use crate::modules::onchain::BitcoinAddressValidator;
+use crate::modules::scanner::NetworkType;
+use rust_blocktank_client::{
+    BitcoinNetworkEnum, BtOrderState2, BtPaymentState2, IBtOrder, IManualRefund,
+    ManualRefundStateEnum,
+};
+use std::str::FromStr;
+
+/// Tag of the received activity a refund is reconciled into
+pub const REFUND_ACTIVITY_TAG: &str = "lsp-refund";
+
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BtRefundState {
+    /// The payment can be refunded but no refund address is attached
+    AwaitingAddress,
+    /// A refund address is attached and Blocktank has not acted on the refund yet
+    AwaitingRefund,
+    Approved,
+    Rejected,
+    /// Blocktank reports the refund as sent
+    Sent,
+    /// The refund transaction is recorded in the activity list
+    Reconciled,
+}
+
+impl BtRefundState {
+    /// Whether the refund address can still be attached or rotated
+    pub fn accepts_address(&self) -> bool {
+        matches!(
+            self,
+            Self::AwaitingAddress | Self::AwaitingRefund | Self::Rejected
+        )
+    }
+}
+
+impl FromStr for BtRefundState {
+    type Err = BlocktankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AwaitingAddress" => Ok(Self::AwaitingAddress),
+            "AwaitingRefund" => Ok(Self::AwaitingRefund),
+            "Approved" => Ok(Self::Approved),
+            "Rejected" => Ok(Self::Rejected),
+            "Sent" => Ok(Self::Sent),
+            "Reconciled" => Ok(Self::Reconciled),
+            _ => Err(BlocktankError::DataError {
+                error_details: format!("Unknown refund state: {}", s),
+            }),
+        }
+    }
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtOrderRefund {
+    pub order_id: String,
+    pub refund_address: Option<String>,
+    pub state: BtRefundState,
+    /// Amount of the manual refund, or the paid amount until Blocktank creates one
+    pub amount_sat: u64,
+    /// Refund transaction, set once reconciled
+    pub tx_id: Option<String>,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Unix timestamp in seconds
+    pub updated_at: u64,
+}
+
+/// Whether the payment of `order` can be refunded
+pub fn is_refund_eligible(order: &IBtOrder) -> bool {
+    let Some(payment) = &order.payment else {
+        return false;
+    };
+    if payment.paid_sat == 0 {
+        return false;
+    }
+
+    match payment.state2 {
+        Some(BtPaymentState2::RefundAvailable) => true,
+        Some(BtPaymentState2::Refunded) => false,
+        _ => order.channel.is_none() && matches!(order.state2, Some(BtOrderState2::Expired)),
+    }
+}
+
+/// Latest on-chain manual refund of `order` to `refund_address`
+fn manual_refund<'a>(order: &'a IBtOrder, refund_address: &str) -> Option<&'a IManualRefund> {
+    order
+        .payment
+        .as_ref()?
+        .manual_refunds
+        .as_ref()?
+        .iter()
+        .rev()
+        .find(|refund| refund.target_type == "onchain" && refund.target == refund_address)
+}
+
+/// Returns the refund state of `order`, or `None` if it has nothing to refund
+pub fn refund_state(order: &IBtOrder, refund_address: Option<&str>) -> Option<BtRefundState> {
+    let payment = order.payment.as_ref()?;
+    let manual_refund = refund_address.and_then(|address| manual_refund(order, address));
+
+    if matches!(payment.state2, Some(BtPaymentState2::Refunded)) {
+        return Some(BtRefundState::Sent);
+    }
+    match manual_refund.map(|refund| &refund.state) {
+        Some(ManualRefundStateEnum::Sent) => return Some(BtRefundState::Sent),
+        Some(ManualRefundStateEnum::Approved) => return Some(BtRefundState::Approved),
+        Some(ManualRefundStateEnum::Rejected) => return Some(BtRefundState::Rejected),
+        Some(ManualRefundStateEnum::Created) | None => {}
+    }
+
+    if manual_refund.is_none() && !is_refund_eligible(order) {
+        return None;
+    }
+    Some(match refund_address {
+        Some(_) => BtRefundState::AwaitingRefund,
+        None => BtRefundState::AwaitingAddress,
+    })
+}
+
+fn refund_amount(order: &IBtOrder, refund_address: Option<&str>) -> u64 {
+    refund_address
+        .and_then(|address| manual_refund(order, address))
+        .map(|refund| refund.amount_sat)
+        .or_else(|| order.payment.as_ref().map(|payment| payment.paid_sat))
+        .unwrap_or(0)
+}
+
+/// Whether `address`, validated as `address_network`, can be used on `network`
+fn is_address_on_network(
+    address: &str,
+    address_network: &NetworkType,
+    network: &BitcoinNetworkEnum,
+) -> bool {
+    match network {
+        BitcoinNetworkEnum::Mainnet => matches!(address_network, NetworkType::Bitcoin),
+        BitcoinNetworkEnum::Testnet | BitcoinNetworkEnum::Signet => {
+            matches!(address_network, NetworkType::Testnet | NetworkType::Signet)
+        }
+        // Base58 regtest addresses share the testnet prefixes, bech32 ones do not
+        BitcoinNetworkEnum::Regtest => match address_network {
+            NetworkType::Regtest => true,
+            NetworkType::Testnet => !address.starts_with("tb1"),
+            _ => false,
+        },
+    }
+}
+
+impl BlocktankDB {
+    /// Returns the stored orders whose payment can be refunded
+    pub async fn get_refundable_orders(&self) -> Result<Vec<IBtOrder>, BlocktankError> {
+        let orders = self.get_orders(None, None).await?;
+        Ok(orders.into_iter().filter(is_refund_eligible).collect())
+    }
+
+    /// Attaches `address` as the refund address of an order, replacing the previous one.
+    ///
+    /// The address has to be valid on the network of the LSP. It can no longer be changed
+    /// once Blocktank approved or sent the refund.
+    pub async fn set_refund_address(
+        &self,
+        order_id: &str,
+        address: &str,
+    ) -> Result<BtOrderRefund, BlocktankError> {
+        let order = self.get_stored_order(order_id).await?;
+        let existing = self.get_order_refund(order_id).await?;
+
+        match &existing {
+            Some(refund) if !refund.state.accepts_address() => {
+                return Err(BlocktankError::OrderState {
+                    error_details: format!(
+                        "Refund of order {} is already {:?}",
+                        order_id, refund.state
+                    ),
+                });
+            }
+            None if !is_refund_eligible(&order) => {
+                return Err(BlocktankError::OrderState {
+                    error_details: format!("Order {} is not eligible for a refund", order_id),
+                });
+            }
+            _ => {}
+        }
+
+        let validation = BitcoinAddressValidator::validate_address(address).map_err(|e| {
+            BlocktankError::InvalidParameter {
+                error_details: format!("Invalid refund address: {}", e),
+            }
+        })?;
+        let network = match self.get_info().await? {
+            Some(info) => info.onchain.network,
+            None => self.fetch_and_store_info().await?.onchain.network,
+        };
+        if !is_address_on_network(address, &validation.network, &network) {
+            return Err(BlocktankError::InvalidParameter {
+                error_details: format!("Refund address {} is not a {:?} address", address, network),
+            });
+        }
+
+        let now = unix_now();
+        let state = refund_state(&order, Some(address)).unwrap_or(BtRefundState::AwaitingRefund);
+        let refund = BtOrderRefund {
+            order_id: order_id.to_string(),
+            refund_address: Some(address.to_string()),
+            state,
+            amount_sat: refund_amount(&order, Some(address)),
+            tx_id: None,
+            created_at: existing.as_ref().map_or(now, |refund| refund.created_at),
+            updated_at: now,
+        };
+        self.upsert_order_refund(&refund, &order, existing.map(|refund| refund.state))
+            .await
+    }
+
+    /// Refreshes refundable orders from Blocktank and updates their refund state.
+    ///
+    /// Stored orders that became refundable are tracked from here on. Returns every
+    /// tracked refund.
+    pub async fn refresh_order_refunds(&self) -> Result<Vec<BtOrderRefund>, BlocktankError> {
+        let tracked = self.get_order_refunds().await?;
+        let pending_ids: Vec<String> = tracked
+            .iter()
+            .filter(|refund| refund.state != BtRefundState::Reconciled)
+            .map(|refund| refund.order_id.clone())
+            .collect();
+        if !pending_ids.is_empty() {
+            self.refresh_orders(&pending_ids).await?;
+        }
+
+        for order in self.get_orders(None, None).await? {
+            let existing = tracked.iter().find(|refund| refund.order_id == order.id);
+            match existing {
+                Some(refund) if refund.state == BtRefundState::Reconciled => continue,
+                None if !is_refund_eligible(&order) => continue,
+                _ => {}
+            }
+            let refund_address = existing.and_then(|refund| refund.refund_address.as_deref());
+            let Some(state) = refund_state(&order, refund_address) else {
+                continue;
+            };
+            let amount_sat = refund_amount(&order, refund_address);
+            if existing
+                .is_some_and(|refund| refund.state == state && refund.amount_sat == amount_sat)
+            {
+                continue;
+            }
+
+            let now = unix_now();
+            let refund = BtOrderRefund {
+                order_id: order.id.clone(),
+                refund_address: refund_address.map(str::to_string),
+                state,
+                amount_sat,
+                tx_id: None,
+                created_at: existing.map_or(now, |refund| refund.created_at),
+                updated_at: now,
+            };
+            self.upsert_order_refund(&refund, &order, existing.map(|refund| refund.state))
+                .await?;
+        }
+
+        self.get_order_refunds().await
+    }
+
+    /// Returns the refund of an order if it can be marked as received in `tx_id`.
+    ///
+    /// The refund needs a refund address and must not be reconciled with another transaction.
+    pub async fn get_reconcilable_refund(
+        &self,
+        order_id: &str,
+        tx_id: &str,
+    ) -> Result<BtOrderRefund, BlocktankError> {
+        self.get_stored_order(order_id).await?;
+        let refund =
+            self.get_order_refund(order_id)
+                .await?
+                .ok_or_else(|| BlocktankError::OrderState {
+                    error_details: format!("Order {} has no refund", order_id),
+                })?;
+
+        if refund.state == BtRefundState::Reconciled {
+            if refund.tx_id.as_deref() == Some(tx_id) {
+                return Ok(refund);
+            }
+            return Err(BlocktankError::OrderState {
+                error_details: format!("Refund of order {} is already reconciled", order_id),
+            });
+        }
+        if refund.refund_address.is_none() {
+            return Err(BlocktankError::OrderState {
+                error_details: format!("Order {} has no refund address", order_id),
+            });
+        }
+        Ok(refund)
+    }
+
+    /// Marks the refund of an order as received in `tx_id`.
+    ///
+    /// Marking a reconciled refund again with the same transaction is a no-op.
+    pub async fn mark_refund_reconciled(
+        &self,
+        order_id: &str,
+        tx_id: &str,
+    ) -> Result<BtOrderRefund, BlocktankError> {
+        let refund = self.get_reconcilable_refund(order_id, tx_id).await?;
+        if refund.state == BtRefundState::Reconciled {
+            return Ok(refund);
+        }
+
+        let order = self.get_stored_order(order_id).await?;
+        let previous_state = refund.state;
+        let reconciled = BtOrderRefund {
+            state: BtRefundState::Reconciled,
+            tx_id: Some(tx_id.to_string()),
+            updated_at: unix_now(),
+            ..refund
+        };
+        self.upsert_order_refund(&reconciled, &order, Some(previous_state))
+            .await
+    }
+
+    async fn get_stored_order(&self, order_id: &str) -> Result<IBtOrder, BlocktankError> {
+        self.get_orders(Some(&[order_id.to_string()]), None)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| BlocktankError::DataError {
+                error_details: format!("Order {} not found", order_id),
+            })
+    }
+}
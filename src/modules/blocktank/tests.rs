@@ -5,8 +5,20 @@ const STAGING_SERVER: &str = "https://api.stag.blocktank.to/blocktank/api/v2";
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+    use crate::modules::blocktank::{
+        cjit_status, cjit_transition_events, decrypt_notification, funding_channel_id,
+        next_poll_interval, notification_sign_message, order_activity_refs, order_status,
+        order_transition_events, run_order_tracker, verify_node_message, BlocktankDB,
+        BlocktankError, BtDeviceRegistrationResult, BtEncryptedNotification, BtLifecycleEvent,
+        BtLifecycleListener, BtLifecycleStatus, BtLspsTransport, BtNodeKeySigner, BtNodeSigner,
+        BtNotification, BtOrderActivityKind, BtOrderActivityLink, BtOrderEvent, BtOrderEventField,
+        BtQuoteRequest, BtRefundState, BtTrackedItemKind, BtTrackerConfig, MockLsp, MockLspConfig,
+        MockLspsConfig, MockLspsServer, OrderActivityTarget, DEFAULT_NOTIFICATION_FEATURES,
+        MOCK_LSP_PUBKEY, MOCK_LSP_START_HEIGHT,
+    };
     use rust_blocktank_client::*;
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_upsert_info() {
@@ -2214,6 +2226,1552 @@ mod tests {
         assert_eq!(orders_new.len(), 1);
     }
 
+    #[test]
+    fn test_order_lifecycle_status() {
+        let mut order = create_test_order("lifecycle_order");
+        assert_eq!(order_status(&order), BtLifecycleStatus::AwaitingPayment);
+
+        order.state2 = Some(BtOrderState2::Paid);
+        order.payment.as_mut().unwrap().state2 = Some(BtPaymentState2::Paid);
+        assert_eq!(order_status(&order), BtLifecycleStatus::PaymentReceived);
+
+        order.state2 = Some(BtOrderState2::Executed);
+        assert_eq!(order_status(&order), BtLifecycleStatus::ChannelOpening);
+
+        order.channel = Some(create_test_channel(BtOpenChannelState::Opening));
+        assert_eq!(order_status(&order), BtLifecycleStatus::ChannelOpening);
+
+        order.channel = Some(create_test_channel(BtOpenChannelState::Open));
+        assert_eq!(order_status(&order), BtLifecycleStatus::ChannelOpen);
+
+        order.channel = Some(create_test_channel(BtOpenChannelState::Closed));
+        assert_eq!(order_status(&order), BtLifecycleStatus::ChannelClosed);
+
+        let mut expired = create_test_order("expired_order");
+        expired.state2 = Some(BtOrderState2::Expired);
+        assert_eq!(order_status(&expired), BtLifecycleStatus::Expired);
+
+        // A refund takes precedence over the expired order state
+        expired.payment.as_mut().unwrap().state2 = Some(BtPaymentState2::RefundAvailable);
+        assert_eq!(order_status(&expired), BtLifecycleStatus::RefundAvailable);
+
+        expired.payment.as_mut().unwrap().state2 = Some(BtPaymentState2::Refunded);
+        assert_eq!(order_status(&expired), BtLifecycleStatus::Refunded);
+        assert!(BtLifecycleStatus::Refunded.is_terminal());
+        assert!(!BtLifecycleStatus::RefundAvailable.is_terminal());
+    }
+
+    #[test]
+    fn test_cjit_lifecycle_status() {
+        let mut entry = create_test_cjit_entry("lifecycle_cjit");
+        assert_eq!(cjit_status(&entry), BtLifecycleStatus::AwaitingPayment);
+
+        entry.channel = Some(create_test_channel(BtOpenChannelState::Opening));
+        assert_eq!(cjit_status(&entry), BtLifecycleStatus::ChannelOpening);
+
+        entry.state = CJitStateEnum::Completed;
+        entry.channel = None;
+        assert_eq!(cjit_status(&entry), BtLifecycleStatus::ChannelOpen);
+
+        entry.state = CJitStateEnum::Expired;
+        assert_eq!(cjit_status(&entry), BtLifecycleStatus::Expired);
+
+        entry.state = CJitStateEnum::Failed;
+        entry.channel_open_error = Some("peer offline".to_string());
+        assert_eq!(cjit_status(&entry), BtLifecycleStatus::Failed);
+    }
+
+    #[test]
+    fn test_lifecycle_transition_events() {
+        let mut order = create_test_order("events_order");
+        assert!(order_transition_events(BtLifecycleStatus::AwaitingPayment, &order).is_empty());
+
+        order.state2 = Some(BtOrderState2::Paid);
+        order.payment.as_mut().unwrap().paid_sat = 51500;
+        assert_eq!(
+            order_transition_events(BtLifecycleStatus::AwaitingPayment, &order),
+            vec![BtLifecycleEvent::PaymentReceived {
+                item_id: "events_order".to_string(),
+                kind: BtTrackedItemKind::Order,
+                paid_sat: 51500,
+            }]
+        );
+
+        // Payment and channel opening observed in the same poll emit both events
+        order.channel = Some(create_test_channel(BtOpenChannelState::Open));
+        let events = order_transition_events(BtLifecycleStatus::AwaitingPayment, &order);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            BtLifecycleEvent::PaymentReceived { .. }
+        ));
+        assert_eq!(
+            events[1],
+            BtLifecycleEvent::ChannelOpen {
+                item_id: "events_order".to_string(),
+                kind: BtTrackedItemKind::Order,
+                funding_tx_id: Some("funding_tx".to_string()),
+                short_channel_id: Some("800000x1x0".to_string()),
+            }
+        );
+
+        let events = order_transition_events(BtLifecycleStatus::ChannelOpening, &order);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], BtLifecycleEvent::ChannelOpen { .. }));
+
+        order.channel = None;
+        order.state2 = Some(BtOrderState2::Expired);
+        order.payment.as_mut().unwrap().state2 = Some(BtPaymentState2::RefundAvailable);
+        assert_eq!(
+            order_transition_events(BtLifecycleStatus::PaymentReceived, &order),
+            vec![BtLifecycleEvent::RefundAvailable {
+                item_id: "events_order".to_string(),
+                kind: BtTrackedItemKind::Order,
+                amount_sat: 51500,
+            }]
+        );
+
+        let mut entry = create_test_cjit_entry("events_cjit");
+        entry.state = CJitStateEnum::Failed;
+        entry.channel_open_error = Some("peer offline".to_string());
+        assert_eq!(
+            cjit_transition_events(BtLifecycleStatus::AwaitingPayment, &entry),
+            vec![BtLifecycleEvent::Failed {
+                item_id: "events_cjit".to_string(),
+                kind: BtTrackedItemKind::CjitEntry,
+                reason: Some("peer offline".to_string()),
+            }]
+        );
+
+        entry.state = CJitStateEnum::Expired;
+        assert_eq!(
+            cjit_transition_events(BtLifecycleStatus::AwaitingPayment, &entry),
+            vec![BtLifecycleEvent::Expired {
+                item_id: "events_cjit".to_string(),
+                kind: BtTrackedItemKind::CjitEntry,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_transition_history() {
+        let db = BlocktankDB::new(":memory:", Some(STAGING_SERVER))
+            .await
+            .unwrap();
+
+        let order = create_test_order("history_order");
+        db.upsert_order(&order).await.unwrap();
+
+        let kind = BtTrackedItemKind::Order;
+        assert!(db.get_last_status(&order.id, kind).await.unwrap().is_none());
+
+        db.record_status_transition(&order.id, kind, None, BtLifecycleStatus::AwaitingPayment)
+            .await
+            .unwrap();
+        db.record_status_transition(
+            &order.id,
+            kind,
+            Some(BtLifecycleStatus::AwaitingPayment),
+            BtLifecycleStatus::PaymentReceived,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            db.get_last_status(&order.id, kind).await.unwrap(),
+            Some(BtLifecycleStatus::PaymentReceived)
+        );
+        // The kind is part of the key
+        assert!(db
+            .get_last_status(&order.id, BtTrackedItemKind::CjitEntry)
+            .await
+            .unwrap()
+            .is_none());
+
+        let history = db.get_status_history(&order.id).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].from_status, None);
+        assert_eq!(history[0].to_status, BtLifecycleStatus::AwaitingPayment);
+        assert_eq!(
+            history[1].from_status,
+            Some(BtLifecycleStatus::AwaitingPayment)
+        );
+        assert_eq!(history[1].to_status, BtLifecycleStatus::PaymentReceived);
+        assert!(history[1].recorded_at >= history[0].recorded_at);
+
+        let latest = db.get_latest_statuses(kind).await.unwrap();
+        assert_eq!(
+            latest.get(&order.id),
+            Some(&BtLifecycleStatus::PaymentReceived)
+        );
+
+        // Transitions of removed orders are no longer reported as tracked
+        db.remove_all_orders().await.unwrap();
+        assert!(db.get_latest_statuses(kind).await.unwrap().is_empty());
+        assert_eq!(db.get_status_history(&order.id).await.unwrap().len(), 2);
+
+        db.wipe_all().await.unwrap();
+        assert!(db.get_status_history(&order.id).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_next_poll_interval() {
+        let config = BtTrackerConfig {
+            base_interval_secs: 5,
+            max_interval_secs: 60,
+        };
+
+        let interval = next_poll_interval(Duration::from_secs(5), &config, false);
+        assert_eq!(interval, Duration::from_secs(10));
+        let interval = next_poll_interval(interval, &config, false);
+        assert_eq!(interval, Duration::from_secs(20));
+        let interval = next_poll_interval(Duration::from_secs(40), &config, false);
+        assert_eq!(interval, Duration::from_secs(60));
+        let interval = next_poll_interval(interval, &config, false);
+        assert_eq!(interval, Duration::from_secs(60));
+
+        let interval = next_poll_interval(interval, &config, true);
+        assert_eq!(interval, Duration::from_secs(5));
+    }
+
+    struct RecordingListener {
+        events: StdMutex<Vec<BtLifecycleEvent>>,
+    }
+
+    impl BtLifecycleListener for RecordingListener {
+        fn on_event(&self, event: BtLifecycleEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_order_tracker_forwards_events() {
+        let listener = Arc::new(RecordingListener {
+            events: StdMutex::new(Vec::new()),
+        });
+        let polls = Arc::new(StdMutex::new(0u32));
+
+        let poll_count = polls.clone();
+        let handle = tokio::spawn(run_order_tracker(
+            BtTrackerConfig::default(),
+            listener.clone(),
+            move || {
+                let poll_count = poll_count.clone();
+                async move {
+                    *poll_count.lock().unwrap() += 1;
+                    Ok::<_, BlocktankError>(vec![BtLifecycleEvent::Expired {
+                        item_id: "tracked_order".to_string(),
+                        kind: BtTrackedItemKind::Order,
+                    }])
+                }
+            },
+        ));
+
+        for _ in 0..100 {
+            if !listener.events.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        handle.abort();
+
+        // The first poll runs immediately, the next one only after the base interval
+        assert_eq!(*polls.lock().unwrap(), 1);
+        assert_eq!(
+            listener.events.lock().unwrap().as_slice(),
+            &[BtLifecycleEvent::Expired {
+                item_id: "tracked_order".to_string(),
+                kind: BtTrackedItemKind::Order,
+            }]
+        );
+    }
+
+    async fn create_mock_db() -> (Arc<MockLsp>, BlocktankDB) {
+        let mock = Arc::new(MockLsp::default());
+        let db = BlocktankDB::with_api(":memory:", mock.clone())
+            .await
+            .expect("Failed to create database");
+        (mock, db)
+    }
+
+    #[tokio::test]
+    async fn test_mock_lsp_order_flow() {
+        let (mock, db) = create_mock_db().await;
+
+        let info = db.fetch_and_store_info().await.unwrap();
+        assert_eq!(info.nodes.len(), 1);
+        assert!(db.get_info().await.unwrap().is_some());
+
+        let order = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        assert_eq!(order.id, "mock-order-1");
+        // 2000 sat network fee plus 1000 ppm of the channel per week
+        assert_eq!(order.network_fee_sat, 2_000);
+        assert_eq!(order.service_fee_sat, 400);
+        assert_eq!(order.fee_sat, 2_400);
+
+        // The first poll only starts tracking the order
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+
+        // A channel can only be opened for a paid order
+        assert!(db
+            .open_channel(order.id.clone(), "client_pubkey@127.0.0.1:9735".to_string())
+            .await
+            .is_err());
+
+        mock.pay_order(&order.id).unwrap();
+        assert_eq!(
+            db.poll_tracked_items().await.unwrap(),
+            vec![BtLifecycleEvent::PaymentReceived {
+                item_id: order.id.clone(),
+                kind: BtTrackedItemKind::Order,
+                paid_sat: 2_400,
+            }]
+        );
+
+        let opened = db
+            .open_channel(order.id.clone(), "client_pubkey@127.0.0.1:9735".to_string())
+            .await
+            .unwrap();
+        let funding_tx_id = opened.channel.unwrap().funding_tx.id;
+        assert_eq!(
+            db.poll_tracked_items().await.unwrap(),
+            vec![BtLifecycleEvent::ChannelOpening {
+                item_id: order.id.clone(),
+                kind: BtTrackedItemKind::Order,
+                funding_tx_id: Some(funding_tx_id.clone()),
+            }]
+        );
+
+        // Nothing changes until the funding transaction confirms
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+        db.regtest_mine(Some(1)).await.unwrap();
+        assert_eq!(mock.block_height(), MOCK_LSP_START_HEIGHT + 1);
+        assert_eq!(
+            db.poll_tracked_items().await.unwrap(),
+            vec![BtLifecycleEvent::ChannelOpen {
+                item_id: order.id.clone(),
+                kind: BtTrackedItemKind::Order,
+                funding_tx_id: Some(funding_tx_id.clone()),
+                short_channel_id: Some(format!("{}x1x0", MOCK_LSP_START_HEIGHT + 1)),
+            }]
+        );
+
+        let history = db.get_status_history(&order.id).await.unwrap();
+        let statuses: Vec<BtLifecycleStatus> = history.iter().map(|t| t.to_status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                BtLifecycleStatus::AwaitingPayment,
+                BtLifecycleStatus::PaymentReceived,
+                BtLifecycleStatus::ChannelOpening,
+                BtLifecycleStatus::ChannelOpen,
+            ]
+        );
+
+        // Closing the channel is not polled for once the channel is open
+        db.regtest_close_channel(&funding_tx_id, 0, None)
+            .await
+            .unwrap();
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+        let stored = db
+            .refresh_orders(std::slice::from_ref(&order.id))
+            .await
+            .unwrap();
+        assert_eq!(order_status(&stored[0]), BtLifecycleStatus::ChannelClosed);
+    }
+
+    #[tokio::test]
+    async fn test_order_timeline() {
+        let (mock, db) = create_mock_db().await;
+
+        let order = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        let changes = |events: &[BtOrderEvent]| {
+            events
+                .iter()
+                .map(|event| {
+                    (
+                        event.field,
+                        event.old_value.clone(),
+                        event.new_value.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let some = |value: &str| Some(value.to_string());
+
+        let timeline = db.get_order_timeline(&order.id).await.unwrap();
+        assert_eq!(
+            changes(&timeline),
+            vec![
+                (BtOrderEventField::State, None, some("Created")),
+                (BtOrderEventField::State2, None, some("Created")),
+                (BtOrderEventField::PaymentState, None, some("Created")),
+            ]
+        );
+        assert!(timeline
+            .iter()
+            .all(|event| event.fee_sat == order.fee_sat
+                && event.order_updated_at == order.updated_at));
+
+        // Storing an unchanged order does not log anything
+        db.upsert_order(&order).await.unwrap();
+        db.refresh_orders(std::slice::from_ref(&order.id))
+            .await
+            .unwrap();
+        assert_eq!(db.get_order_timeline(&order.id).await.unwrap().len(), 3);
+
+        mock.pay_order(&order.id).unwrap();
+        db.refresh_orders(std::slice::from_ref(&order.id))
+            .await
+            .unwrap();
+        db.open_channel(order.id.clone(), "client_pubkey@127.0.0.1:9735".to_string())
+            .await
+            .unwrap();
+        mock.advance(Duration::from_secs(600));
+        db.refresh_orders(std::slice::from_ref(&order.id))
+            .await
+            .unwrap();
+
+        let timeline = db.get_order_timeline(&order.id).await.unwrap();
+        assert_eq!(
+            changes(&timeline[3..]),
+            vec![
+                (BtOrderEventField::State2, some("Created"), some("Paid")),
+                (
+                    BtOrderEventField::PaymentState,
+                    some("Created"),
+                    some("Paid")
+                ),
+                (BtOrderEventField::State2, some("Paid"), some("Executed")),
+                (BtOrderEventField::ChannelState, None, some("Opening")),
+                (BtOrderEventField::State, some("Created"), some("Open")),
+                (
+                    BtOrderEventField::ChannelState,
+                    some("Opening"),
+                    some("Open")
+                ),
+            ]
+        );
+        assert!(timeline
+            .windows(2)
+            .all(|w| w[0].recorded_at <= w[1].recorded_at));
+
+        // The log outlives the order row until the database is wiped
+        db.remove_all_orders().await.unwrap();
+        assert_eq!(db.get_order_timeline(&order.id).await.unwrap().len(), 9);
+        db.wipe_all().await.unwrap();
+        assert!(db.get_order_timeline(&order.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_compare_channel_quotes() {
+        let (mock, db) = create_mock_db().await;
+
+        let request = BtQuoteRequest {
+            target_inbound_sat: 100_000,
+            target_outbound_sat: 200_000,
+            budget_sat: None,
+            // 104 weeks is above the LSP maximum of 52
+            expiry_weeks: vec![4, 2, 104, 4],
+            client_balance_steps: 2,
+            include_zero_conf: true,
+        };
+        let quotes = db.compare_channel_quotes(&request, None).await.unwrap();
+        // The limits are fetched when no info is stored
+        assert!(db.get_info().await.unwrap().is_some());
+
+        // 0, 100k and 200k client balance for 2 and 4 weeks, zero-conf up to 100k
+        assert_eq!(quotes.len(), 10);
+        assert!(quotes
+            .windows(2)
+            .all(|w| w[0].cost_per_sat_week <= w[1].cost_per_sat_week));
+        assert!(quotes
+            .iter()
+            .all(|quote| !quote.zero_conf || quote.client_balance_sat <= 100_000));
+
+        // The largest and longest channel spreads the network fee the most
+        let best = &quotes[0];
+        assert_eq!(best.lsp_balance_sat, 100_000);
+        assert_eq!(best.client_balance_sat, 200_000);
+        assert_eq!(best.channel_expiry_weeks, 4);
+        assert!(!best.zero_conf);
+        assert_eq!(best.fee_sat, 2_000 + 1_200 + 200_000);
+        assert_eq!(best.cost_sat, 3_200);
+        assert!((best.cost_per_sat_week - 3_200.0 / 1_200_000.0).abs() < f64::EPSILON);
+
+        // Only quotes without a client balance fit the budget
+        let budget_request = BtQuoteRequest {
+            budget_sat: Some(5_000),
+            ..request.clone()
+        };
+        let quotes = db
+            .compare_channel_quotes(&budget_request, None)
+            .await
+            .unwrap();
+        assert_eq!(quotes.len(), 4);
+        assert!(quotes.iter().all(|quote| quote.client_balance_sat == 0));
+        assert_eq!(quotes[0].channel_expiry_weeks, 4);
+        assert_eq!(quotes[0].fee_sat, 2_400);
+
+        let invalid = [
+            BtQuoteRequest {
+                target_inbound_sat: 0,
+                ..request.clone()
+            },
+            BtQuoteRequest {
+                expiry_weeks: vec![104],
+                ..request.clone()
+            },
+            BtQuoteRequest {
+                target_inbound_sat: 20_000_000,
+                ..request.clone()
+            },
+        ];
+        for invalid_request in &invalid {
+            assert!(matches!(
+                db.compare_channel_quotes(invalid_request, None).await,
+                Err(BlocktankError::InvalidParameter { .. })
+            ));
+        }
+
+        mock.set_unreachable(true);
+        assert!(matches!(
+            db.compare_channel_quotes(&request, None).await,
+            Err(BlocktankError::DataError { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_order_refund_flow() {
+        let (mock, db) = create_mock_db().await;
+        let refund_address = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+        let rotated_address = "bcrt1qqypqxpq9qcrsszg2pvxq6rs0zqg3yyc5phstwt";
+
+        let order = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        assert!(matches!(
+            db.set_refund_address(&order.id, refund_address).await,
+            Err(BlocktankError::OrderState { .. })
+        ));
+
+        // A paid order that expires without a channel can be refunded
+        mock.pay_order(&order.id).unwrap();
+        mock.advance(Duration::from_secs(3_600));
+        let refunds = db.refresh_order_refunds().await.unwrap();
+        assert!(refunds.is_empty());
+        db.refresh_orders(std::slice::from_ref(&order.id))
+            .await
+            .unwrap();
+        let refundable = db.get_refundable_orders().await.unwrap();
+        assert_eq!(refundable.len(), 1);
+        assert_eq!(refundable[0].id, order.id);
+
+        let refunds = db.refresh_order_refunds().await.unwrap();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].state, BtRefundState::AwaitingAddress);
+        assert_eq!(refunds[0].refund_address, None);
+        assert_eq!(refunds[0].amount_sat, order.fee_sat);
+
+        // The address has to be valid on the LSP network
+        for address in [
+            "not an address",
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+        ] {
+            assert!(matches!(
+                db.set_refund_address(&order.id, address).await,
+                Err(BlocktankError::InvalidParameter { .. })
+            ));
+        }
+
+        let refund = db
+            .set_refund_address(&order.id, refund_address)
+            .await
+            .unwrap();
+        assert_eq!(refund.state, BtRefundState::AwaitingRefund);
+        let refund = db
+            .set_refund_address(&order.id, rotated_address)
+            .await
+            .unwrap();
+        assert_eq!(refund.refund_address.as_deref(), Some(rotated_address));
+        assert_eq!(
+            db.get_order_refund(&order.id).await.unwrap(),
+            Some(refund.clone())
+        );
+
+        // Refunds to the previous address are not attributed to the order
+        mock.manual_refund(&order.id, refund_address, ManualRefundStateEnum::Approved)
+            .unwrap();
+        let refunds = db.refresh_order_refunds().await.unwrap();
+        assert_eq!(refunds[0].state, BtRefundState::AwaitingRefund);
+
+        mock.manual_refund(&order.id, rotated_address, ManualRefundStateEnum::Approved)
+            .unwrap();
+        let refunds = db.refresh_order_refunds().await.unwrap();
+        assert_eq!(refunds[0].state, BtRefundState::Approved);
+        assert!(matches!(
+            db.set_refund_address(&order.id, refund_address).await,
+            Err(BlocktankError::OrderState { .. })
+        ));
+
+        mock.manual_refund(&order.id, rotated_address, ManualRefundStateEnum::Sent)
+            .unwrap();
+        let refunds = db.refresh_order_refunds().await.unwrap();
+        assert_eq!(refunds[0].state, BtRefundState::Sent);
+        assert!(db.get_refundable_orders().await.unwrap().is_empty());
+
+        let tx_id = "a".repeat(64);
+        assert_eq!(
+            db.get_reconcilable_refund(&order.id, &tx_id)
+                .await
+                .unwrap()
+                .state,
+            BtRefundState::Sent
+        );
+        let refund = db.mark_refund_reconciled(&order.id, &tx_id).await.unwrap();
+        assert_eq!(refund.state, BtRefundState::Reconciled);
+        assert_eq!(refund.tx_id.as_deref(), Some(tx_id.as_str()));
+        assert_eq!(
+            db.mark_refund_reconciled(&order.id, &tx_id).await.unwrap(),
+            refund
+        );
+        assert!(db
+            .mark_refund_reconciled(&order.id, &"b".repeat(64))
+            .await
+            .is_err());
+        assert!(matches!(
+            db.get_reconcilable_refund(&order.id, &"b".repeat(64)).await,
+            Err(BlocktankError::OrderState { .. })
+        ));
+        assert_eq!(
+            db.refresh_order_refunds().await.unwrap()[0].state,
+            BtRefundState::Reconciled
+        );
+
+        // Every refund state change is on the order timeline
+        let refund_states: Vec<(Option<String>, Option<String>)> = db
+            .get_order_timeline(&order.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|event| event.field == BtOrderEventField::RefundState)
+            .map(|event| (event.old_value, event.new_value))
+            .collect();
+        let some = |value: &str| Some(value.to_string());
+        assert_eq!(
+            refund_states,
+            vec![
+                (None, some("AwaitingAddress")),
+                (some("AwaitingAddress"), some("AwaitingRefund")),
+                (some("AwaitingRefund"), some("Approved")),
+                (some("Approved"), some("Sent")),
+                (some("Sent"), some("Reconciled")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_lsp_order_expiry() {
+        let (mock, db) = create_mock_db().await;
+
+        let unpaid = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        let paid = db.create_and_store_order(200_000, 4, None).await.unwrap();
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+
+        mock.pay_order(&paid.id).unwrap();
+        assert_eq!(db.poll_tracked_items().await.unwrap().len(), 1);
+
+        mock.advance(Duration::from_secs(3_599));
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+
+        mock.advance(Duration::from_secs(1));
+        let events = db.poll_tracked_items().await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.contains(&BtLifecycleEvent::Expired {
+            item_id: unpaid.id.clone(),
+            kind: BtTrackedItemKind::Order,
+        }));
+        assert!(events.contains(&BtLifecycleEvent::RefundAvailable {
+            item_id: paid.id.clone(),
+            kind: BtTrackedItemKind::Order,
+            amount_sat: paid.fee_sat,
+        }));
+
+        // Expired orders can no longer be paid
+        assert!(mock.pay_order(&unpaid.id).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_lsp_cjit_flow() {
+        let (mock, db) = create_mock_db().await;
+
+        let entry = db
+            .create_cjit_entry(200_000, 50_000, "test", "client_pubkey", 4, None)
+            .await
+            .unwrap();
+        let failing = db
+            .create_cjit_entry(200_000, 50_000, "test", "client_pubkey", 4, None)
+            .await
+            .unwrap();
+        // The invoice has to cover the channel fee
+        assert!(db
+            .create_cjit_entry(200_000, 1_000, "test", "client_pubkey", 4, None)
+            .await
+            .is_err());
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+
+        let payment_id = db.regtest_pay(&entry.invoice.request, None).await.unwrap();
+        assert_eq!(payment_id, entry.id);
+        mock.fail_cjit_entry(&failing.id, "peer offline").unwrap();
+
+        let events = db.poll_tracked_items().await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().any(|event| matches!(
+            event,
+            BtLifecycleEvent::ChannelOpening { item_id, .. } if item_id == &entry.id
+        )));
+        assert!(events.contains(&BtLifecycleEvent::Failed {
+            item_id: failing.id.clone(),
+            kind: BtTrackedItemKind::CjitEntry,
+            reason: Some("peer offline".to_string()),
+        }));
+
+        mock.advance(Duration::from_secs(600));
+        let events = db.poll_tracked_items().await.unwrap();
+        assert!(matches!(
+            &events[..],
+            [BtLifecycleEvent::ChannelOpen { item_id, .. }] if item_id == &entry.id
+        ));
+
+        let payment = db.get_payment(&payment_id).await.unwrap();
+        assert!(matches!(payment.state, BtBolt11InvoiceState::Paid));
+    }
+
+    #[tokio::test]
+    async fn test_mock_lsp_unreachable() {
+        let (mock, db) = create_mock_db().await;
+
+        let order = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+
+        mock.set_unreachable(true);
+        mock.pay_order(&order.id).unwrap();
+        assert!(db.poll_tracked_items().await.is_err());
+        assert!(db.fetch_and_store_info().await.is_err());
+
+        // The missed transition is reported once the LSP is back
+        mock.set_unreachable(false);
+        assert_eq!(db.poll_tracked_items().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mock_lsp_notifications_and_gifts() {
+        let (_mock, db) = create_mock_db().await;
+
+        assert!(db
+            .test_notification("device_token", "secret", None, None)
+            .await
+            .is_err());
+        db.register_device(
+            "device_token",
+            "public_key",
+            &["blocktank.incomingHtlc".to_string()],
+            "node_id",
+            "2024-01-01T00:00:00Z",
+            "signature",
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(db
+            .test_notification("device_token", "secret", None, None)
+            .await
+            .is_ok());
+
+        let gift = db.gift_order("client_pubkey", "GIFT").await.unwrap();
+        let order_id = gift.order_id.clone().unwrap();
+        assert_eq!(
+            db.get_gift(&gift.id).await.unwrap().order_id,
+            Some(order_id.clone())
+        );
+        let orders = db.refresh_orders(&[order_id]).await.unwrap();
+        assert_eq!(order_status(&orders[0]), BtLifecycleStatus::PaymentReceived);
+    }
+
+    #[test]
+    fn test_order_activity_refs() {
+        let funding_tx_id = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+        let channel_id = "3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5f48";
+
+        // Unpaid order without a channel
+        let mut order = create_test_order("order1");
+        let refs = order_activity_refs(&order);
+        assert_eq!(refs.len(), 1);
+        assert_eq!(
+            refs[0].target,
+            OrderActivityTarget::Invoice("lnbc...".to_string())
+        );
+        assert_eq!(refs[0].kind, BtOrderActivityKind::Payment);
+        assert_eq!(refs[0].channel_id, None);
+
+        // Paid on-chain and opened
+        let mut channel = create_test_channel(BtOpenChannelState::Open);
+        channel.funding_tx = FundingTx {
+            id: funding_tx_id.to_string(),
+            vout: 258,
+        };
+        order.channel = Some(channel);
+        order
+            .payment
+            .as_mut()
+            .unwrap()
+            .onchain
+            .as_mut()
+            .unwrap()
+            .transactions
+            .push(IBtOnchainTransaction {
+                amount_sat: 1000,
+                tx_id: "payment_tx".to_string(),
+                vout: 1,
+                block_height: Some(800_000),
+                block_confirmation_count: 3,
+                fee_rate_sat_per_vbyte: 2.0,
+                confirmed: true,
+                suspicious_0_conf_reason: String::new(),
+            });
+
+        let refs = order_activity_refs(&order);
+        let targets: Vec<_> = refs
+            .iter()
+            .map(|reference| (reference.target.clone(), reference.kind))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                (
+                    OrderActivityTarget::Invoice("lnbc...".to_string()),
+                    BtOrderActivityKind::Payment
+                ),
+                (
+                    OrderActivityTarget::TxId("payment_tx".to_string()),
+                    BtOrderActivityKind::Payment
+                ),
+                (
+                    OrderActivityTarget::TxId(funding_tx_id.to_string()),
+                    BtOrderActivityKind::ChannelFunding
+                ),
+            ]
+        );
+        assert!(refs
+            .iter()
+            .all(|reference| reference.channel_id.as_deref() == Some(channel_id)));
+        assert_eq!(refs[0].transfer_tx_id, None);
+        assert_eq!(refs[1].transfer_tx_id.as_deref(), Some(funding_tx_id));
+        assert_eq!(refs[2].transfer_tx_id, None);
+
+        // No channel id for funding transactions that are not txids
+        let funding_tx = FundingTx {
+            id: "funding_tx".to_string(),
+            vout: 0,
+        };
+        assert_eq!(funding_channel_id(&funding_tx), None);
+    }
+
+    #[tokio::test]
+    async fn test_order_activity_links() {
+        let (mock, db) = create_mock_db().await;
+        let order = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        mock.pay_order(&order.id).unwrap();
+        db.refresh_orders(std::slice::from_ref(&order.id))
+            .await
+            .unwrap();
+
+        assert!(db
+            .get_order_for_activity("payment")
+            .await
+            .unwrap()
+            .is_none());
+
+        let payment = BtOrderActivityLink {
+            order_id: order.id.clone(),
+            activity_id: "payment".to_string(),
+            kind: BtOrderActivityKind::Payment,
+            channel_id: None,
+        };
+        db.upsert_order_activity_links(std::slice::from_ref(&payment))
+            .await
+            .unwrap();
+
+        // Reconciling again updates the link in place
+        let opened = BtOrderActivityLink {
+            channel_id: Some("channel".to_string()),
+            ..payment.clone()
+        };
+        let funding = BtOrderActivityLink {
+            activity_id: "funding".to_string(),
+            kind: BtOrderActivityKind::ChannelFunding,
+            ..opened.clone()
+        };
+        db.upsert_order_activity_links(&[opened.clone(), funding.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.get_order_activity_links(&order.id).await.unwrap(),
+            vec![opened.clone(), funding]
+        );
+        assert_eq!(
+            db.get_activity_order_links("payment").await.unwrap(),
+            vec![opened]
+        );
+        let linked = db.get_order_for_activity("payment").await.unwrap().unwrap();
+        assert_eq!(linked.id, order.id);
+
+        db.wipe_all().await.unwrap();
+        assert!(db
+            .get_order_activity_links(&order.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_node_message_signing() {
+        let signer = BtNodeKeySigner::new(&"01".repeat(32)).unwrap();
+        let other = BtNodeKeySigner::new(&"02".repeat(32)).unwrap();
+        let signature = signer.sign_message("message".to_string()).unwrap();
+        assert_eq!(
+            signature,
+            "rytazokosccaudnxj63bh3xhb4r77kcyjzsr9enq8ttjwe6b3wo3hn6qbk6tnjgw38mozmginng7s66d7si9ts9rgbsspf19hpwp34m3"
+        );
+        assert!(verify_node_message(
+            "message",
+            &signature,
+            &signer.node_id()
+        ));
+        assert!(!verify_node_message(
+            "other message",
+            &signature,
+            &signer.node_id()
+        ));
+        assert!(!verify_node_message(
+            "message",
+            &signature,
+            &other.node_id()
+        ));
+        assert!(!verify_node_message(
+            "message",
+            "not zbase32!",
+            &signer.node_id()
+        ));
+
+        for key in ["", "zz", &"00".repeat(32)] {
+            assert!(matches!(
+                BtNodeKeySigner::new(key),
+                Err(BlocktankError::InvalidParameter { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ensure_device_registered() {
+        let (mock, db) = create_mock_db().await;
+        let signer: Arc<dyn BtNodeSigner> =
+            Arc::new(BtNodeKeySigner::new(&"01".repeat(32)).unwrap());
+        assert!(matches!(
+            db.ensure_device_registered("", &[], signer.clone(), None, None)
+                .await,
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+
+        let first = db
+            .ensure_device_registered("token1", &[], signer.clone(), Some(false), None)
+            .await
+            .unwrap();
+        assert!(first.registered);
+        let registration = &first.registration;
+        let mut default_features = DEFAULT_NOTIFICATION_FEATURES.map(str::to_string).to_vec();
+        default_features.sort();
+        assert_eq!(registration.features, default_features);
+        assert_eq!(registration.node_id, signer.node_id());
+        // The public key belongs to the returned secret key
+        assert_eq!(
+            BtNodeKeySigner::new(&first.encryption_secret_key)
+                .unwrap()
+                .node_id(),
+            registration.public_key
+        );
+
+        let device = mock.registered_device("token1").unwrap();
+        assert_eq!(device.public_key, registration.public_key);
+        assert_eq!(device.node_id, registration.node_id);
+        assert_eq!(device.features, default_features);
+        assert!(verify_node_message(
+            &notification_sign_message("token1", &device.iso_timestamp, &device.public_key),
+            &device.signature,
+            &device.node_id,
+        ));
+        assert_eq!(
+            db.get_device_registration().await.unwrap().as_ref(),
+            Some(registration)
+        );
+
+        // Unchanged registrations are not sent again
+        let unchanged = db
+            .ensure_device_registered(
+                "token1",
+                &default_features,
+                signer.clone(),
+                Some(false),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(!unchanged.registered);
+        assert_eq!(
+            unchanged,
+            BtDeviceRegistrationResult {
+                registered: false,
+                ..first.clone()
+            }
+        );
+        assert_eq!(mock.registered_device("token1").unwrap().registrations, 1);
+
+        // Changed features and device tokens keep the keypair
+        let features = vec!["blocktank.incomingHtlc".to_string()];
+        let changed = db
+            .ensure_device_registered("token1", &features, signer.clone(), Some(false), None)
+            .await
+            .unwrap();
+        assert!(changed.registered);
+        assert_eq!(changed.registration.features, features);
+        assert_eq!(mock.registered_device("token1").unwrap().registrations, 2);
+
+        let rotated = db
+            .ensure_device_registered("token2", &features, signer.clone(), Some(false), None)
+            .await
+            .unwrap();
+        assert!(rotated.registered);
+        assert_eq!(rotated.registration.public_key, registration.public_key);
+        assert_eq!(rotated.encryption_secret_key, first.encryption_secret_key);
+        assert!(mock.registered_device("token2").is_some());
+
+        // A failed registration keeps the previous one
+        let other_node: Arc<dyn BtNodeSigner> =
+            Arc::new(BtNodeKeySigner::new(&"02".repeat(32)).unwrap());
+        mock.set_unreachable(true);
+        assert!(db
+            .ensure_device_registered("token2", &features, other_node.clone(), Some(false), None)
+            .await
+            .is_err());
+        mock.set_unreachable(false);
+        assert_eq!(
+            db.get_device_registration().await.unwrap(),
+            Some(rotated.registration)
+        );
+
+        // Another node gets a new keypair
+        let other = db
+            .ensure_device_registered("token2", &features, other_node, Some(false), None)
+            .await
+            .unwrap();
+        assert!(other.registered);
+        assert_ne!(other.registration.public_key, registration.public_key);
+        assert_eq!(
+            db.get_notification_secret_key().await.unwrap(),
+            Some(other.encryption_secret_key)
+        );
+    }
+
+    #[test]
+    fn test_decrypt_notification() {
+        // Encrypted by an independent implementation from sender key 03..03 to 02..02,
+        // with a 16 byte iv
+        let notification = BtEncryptedNotification {
+            cipher: "z5juBbNIt4DgXywNllB5kC9GM6ReLP8fOffOhUCf9oY4k+E17OrZ+0TH+hBqcy1unAYkc+He/VnFeB5rCCUSPQ==".to_string(),
+            iv: "000102030405060708090a0b0c0d0e0f".to_string(),
+            tag: "ed216ea77f65fc9685b33936f743e67a".to_string(),
+            public_key: "02531fe6068134503d2723133227c867ac8fa6c83c537e9a44c3c5bdbdcb1fe337"
+                .to_string(),
+        };
+        assert_eq!(
+            decrypt_notification(&notification, &"02".repeat(32)).unwrap(),
+            BtNotification::OrderPaymentConfirmed {
+                order_id: "ord-123".to_string()
+            }
+        );
+
+        assert!(matches!(
+            decrypt_notification(&notification, &"03".repeat(32)),
+            Err(BlocktankError::DataError { .. })
+        ));
+        let tampered = BtEncryptedNotification {
+            tag: "ed216ea77f65fc9685b33936f743e67b".to_string(),
+            ..notification.clone()
+        };
+        assert!(matches!(
+            decrypt_notification(&tampered, &"02".repeat(32)),
+            Err(BlocktankError::DataError { .. })
+        ));
+        let bad_iv = BtEncryptedNotification {
+            iv: "0001".to_string(),
+            ..notification
+        };
+        assert!(matches!(
+            decrypt_notification(&bad_iv, &"02".repeat(32)),
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_registered_notification() {
+        let (mock, db) = create_mock_db().await;
+        let notification = BtEncryptedNotification {
+            cipher: String::new(),
+            iv: String::new(),
+            tag: String::new(),
+            public_key: String::new(),
+        };
+        assert!(matches!(
+            db.decrypt_notification(&notification).await,
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+
+        let signer: Arc<dyn BtNodeSigner> =
+            Arc::new(BtNodeKeySigner::new(&"01".repeat(32)).unwrap());
+        db.ensure_device_registered("token1", &[], signer, Some(false), None)
+            .await
+            .unwrap();
+
+        let cases = [
+            (
+                "incomingHtlc",
+                serde_json::json!({ "paymentHash": "ab".repeat(32) }),
+                BtNotification::IncomingHtlc {
+                    payment_hash: Some("ab".repeat(32)),
+                },
+            ),
+            (
+                "cjitPaymentArrived",
+                serde_json::json!({ "cjitEntryId": "cjit-1" }),
+                BtNotification::CjitChannelOpen {
+                    cjit_entry_id: Some("cjit-1".to_string()),
+                },
+            ),
+            (
+                "orderPaymentConfirmed",
+                serde_json::json!({ "orderId": "order-1" }),
+                BtNotification::OrderPaymentConfirmed {
+                    order_id: "order-1".to_string(),
+                },
+            ),
+            (
+                "wakeToTimeout",
+                serde_json::json!({}),
+                BtNotification::ChannelExpiryWarning { channel_id: None },
+            ),
+            (
+                "mutualClose",
+                serde_json::json!({ "channelId": "cd".repeat(32) }),
+                BtNotification::MutualClose {
+                    channel_id: Some("cd".repeat(32)),
+                },
+            ),
+        ];
+        for (notification_type, payload, expected) in cases {
+            let notification = mock.notify("token1", notification_type, payload).unwrap();
+            assert_eq!(
+                db.decrypt_notification(&notification).await.unwrap(),
+                expected
+            );
+        }
+
+        for (notification_type, payload) in [
+            ("unknownType", serde_json::json!({})),
+            ("orderPaymentConfirmed", serde_json::json!({})),
+        ] {
+            let notification = mock.notify("token1", notification_type, payload).unwrap();
+            assert!(matches!(
+                db.decrypt_notification(&notification).await,
+                Err(BlocktankError::DataError { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multiple_lsps() {
+        const PARTNER_URL: &str = "https://partner.example/api";
+        let (mock, mut db) = create_mock_db().await;
+        let partner = Arc::new(MockLsp::new(MockLspConfig {
+            service_fee_ppm_per_week: 500,
+            options: IBtInfoOptions {
+                max_channel_size_sat: 500_000,
+                ..MockLspConfig::default().options
+            },
+            id_prefix: "partner".to_string(),
+            ..MockLspConfig::default()
+        }));
+        assert!(matches!(
+            db.register_lsp_api("", partner.clone()),
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+        db.register_lsp_api(PARTNER_URL, partner.clone()).unwrap();
+        assert_eq!(db.lsp_urls(), vec!["".to_string(), PARTNER_URL.to_string()]);
+
+        // The partner is cheaper for channels within its limits
+        let quotes = db.compare_lsp_quotes(100_000, 4, None).await.unwrap();
+        let urls: Vec<&str> = quotes.iter().map(|q| q.lsp_url.as_str()).collect();
+        assert_eq!(urls, vec![PARTNER_URL, ""]);
+        assert_eq!(quotes[0].service_fee_sat, 200);
+        assert_eq!(quotes[1].service_fee_sat, 400);
+        assert_eq!(
+            db.get_lsp_info(PARTNER_URL)
+                .await
+                .unwrap()
+                .unwrap()
+                .options
+                .max_channel_size_sat,
+            500_000
+        );
+        let selected = db.select_lsp(1_000_000, 4, None).await.unwrap();
+        assert_eq!(selected.lsp_url, "");
+        assert!(matches!(
+            db.select_lsp(100_000, 100, None).await,
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+
+        // Orders and CJIT entries are refreshed from the LSP they were created with
+        let default_order = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        let partner_order = db
+            .create_lsp_order(PARTNER_URL, 100_000, 4, None)
+            .await
+            .unwrap();
+        assert_eq!(partner_order.id, "partner-order-1");
+        // The default LSP does not know the partner order
+        assert!(mock.pay_order(&partner_order.id).is_err());
+        assert_eq!(
+            db.get_item_lsp(&partner_order.id, BtTrackedItemKind::Order)
+                .await
+                .unwrap(),
+            PARTNER_URL
+        );
+        assert_eq!(
+            db.get_item_lsp(&default_order.id, BtTrackedItemKind::Order)
+                .await
+                .unwrap(),
+            ""
+        );
+        assert!(db.poll_tracked_items().await.unwrap().is_empty());
+        partner.pay_order(&partner_order.id).unwrap();
+        assert_eq!(
+            db.poll_tracked_items().await.unwrap(),
+            vec![BtLifecycleEvent::PaymentReceived {
+                item_id: partner_order.id.clone(),
+                kind: BtTrackedItemKind::Order,
+                paid_sat: 2_200,
+            }]
+        );
+        let orders = db
+            .refresh_orders(&[default_order.id.clone(), partner_order.id.clone()])
+            .await
+            .unwrap();
+        assert_eq!(orders.len(), 2);
+        db.open_channel(
+            partner_order.id.clone(),
+            "client_pubkey@127.0.0.1:9735".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let entry = db
+            .create_lsp_cjit_entry(
+                PARTNER_URL,
+                200_000,
+                10_000,
+                "Partner CJIT",
+                "03c8533232c155c41c42e5a8f8487b192dd36f1d354b86ef461cc82e67e3388839",
+                4,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(entry.id.starts_with("partner-cjit-"));
+        assert_eq!(db.refresh_cjit_entry(&entry.id).await.unwrap().id, entry.id);
+
+        // An unreachable LSP is left out of the quotes
+        partner.set_unreachable(true);
+        let quotes = db.compare_lsp_quotes(100_000, 4, None).await.unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].lsp_url, "");
+        partner.set_unreachable(false);
+
+        // Orders of an unregistered LSP are skipped without failing the others
+        assert!(db.unregister_lsp(PARTNER_URL).unwrap());
+        assert!(!db.unregister_lsp(PARTNER_URL).unwrap());
+        let orders = db
+            .refresh_orders(&[partner_order.id.clone(), default_order.id.clone()])
+            .await
+            .unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].id, default_order.id);
+        assert!(matches!(
+            db.open_channel(
+                partner_order.id.clone(),
+                "client_pubkey@127.0.0.1:9735".to_string()
+            )
+            .await,
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_lsps_persist_across_restarts() {
+        const PARTNER_URL: &str = "https://partner.example/api";
+        const NEW_DEFAULT_URL: &str = "https://new-default.example/api";
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("blocktank.db");
+        let db_path = db_path.to_str().unwrap();
+        let mock = Arc::new(MockLsp::default());
+        let partner = Arc::new(MockLsp::new(MockLspConfig {
+            id_prefix: "partner".to_string(),
+            ..MockLspConfig::default()
+        }));
+
+        let mut db = BlocktankDB::with_api(db_path, mock.clone()).await.unwrap();
+        db.register_lsp(PARTNER_URL).unwrap();
+        db.register_lsp_api(PARTNER_URL, partner.clone()).unwrap();
+        let default_order = db.create_and_store_order(100_000, 4, None).await.unwrap();
+        let partner_order = db
+            .create_lsp_order(PARTNER_URL, 100_000, 4, None)
+            .await
+            .unwrap();
+        drop(db);
+
+        // The registration is stored, and every order keeps its LSP
+        let mut db = BlocktankDB::with_api(db_path, mock.clone()).await.unwrap();
+        assert_eq!(db.lsp_urls(), vec!["".to_string(), PARTNER_URL.to_string()]);
+        db.register_lsp_api(PARTNER_URL, partner.clone()).unwrap();
+        assert_eq!(
+            db.refresh_orders(&[default_order.id.clone(), partner_order.id.clone()])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // Orders of the default LSP are not moved to a new default LSP
+        db.update_blocktank_url(NEW_DEFAULT_URL).await.unwrap();
+        assert_eq!(
+            db.get_item_lsp(&default_order.id, BtTrackedItemKind::Order)
+                .await
+                .unwrap(),
+            ""
+        );
+        assert_eq!(
+            db.get_item_lsp(&partner_order.id, BtTrackedItemKind::Order)
+                .await
+                .unwrap(),
+            PARTNER_URL
+        );
+        let orders = db
+            .refresh_orders(&[default_order.id.clone(), partner_order.id.clone()])
+            .await
+            .unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].id, partner_order.id);
+
+        // Unregistering forgets the stored registration
+        assert!(db.unregister_lsp(PARTNER_URL).unwrap());
+        drop(db);
+        let db = BlocktankDB::with_api(db_path, mock).await.unwrap();
+        assert_eq!(db.lsp_urls(), vec!["".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_lsps_lsp() {
+        let (_, mut db) = create_mock_db().await;
+        let server = Arc::new(MockLspsServer::new(
+            Arc::new(MockLsp::new(MockLspConfig {
+                id_prefix: "lsps".to_string(),
+                ..MockLspConfig::default()
+            })),
+            MockLspsConfig::default(),
+        ));
+        assert!(matches!(
+            db.register_lsps_lsp("not-a-node-id", BitcoinNetworkEnum::Regtest, server.clone()),
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+        let lsp_url = db
+            .register_lsps_lsp(MOCK_LSP_PUBKEY, BitcoinNetworkEnum::Regtest, server.clone())
+            .unwrap();
+        assert_eq!(lsp_url, format!("lsps:{}", MOCK_LSP_PUBKEY));
+
+        // LSPS1 can not estimate fees, so quotes do not even fetch its info. The default LSP
+        // needs at least two weeks.
+        assert!(matches!(
+            db.compare_lsp_quotes(100_000, 1, None).await,
+            Err(BlocktankError::InvalidParameter { .. })
+        ));
+        assert!(server.requests().is_empty());
+
+        // LSPS1 options become the LSP info
+        let info = db.fetch_and_store_lsp_info(&lsp_url).await.unwrap();
+        assert_eq!(info.nodes[0].pubkey, MOCK_LSP_PUBKEY);
+        assert_eq!(info.options.min_expiry_weeks, 1);
+        assert_eq!(info.options.max_expiry_weeks, 52);
+        assert_eq!(info.options.max_0_conf_client_balance_sat, 1_000_000);
+
+        // Only the default LSP is quoted
+        let quotes = db.compare_lsp_quotes(100_000, 4, None).await.unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].lsp_url, "");
+
+        let order = db
+            .create_lsp_order(&lsp_url, 100_000, 4, None)
+            .await
+            .unwrap();
+        assert_eq!(order.id, "lsps-order-1");
+        assert_eq!(order.state2, Some(BtOrderState2::Created));
+        assert_eq!(order.fee_sat, 2_400);
+        assert_eq!(order.service_fee_sat, 2_400);
+        assert_eq!(order.channel_expiry_weeks, 4);
+        assert_eq!(order.source.as_deref(), Some("lsps1"));
+        assert!(!order.zero_conf);
+
+        // The LSP opens the channel by itself once the order is paid
+        server.lsp().pay_order(&order.id).unwrap();
+        let order = db
+            .refresh_orders(&[order.id.clone()])
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(order.state2, Some(BtOrderState2::Executed));
+        let channel = order.channel.unwrap();
+        assert_eq!(channel.state, BtOpenChannelState::Opening);
+        assert_eq!(channel.lsp_node_pubkey, MOCK_LSP_PUBKEY);
+        server.lsp().advance(Duration::from_secs(3_600));
+        let order = db
+            .refresh_orders(&[order.id.clone()])
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(order.state, BtOrderState::Open);
+        assert_eq!(order.channel.unwrap().state, BtOpenChannelState::Open);
+        assert_eq!(
+            db.get_item_lsp(&order.id, BtTrackedItemKind::Order)
+                .await
+                .unwrap(),
+            lsp_url
+        );
+
+        // JIT channels are bought over LSPS2, with the invoice created by the node
+        let entry = db
+            .create_lsp_cjit_entry(
+                &lsp_url,
+                200_000,
+                500_000,
+                "LSPS2 JIT",
+                "03c8533232c155c41c42e5a8f8487b192dd36f1d354b86ef461cc82e67e3388839",
+                4,
+                None,
+            )
+            .await
+            .unwrap();
+        let scid = format!("{}x1x0", MOCK_LSP_START_HEIGHT);
+        assert_eq!(entry.id, scid);
+        assert_eq!(entry.fee_sat, 5_000);
+        assert_eq!(entry.source.as_deref(), Some("lsps2"));
+        let invoices = server.jit_invoices();
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].jit_channel_scid, scid);
+        assert_eq!(invoices[0].amount_msat, 500_000_000);
+        assert_eq!(invoices[0].cltv_expiry_delta, 144);
+        assert_eq!(
+            entry.invoice.request,
+            format!("lnbcrt5000000n1mockjit{}", scid)
+        );
+        assert_eq!(
+            db.refresh_cjit_entry(&entry.id).await.unwrap().state,
+            CJitStateEnum::Created
+        );
+
+        // Entries are refreshed from the database, also while the LSP is not registered
+        assert!(db.unregister_lsp(&lsp_url).unwrap());
+        let refreshed = db.refresh_cjit_entry(&entry.id).await.unwrap();
+        assert_eq!(refreshed.id, entry.id);
+        assert_eq!(refreshed.state, CJitStateEnum::Created);
+
+        // Once the fee parameters expire, only unpaid entries without a channel expire
+        let mut unpaid = entry.clone();
+        unpaid.expires_at = "2020-01-01T00:00:00Z".to_string();
+        let mut paid = unpaid.clone();
+        paid.id = "paid-jit".to_string();
+        paid.invoice.state = BtBolt11InvoiceState::Paid;
+        db.upsert_cjit_entries(&[unpaid.clone(), paid.clone()])
+            .await
+            .unwrap();
+        db.set_item_lsp(&paid.id, BtTrackedItemKind::CjitEntry, &lsp_url)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.refresh_cjit_entry(&unpaid.id).await.unwrap().state,
+            CJitStateEnum::Expired
+        );
+        assert_eq!(
+            db.get_cjit_entries(Some(&[unpaid.id.clone()]), None)
+                .await
+                .unwrap()[0]
+                .state,
+            CJitStateEnum::Expired
+        );
+        assert_eq!(
+            db.refresh_cjit_entry(&paid.id).await.unwrap().state,
+            CJitStateEnum::Created
+        );
+        db.register_lsps_lsp(MOCK_LSP_PUBKEY, BitcoinNetworkEnum::Regtest, server.clone())
+            .unwrap();
+
+        // Small payments pay the minimum fee, channels outliving the fee menu are refused
+        let entry = db
+            .create_lsp_cjit_entry(&lsp_url, 200_000, 20_000, "", "", 4, None)
+            .await
+            .unwrap();
+        assert_eq!(entry.fee_sat, 2_000);
+        assert!(matches!(
+            db.create_lsp_cjit_entry(&lsp_url, 200_000, 20_000, "", "", 13, None)
+                .await,
+            Err(BlocktankError::DataError { .. })
+        ));
+        assert_eq!(
+            server.requests(),
+            vec![
+                "lsps1.get_info",
+                "lsps1.get_info",
+                "lsps1.create_order",
+                "lsps1.get_order",
+                "lsps1.get_order",
+                "lsps2.get_info",
+                "lsps2.buy",
+                "lsps2.get_info",
+                "lsps2.buy",
+                "lsps2.get_info",
+            ]
+        );
+
+        // Unknown methods are answered with a JSON-RPC error
+        let response = server
+            .send_request(
+                MOCK_LSP_PUBKEY.to_string(),
+                r#"{"jsonrpc":"2.0","method":"lsps5.list_webhooks","params":{},"id":"1"}"#
+                    .to_string(),
+            )
+            .await
+            .unwrap();
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["id"], "1");
+        assert_eq!(response["error"]["code"], -32601);
+    }
+
     // Helper function to create test orders
     fn create_test_order(id: &str) -> IBtOrder {
         let now = chrono::Utc::now();
@@ -2306,6 +3864,23 @@ mod tests {
         }
     }
 
+    // Helper function to create a test channel in the given state
+    fn create_test_channel(state: BtOpenChannelState) -> IBtChannel {
+        IBtChannel {
+            state,
+            lsp_node_pubkey: "test_pubkey".to_string(),
+            client_node_pubkey: "test_node_id".to_string(),
+            announce_channel: false,
+            funding_tx: FundingTx {
+                id: "funding_tx".to_string(),
+                vout: 0,
+            },
+            closing_tx_id: None,
+            close: None,
+            short_channel_id: Some("800000x1x0".to_string()),
+        }
+    }
+
     // Helper function to create test info
     fn create_test_info() -> IBtInfo {
         IBtInfo {
@@ -0,0 +1,468 @@
+//! Order tracking for Blocktank orders and CJIT entries.
+//!
+//! Each tracked item is reduced to a [`BtLifecycleStatus`]. Every change of that status is
+//! persisted in the `status_transitions` table and surfaced as a [`BtLifecycleEvent`].
+
+use crate::modules::blocktank::{BlocktankDB, BlocktankError};
+use rust_blocktank_client::{
+    BtOpenChannelState, BtOrderState2, BtPaymentState2, CJitStateEnum, IBtOrder, ICJitEntry,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_TRACKER_BASE_INTERVAL_SECS: u64 = 5;
+pub const DEFAULT_TRACKER_MAX_INTERVAL_SECS: u64 = 300;
+
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BtTrackedItemKind {
+    Order,
+    CjitEntry,
+}
+
+impl FromStr for BtTrackedItemKind {
+    type Err = BlocktankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Order" => Ok(Self::Order),
+            "CjitEntry" => Ok(Self::CjitEntry),
+            _ => Err(BlocktankError::DataError {
+                error_details: format!("Unknown tracked item kind: {}", s),
+            }),
+        }
+    }
+}
+
+/// Lifecycle of an order or CJIT entry as seen by the tracker
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BtLifecycleStatus {
+    AwaitingPayment,
+    PaymentReceived,
+    ChannelOpening,
+    ChannelOpen,
+    ChannelClosed,
+    Expired,
+    RefundAvailable,
+    Refunded,
+    Failed,
+}
+
+impl BtLifecycleStatus {
+    /// Terminal statuses are no longer polled
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            Self::ChannelOpen | Self::ChannelClosed | Self::Expired | Self::Refunded | Self::Failed
+        )
+    }
+}
+
+impl FromStr for BtLifecycleStatus {
+    type Err = BlocktankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AwaitingPayment" => Ok(Self::AwaitingPayment),
+            "PaymentReceived" => Ok(Self::PaymentReceived),
+            "ChannelOpening" => Ok(Self::ChannelOpening),
+            "ChannelOpen" => Ok(Self::ChannelOpen),
+            "ChannelClosed" => Ok(Self::ChannelClosed),
+            "Expired" => Ok(Self::Expired),
+            "RefundAvailable" => Ok(Self::RefundAvailable),
+            "Refunded" => Ok(Self::Refunded),
+            "Failed" => Ok(Self::Failed),
+            _ => Err(BlocktankError::DataError {
+                error_details: format!("Unknown lifecycle status: {}", s),
+            }),
+        }
+    }
+}
+
+#[derive(uniffi::Enum, Debug, Clone, PartialEq, Eq)]
+pub enum BtLifecycleEvent {
+    PaymentReceived {
+        item_id: String,
+        kind: BtTrackedItemKind,
+        paid_sat: u64,
+    },
+    ChannelOpening {
+        item_id: String,
+        kind: BtTrackedItemKind,
+        funding_tx_id: Option<String>,
+    },
+    ChannelOpen {
+        item_id: String,
+        kind: BtTrackedItemKind,
+        funding_tx_id: Option<String>,
+        short_channel_id: Option<String>,
+    },
+    Expired {
+        item_id: String,
+        kind: BtTrackedItemKind,
+    },
+    RefundAvailable {
+        item_id: String,
+        kind: BtTrackedItemKind,
+        amount_sat: u64,
+    },
+    Failed {
+        item_id: String,
+        kind: BtTrackedItemKind,
+        reason: Option<String>,
+    },
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtStatusTransition {
+    pub item_id: String,
+    pub kind: BtTrackedItemKind,
+    /// `None` for the first observation of an item
+    pub from_status: Option<BtLifecycleStatus>,
+    pub to_status: BtLifecycleStatus,
+    /// Unix timestamp in seconds
+    pub recorded_at: u64,
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq)]
+pub struct BtTrackerConfig {
+    /// Poll interval after a transition was observed
+    pub base_interval_secs: u64,
+    /// Upper bound the interval backs off to while nothing changes or polling fails
+    pub max_interval_secs: u64,
+}
+
+impl Default for BtTrackerConfig {
+    fn default() -> Self {
+        Self {
+            base_interval_secs: DEFAULT_TRACKER_BASE_INTERVAL_SECS,
+            max_interval_secs: DEFAULT_TRACKER_MAX_INTERVAL_SECS,
+        }
+    }
+}
+
+/// Receives lifecycle events from the background order tracker
+#[uniffi::export(with_foreign)]
+pub trait BtLifecycleListener: Send + Sync {
+    fn on_event(&self, event: BtLifecycleEvent);
+}
+
+/// Details carried by the events of a single transition
+struct EventDetails {
+    paid_sat: u64,
+    funding_tx_id: Option<String>,
+    short_channel_id: Option<String>,
+    failure_reason: Option<String>,
+}
+
+/// Derives the lifecycle status of an order
+pub fn order_status(order: &IBtOrder) -> BtLifecycleStatus {
+    let payment_state = order
+        .payment
+        .as_ref()
+        .and_then(|payment| payment.state2.as_ref());
+
+    match payment_state {
+        Some(BtPaymentState2::RefundAvailable) => return BtLifecycleStatus::RefundAvailable,
+        Some(BtPaymentState2::Refunded) => return BtLifecycleStatus::Refunded,
+        _ => {}
+    }
+
+    if let Some(channel) = &order.channel {
+        return match channel.state {
+            BtOpenChannelState::Opening => BtLifecycleStatus::ChannelOpening,
+            BtOpenChannelState::Open => BtLifecycleStatus::ChannelOpen,
+            BtOpenChannelState::Closed => BtLifecycleStatus::ChannelClosed,
+        };
+    }
+
+    match order.state2 {
+        Some(BtOrderState2::Expired) => BtLifecycleStatus::Expired,
+        Some(BtOrderState2::Executed) => BtLifecycleStatus::ChannelOpening,
+        Some(BtOrderState2::Paid) => BtLifecycleStatus::PaymentReceived,
+        _ if matches!(payment_state, Some(BtPaymentState2::Paid)) => {
+            BtLifecycleStatus::PaymentReceived
+        }
+        _ => BtLifecycleStatus::AwaitingPayment,
+    }
+}
+
+/// Derives the lifecycle status of a CJIT entry
+pub fn cjit_status(entry: &ICJitEntry) -> BtLifecycleStatus {
+    match entry.state {
+        CJitStateEnum::Failed => return BtLifecycleStatus::Failed,
+        CJitStateEnum::Expired => return BtLifecycleStatus::Expired,
+        _ => {}
+    }
+
+    if let Some(channel) = &entry.channel {
+        return match channel.state {
+            BtOpenChannelState::Opening => BtLifecycleStatus::ChannelOpening,
+            BtOpenChannelState::Open => BtLifecycleStatus::ChannelOpen,
+            BtOpenChannelState::Closed => BtLifecycleStatus::ChannelClosed,
+        };
+    }
+
+    match entry.state {
+        CJitStateEnum::Completed => BtLifecycleStatus::ChannelOpen,
+        _ => BtLifecycleStatus::AwaitingPayment,
+    }
+}
+
+fn order_event_details(order: &IBtOrder) -> EventDetails {
+    EventDetails {
+        paid_sat: order
+            .payment
+            .as_ref()
+            .map(|payment| payment.paid_sat)
+            .unwrap_or(0),
+        funding_tx_id: order
+            .channel
+            .as_ref()
+            .map(|channel| channel.funding_tx.id.clone()),
+        short_channel_id: order
+            .channel
+            .as_ref()
+            .and_then(|channel| channel.short_channel_id.clone()),
+        failure_reason: None,
+    }
+}
+
+fn cjit_event_details(entry: &ICJitEntry) -> EventDetails {
+    EventDetails {
+        paid_sat: 0,
+        funding_tx_id: entry
+            .channel
+            .as_ref()
+            .map(|channel| channel.funding_tx.id.clone()),
+        short_channel_id: entry
+            .channel
+            .as_ref()
+            .and_then(|channel| channel.short_channel_id.clone()),
+        failure_reason: entry.channel_open_error.clone(),
+    }
+}
+
+/// Returns the events for a status change of a single item.
+///
+/// A payment that was only observed together with the channel opening still emits
+/// `PaymentReceived` before the channel event.
+fn transition_events(
+    item_id: &str,
+    kind: BtTrackedItemKind,
+    from: BtLifecycleStatus,
+    to: BtLifecycleStatus,
+    details: EventDetails,
+) -> Vec<BtLifecycleEvent> {
+    let mut events = Vec::new();
+    if from == to {
+        return events;
+    }
+
+    let id = item_id.to_string();
+    let skipped_payment = kind == BtTrackedItemKind::Order
+        && from == BtLifecycleStatus::AwaitingPayment
+        && matches!(
+            to,
+            BtLifecycleStatus::ChannelOpening | BtLifecycleStatus::ChannelOpen
+        );
+    if to == BtLifecycleStatus::PaymentReceived || skipped_payment {
+        events.push(BtLifecycleEvent::PaymentReceived {
+            item_id: id.clone(),
+            kind,
+            paid_sat: details.paid_sat,
+        });
+    }
+
+    match to {
+        BtLifecycleStatus::ChannelOpening => events.push(BtLifecycleEvent::ChannelOpening {
+            item_id: id,
+            kind,
+            funding_tx_id: details.funding_tx_id,
+        }),
+        BtLifecycleStatus::ChannelOpen => events.push(BtLifecycleEvent::ChannelOpen {
+            item_id: id,
+            kind,
+            funding_tx_id: details.funding_tx_id,
+            short_channel_id: details.short_channel_id,
+        }),
+        BtLifecycleStatus::Expired => events.push(BtLifecycleEvent::Expired { item_id: id, kind }),
+        BtLifecycleStatus::RefundAvailable => events.push(BtLifecycleEvent::RefundAvailable {
+            item_id: id,
+            kind,
+            amount_sat: details.paid_sat,
+        }),
+        BtLifecycleStatus::Failed => events.push(BtLifecycleEvent::Failed {
+            item_id: id,
+            kind,
+            reason: details.failure_reason,
+        }),
+        BtLifecycleStatus::AwaitingPayment
+        | BtLifecycleStatus::PaymentReceived
+        | BtLifecycleStatus::ChannelClosed
+        | BtLifecycleStatus::Refunded => {}
+    }
+
+    events
+}
+
+/// Returns the events for an order moving from the `from` status to its current status
+pub fn order_transition_events(from: BtLifecycleStatus, order: &IBtOrder) -> Vec<BtLifecycleEvent> {
+    transition_events(
+        &order.id,
+        BtTrackedItemKind::Order,
+        from,
+        order_status(order),
+        order_event_details(order),
+    )
+}
+
+/// Returns the events for a CJIT entry moving from the `from` status to its current status
+pub fn cjit_transition_events(
+    from: BtLifecycleStatus,
+    entry: &ICJitEntry,
+) -> Vec<BtLifecycleEvent> {
+    transition_events(
+        &entry.id,
+        BtTrackedItemKind::CjitEntry,
+        from,
+        cjit_status(entry),
+        cjit_event_details(entry),
+    )
+}
+
+/// Returns the interval to wait before the next poll.
+///
+/// Polling resets to the base interval after a transition and doubles up to the
+/// maximum interval while nothing changes or polling fails.
+pub fn next_poll_interval(
+    current: Duration,
+    config: &BtTrackerConfig,
+    observed_transition: bool,
+) -> Duration {
+    let base = Duration::from_secs(config.base_interval_secs.max(1));
+    let max = Duration::from_secs(config.max_interval_secs).max(base);
+    if observed_transition {
+        return base;
+    }
+    current.saturating_mul(2).clamp(base, max)
+}
+
+/// Polls until the task is aborted, forwarding events to `listener`
+pub async fn run_order_tracker<F, Fut>(
+    config: BtTrackerConfig,
+    listener: Arc<dyn BtLifecycleListener>,
+    mut poll: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Vec<BtLifecycleEvent>, BlocktankError>>,
+{
+    let mut interval = Duration::from_secs(config.base_interval_secs.max(1));
+    loop {
+        let observed_transition = match poll().await {
+            Ok(events) => {
+                let observed = !events.is_empty();
+                for event in events {
+                    listener.on_event(event);
+                }
+                observed
+            }
+            Err(_) => false,
+        };
+        interval = next_poll_interval(interval, &config, observed_transition);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+impl BlocktankDB {
+    /// Refreshes every tracked order and CJIT entry, records status transitions and
+    /// returns the resulting events.
+    ///
+    /// Active orders and CJIT entries start being tracked on their first poll and stay
+    /// tracked until they reach a terminal status.
+    pub async fn poll_tracked_items(&self) -> Result<Vec<BtLifecycleEvent>, BlocktankError> {
+        let mut events = self.poll_tracked_orders().await?;
+        events.extend(self.poll_tracked_cjit_entries().await?);
+        Ok(events)
+    }
+
+    async fn poll_tracked_orders(&self) -> Result<Vec<BtLifecycleEvent>, BlocktankError> {
+        let kind = BtTrackedItemKind::Order;
+        for order in self.get_active_orders().await? {
+            if self.get_last_status(&order.id, kind).await?.is_none() {
+                self.record_status_transition(&order.id, kind, None, order_status(&order))
+                    .await?;
+            }
+        }
+
+        let previous = self.get_pending_statuses(kind).await?;
+        if previous.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let order_ids: Vec<String> = previous.keys().cloned().collect();
+        let mut events = Vec::new();
+        for order in self.refresh_orders(&order_ids).await? {
+            let Some(&from) = previous.get(&order.id) else {
+                continue;
+            };
+            let to = order_status(&order);
+            if from == to {
+                continue;
+            }
+            self.record_status_transition(&order.id, kind, Some(from), to)
+                .await?;
+            events.extend(order_transition_events(from, &order));
+        }
+
+        Ok(events)
+    }
+
+    async fn poll_tracked_cjit_entries(&self) -> Result<Vec<BtLifecycleEvent>, BlocktankError> {
+        let kind = BtTrackedItemKind::CjitEntry;
+        for entry in self.get_active_cjit_entries().await? {
+            if self.get_last_status(&entry.id, kind).await?.is_none() {
+                self.record_status_transition(&entry.id, kind, None, cjit_status(&entry))
+                    .await?;
+            }
+        }
+
+        let previous = self.get_pending_statuses(kind).await?;
+        let mut events = Vec::new();
+        for (entry_id, from) in previous {
+            // There is no bulk endpoint for CJIT entries, so a failing entry is
+            // retried on the next poll instead of failing the whole poll
+            let entry = match self.refresh_cjit_entry(&entry_id).await {
+                Ok(entry) => entry,
+                Err(e) => {
+                    println!("Warning: Failed to refresh CJIT entry {}: {}", entry_id, e);
+                    continue;
+                }
+            };
+            let to = cjit_status(&entry);
+            if from == to {
+                continue;
+            }
+            self.record_status_transition(&entry.id, kind, Some(from), to)
+                .await?;
+            events.extend(cjit_transition_events(from, &entry));
+        }
+
+        Ok(events)
+    }
+
+    /// Returns the latest status of every tracked item of `kind` that is not terminal
+    async fn get_pending_statuses(
+        &self,
+        kind: BtTrackedItemKind,
+    ) -> Result<HashMap<String, BtLifecycleStatus>, BlocktankError> {
+        let latest = self.get_latest_statuses(kind).await?;
+        Ok(latest
+            .into_iter()
+            .filter(|(_, status)| !status.is_terminal())
+            .collect())
+    }
+}
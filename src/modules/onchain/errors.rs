@@ -16,3 +16,81 @@ pub enum AddressError {
     #[error("Address derivation failed")]
     AddressDerivationFailed,
 }
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum Slip39Error {
+    #[error("Invalid SLIP-39 configuration: {error_details}")]
+    InvalidConfiguration { error_details: String },
+    #[error("Invalid master secret: {error_details}")]
+    InvalidMasterSecret { error_details: String },
+    #[error("Invalid passphrase: {error_details}")]
+    InvalidPassphrase { error_details: String },
+    /// A single share is malformed (unknown word, bad length, bad checksum, bad padding).
+    /// `share_index` is the zero-based position of the share in the provided list.
+    #[error("Invalid share #{share_index}: {error_details}")]
+    InvalidShare {
+        share_index: u32,
+        error_details: String,
+    },
+    /// A share is well-formed but does not belong with the other shares.
+    #[error("Share #{share_index} does not match the other shares: {error_details}")]
+    ShareMismatch {
+        share_index: u32,
+        error_details: String,
+    },
+    #[error("Insufficient shares: {error_details}")]
+    InsufficientShares { error_details: String },
+    /// The shares are consistent but do not combine into a valid secret.
+    #[error("Share digest verification failed: {error_details}")]
+    InvalidDigest { error_details: String },
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum MessageSigningError {
+    #[error("Invalid private key: {error_details}")]
+    InvalidPrivateKey { error_details: String },
+    #[error("Invalid address: {error_details}")]
+    InvalidAddress { error_details: String },
+    #[error("Unsupported address type: {error_details}")]
+    UnsupportedAddressType { error_details: String },
+    #[error("Invalid signature: {error_details}")]
+    InvalidSignature { error_details: String },
+    #[error("Signing failed: {error_details}")]
+    SigningFailed { error_details: String },
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum FeeEstimationError {
+    #[error("Invalid transaction shape: {error_details}")]
+    InvalidTransactionShape { error_details: String },
+    #[error("Invalid fee rate: {error_details}")]
+    InvalidFeeRate { error_details: String },
+    #[error("Fee source error: {error_details}")]
+    SourceError { error_details: String },
+    #[error("No fee estimates available: {error_details}")]
+    NoEstimatesAvailable { error_details: String },
+}
+
+#[derive(uniffi::Error, Debug, Error)]
+#[non_exhaustive]
+pub enum FeeBumpError {
+    #[error("Invalid transaction: {error_details}")]
+    InvalidTransaction { error_details: String },
+    #[error("Invalid fee rate: {error_details}")]
+    InvalidFeeRate { error_details: String },
+    #[error("Invalid address: {error_details}")]
+    InvalidAddress { error_details: String },
+    #[error("Transaction already confirmed: {error_details}")]
+    AlreadyConfirmed { error_details: String },
+    #[error("Transaction is not replaceable: {error_details}")]
+    NotReplaceable { error_details: String },
+    #[error("Missing UTXO: {error_details}")]
+    MissingUtxo { error_details: String },
+    #[error("No spendable output: {error_details}")]
+    NoSpendableOutput { error_details: String },
+    #[error("Insufficient funds: {error_details}")]
+    InsufficientFunds { error_details: String },
+}
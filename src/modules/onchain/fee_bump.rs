@@ -0,0 +1,504 @@
+//! Replace-by-fee and child-pays-for-parent planning for pending onchain activities.
+//!
+//! Plans are returned as unsigned PSBTs, signing and broadcasting is left to the wallet.
+//! Once the new transaction is broadcast, link it to the original activity with
+//! `ActivityDB::add_boost_tx_id`.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, OutPoint, Psbt, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+
+use super::errors::FeeBumpError;
+use super::types::{AddressType, FeeBumpMethod, FeeBumpPlan, WalletUtxo};
+use super::vsize;
+use crate::modules::activity::{OnchainActivity, PaymentType, TransactionDetails};
+
+/// BIP125 rule 4: the replacement pays for its own bandwidth at the incremental relay fee
+const INCREMENTAL_RELAY_FEE_RATE: f64 = 1.0;
+const MIN_RELAY_FEE_RATE: f64 = 1.0;
+
+#[derive(Debug, Clone)]
+struct Coin {
+    outpoint: OutPoint,
+    txout: TxOut,
+    address_type: AddressType,
+    confirmed: bool,
+}
+
+impl Coin {
+    fn from_utxo(utxo: &WalletUtxo) -> Result<Self, FeeBumpError> {
+        let txid = Txid::from_str(&utxo.txid).map_err(|e| FeeBumpError::InvalidTransaction {
+            error_details: format!("Invalid UTXO txid {}: {}", utxo.txid, e),
+        })?;
+        let script_pubkey = ScriptBuf::from_hex(&utxo.script_pubkey).map_err(|e| {
+            FeeBumpError::InvalidTransaction {
+                error_details: format!("Invalid UTXO script pubkey: {}", e),
+            }
+        })?;
+        Ok(Coin {
+            outpoint: OutPoint::new(txid, utxo.vout),
+            address_type: vsize::address_type_for_script(&script_pubkey),
+            txout: TxOut {
+                value: Amount::from_sat(utxo.value),
+                script_pubkey,
+            },
+            confirmed: utxo.confirmed,
+        })
+    }
+
+    fn value(&self) -> u64 {
+        self.txout.value.to_sat()
+    }
+}
+
+/// Id of the original transaction, as recorded in its details
+fn parse_txid(details: &TransactionDetails) -> Result<Txid, FeeBumpError> {
+    Txid::from_str(&details.tx_id).map_err(|e| FeeBumpError::InvalidTransaction {
+        error_details: format!("Invalid transaction id {}: {}", details.tx_id, e),
+    })
+}
+
+/// Rebuilds the inputs and outputs of the original transaction, including witnesses, from
+/// its stored details. Its version and lock time are not stored, so the rebuilt transaction
+/// only serves for its size and replaceability, use `parse_txid` for its id.
+fn parse_transaction(details: &TransactionDetails) -> Result<Transaction, FeeBumpError> {
+    let invalid = |error_details: String| FeeBumpError::InvalidTransaction { error_details };
+
+    let input = details
+        .inputs
+        .iter()
+        .map(|input| {
+            let txid = Txid::from_str(&input.txid)
+                .map_err(|e| invalid(format!("Invalid input txid {}: {}", input.txid, e)))?;
+            let script_sig = ScriptBuf::from_hex(&input.scriptsig)
+                .map_err(|e| invalid(format!("Invalid input script sig: {}", e)))?;
+            let witness = input
+                .witness
+                .iter()
+                .map(hex::decode)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| invalid(format!("Invalid input witness: {}", e)))?;
+            Ok(TxIn {
+                previous_output: OutPoint::new(txid, input.vout),
+                script_sig,
+                sequence: Sequence(input.sequence),
+                witness: Witness::from_slice(&witness),
+            })
+        })
+        .collect::<Result<Vec<_>, FeeBumpError>>()?;
+
+    let mut outputs = details.outputs.clone();
+    outputs.sort_by_key(|output| output.n);
+    let output = outputs
+        .iter()
+        .map(|output| {
+            let script_pubkey = ScriptBuf::from_hex(&output.scriptpubkey)
+                .map_err(|e| invalid(format!("Invalid output script pubkey: {}", e)))?;
+            Ok(TxOut {
+                value: Amount::from_sat(output.value),
+                script_pubkey,
+            })
+        })
+        .collect::<Result<Vec<_>, FeeBumpError>>()?;
+
+    if input.is_empty() || output.is_empty() {
+        return Err(invalid(
+            "Transaction details must include inputs and outputs".to_string(),
+        ));
+    }
+
+    Ok(Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input,
+        output,
+    })
+}
+
+fn check_fee_rate(fee_rate: f64) -> Result<(), FeeBumpError> {
+    if !fee_rate.is_finite() || fee_rate <= 0.0 {
+        return Err(FeeBumpError::InvalidFeeRate {
+            error_details: format!("Fee rate must be a positive number, got {}", fee_rate),
+        });
+    }
+    Ok(())
+}
+
+fn ensure_pending(activity: &OnchainActivity) -> Result<(), FeeBumpError> {
+    if activity.confirmed {
+        return Err(FeeBumpError::AlreadyConfirmed {
+            error_details: format!("Transaction {} is already confirmed", activity.tx_id),
+        });
+    }
+    Ok(())
+}
+
+fn parse_script(address: &str) -> Result<ScriptBuf, FeeBumpError> {
+    Address::from_str(address)
+        .map(|address| address.assume_checked().script_pubkey())
+        .map_err(|e| FeeBumpError::InvalidAddress {
+            error_details: format!("{}: {}", address, e),
+        })
+}
+
+fn fee_for_vsize(fee_rate: f64, vsize: u64) -> u64 {
+    (fee_rate * vsize as f64).ceil() as u64
+}
+
+fn estimate_vsize(coins: &[Coin], output_scripts: &[ScriptBuf]) -> Result<u64, FeeBumpError> {
+    let input_types: Vec<AddressType> =
+        coins.iter().map(|coin| coin.address_type.clone()).collect();
+    vsize::estimate_vsize_for_scripts(&input_types, output_scripts).map_err(|e| {
+        FeeBumpError::InvalidTransaction {
+            error_details: e.to_string(),
+        }
+    })
+}
+
+/// Fee of `tx` if every one of its inputs is among `wallet_coins`.
+fn known_fee(tx: &Transaction, wallet_coins: &[Coin]) -> Option<u64> {
+    let input_total = tx
+        .input
+        .iter()
+        .map(|input| {
+            wallet_coins
+                .iter()
+                .find(|coin| coin.outpoint == input.previous_output)
+                .map(Coin::value)
+        })
+        .sum::<Option<u64>>()?;
+    let output_total: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+    input_total.checked_sub(output_total)
+}
+
+/// Confirmed wallet coins that may be added to a bump, largest first.
+fn additional_coins(wallet_coins: &[Coin], excluded: &HashSet<OutPoint>, txid: Txid) -> Vec<Coin> {
+    let mut coins: Vec<Coin> = wallet_coins
+        .iter()
+        .filter(|coin| {
+            coin.confirmed && coin.outpoint.txid != txid && !excluded.contains(&coin.outpoint)
+        })
+        .cloned()
+        .collect();
+    coins.sort_by_key(|coin| Reverse(coin.value()));
+    coins
+}
+
+fn build_plan(
+    method: FeeBumpMethod,
+    coins: &[Coin],
+    output: Vec<TxOut>,
+    fee_sats: u64,
+    vsize: u64,
+    effective_fee_rate: f64,
+    change_sats: Option<u64>,
+) -> Result<FeeBumpPlan, FeeBumpError> {
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: coins
+            .iter()
+            .map(|coin| TxIn {
+                previous_output: coin.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output,
+    };
+
+    let mut psbt = Psbt::from_unsigned_tx(tx).map_err(|e| FeeBumpError::InvalidTransaction {
+        error_details: e.to_string(),
+    })?;
+    for (input, coin) in psbt.inputs.iter_mut().zip(coins) {
+        // Legacy inputs need the full previous transaction, which is not known here
+        if coin.address_type != AddressType::P2PKH {
+            input.witness_utxo = Some(coin.txout.clone());
+        }
+    }
+
+    Ok(FeeBumpPlan {
+        method,
+        tx_id: psbt.unsigned_tx.compute_txid().to_string(),
+        psbt: psbt.to_string(),
+        vsize,
+        fee_sats,
+        effective_fee_rate,
+        change_sats,
+    })
+}
+
+/// Plans a BIP125 replacement of a pending sent transaction paying `target_fee_rate` sat/vB.
+///
+/// `utxos` must contain the outputs spent by the original transaction. Payment outputs are
+/// kept unchanged, the output to `change_address` absorbs the fee increase and confirmed
+/// `utxos` are added as inputs when the change is not enough.
+///
+/// Boosted activities are not replaced: the fees of a CPFP child, which the replacement would
+/// evict, or of a previous replacement are not known, so BIP125 rule 3 could not be met.
+pub fn plan_rbf(
+    activity: &OnchainActivity,
+    details: &TransactionDetails,
+    utxos: &[WalletUtxo],
+    change_address: &str,
+    target_fee_rate: f64,
+) -> Result<FeeBumpPlan, FeeBumpError> {
+    check_fee_rate(target_fee_rate)?;
+    ensure_pending(activity)?;
+    if activity.tx_type != PaymentType::Sent {
+        return Err(FeeBumpError::NotReplaceable {
+            error_details: "Only sent transactions can be replaced".to_string(),
+        });
+    }
+    if activity.is_boosted || !activity.boost_tx_ids.is_empty() {
+        return Err(FeeBumpError::NotReplaceable {
+            error_details: format!(
+                "Transaction {} was already boosted by {}",
+                activity.tx_id,
+                activity.boost_tx_ids.join(", ")
+            ),
+        });
+    }
+
+    let original = parse_transaction(details)?;
+    let original_txid = parse_txid(details)?;
+    if !original.is_explicitly_rbf() {
+        return Err(FeeBumpError::NotReplaceable {
+            error_details: "The original transaction does not signal BIP125 replaceability"
+                .to_string(),
+        });
+    }
+    let change_script = parse_script(change_address)?;
+    let wallet_coins = utxos
+        .iter()
+        .map(Coin::from_utxo)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut coins = original
+        .input
+        .iter()
+        .map(|input| {
+            wallet_coins
+                .iter()
+                .find(|coin| coin.outpoint == input.previous_output)
+                .cloned()
+                .ok_or(FeeBumpError::MissingUtxo {
+                    error_details: format!(
+                        "No UTXO provided for input {} of the original transaction",
+                        input.previous_output
+                    ),
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let original_fee = known_fee(&original, &coins).ok_or(FeeBumpError::InvalidTransaction {
+        error_details: "Original outputs exceed the provided input values".to_string(),
+    })?;
+    let original_vsize = original.vsize() as u64;
+    let original_fee_rate = original_fee as f64 / original_vsize as f64;
+    if target_fee_rate <= original_fee_rate {
+        return Err(FeeBumpError::InvalidFeeRate {
+            error_details: format!(
+                "Target fee rate {} sat/vB must exceed the original fee rate of {:.2} sat/vB",
+                target_fee_rate, original_fee_rate
+            ),
+        });
+    }
+
+    let payments: Vec<TxOut> = original
+        .output
+        .iter()
+        .filter(|output| output.script_pubkey != change_script)
+        .cloned()
+        .collect();
+    let payment_total: u64 = payments.iter().map(|output| output.value.to_sat()).sum();
+    let payment_scripts: Vec<ScriptBuf> = payments
+        .iter()
+        .map(|output| output.script_pubkey.clone())
+        .collect();
+    // Keep the change where it was so the output order does not change
+    let change_index = original
+        .output
+        .iter()
+        .take_while(|output| output.script_pubkey != change_script)
+        .count();
+    let mut scripts_with_change = payment_scripts.clone();
+    scripts_with_change.insert(change_index, change_script.clone());
+    let dust_limit = change_script.minimal_non_dust().to_sat();
+
+    let required_fee = |vsize: u64| {
+        fee_for_vsize(target_fee_rate, vsize)
+            .max(original_fee + fee_for_vsize(INCREMENTAL_RELAY_FEE_RATE, vsize))
+    };
+
+    // BIP125 rule 2: a replacement may not add unconfirmed inputs
+    let spent: HashSet<OutPoint> = coins.iter().map(|coin| coin.outpoint).collect();
+    let mut candidates = additional_coins(&wallet_coins, &spent, original_txid).into_iter();
+
+    loop {
+        let input_total: u64 = coins.iter().map(Coin::value).sum();
+
+        let vsize = estimate_vsize(&coins, &scripts_with_change)?;
+        let fee = required_fee(vsize);
+        if let Some(change) = input_total
+            .checked_sub(payment_total + fee)
+            .filter(|change| *change >= dust_limit)
+        {
+            let mut output = payments.clone();
+            output.insert(
+                change_index,
+                TxOut {
+                    value: Amount::from_sat(change),
+                    script_pubkey: change_script.clone(),
+                },
+            );
+            return build_plan(
+                FeeBumpMethod::Rbf,
+                &coins,
+                output,
+                fee,
+                vsize,
+                fee as f64 / vsize as f64,
+                Some(change),
+            );
+        }
+
+        if !payments.is_empty() {
+            let vsize = estimate_vsize(&coins, &payment_scripts)?;
+            let fee = required_fee(vsize);
+            if input_total >= payment_total + fee {
+                // Whatever is left is below the dust limit and goes to the miners
+                let fee = input_total - payment_total;
+                return build_plan(
+                    FeeBumpMethod::Rbf,
+                    &coins,
+                    payments,
+                    fee,
+                    vsize,
+                    fee as f64 / vsize as f64,
+                    None,
+                );
+            }
+        }
+
+        match candidates.next() {
+            Some(coin) => coins.push(coin),
+            None => {
+                return Err(FeeBumpError::InsufficientFunds {
+                    error_details: format!(
+                        "Wallet UTXOs cannot cover a replacement fee of {} sats",
+                        fee
+                    ),
+                })
+            }
+        }
+    }
+}
+
+/// Plans a child transaction spending our output of a pending transaction so that parent
+/// and child together pay `target_fee_rate` sat/vB.
+///
+/// Sent transactions are bumped through their output to `change_address`, received ones
+/// through the output to the activity address. The child pays everything back to
+/// `change_address`, adding confirmed `utxos` when the spent output is not enough.
+pub fn plan_cpfp(
+    activity: &OnchainActivity,
+    details: &TransactionDetails,
+    utxos: &[WalletUtxo],
+    change_address: &str,
+    target_fee_rate: f64,
+) -> Result<FeeBumpPlan, FeeBumpError> {
+    check_fee_rate(target_fee_rate)?;
+    ensure_pending(activity)?;
+
+    let parent = parse_transaction(details)?;
+    let parent_txid = parse_txid(details)?;
+    let change_script = parse_script(change_address)?;
+    let owned_script = match activity.tx_type {
+        PaymentType::Sent => change_script.clone(),
+        PaymentType::Received => parse_script(&activity.address)?,
+    };
+    let wallet_coins = utxos
+        .iter()
+        .map(Coin::from_utxo)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (vout, parent_output) = parent
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.script_pubkey == owned_script)
+        .max_by_key(|(_, output)| output.value)
+        .ok_or(FeeBumpError::NoSpendableOutput {
+            error_details: "The transaction has no output paying to the wallet".to_string(),
+        })?;
+    let parent_coin = Coin {
+        outpoint: OutPoint::new(parent_txid, vout as u32),
+        address_type: vsize::address_type_for_script(&parent_output.script_pubkey),
+        txout: parent_output.clone(),
+        confirmed: false,
+    };
+
+    // Received transactions are funded by someone else, so rely on the recorded fee
+    let parent_fee = known_fee(&parent, &wallet_coins).unwrap_or(activity.fee);
+    let parent_vsize = parent.vsize() as u64;
+    let parent_fee_rate = parent_fee as f64 / parent_vsize as f64;
+    if target_fee_rate <= parent_fee_rate {
+        return Err(FeeBumpError::InvalidFeeRate {
+            error_details: format!(
+                "Target fee rate {} sat/vB must exceed the parent fee rate of {:.2} sat/vB",
+                target_fee_rate, parent_fee_rate
+            ),
+        });
+    }
+
+    let dust_limit = change_script.minimal_non_dust().to_sat();
+    let output_scripts = [change_script.clone()];
+    let excluded = HashSet::from([parent_coin.outpoint]);
+    let mut candidates = additional_coins(&wallet_coins, &excluded, parent_txid).into_iter();
+    let mut coins = vec![parent_coin];
+
+    loop {
+        let input_total: u64 = coins.iter().map(Coin::value).sum();
+        let vsize = estimate_vsize(&coins, &output_scripts)?;
+        let package_fee = fee_for_vsize(target_fee_rate, parent_vsize + vsize);
+        let fee = package_fee
+            .saturating_sub(parent_fee)
+            .max(fee_for_vsize(MIN_RELAY_FEE_RATE, vsize));
+
+        if let Some(change) = input_total
+            .checked_sub(fee)
+            .filter(|change| *change >= dust_limit)
+        {
+            let output = vec![TxOut {
+                value: Amount::from_sat(change),
+                script_pubkey: change_script.clone(),
+            }];
+            let effective_fee_rate = (parent_fee + fee) as f64 / (parent_vsize + vsize) as f64;
+            return build_plan(
+                FeeBumpMethod::Cpfp,
+                &coins,
+                output,
+                fee,
+                vsize,
+                effective_fee_rate,
+                Some(change),
+            );
+        }
+
+        match candidates.next() {
+            Some(coin) => coins.push(coin),
+            None => {
+                return Err(FeeBumpError::InsufficientFunds {
+                    error_details: format!("Wallet UTXOs cannot cover a child fee of {} sats", fee),
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Fee rate estimation combining multiple sources.
+//!
+//! Samples from Blocktank, an Esplora `/fee-estimates` endpoint and mempool-block
+//! projections are validated against sanity bounds and combined per bucket using
+//! the median. The last good estimate is kept as a fallback for when every source
+//! fails.
+
+use super::errors::FeeEstimationError;
+use super::types::{
+    AddressType, FeeConfidence, FeeEstimates, FeeRateSample, TransactionFee, TransactionFees,
+};
+use super::vsize::estimate_vsize;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_MIN_FEE_RATE: f64 = 1.0;
+pub const DEFAULT_MAX_FEE_RATE: f64 = 2000.0;
+pub const DEFAULT_MAX_CACHE_AGE_SECS: u64 = 30 * 60;
+
+/// Sources whose fast rates deviate less than this from the median are considered in agreement.
+const AGREEMENT_TOLERANCE: f64 = 0.5;
+
+const FAST_TARGET_BLOCKS: u16 = 1;
+const NORMAL_TARGET_BLOCKS: u16 = 3;
+const SLOW_TARGET_BLOCKS: u16 = 6;
+const MINIMUM_TARGET_BLOCKS: u16 = 144;
+
+pub const ESPLORA_SOURCE: &str = "esplora";
+pub const MEMPOOL_BLOCKS_SOURCE: &str = "mempool-blocks";
+pub const BLOCKTANK_SOURCE: &str = "blocktank";
+
+/// A projected block from mempool.space's `/v1/fees/mempool-blocks` endpoint.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MempoolBlock {
+    pub block_vsize: f64,
+    pub n_tx: u32,
+    pub median_fee: f64,
+    pub fee_range: Vec<f64>,
+}
+
+/// Client for Esplora compatible fee endpoints, injectable for testing.
+#[async_trait]
+pub trait FeeEstimatesClient: Send + Sync {
+    /// Fee rates in sat/vB keyed by confirmation target in blocks.
+    async fn fee_estimates(&self) -> Result<HashMap<u16, f64>, FeeEstimationError>;
+
+    /// Projected next blocks, ordered from the next block onwards.
+    async fn mempool_blocks(&self) -> Result<Vec<MempoolBlock>, FeeEstimationError>;
+}
+
+/// HTTP client for an Esplora API such as `https://mempool.space/api`.
+pub struct HttpFeeEstimatesClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl HttpFeeEstimatesClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<T, FeeEstimationError> {
+        let url = format!("{}{}", self.base_url, path);
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| FeeEstimationError::SourceError {
+                error_details: format!("Request to {} failed: {}", url, e),
+            })?
+            .json::<T>()
+            .await
+            .map_err(|e| FeeEstimationError::SourceError {
+                error_details: format!("Invalid response from {}: {}", url, e),
+            })
+    }
+}
+
+#[async_trait]
+impl FeeEstimatesClient for HttpFeeEstimatesClient {
+    async fn fee_estimates(&self) -> Result<HashMap<u16, f64>, FeeEstimationError> {
+        let estimates: HashMap<String, f64> = self.get_json("/fee-estimates").await?;
+        Ok(estimates
+            .into_iter()
+            .filter_map(|(target, rate)| target.parse().ok().map(|target| (target, rate)))
+            .collect())
+    }
+
+    async fn mempool_blocks(&self) -> Result<Vec<MempoolBlock>, FeeEstimationError> {
+        self.get_json("/v1/fees/mempool-blocks").await
+    }
+}
+
+/// Combines fee rate samples into fast/normal/slow/minimum estimates.
+pub struct FeeEstimator {
+    min_fee_rate: f64,
+    max_fee_rate: f64,
+    max_cache_age_secs: u64,
+    cache: Mutex<Option<FeeEstimates>>,
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_MIN_FEE_RATE,
+            DEFAULT_MAX_FEE_RATE,
+            DEFAULT_MAX_CACHE_AGE_SECS,
+        )
+    }
+}
+
+impl FeeEstimator {
+    pub fn new(min_fee_rate: f64, max_fee_rate: f64, max_cache_age_secs: u64) -> Self {
+        Self {
+            min_fee_rate,
+            max_fee_rate,
+            max_cache_age_secs,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Estimates fee rates from `samples` (e.g. Blocktank's fee rates) and, if provided,
+    /// the Esplora fee estimates and mempool-block projections of `client`.
+    ///
+    /// Failing sources are skipped. If no source yields a valid sample, the last
+    /// estimate is returned as a low confidence fallback while it is fresh enough.
+    pub async fn estimate(
+        &self,
+        mut samples: Vec<FeeRateSample>,
+        client: Option<&dyn FeeEstimatesClient>,
+    ) -> Result<FeeEstimates, FeeEstimationError> {
+        if let Some(client) = client {
+            if let Ok(estimates) = client.fee_estimates().await {
+                samples.extend(sample_from_fee_estimates(&estimates));
+            }
+            if let Ok(blocks) = client.mempool_blocks().await {
+                samples.extend(sample_from_mempool_blocks(&blocks));
+            }
+        }
+        self.combine(samples, now_secs())
+    }
+
+    /// Combines `samples` at time `now` (unix seconds), updating or falling back to the cache.
+    pub fn combine(
+        &self,
+        samples: Vec<FeeRateSample>,
+        now: u64,
+    ) -> Result<FeeEstimates, FeeEstimationError> {
+        let valid: Vec<FeeRateSample> = samples
+            .into_iter()
+            .filter(|sample| self.is_valid_sample(sample))
+            .collect();
+
+        let mut cache = self.cache.lock().unwrap();
+        if valid.is_empty() {
+            return match cache.as_ref() {
+                Some(cached) if now.saturating_sub(cached.timestamp) <= self.max_cache_age_secs => {
+                    Ok(FeeEstimates {
+                        confidence: FeeConfidence::Low,
+                        is_fallback: true,
+                        ..cached.clone()
+                    })
+                }
+                _ => Err(FeeEstimationError::NoEstimatesAvailable {
+                    error_details: "No source returned a valid fee estimate".to_string(),
+                }),
+            };
+        }
+
+        let bucket = |f: fn(&FeeRateSample) -> Option<f64>| {
+            let values: Vec<f64> = valid.iter().filter_map(f).collect();
+            median(&values)
+        };
+        let fast = self.clamp(bucket(|s| Some(s.fast)).unwrap_or(self.min_fee_rate));
+        let normal = self
+            .clamp(bucket(|s| Some(s.normal)).unwrap_or(self.min_fee_rate))
+            .min(fast);
+        let slow = self
+            .clamp(bucket(|s| Some(s.slow)).unwrap_or(self.min_fee_rate))
+            .min(normal);
+        let minimum = self
+            .clamp(bucket(|s| s.minimum).unwrap_or(self.min_fee_rate))
+            .min(slow);
+
+        let estimates = FeeEstimates {
+            fast,
+            normal,
+            slow,
+            minimum,
+            confidence: self.confidence(&valid),
+            sources: valid.iter().map(|s| s.source.clone()).collect(),
+            is_fallback: false,
+            timestamp: now,
+        };
+        *cache = Some(estimates.clone());
+        Ok(estimates)
+    }
+
+    fn is_valid_sample(&self, sample: &FeeRateSample) -> bool {
+        let in_bounds = |rate: f64| rate.is_finite() && rate > 0.0 && rate <= self.max_fee_rate;
+        in_bounds(sample.fast)
+            && in_bounds(sample.normal)
+            && in_bounds(sample.slow)
+            && sample.minimum.is_none_or(in_bounds)
+    }
+
+    fn clamp(&self, rate: f64) -> f64 {
+        rate.clamp(self.min_fee_rate, self.max_fee_rate)
+    }
+
+    fn confidence(&self, samples: &[FeeRateSample]) -> FeeConfidence {
+        if samples.len() < 2 {
+            return FeeConfidence::Medium;
+        }
+        let fast: Vec<f64> = samples.iter().map(|s| s.fast).collect();
+        let median = median(&fast).unwrap_or_default();
+        let agree = fast
+            .iter()
+            .all(|rate| (rate - median).abs() <= median * AGREEMENT_TOLERANCE);
+        if agree {
+            FeeConfidence::High
+        } else {
+            FeeConfidence::Medium
+        }
+    }
+}
+
+impl FeeEstimates {
+    /// Absolute fees of a transaction spending `inputs` into `outputs` for each bucket.
+    pub fn fees_for_shape(
+        &self,
+        inputs: &[AddressType],
+        outputs: &[AddressType],
+    ) -> Result<TransactionFees, FeeEstimationError> {
+        let vsize = estimate_vsize(inputs, outputs)?;
+        Ok(TransactionFees {
+            vsize,
+            fast_sats: fee_for_vsize(vsize, self.fast),
+            normal_sats: fee_for_vsize(vsize, self.normal),
+            slow_sats: fee_for_vsize(vsize, self.slow),
+            minimum_sats: fee_for_vsize(vsize, self.minimum),
+        })
+    }
+}
+
+/// Absolute fee of a transaction spending `inputs` into `outputs` at `fee_rate` sat/vB.
+pub fn calculate_fee(
+    inputs: &[AddressType],
+    outputs: &[AddressType],
+    fee_rate: f64,
+) -> Result<TransactionFee, FeeEstimationError> {
+    if !fee_rate.is_finite() || fee_rate < 0.0 {
+        return Err(FeeEstimationError::InvalidFeeRate {
+            error_details: format!("Fee rate must be a non-negative number, got {}", fee_rate),
+        });
+    }
+    let vsize = estimate_vsize(inputs, outputs)?;
+    Ok(TransactionFee {
+        vsize,
+        fee_rate,
+        fee_sats: fee_for_vsize(vsize, fee_rate),
+    })
+}
+
+fn fee_for_vsize(vsize: u64, fee_rate: f64) -> u64 {
+    (vsize as f64 * fee_rate).ceil() as u64
+}
+
+/// Maps Esplora's confirmation target estimates to fee buckets.
+pub fn sample_from_fee_estimates(estimates: &HashMap<u16, f64>) -> Option<FeeRateSample> {
+    if estimates.is_empty() {
+        return None;
+    }
+    let mut targets: Vec<u16> = estimates.keys().copied().collect();
+    targets.sort_unstable();
+    // Use the slowest target that is still at least as fast as requested, or the fastest
+    // available
+    let at_target = |target: u16| {
+        let key = targets
+            .iter()
+            .rev()
+            .find(|t| **t <= target)
+            .or(targets.first())?;
+        estimates.get(key).copied()
+    };
+    Some(FeeRateSample {
+        source: ESPLORA_SOURCE.to_string(),
+        fast: at_target(FAST_TARGET_BLOCKS)?,
+        normal: at_target(NORMAL_TARGET_BLOCKS)?,
+        slow: at_target(SLOW_TARGET_BLOCKS)?,
+        minimum: at_target(MINIMUM_TARGET_BLOCKS),
+    })
+}
+
+/// Projects fee buckets from the median fee rates of the next mempool blocks.
+pub fn sample_from_mempool_blocks(blocks: &[MempoolBlock]) -> Option<FeeRateSample> {
+    let last = blocks.last()?;
+    let at_block = |index: usize| blocks[index.min(blocks.len() - 1)].median_fee;
+    Some(FeeRateSample {
+        source: MEMPOOL_BLOCKS_SOURCE.to_string(),
+        fast: at_block(FAST_TARGET_BLOCKS as usize - 1),
+        normal: at_block(NORMAL_TARGET_BLOCKS as usize - 1),
+        slow: at_block(SLOW_TARGET_BLOCKS as usize - 1),
+        minimum: last.fee_range.first().copied(),
+    })
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    Some(if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
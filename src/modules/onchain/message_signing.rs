@@ -0,0 +1,672 @@
+//! Bitcoin message signing and verification.
+//!
+//! Supports BIP322 generic signed messages (simple and full encodings) for
+//! native SegWit and Taproot addresses, and the legacy BIP137 format
+//! ("Bitcoin Signed Message") for P2PKH, P2SH-P2WPKH and P2WPKH addresses.
+//! BIP322 signatures of P2WSH and P2SH addresses are verified when their script
+//! is a single-key or multisig script.
+
+use super::errors::MessageSigningError;
+use super::types::MessageSignatureFormat;
+use bitcoin::address::{Address, AddressType, NetworkUnchecked};
+use bitcoin::base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bitcoin::blockdata::opcodes::all::{OP_CHECKMULTISIG, OP_CHECKSIG, OP_RETURN};
+use bitcoin::blockdata::script::{Builder, Instruction};
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::{hash160, sha256, Hash, HashEngine};
+use bitcoin::key::{CompressedPublicKey, Keypair, PrivateKey, PublicKey, TapTweak};
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{All, Message, Secp256k1, XOnlyPublicKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::sign_message::signed_msg_hash;
+use bitcoin::{
+    absolute, ecdsa, taproot, transaction, Amount, Network, NetworkKind, OutPoint, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use rand::RngCore;
+use std::str::FromStr;
+
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// Header byte offsets of BIP137 signatures, by address type.
+const BIP137_P2PKH_UNCOMPRESSED: u8 = 27;
+const BIP137_P2PKH_COMPRESSED: u8 = 31;
+const BIP137_P2SH_P2WPKH: u8 = 35;
+const BIP137_P2WPKH: u8 = 39;
+
+/// Signs `message` with the WIF encoded `private_key` for `address`.
+///
+/// The key must control `address`. When `format` is `None`, BIP322 simple is used
+/// for SegWit and Taproot addresses and BIP137 for legacy addresses.
+/// Returns the base64 encoded signature.
+pub fn sign_message(
+    message: &str,
+    address: &str,
+    private_key: &str,
+    format: Option<MessageSignatureFormat>,
+) -> Result<String, MessageSigningError> {
+    let secp = Secp256k1::new();
+    let private_key =
+        PrivateKey::from_wif(private_key).map_err(|e| MessageSigningError::InvalidPrivateKey {
+            error_details: e.to_string(),
+        })?;
+    let address = parse_address_for_network(address, private_key.network)?;
+    let address_type = address.address_type();
+
+    let format = format.unwrap_or(match address_type {
+        Some(AddressType::P2wpkh) | Some(AddressType::P2tr) => MessageSignatureFormat::Bip322Simple,
+        _ => MessageSignatureFormat::Legacy,
+    });
+
+    match format {
+        MessageSignatureFormat::Legacy => sign_bip137(&secp, message, &address, &private_key),
+        MessageSignatureFormat::Bip322Simple | MessageSignatureFormat::Bip322Full => {
+            let to_sign = sign_bip322(&secp, message, &address, &private_key)?;
+            let encoded = if format == MessageSignatureFormat::Bip322Simple {
+                serialize(&to_sign.input[0].witness)
+            } else {
+                serialize(&to_sign)
+            };
+            Ok(BASE64.encode(encoded))
+        }
+    }
+}
+
+/// Verifies a base64 encoded BIP322 (simple or full) or BIP137 signature of `message`
+/// for `address`.
+///
+/// Returns `Ok(false)` when the signature is well-formed but was not produced by the
+/// owner of `address`, and an error when the signature or address cannot be decoded.
+pub fn verify_message(
+    message: &str,
+    address: &str,
+    signature: &str,
+) -> Result<bool, MessageSigningError> {
+    let secp = Secp256k1::verification_only();
+    let address = Address::from_str(address)
+        .map_err(|e| MessageSigningError::InvalidAddress {
+            error_details: e.to_string(),
+        })?
+        .assume_checked();
+    let bytes =
+        BASE64
+            .decode(signature.trim())
+            .map_err(|e| MessageSigningError::InvalidSignature {
+                error_details: format!("Invalid base64: {}", e),
+            })?;
+
+    if bytes.len() == 65 && (BIP137_P2PKH_UNCOMPRESSED..BIP137_P2WPKH + 4).contains(&bytes[0]) {
+        // A 65 byte BIP322 witness would start with a small item count instead
+        return verify_bip137(&secp, message, &address, &bytes);
+    }
+
+    let to_spend = create_to_spend(message, &address.script_pubkey());
+    let to_sign = if let Ok(witness) = deserialize::<Witness>(&bytes) {
+        let mut to_sign = create_to_sign(&to_spend);
+        to_sign.input[0].witness = witness;
+        to_sign
+    } else if let Ok(tx) = deserialize::<Transaction>(&bytes) {
+        validate_full_to_sign(&tx)?;
+        if tx.input[0].previous_output != create_to_sign(&to_spend).input[0].previous_output {
+            // Signed for a different message or address
+            return Ok(false);
+        }
+        tx
+    } else {
+        return Err(MessageSigningError::InvalidSignature {
+            error_details:
+                "Signature is neither a BIP137 signature, a BIP322 witness nor a BIP322 transaction"
+                    .to_string(),
+        });
+    };
+
+    verify_bip322(&secp, &address, &to_spend, &to_sign)
+}
+
+/// BIP340 tagged hash of the message as defined by BIP322.
+pub(crate) fn bip322_message_hash(message: &str) -> [u8; 32] {
+    let tag = sha256::Hash::hash(BIP322_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// The virtual `to_spend` transaction committing to the message and the address.
+pub(crate) fn create_to_spend(message: &str, script_pubkey: &ScriptBuf) -> Transaction {
+    let script_sig = Builder::new()
+        .push_int(0)
+        .push_slice(bip322_message_hash(message))
+        .into_script();
+
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: Txid::all_zeros(),
+                vout: 0xFFFFFFFF,
+            },
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.clone(),
+        }],
+    }
+}
+
+/// The unsigned virtual `to_sign` transaction spending `to_spend`.
+pub(crate) fn create_to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: to_spend.compute_txid(),
+                vout: 0,
+            },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+
+fn parse_address_for_network(
+    address: &str,
+    network: NetworkKind,
+) -> Result<Address, MessageSigningError> {
+    let unchecked: Address<NetworkUnchecked> =
+        Address::from_str(address).map_err(|e| MessageSigningError::InvalidAddress {
+            error_details: e.to_string(),
+        })?;
+    let networks: &[Network] = match network {
+        NetworkKind::Main => &[Network::Bitcoin],
+        NetworkKind::Test => &[
+            Network::Testnet,
+            Network::Testnet4,
+            Network::Signet,
+            Network::Regtest,
+        ],
+    };
+    if !networks.iter().any(|n| unchecked.is_valid_for_network(*n)) {
+        return Err(MessageSigningError::InvalidAddress {
+            error_details: "Address network does not match the private key network".to_string(),
+        });
+    }
+    Ok(unchecked.assume_checked())
+}
+
+fn key_mismatch() -> MessageSigningError {
+    MessageSigningError::InvalidPrivateKey {
+        error_details: "Private key does not control the given address".to_string(),
+    }
+}
+
+fn compressed_public_key(
+    secp: &Secp256k1<All>,
+    private_key: &PrivateKey,
+) -> Result<CompressedPublicKey, MessageSigningError> {
+    CompressedPublicKey::from_private_key(secp, private_key).map_err(|_| {
+        MessageSigningError::InvalidPrivateKey {
+            error_details: "SegWit addresses require a compressed private key".to_string(),
+        }
+    })
+}
+
+fn sign_bip137(
+    secp: &Secp256k1<All>,
+    message: &str,
+    address: &Address,
+    private_key: &PrivateKey,
+) -> Result<String, MessageSigningError> {
+    let public_key = private_key.public_key(secp);
+    let header_base = match address.address_type() {
+        Some(AddressType::P2pkh) => {
+            if !address.matches_script_pubkey(&ScriptBuf::new_p2pkh(&public_key.pubkey_hash())) {
+                return Err(key_mismatch());
+            }
+            if private_key.compressed {
+                BIP137_P2PKH_COMPRESSED
+            } else {
+                BIP137_P2PKH_UNCOMPRESSED
+            }
+        }
+        Some(AddressType::P2sh) => {
+            let compressed = compressed_public_key(secp, private_key)?;
+            if !address.matches_script_pubkey(&p2shwpkh_script(&compressed)) {
+                return Err(key_mismatch());
+            }
+            BIP137_P2SH_P2WPKH
+        }
+        Some(AddressType::P2wpkh) => {
+            let compressed = compressed_public_key(secp, private_key)?;
+            if !address.matches_script_pubkey(&ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash())) {
+                return Err(key_mismatch());
+            }
+            BIP137_P2WPKH
+        }
+        _ => {
+            return Err(MessageSigningError::UnsupportedAddressType {
+                error_details:
+                    "Legacy message signing supports P2PKH, P2SH-P2WPKH and P2WPKH addresses"
+                        .to_string(),
+            })
+        }
+    };
+
+    let msg = Message::from_digest(signed_msg_hash(message).to_byte_array());
+    let signature = secp.sign_ecdsa_recoverable(&msg, &private_key.inner);
+    let (recovery_id, compact) = signature.serialize_compact();
+
+    let mut bytes = Vec::with_capacity(65);
+    bytes.push(header_base + recovery_id.to_i32() as u8);
+    bytes.extend_from_slice(&compact);
+    Ok(BASE64.encode(bytes))
+}
+
+fn verify_bip137(
+    secp: &Secp256k1<bitcoin::secp256k1::VerifyOnly>,
+    message: &str,
+    address: &Address,
+    bytes: &[u8],
+) -> Result<bool, MessageSigningError> {
+    let header = bytes[0];
+    let recovery_id = RecoveryId::from_i32(((header - BIP137_P2PKH_UNCOMPRESSED) % 4) as i32)
+        .map_err(|e| MessageSigningError::InvalidSignature {
+            error_details: e.to_string(),
+        })?;
+    let signature = RecoverableSignature::from_compact(&bytes[1..], recovery_id).map_err(|e| {
+        MessageSigningError::InvalidSignature {
+            error_details: e.to_string(),
+        }
+    })?;
+
+    let msg = Message::from_digest(signed_msg_hash(message).to_byte_array());
+    let Ok(recovered) = secp.recover_ecdsa(&msg, &signature) else {
+        return Ok(false);
+    };
+
+    if header < BIP137_P2PKH_COMPRESSED {
+        let public_key = PublicKey::new_uncompressed(recovered);
+        return Ok(address.matches_script_pubkey(&ScriptBuf::new_p2pkh(&public_key.pubkey_hash())));
+    }
+
+    // Many wallets use the compressed P2PKH header range for SegWit addresses too,
+    // so accept any single-key address type derived from the recovered key.
+    let compressed = CompressedPublicKey(recovered);
+    Ok(
+        address.matches_script_pubkey(&ScriptBuf::new_p2pkh(&compressed.pubkey_hash()))
+            || address.matches_script_pubkey(&p2shwpkh_script(&compressed))
+            || address.matches_script_pubkey(&ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash())),
+    )
+}
+
+fn sign_bip322(
+    secp: &Secp256k1<All>,
+    message: &str,
+    address: &Address,
+    private_key: &PrivateKey,
+) -> Result<Transaction, MessageSigningError> {
+    let script_pubkey = address.script_pubkey();
+    let to_spend = create_to_spend(message, &script_pubkey);
+    let mut to_sign = create_to_sign(&to_spend);
+
+    let witness = match address.address_type() {
+        Some(AddressType::P2wpkh) => {
+            let public_key = compressed_public_key(secp, private_key)?;
+            if script_pubkey != ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()) {
+                return Err(key_mismatch());
+            }
+            let sighash = SighashCache::new(&to_sign)
+                .p2wpkh_signature_hash(0, &script_pubkey, Amount::ZERO, EcdsaSighashType::All)
+                .map_err(|e| MessageSigningError::SigningFailed {
+                    error_details: e.to_string(),
+                })?;
+            let signature = ecdsa::Signature {
+                signature: secp.sign_ecdsa_low_r(&Message::from(sighash), &private_key.inner),
+                sighash_type: EcdsaSighashType::All,
+            };
+            Witness::p2wpkh(&signature, &public_key.0)
+        }
+        Some(AddressType::P2tr) => {
+            let keypair = Keypair::from_secret_key(secp, &private_key.inner);
+            let (internal_key, _) = keypair.x_only_public_key();
+            if script_pubkey != ScriptBuf::new_p2tr(secp, internal_key, None) {
+                return Err(key_mismatch());
+            }
+            let prevouts = [to_spend.output[0].clone()];
+            let sighash = SighashCache::new(&to_sign)
+                .taproot_key_spend_signature_hash(
+                    0,
+                    &Prevouts::All(&prevouts),
+                    TapSighashType::Default,
+                )
+                .map_err(|e| MessageSigningError::SigningFailed {
+                    error_details: e.to_string(),
+                })?;
+            let tweaked = keypair.tap_tweak(secp, None);
+            let mut aux_rand = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut aux_rand);
+            let signature = taproot::Signature {
+                signature: secp.sign_schnorr_with_aux_rand(
+                    &Message::from(sighash),
+                    &tweaked.to_keypair(),
+                    &aux_rand,
+                ),
+                sighash_type: TapSighashType::Default,
+            };
+            Witness::p2tr_key_spend(&signature)
+        }
+        _ => {
+            return Err(MessageSigningError::UnsupportedAddressType {
+                error_details: "BIP322 signing supports P2WPKH and P2TR addresses".to_string(),
+            })
+        }
+    };
+
+    to_sign.input[0].witness = witness;
+    Ok(to_sign)
+}
+
+fn validate_full_to_sign(to_sign: &Transaction) -> Result<(), MessageSigningError> {
+    let invalid = |error_details: &str| MessageSigningError::InvalidSignature {
+        error_details: error_details.to_string(),
+    };
+    if to_sign.input.len() != 1 {
+        return Err(invalid("BIP322 transaction must have exactly one input"));
+    }
+    if to_sign.output.len() != 1
+        || to_sign.output[0].value != Amount::ZERO
+        || !to_sign.output[0].script_pubkey.is_op_return()
+    {
+        return Err(invalid(
+            "BIP322 transaction must have a single empty OP_RETURN output",
+        ));
+    }
+    Ok(())
+}
+
+fn verify_bip322(
+    secp: &Secp256k1<bitcoin::secp256k1::VerifyOnly>,
+    address: &Address,
+    to_spend: &Transaction,
+    to_sign: &Transaction,
+) -> Result<bool, MessageSigningError> {
+    let script_pubkey = &to_spend.output[0].script_pubkey;
+    let input = &to_sign.input[0];
+    let witness: Vec<&[u8]> = input.witness.iter().collect();
+
+    match address.address_type() {
+        Some(AddressType::P2wpkh) => verify_p2wpkh(secp, to_sign, script_pubkey, &witness),
+        Some(AddressType::P2wsh) => verify_p2wsh(secp, to_sign, script_pubkey, &witness),
+        Some(AddressType::P2sh) => {
+            let Some(pushes) = script_pushes(&input.script_sig) else {
+                return Ok(false);
+            };
+            if witness.is_empty() {
+                // Legacy P2SH, only expressible in the full format
+                let Some((redeem_script, stack)) = pushes.split_last() else {
+                    return Ok(false);
+                };
+                let redeem_script = ScriptBuf::from_bytes(redeem_script.to_vec());
+                if redeem_script.to_p2sh() != *script_pubkey {
+                    return Ok(false);
+                }
+                return verify_script(secp, &redeem_script, stack, |sighash_type| {
+                    SighashCache::new(to_sign)
+                        .legacy_signature_hash(0, &redeem_script, sighash_type.to_u32())
+                        .map(Message::from)
+                });
+            }
+
+            // Nested SegWit: the full format carries the redeem script push, the simple
+            // format implies it from the witness
+            let redeem_script = match pushes[..] {
+                [redeem_script] => ScriptBuf::from_bytes(redeem_script.to_vec()),
+                [] => match implied_redeem_script(script_pubkey, &witness) {
+                    Some(redeem_script) => redeem_script,
+                    None => return Ok(false),
+                },
+                _ => return Ok(false),
+            };
+            if redeem_script.to_p2sh() != *script_pubkey {
+                return Ok(false);
+            }
+            if redeem_script.is_p2wpkh() {
+                verify_p2wpkh(secp, to_sign, &redeem_script, &witness)
+            } else if redeem_script.is_p2wsh() {
+                verify_p2wsh(secp, to_sign, &redeem_script, &witness)
+            } else {
+                Ok(false)
+            }
+        }
+        Some(AddressType::P2tr) => {
+            let [signature] = witness[..] else {
+                return Ok(false);
+            };
+            let Ok(signature) = taproot::Signature::from_slice(signature) else {
+                return Ok(false);
+            };
+            let Ok(output_key) = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]) else {
+                return Ok(false);
+            };
+            let prevouts = [to_spend.output[0].clone()];
+            let sighash = SighashCache::new(to_sign)
+                .taproot_key_spend_signature_hash(
+                    0,
+                    &Prevouts::All(&prevouts),
+                    signature.sighash_type,
+                )
+                .map_err(|e| MessageSigningError::InvalidSignature {
+                    error_details: e.to_string(),
+                })?;
+            Ok(secp
+                .verify_schnorr(&signature.signature, &Message::from(sighash), &output_key)
+                .is_ok())
+        }
+        Some(AddressType::P2pkh) => {
+            // Only expressible in the full format, with the signature in the script_sig
+            let Some(pushes) = script_pushes(&input.script_sig) else {
+                return Ok(false);
+            };
+            let [signature, public_key] = pushes[..] else {
+                return Ok(false);
+            };
+            let Ok(public_key) = PublicKey::from_slice(public_key) else {
+                return Ok(false);
+            };
+            let Ok(signature) = ecdsa::Signature::from_slice(signature) else {
+                return Ok(false);
+            };
+            if hash160::Hash::hash(&public_key.to_bytes()).to_byte_array()
+                != script_pubkey.as_bytes()[3..23]
+            {
+                return Ok(false);
+            }
+            let sighash = SighashCache::new(to_sign)
+                .legacy_signature_hash(0, script_pubkey, signature.sighash_type.to_u32())
+                .map_err(|e| MessageSigningError::InvalidSignature {
+                    error_details: e.to_string(),
+                })?;
+            Ok(secp
+                .verify_ecdsa(
+                    &Message::from(sighash),
+                    &signature.signature,
+                    &public_key.inner,
+                )
+                .is_ok())
+        }
+        _ => Err(MessageSigningError::UnsupportedAddressType {
+            error_details: "BIP322 verification does not support this address type".to_string(),
+        }),
+    }
+}
+
+/// Verifies a P2WPKH spend of `p2wpkh_script`, native or nested in P2SH.
+fn verify_p2wpkh(
+    secp: &Secp256k1<bitcoin::secp256k1::VerifyOnly>,
+    to_sign: &Transaction,
+    p2wpkh_script: &ScriptBuf,
+    witness: &[&[u8]],
+) -> Result<bool, MessageSigningError> {
+    let [signature, public_key] = witness[..] else {
+        return Ok(false);
+    };
+    let Ok(public_key) = CompressedPublicKey::from_slice(public_key) else {
+        return Ok(false);
+    };
+    let Ok(signature) = ecdsa::Signature::from_slice(signature) else {
+        return Ok(false);
+    };
+    if *p2wpkh_script != ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()) {
+        return Ok(false);
+    }
+
+    let sighash = SighashCache::new(to_sign)
+        .p2wpkh_signature_hash(0, p2wpkh_script, Amount::ZERO, signature.sighash_type)
+        .map_err(|e| MessageSigningError::InvalidSignature {
+            error_details: e.to_string(),
+        })?;
+    Ok(secp
+        .verify_ecdsa(&Message::from(sighash), &signature.signature, &public_key.0)
+        .is_ok())
+}
+
+/// Verifies a P2WSH spend of `p2wsh_script`, native or nested in P2SH. The witness script
+/// is the last witness item.
+fn verify_p2wsh(
+    secp: &Secp256k1<bitcoin::secp256k1::VerifyOnly>,
+    to_sign: &Transaction,
+    p2wsh_script: &ScriptBuf,
+    witness: &[&[u8]],
+) -> Result<bool, MessageSigningError> {
+    let Some((witness_script, stack)) = witness.split_last() else {
+        return Ok(false);
+    };
+    let witness_script = ScriptBuf::from_bytes(witness_script.to_vec());
+    if witness_script.to_p2wsh() != *p2wsh_script {
+        return Ok(false);
+    }
+    verify_script(secp, &witness_script, stack, |sighash_type| {
+        SighashCache::new(to_sign)
+            .p2wsh_signature_hash(0, &witness_script, Amount::ZERO, sighash_type)
+            .map(Message::from)
+    })
+}
+
+/// Redeem script of a nested SegWit spend in the simple format, which has no script_sig:
+/// the P2WPKH program of the witness key or the P2WSH program of the witness script,
+/// whichever `script_pubkey` commits to.
+fn implied_redeem_script(script_pubkey: &ScriptBuf, witness: &[&[u8]]) -> Option<ScriptBuf> {
+    let last = witness.last()?;
+    let p2wsh = ScriptBuf::from_bytes(last.to_vec()).to_p2wsh();
+    let p2wpkh = CompressedPublicKey::from_slice(last)
+        .ok()
+        .filter(|_| witness.len() == 2)
+        .map(|public_key| ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()));
+    [Some(p2wsh), p2wpkh]
+        .into_iter()
+        .flatten()
+        .find(|redeem_script| redeem_script.to_p2sh() == *script_pubkey)
+}
+
+/// Data pushes of a push-only script, `None` if it executes other opcodes.
+fn script_pushes(script: &ScriptBuf) -> Option<Vec<&[u8]>> {
+    script
+        .instructions()
+        .map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Verifies `stack` against a single-key (`<key> OP_CHECKSIG`) or bare multisig
+/// (`m <keys> n OP_CHECKMULTISIG`) `script`. Other scripts need a script interpreter and
+/// are rejected as unsupported.
+fn verify_script<E: std::fmt::Display>(
+    secp: &Secp256k1<bitcoin::secp256k1::VerifyOnly>,
+    script: &ScriptBuf,
+    stack: &[&[u8]],
+    sighash: impl Fn(EcdsaSighashType) -> Result<Message, E>,
+) -> Result<bool, MessageSigningError> {
+    let Some((public_keys, required)) = script_keys(script) else {
+        return Err(MessageSigningError::UnsupportedAddressType {
+            error_details: "BIP322 verification supports single-key and multisig scripts only"
+                .to_string(),
+        });
+    };
+
+    // OP_CHECKMULTISIG pops an extra, empty element before the signatures
+    let signatures = match (required, stack) {
+        (None, [signature]) => std::slice::from_ref(signature),
+        (Some(required), [dummy, signatures @ ..])
+            if dummy.is_empty() && signatures.len() == required =>
+        {
+            signatures
+        }
+        _ => return Ok(false),
+    };
+
+    // Signatures have to match the keys in script order
+    let mut public_keys = public_keys.iter();
+    for signature in signatures {
+        let Ok(signature) = ecdsa::Signature::from_slice(signature) else {
+            return Ok(false);
+        };
+        let msg =
+            sighash(signature.sighash_type).map_err(|e| MessageSigningError::InvalidSignature {
+                error_details: e.to_string(),
+            })?;
+        if !public_keys.any(|public_key| {
+            secp.verify_ecdsa(&msg, &signature.signature, &public_key.inner)
+                .is_ok()
+        }) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Value of an `OP_1` to `OP_16` instruction
+fn pushnum(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Op(_) => usize::try_from(instruction.script_num()?).ok(),
+        Instruction::PushBytes(_) => None,
+    }
+}
+
+/// Keys of a single-key or multisig script, with the number of required signatures of a
+/// multisig script.
+fn script_keys(script: &ScriptBuf) -> Option<(Vec<PublicKey>, Option<usize>)> {
+    let instructions: Vec<Instruction> = script.instructions().collect::<Result<_, _>>().ok()?;
+    let public_key = |instruction: &Instruction| {
+        PublicKey::from_slice(instruction.push_bytes()?.as_bytes()).ok()
+    };
+
+    match &instructions[..] {
+        [key, Instruction::Op(op)] if *op == OP_CHECKSIG => Some((vec![public_key(key)?], None)),
+        [required, keys @ .., total, Instruction::Op(op)] if *op == OP_CHECKMULTISIG => {
+            let (required, total) = (pushnum(required)?, pushnum(total)?);
+            if keys.len() != total || required == 0 || required > total {
+                return None;
+            }
+            let keys = keys.iter().map(public_key).collect::<Option<Vec<_>>>()?;
+            Some((keys, Some(required)))
+        }
+        _ => None,
+    }
+}
+
+fn p2shwpkh_script(public_key: &CompressedPublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2sh(&ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()).script_hash())
+}
@@ -1,11 +1,21 @@
 mod errors;
+pub mod fee_bump;
+pub mod fees;
 mod implementation;
+pub mod message_signing;
+pub mod slip39;
 mod types;
+pub mod vsize;
 
-pub use errors::AddressError;
+pub use errors::{
+    AddressError, FeeBumpError, FeeEstimationError, MessageSigningError, Slip39Error,
+};
 pub use implementation::BitcoinAddressValidator;
 pub use types::{
-    AddressType, GetAddressResponse, GetAddressesResponse, Network, ValidationResult, WordCount,
+    AddressType, FeeBumpMethod, FeeBumpPlan, FeeConfidence, FeeEstimates, FeeRateSample,
+    GetAddressResponse, GetAddressesResponse, MessageSignatureFormat, Network, Slip39Group,
+    Slip39GroupShares, Slip39ShareInfo, TransactionFee, TransactionFees, ValidationResult,
+    WalletUtxo, WordCount,
 };
 
 #[cfg(test)]
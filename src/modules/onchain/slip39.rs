@@ -0,0 +1,768 @@
+//! SLIP-39 Shamir secret sharing of a master secret.
+//!
+//! Implements share generation and recovery as specified in
+//! <https://github.com/satoshilabs/slips/blob/master/slip-0039.md>, including
+//! two-level (group/member) thresholds, passphrase encryption and the
+//! extendable backup flag.
+
+use super::errors::Slip39Error;
+use super::implementation::BitcoinAddressValidator;
+use super::types::{Slip39Group, Slip39GroupShares, Slip39ShareInfo};
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use std::collections::BTreeMap;
+
+const RADIX_BITS: usize = 10;
+const ID_LENGTH_BITS: usize = 15;
+const ITERATION_EXP_LENGTH_BITS: usize = 4;
+const EXTENDABLE_FLAG_LENGTH_BITS: usize = 1;
+const ID_EXP_LENGTH_WORDS: usize = 2;
+const CHECKSUM_LENGTH_WORDS: usize = 3;
+const METADATA_LENGTH_WORDS: usize = ID_EXP_LENGTH_WORDS + 2 + CHECKSUM_LENGTH_WORDS;
+const MIN_STRENGTH_BITS: usize = 128;
+const MIN_MNEMONIC_LENGTH_WORDS: usize =
+    METADATA_LENGTH_WORDS + MIN_STRENGTH_BITS.div_ceil(RADIX_BITS);
+const MAX_SHARE_COUNT: usize = 16;
+const MAX_ITERATION_EXPONENT: u8 = (1 << ITERATION_EXP_LENGTH_BITS) - 1;
+const BASE_ITERATION_COUNT: u32 = 10000;
+const ROUND_COUNT: u8 = 4;
+const DIGEST_INDEX: u8 = 254;
+const SECRET_INDEX: u8 = 255;
+const DIGEST_LENGTH_BYTES: usize = 4;
+const CUSTOMIZATION_STRING: &[u8] = b"shamir";
+const CUSTOMIZATION_STRING_EXTENDABLE: &[u8] = b"shamir_extendable";
+
+static WORDLIST: Lazy<Vec<&'static str>> =
+    Lazy::new(|| include_str!("slip39_wordlist.txt").lines().collect());
+
+/// Exponent and logarithm tables of GF(256) using the polynomial
+/// x^8 + x^4 + x^3 + x + 1 with generator 3.
+static GF256_TABLES: Lazy<([u8; 255], [u8; 256])> = Lazy::new(|| {
+    let mut exp = [0u8; 255];
+    let mut log = [0u8; 256];
+    let mut poly: u16 = 1;
+    for (i, value) in exp.iter_mut().enumerate() {
+        *value = poly as u8;
+        log[poly as usize] = i as u8;
+        poly = (poly << 1) ^ poly;
+        if poly & 0x100 != 0 {
+            poly ^= 0x11b;
+        }
+    }
+    (exp, log)
+});
+
+/// A decoded SLIP-39 share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Share {
+    identifier: u16,
+    extendable: bool,
+    iteration_exponent: u8,
+    group_index: u8,
+    group_threshold: u8,
+    group_count: u8,
+    member_index: u8,
+    member_threshold: u8,
+    value: Vec<u8>,
+}
+
+impl Share {
+    fn info(&self) -> Slip39ShareInfo {
+        Slip39ShareInfo {
+            identifier: self.identifier,
+            extendable: self.extendable,
+            iteration_exponent: self.iteration_exponent,
+            group_index: self.group_index,
+            group_threshold: self.group_threshold,
+            group_count: self.group_count,
+            member_index: self.member_index,
+            member_threshold: self.member_threshold,
+        }
+    }
+
+    fn to_mnemonic(&self) -> String {
+        let id_exp = ((self.identifier as u64)
+            << (ITERATION_EXP_LENGTH_BITS + EXTENDABLE_FLAG_LENGTH_BITS))
+            | ((self.extendable as u64) << ITERATION_EXP_LENGTH_BITS)
+            | self.iteration_exponent as u64;
+        let params = ((self.group_index as u64) << 16)
+            | (((self.group_threshold - 1) as u64) << 12)
+            | (((self.group_count - 1) as u64) << 8)
+            | ((self.member_index as u64) << 4)
+            | (self.member_threshold - 1) as u64;
+
+        let mut indices = Vec::new();
+        indices.extend(int_to_indices(id_exp, ID_EXP_LENGTH_WORDS));
+        indices.extend(int_to_indices(params, 2));
+        indices.extend(bytes_to_indices(&self.value));
+        let checksum = create_checksum(&indices, self.extendable);
+        indices.extend(checksum);
+
+        indices
+            .iter()
+            .map(|&i| WORDLIST[i as usize])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn from_mnemonic(mnemonic: &str, share_index: u32) -> Result<Self, Slip39Error> {
+        let invalid = |error_details: String| Slip39Error::InvalidShare {
+            share_index,
+            error_details,
+        };
+
+        let words: Vec<String> = mnemonic
+            .split_whitespace()
+            .map(|w| w.to_lowercase())
+            .collect();
+        if words.len() < MIN_MNEMONIC_LENGTH_WORDS {
+            return Err(invalid(format!(
+                "Mnemonic must have at least {} words, got {}",
+                MIN_MNEMONIC_LENGTH_WORDS,
+                words.len()
+            )));
+        }
+
+        let mut indices = Vec::with_capacity(words.len());
+        for (position, word) in words.iter().enumerate() {
+            let index = WORDLIST.binary_search(&word.as_str()).map_err(|_| {
+                invalid(format!(
+                    "Unknown word '{}' at position {}",
+                    word,
+                    position + 1
+                ))
+            })?;
+            indices.push(index as u16);
+        }
+
+        let padding_len = (RADIX_BITS * (indices.len() - METADATA_LENGTH_WORDS)) % 16;
+        if padding_len > 8 {
+            return Err(invalid(format!(
+                "Invalid mnemonic length of {} words",
+                indices.len()
+            )));
+        }
+
+        let id_exp = indices_to_int(&indices[..ID_EXP_LENGTH_WORDS]);
+        let identifier = (id_exp >> (ITERATION_EXP_LENGTH_BITS + EXTENDABLE_FLAG_LENGTH_BITS))
+            as u16
+            & ((1 << ID_LENGTH_BITS) - 1);
+        let extendable = (id_exp >> ITERATION_EXP_LENGTH_BITS) & 1 == 1;
+        let iteration_exponent = (id_exp & MAX_ITERATION_EXPONENT as u64) as u8;
+
+        if !verify_checksum(&indices, extendable) {
+            return Err(invalid("Invalid checksum".to_string()));
+        }
+
+        let params = indices_to_int(&indices[ID_EXP_LENGTH_WORDS..ID_EXP_LENGTH_WORDS + 2]);
+        let nibble = |shift: u32| ((params >> shift) & 0xf) as u8;
+        let group_index = nibble(16);
+        let group_threshold = nibble(12) + 1;
+        let group_count = nibble(8) + 1;
+        let member_index = nibble(4);
+        let member_threshold = nibble(0) + 1;
+
+        if group_count < group_threshold {
+            return Err(invalid(format!(
+                "Group threshold {} exceeds the number of groups {}",
+                group_threshold, group_count
+            )));
+        }
+
+        let value_indices =
+            &indices[ID_EXP_LENGTH_WORDS + 2..indices.len() - CHECKSUM_LENGTH_WORDS];
+        let value = indices_to_bytes(value_indices, padding_len)
+            .ok_or_else(|| invalid("Invalid padding".to_string()))?;
+
+        Ok(Share {
+            identifier,
+            extendable,
+            iteration_exponent,
+            group_index,
+            group_threshold,
+            group_count,
+            member_index,
+            member_threshold,
+            value,
+        })
+    }
+}
+
+/// Splits `master_secret` into SLIP-39 mnemonic shares.
+///
+/// `group_threshold` groups out of `groups` are required to recover the secret,
+/// and each group needs `member_threshold` of its members. The secret is encrypted
+/// with `passphrase` before splitting, using `10000 << iteration_exponent` PBKDF2
+/// iterations. Generated shares are extendable.
+pub fn generate_shares(
+    master_secret: &[u8],
+    group_threshold: u8,
+    groups: &[Slip39Group],
+    passphrase: &str,
+    iteration_exponent: u8,
+) -> Result<Vec<Slip39GroupShares>, Slip39Error> {
+    validate_master_secret(master_secret)?;
+    validate_passphrase(passphrase)?;
+
+    let config_error = |error_details: String| Slip39Error::InvalidConfiguration { error_details };
+
+    if groups.is_empty() || groups.len() > MAX_SHARE_COUNT {
+        return Err(config_error(format!(
+            "The number of groups must be between 1 and {}",
+            MAX_SHARE_COUNT
+        )));
+    }
+    if group_threshold == 0 || group_threshold as usize > groups.len() {
+        return Err(config_error(format!(
+            "Group threshold must be between 1 and the number of groups ({})",
+            groups.len()
+        )));
+    }
+    if iteration_exponent > MAX_ITERATION_EXPONENT {
+        return Err(config_error(format!(
+            "Iteration exponent must not exceed {}",
+            MAX_ITERATION_EXPONENT
+        )));
+    }
+    for (i, group) in groups.iter().enumerate() {
+        if group.member_count == 0 || group.member_count as usize > MAX_SHARE_COUNT {
+            return Err(config_error(format!(
+                "Group {} must have between 1 and {} members",
+                i, MAX_SHARE_COUNT
+            )));
+        }
+        if group.member_threshold == 0 || group.member_threshold > group.member_count {
+            return Err(config_error(format!(
+                "Member threshold of group {} must be between 1 and its member count ({})",
+                i, group.member_count
+            )));
+        }
+        if group.member_threshold == 1 && group.member_count > 1 {
+            return Err(config_error(format!(
+                "Group {} uses a member threshold of 1, which only allows a single member share",
+                i
+            )));
+        }
+    }
+
+    let identifier = (rand::thread_rng().next_u32() & ((1 << ID_LENGTH_BITS) - 1)) as u16;
+    let extendable = true;
+    let encrypted_master_secret = encrypt(
+        master_secret,
+        passphrase.as_bytes(),
+        iteration_exponent,
+        identifier,
+        extendable,
+    );
+
+    let group_secrets = split_secret(
+        group_threshold,
+        groups.len() as u8,
+        &encrypted_master_secret,
+    )?;
+
+    let mut result = Vec::with_capacity(groups.len());
+    for ((group_index, group_secret), group) in group_secrets.into_iter().zip(groups) {
+        let member_secrets =
+            split_secret(group.member_threshold, group.member_count, &group_secret)?;
+        let shares = member_secrets
+            .into_iter()
+            .map(|(member_index, value)| {
+                Share {
+                    identifier,
+                    extendable,
+                    iteration_exponent,
+                    group_index,
+                    group_threshold,
+                    group_count: groups.len() as u8,
+                    member_index,
+                    member_threshold: group.member_threshold,
+                    value,
+                }
+                .to_mnemonic()
+            })
+            .collect();
+        result.push(Slip39GroupShares {
+            group_index,
+            member_threshold: group.member_threshold,
+            shares,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Recovers the master secret from a set of SLIP-39 mnemonic shares.
+///
+/// Shares may be provided in any order and groups that do not reach their member
+/// threshold are ignored as long as enough complete groups are present. Errors
+/// that can be attributed to a single share report its position in `mnemonics`.
+pub fn combine_shares(mnemonics: &[String], passphrase: &str) -> Result<Vec<u8>, Slip39Error> {
+    validate_passphrase(passphrase)?;
+
+    if mnemonics.is_empty() {
+        return Err(Slip39Error::InsufficientShares {
+            error_details: "No shares provided".to_string(),
+        });
+    }
+
+    let shares = mnemonics
+        .iter()
+        .enumerate()
+        .map(|(i, m)| Share::from_mnemonic(m, i as u32))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let first = &shares[0];
+    let mut groups: BTreeMap<u8, BTreeMap<u8, &Share>> = BTreeMap::new();
+    for (i, share) in shares.iter().enumerate() {
+        let mismatch = |error_details: String| Slip39Error::ShareMismatch {
+            share_index: i as u32,
+            error_details,
+        };
+        if share.identifier != first.identifier || share.extendable != first.extendable {
+            return Err(mismatch(
+                "Share belongs to a different backup (identifier mismatch)".to_string(),
+            ));
+        }
+        if share.iteration_exponent != first.iteration_exponent {
+            return Err(mismatch("Iteration exponent mismatch".to_string()));
+        }
+        if share.group_threshold != first.group_threshold || share.group_count != first.group_count
+        {
+            return Err(mismatch("Group parameters mismatch".to_string()));
+        }
+        if share.value.len() != first.value.len() {
+            return Err(mismatch("Share length mismatch".to_string()));
+        }
+
+        let members = groups.entry(share.group_index).or_default();
+        if let Some(existing) = members.values().next() {
+            if existing.member_threshold != share.member_threshold {
+                return Err(mismatch(format!(
+                    "Member threshold mismatch within group {}",
+                    share.group_index
+                )));
+            }
+        }
+        if let Some(existing) = members.get(&share.member_index) {
+            if existing.value != share.value {
+                return Err(mismatch(format!(
+                    "Conflicting duplicate of member {} in group {}",
+                    share.member_index, share.group_index
+                )));
+            }
+            continue;
+        }
+        members.insert(share.member_index, share);
+    }
+
+    let mut group_secrets = Vec::new();
+    let mut incomplete = Vec::new();
+    for (group_index, members) in &groups {
+        let member_threshold = members.values().next().map_or(1, |s| s.member_threshold);
+        if members.len() < member_threshold as usize {
+            incomplete.push(format!(
+                "group {} has {} of {} required shares",
+                group_index,
+                members.len(),
+                member_threshold
+            ));
+            continue;
+        }
+        let member_shares: Vec<(u8, Vec<u8>)> = members
+            .values()
+            .take(member_threshold as usize)
+            .map(|s| (s.member_index, s.value.clone()))
+            .collect();
+        let group_secret = recover_secret(member_threshold, &member_shares).map_err(|_| {
+            Slip39Error::InvalidDigest {
+                error_details: format!(
+                    "Shares of group {} do not combine into a valid group secret",
+                    group_index
+                ),
+            }
+        })?;
+        group_secrets.push((*group_index, group_secret));
+    }
+
+    if group_secrets.len() < first.group_threshold as usize {
+        let mut error_details = format!(
+            "{} of {} required groups are complete",
+            group_secrets.len(),
+            first.group_threshold
+        );
+        if !incomplete.is_empty() {
+            error_details.push_str(&format!(" ({})", incomplete.join(", ")));
+        }
+        return Err(Slip39Error::InsufficientShares { error_details });
+    }
+    group_secrets.truncate(first.group_threshold as usize);
+
+    let encrypted_master_secret =
+        recover_secret(first.group_threshold, &group_secrets).map_err(|_| {
+            Slip39Error::InvalidDigest {
+                error_details: "Groups do not combine into a valid master secret".to_string(),
+            }
+        })?;
+
+    Ok(decrypt(
+        &encrypted_master_secret,
+        passphrase.as_bytes(),
+        first.iteration_exponent,
+        first.identifier,
+        first.extendable,
+    ))
+}
+
+/// Decodes and checksums a single share mnemonic without combining it.
+pub fn parse_share(mnemonic: &str) -> Result<Slip39ShareInfo, Slip39Error> {
+    Share::from_mnemonic(mnemonic, 0).map(|share| share.info())
+}
+
+/// Splits the entropy of a BIP39 mnemonic into SLIP-39 shares.
+///
+/// The BIP39 mnemonic can later be restored with [`combine_shares_to_mnemonic`].
+/// Note that the SLIP-39 passphrase encrypts the entropy and is unrelated to the
+/// BIP39 passphrase used for seed derivation.
+pub fn generate_shares_from_mnemonic(
+    mnemonic_phrase: &str,
+    group_threshold: u8,
+    groups: &[Slip39Group],
+    passphrase: &str,
+    iteration_exponent: u8,
+) -> Result<Vec<Slip39GroupShares>, Slip39Error> {
+    let entropy = BitcoinAddressValidator::mnemonic_to_entropy(mnemonic_phrase).map_err(|_| {
+        Slip39Error::InvalidMasterSecret {
+            error_details: "Invalid BIP39 mnemonic".to_string(),
+        }
+    })?;
+    generate_shares(
+        &entropy,
+        group_threshold,
+        groups,
+        passphrase,
+        iteration_exponent,
+    )
+}
+
+/// Recovers a BIP39 mnemonic from SLIP-39 shares created with
+/// [`generate_shares_from_mnemonic`].
+pub fn combine_shares_to_mnemonic(
+    mnemonics: &[String],
+    passphrase: &str,
+) -> Result<String, Slip39Error> {
+    let entropy = combine_shares(mnemonics, passphrase)?;
+    BitcoinAddressValidator::entropy_to_mnemonic(&entropy).map_err(|_| {
+        Slip39Error::InvalidMasterSecret {
+            error_details: format!(
+                "Recovered secret of {} bytes is not valid BIP39 entropy",
+                entropy.len()
+            ),
+        }
+    })
+}
+
+fn validate_master_secret(master_secret: &[u8]) -> Result<(), Slip39Error> {
+    if master_secret.len() * 8 < MIN_STRENGTH_BITS {
+        return Err(Slip39Error::InvalidMasterSecret {
+            error_details: format!("Master secret must be at least {} bits", MIN_STRENGTH_BITS),
+        });
+    }
+    if !master_secret.len().is_multiple_of(2) {
+        return Err(Slip39Error::InvalidMasterSecret {
+            error_details: "Master secret length in bytes must be even".to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), Slip39Error> {
+    if !passphrase.bytes().all(|b| (32..=126).contains(&b)) {
+        return Err(Slip39Error::InvalidPassphrase {
+            error_details: "Passphrase must only contain printable ASCII characters".to_string(),
+        });
+    }
+    Ok(())
+}
+
+// MARK: - Shamir secret sharing over GF(256)
+
+fn split_secret(
+    threshold: u8,
+    share_count: u8,
+    secret: &[u8],
+) -> Result<Vec<(u8, Vec<u8>)>, Slip39Error> {
+    if threshold == 1 {
+        return Ok((0..share_count).map(|i| (i, secret.to_vec())).collect());
+    }
+
+    let mut rng = rand::thread_rng();
+    let random_share_count = threshold - 2;
+    let mut shares: Vec<(u8, Vec<u8>)> = (0..random_share_count)
+        .map(|i| {
+            let mut value = vec![0u8; secret.len()];
+            rng.fill_bytes(&mut value);
+            (i, value)
+        })
+        .collect();
+
+    let mut random_part = vec![0u8; secret.len() - DIGEST_LENGTH_BYTES];
+    rng.fill_bytes(&mut random_part);
+    let mut digest_share = create_digest(&random_part, secret).to_vec();
+    digest_share.extend_from_slice(&random_part);
+
+    let mut base_shares = shares.clone();
+    base_shares.push((DIGEST_INDEX, digest_share));
+    base_shares.push((SECRET_INDEX, secret.to_vec()));
+
+    for i in random_share_count..share_count {
+        shares.push((i, interpolate(&base_shares, i)));
+    }
+
+    Ok(shares)
+}
+
+fn recover_secret(threshold: u8, shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, ()> {
+    if threshold == 1 {
+        return shares.first().map(|(_, v)| v.clone()).ok_or(());
+    }
+
+    let secret = interpolate(shares, SECRET_INDEX);
+    let digest_share = interpolate(shares, DIGEST_INDEX);
+    let (digest, random_part) = digest_share.split_at(DIGEST_LENGTH_BYTES);
+    if digest != create_digest(random_part, &secret) {
+        return Err(());
+    }
+    Ok(secret)
+}
+
+fn interpolate(shares: &[(u8, Vec<u8>)], x: u8) -> Vec<u8> {
+    if let Some((_, value)) = shares.iter().find(|(sx, _)| *sx == x) {
+        return value.clone();
+    }
+
+    let (exp, log) = &*GF256_TABLES;
+    let len = shares[0].1.len();
+    let log_prod: u32 = shares
+        .iter()
+        .map(|(sx, _)| log[(sx ^ x) as usize] as u32)
+        .sum();
+
+    let mut result = vec![0u8; len];
+    for (sx, value) in shares {
+        let others: u32 = shares
+            .iter()
+            .filter(|(ox, _)| ox != sx)
+            .map(|(ox, _)| log[(sx ^ ox) as usize] as u32)
+            .sum();
+        let log_basis = (log_prod + 255 * 16 - log[(sx ^ x) as usize] as u32 - others) % 255;
+        for (r, &v) in result.iter_mut().zip(value) {
+            if v != 0 {
+                *r ^= exp[((log[v as usize] as u32 + log_basis) % 255) as usize];
+            }
+        }
+    }
+    result
+}
+
+fn create_digest(random_part: &[u8], secret: &[u8]) -> [u8; DIGEST_LENGTH_BYTES] {
+    let mut digest = [0u8; DIGEST_LENGTH_BYTES];
+    digest.copy_from_slice(&hmac_sha256(random_part, secret)[..DIGEST_LENGTH_BYTES]);
+    digest
+}
+
+// MARK: - Passphrase encryption
+
+fn encrypt(
+    master_secret: &[u8],
+    passphrase: &[u8],
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+) -> Vec<u8> {
+    feistel(
+        master_secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+        0..ROUND_COUNT,
+    )
+}
+
+fn decrypt(
+    encrypted_master_secret: &[u8],
+    passphrase: &[u8],
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+) -> Vec<u8> {
+    feistel(
+        encrypted_master_secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+        (0..ROUND_COUNT).rev(),
+    )
+}
+
+fn feistel(
+    input: &[u8],
+    passphrase: &[u8],
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+    rounds: impl Iterator<Item = u8>,
+) -> Vec<u8> {
+    let half = input.len() / 2;
+    let mut left = input[..half].to_vec();
+    let mut right = input[half..].to_vec();
+
+    let mut salt_prefix = Vec::new();
+    if !extendable {
+        salt_prefix.extend_from_slice(CUSTOMIZATION_STRING);
+        salt_prefix.extend_from_slice(&identifier.to_be_bytes());
+    }
+    let iterations = (BASE_ITERATION_COUNT << iteration_exponent) / ROUND_COUNT as u32;
+
+    for round in rounds {
+        let mut password = vec![round];
+        password.extend_from_slice(passphrase);
+        let mut salt = salt_prefix.clone();
+        salt.extend_from_slice(&right);
+        let f = pbkdf2_hmac_sha256(&password, &salt, iterations, right.len());
+        let new_right: Vec<u8> = left.iter().zip(&f).map(|(l, f)| l ^ f).collect();
+        left = std::mem::replace(&mut right, new_right);
+    }
+
+    right.extend_from_slice(&left);
+    right
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(key);
+    engine.input(data);
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32, length: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(length);
+    let mut block_index: u32 = 1;
+    while output.len() < length {
+        let mut engine = hmac::HmacEngine::<sha256::Hash>::new(password);
+        engine.input(salt);
+        engine.input(&block_index.to_be_bytes());
+        let mut u = hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+        let mut block = u;
+        for _ in 1..iterations {
+            u = hmac_sha256(password, &u);
+            block.iter_mut().zip(&u).for_each(|(b, u)| *b ^= u);
+        }
+        let take = (length - output.len()).min(block.len());
+        output.extend_from_slice(&block[..take]);
+        block_index += 1;
+    }
+    output
+}
+
+// MARK: - Word encoding and RS1024 checksum
+
+fn int_to_indices(value: u64, length: usize) -> Vec<u16> {
+    (0..length)
+        .rev()
+        .map(|i| ((value >> (i * RADIX_BITS)) & 0x3ff) as u16)
+        .collect()
+}
+
+fn indices_to_int(indices: &[u16]) -> u64 {
+    indices
+        .iter()
+        .fold(0u64, |acc, &i| (acc << RADIX_BITS) | i as u64)
+}
+
+fn bytes_to_indices(bytes: &[u8]) -> Vec<u16> {
+    let word_count = (bytes.len() * 8).div_ceil(RADIX_BITS);
+    let padding = word_count * RADIX_BITS - bytes.len() * 8;
+    let mut indices = Vec::with_capacity(word_count);
+    let mut acc: u32 = 0;
+    let mut bits = padding;
+    for &byte in bytes {
+        acc = (acc << 8) | byte as u32;
+        bits += 8;
+        while bits >= RADIX_BITS {
+            bits -= RADIX_BITS;
+            indices.push(((acc >> bits) & 0x3ff) as u16);
+        }
+    }
+    indices
+}
+
+fn indices_to_bytes(indices: &[u16], padding_len: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity((indices.len() * RADIX_BITS - padding_len) / 8);
+    let mut acc: u32 = 0;
+    let mut bits: usize = 0;
+    let mut padding_left = padding_len;
+    for &index in indices {
+        acc = (acc << RADIX_BITS) | index as u32;
+        bits += RADIX_BITS;
+        if padding_left > 0 {
+            if (acc >> (bits - padding_left)) & ((1 << padding_left) - 1) != 0 {
+                return None;
+            }
+            bits -= padding_left;
+            padding_left = 0;
+        }
+        while bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+        acc &= (1 << bits) - 1;
+    }
+    Some(bytes)
+}
+
+fn rs1024_polymod(values: impl Iterator<Item = u32>) -> u32 {
+    const GEN: [u32; 10] = [
+        0xe0e040, 0x1c1c080, 0x3838100, 0x7070200, 0xe0e0009, 0x1c0c2412, 0x38086c24, 0x3090fc48,
+        0x21b1f890, 0x3f3f120,
+    ];
+    let mut chk: u32 = 1;
+    for v in values {
+        let b = chk >> 20;
+        chk = ((chk & 0xfffff) << 10) ^ v;
+        for (i, g) in GEN.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn customization_string(extendable: bool) -> &'static [u8] {
+    if extendable {
+        CUSTOMIZATION_STRING_EXTENDABLE
+    } else {
+        CUSTOMIZATION_STRING
+    }
+}
+
+fn create_checksum(data: &[u16], extendable: bool) -> Vec<u16> {
+    let values = customization_string(extendable)
+        .iter()
+        .map(|&c| c as u32)
+        .chain(data.iter().map(|&d| d as u32))
+        .chain(std::iter::repeat_n(0, CHECKSUM_LENGTH_WORDS));
+    let polymod = rs1024_polymod(values) ^ 1;
+    int_to_indices(polymod as u64, CHECKSUM_LENGTH_WORDS)
+}
+
+fn verify_checksum(data: &[u16], extendable: bool) -> bool {
+    let values = customization_string(extendable)
+        .iter()
+        .map(|&c| c as u32)
+        .chain(data.iter().map(|&d| d as u32));
+    rs1024_polymod(values) == 1
+}
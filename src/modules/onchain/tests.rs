@@ -1,9 +1,26 @@
 #[cfg(test)]
 mod tests {
-    use crate::modules::onchain::{AddressType, BitcoinAddressValidator};
+    use crate::modules::activity::{
+        OnchainActivity, PaymentType, TransactionDetails, TxInput, TxOutput,
+    };
+    use crate::modules::onchain::{fee_bump, fees, message_signing, slip39, vsize};
+    use crate::modules::onchain::{
+        AddressType, BitcoinAddressValidator, FeeBumpError, FeeBumpMethod, FeeBumpPlan,
+        FeeConfidence, FeeEstimationError, FeeRateSample, MessageSignatureFormat,
+        MessageSigningError, Slip39Error, Slip39Group, WalletUtxo,
+    };
     use crate::modules::scanner::NetworkType;
     use crate::onchain::types::WordCount;
-    use bitcoin::Network;
+    use bitcoin::base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use bitcoin::blockdata::opcodes::all::{OP_CHECKMULTISIG, OP_CHECKSIG};
+    use bitcoin::blockdata::script::Builder;
+    use bitcoin::consensus::serialize;
+    use bitcoin::key::{Keypair, PrivateKey};
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+    use bitcoin::{ecdsa, Amount, Network, ScriptBuf, Witness};
+    use std::collections::HashMap;
+    use std::str::FromStr;
 
     #[test]
     fn test_address_types() {
@@ -293,4 +310,1064 @@ mod tests {
 
         assert_ne!(seed1, seed2);
     }
+
+    fn slip39_groups() -> Vec<Slip39Group> {
+        vec![
+            Slip39Group {
+                member_threshold: 1,
+                member_count: 1,
+            },
+            Slip39Group {
+                member_threshold: 2,
+                member_count: 3,
+            },
+            Slip39Group {
+                member_threshold: 3,
+                member_count: 5,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_slip39_reference_vectors() {
+        // Vectors from the SLIP-39 specification (passphrase "TREZOR")
+        let single = vec!["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard".to_string()];
+        let secret = slip39::combine_shares(&single, "TREZOR").unwrap();
+        assert_eq!(hex::encode(secret), "bb54aac4b89dc868ba37d9cc21b2cece");
+
+        let two_of_three = vec![
+            "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed".to_string(),
+            "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking".to_string(),
+        ];
+        let secret = slip39::combine_shares(&two_of_three, "TREZOR").unwrap();
+        assert_eq!(hex::encode(secret), "b43ceb7e57a0ea8766221624d01b0864");
+
+        let single_256 = vec!["theory painting academic academic armed sweater year military elder discuss acne wildlife boring employer fused large satoshi bundle carbon diagnose anatomy hamster leaves tracks paces beyond phantom capital marvel lips brave detect luck".to_string()];
+        let secret = slip39::combine_shares(&single_256, "TREZOR").unwrap();
+        assert_eq!(
+            hex::encode(secret),
+            "989baf9dcaad5b10ca33dfd8cc75e42477025dce88ae83e75a230086a0e00e92"
+        );
+    }
+
+    #[test]
+    fn test_slip39_generate_and_combine_groups() {
+        let master_secret = hex::decode("0102030405060708090a0b0c0d0e0f10").unwrap();
+        let groups =
+            slip39::generate_shares(&master_secret, 2, &slip39_groups(), "family", 0).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[1].shares.len(), 3);
+        assert_eq!(groups[2].shares.len(), 5);
+
+        let info = slip39::parse_share(&groups[2].shares[4]).unwrap();
+        assert_eq!(info.group_index, 2);
+        assert_eq!(info.group_threshold, 2);
+        assert_eq!(info.group_count, 3);
+        assert_eq!(info.member_index, 4);
+        assert_eq!(info.member_threshold, 3);
+        assert!(info.extendable);
+
+        // Group 1 (2 of 3) and group 2 (3 of 5), in arbitrary order
+        let shares = vec![
+            groups[1].shares[2].clone(),
+            groups[2].shares[0].clone(),
+            groups[1].shares[0].clone(),
+            groups[2].shares[4].clone(),
+            groups[2].shares[3].clone(),
+        ];
+        let recovered = slip39::combine_shares(&shares, "family").unwrap();
+        assert_eq!(recovered, master_secret);
+
+        // A wrong passphrase yields a different secret rather than an error
+        let wrong = slip39::combine_shares(&shares, "").unwrap();
+        assert_ne!(wrong, master_secret);
+
+        // Group 0 alone is not enough
+        let result = slip39::combine_shares(&groups[0].shares, "family");
+        assert!(matches!(
+            result,
+            Err(Slip39Error::InsufficientShares { .. })
+        ));
+
+        // Incomplete member groups are reported
+        let result = slip39::combine_shares(&shares[..4], "family");
+        match result {
+            Err(Slip39Error::InsufficientShares { error_details }) => {
+                assert!(error_details.contains("group 2 has 2 of 3"));
+            }
+            other => panic!("Expected InsufficientShares, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_slip39_mnemonic_round_trip() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let groups = slip39::generate_shares_from_mnemonic(
+            mnemonic,
+            1,
+            &[Slip39Group {
+                member_threshold: 2,
+                member_count: 3,
+            }],
+            "",
+            0,
+        )
+        .unwrap();
+
+        let shares = vec![groups[0].shares[2].clone(), groups[0].shares[1].clone()];
+        let recovered = slip39::combine_shares_to_mnemonic(&shares, "").unwrap();
+        assert_eq!(recovered, mnemonic);
+
+        let entropy = BitcoinAddressValidator::mnemonic_to_entropy(mnemonic).unwrap();
+        assert_eq!(slip39::combine_shares(&shares, "").unwrap(), entropy);
+    }
+
+    #[test]
+    fn test_slip39_invalid_share_is_identified() {
+        let master_secret = [7u8; 16];
+        let groups = slip39::generate_shares(
+            &master_secret,
+            1,
+            &[Slip39Group {
+                member_threshold: 2,
+                member_count: 3,
+            }],
+            "",
+            0,
+        )
+        .unwrap();
+
+        // Corrupt the last word of the second share
+        let mut words: Vec<&str> = groups[0].shares[1].split(' ').collect();
+        let last = words.len() - 1;
+        words[last] = if words[last] == "academic" {
+            "acid"
+        } else {
+            "academic"
+        };
+        let corrupted = words.join(" ");
+
+        let result = slip39::combine_shares(&[groups[0].shares[0].clone(), corrupted], "");
+        match result {
+            Err(Slip39Error::InvalidShare { share_index, .. }) => assert_eq!(share_index, 1),
+            other => panic!("Expected InvalidShare, got {:?}", other),
+        }
+
+        // Unknown word
+        let unknown = groups[0].shares[0].replacen(' ', " bitcoin ", 1);
+        let result = slip39::combine_shares(&[groups[0].shares[1].clone(), unknown], "");
+        match result {
+            Err(Slip39Error::InvalidShare {
+                share_index,
+                error_details,
+            }) => {
+                assert_eq!(share_index, 1);
+                assert!(error_details.contains("bitcoin"));
+            }
+            other => panic!("Expected InvalidShare, got {:?}", other),
+        }
+
+        // Share from a different backup
+        let other = slip39::generate_shares(
+            &master_secret,
+            1,
+            &[Slip39Group {
+                member_threshold: 2,
+                member_count: 3,
+            }],
+            "",
+            0,
+        )
+        .unwrap();
+        let result = slip39::combine_shares(
+            &[groups[0].shares[0].clone(), other[0].shares[1].clone()],
+            "",
+        );
+        assert!(matches!(
+            result,
+            Err(Slip39Error::ShareMismatch { share_index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_slip39_invalid_configuration() {
+        let master_secret = [1u8; 16];
+        let too_short = slip39::generate_shares(&[1u8; 15], 1, &slip39_groups(), "", 0);
+        assert!(matches!(
+            too_short,
+            Err(Slip39Error::InvalidMasterSecret { .. })
+        ));
+
+        let threshold_too_high =
+            slip39::generate_shares(&master_secret, 4, &slip39_groups(), "", 0);
+        assert!(matches!(
+            threshold_too_high,
+            Err(Slip39Error::InvalidConfiguration { .. })
+        ));
+
+        let single_threshold_many_members = slip39::generate_shares(
+            &master_secret,
+            1,
+            &[Slip39Group {
+                member_threshold: 1,
+                member_count: 2,
+            }],
+            "",
+            0,
+        );
+        assert!(matches!(
+            single_threshold_many_members,
+            Err(Slip39Error::InvalidConfiguration { .. })
+        ));
+
+        let bad_passphrase =
+            slip39::generate_shares(&master_secret, 1, &slip39_groups(), "pässword", 0);
+        assert!(matches!(
+            bad_passphrase,
+            Err(Slip39Error::InvalidPassphrase { .. })
+        ));
+    }
+
+    const BIP322_TEST_WIF: &str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+    const BIP322_TEST_ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+
+    #[test]
+    fn test_bip322_message_hash_and_to_spend() {
+        // Vectors from BIP322
+        assert_eq!(
+            hex::encode(message_signing::bip322_message_hash("")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            hex::encode(message_signing::bip322_message_hash("Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+
+        let script_pubkey = bitcoin::Address::from_str(BIP322_TEST_ADDRESS)
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        let to_spend = message_signing::create_to_spend("Hello World", &script_pubkey);
+        assert_eq!(
+            to_spend.compute_txid().to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b"
+        );
+    }
+
+    #[test]
+    fn test_bip322_simple_p2wpkh() {
+        let signature = message_signing::sign_message(
+            "Hello World",
+            BIP322_TEST_ADDRESS,
+            BIP322_TEST_WIF,
+            None,
+        )
+        .unwrap();
+        // Deterministic low-R signature from the BIP322 test vectors
+        assert_eq!(
+            signature,
+            "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI="
+        );
+
+        assert!(
+            message_signing::verify_message("Hello World", BIP322_TEST_ADDRESS, &signature)
+                .unwrap()
+        );
+        assert!(
+            !message_signing::verify_message("Hello World!", BIP322_TEST_ADDRESS, &signature)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_bip322_full_and_taproot() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_wif(BIP322_TEST_WIF).unwrap();
+        let keypair = Keypair::from_secret_key(&secp, &private_key.inner);
+        let taproot_address =
+            bitcoin::Address::p2tr(&secp, keypair.x_only_public_key().0, None, Network::Bitcoin)
+                .to_string();
+
+        for address in [BIP322_TEST_ADDRESS, taproot_address.as_str()] {
+            for format in [
+                MessageSignatureFormat::Bip322Simple,
+                MessageSignatureFormat::Bip322Full,
+            ] {
+                let signature = message_signing::sign_message(
+                    "proof of ownership",
+                    address,
+                    BIP322_TEST_WIF,
+                    Some(format),
+                )
+                .unwrap();
+                assert!(
+                    message_signing::verify_message("proof of ownership", address, &signature)
+                        .unwrap()
+                );
+                assert!(!message_signing::verify_message("tampered", address, &signature).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_bip322_script_hash_verification() {
+        let secp = Secp256k1::new();
+        let keys: Vec<PrivateKey> = (1..=3u8)
+            .map(|i| PrivateKey::new(SecretKey::from_slice(&[i; 32]).unwrap(), Network::Bitcoin))
+            .collect();
+        let public_keys: Vec<_> = keys.iter().map(|key| key.public_key(&secp)).collect();
+        let single_key = Builder::new()
+            .push_key(&public_keys[0])
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let multisig = Builder::new()
+            .push_int(2)
+            .push_key(&public_keys[0])
+            .push_key(&public_keys[1])
+            .push_key(&public_keys[2])
+            .push_int(3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let sign = |sighash: Message, key: &PrivateKey| {
+            ecdsa::Signature::sighash_all(secp.sign_ecdsa(&sighash, &key.inner)).to_vec()
+        };
+        // BIP322 simple signature of `message` for a P2WSH or P2SH-P2WSH `address`
+        let sign_witness = |message: &str, address: &bitcoin::Address, signers: &[usize]| {
+            let script = if signers.len() == 1 {
+                &single_key
+            } else {
+                &multisig
+            };
+            let to_spend = message_signing::create_to_spend(message, &address.script_pubkey());
+            let to_sign = message_signing::create_to_sign(&to_spend);
+            let sighash = SighashCache::new(&to_sign)
+                .p2wsh_signature_hash(0, script, Amount::ZERO, EcdsaSighashType::All)
+                .unwrap();
+            let mut items: Vec<Vec<u8>> = signers
+                .iter()
+                .map(|&i| sign(Message::from(sighash), &keys[i]))
+                .collect();
+            if signers.len() > 1 {
+                items.insert(0, Vec::new());
+            }
+            items.push(script.to_bytes());
+            BASE64.encode(serialize(&Witness::from_slice(&items)))
+        };
+
+        let p2wsh = bitcoin::Address::p2wsh(&single_key, Network::Bitcoin);
+        let signature = sign_witness("Hello World", &p2wsh, &[0]);
+        assert!(
+            message_signing::verify_message("Hello World", &p2wsh.to_string(), &signature).unwrap()
+        );
+        assert!(
+            !message_signing::verify_message("tampered", &p2wsh.to_string(), &signature).unwrap()
+        );
+
+        let p2wsh = bitcoin::Address::p2wsh(&multisig, Network::Bitcoin);
+        let signature = sign_witness("Hello World", &p2wsh, &[0, 2]);
+        assert!(
+            message_signing::verify_message("Hello World", &p2wsh.to_string(), &signature).unwrap()
+        );
+        // Signatures have to be in key order
+        let signature = sign_witness("Hello World", &p2wsh, &[2, 0]);
+        assert!(
+            !message_signing::verify_message("Hello World", &p2wsh.to_string(), &signature)
+                .unwrap()
+        );
+
+        let p2shwsh = bitcoin::Address::p2shwsh(&multisig, Network::Bitcoin);
+        let signature = sign_witness("Hello World", &p2shwsh, &[1, 2]);
+        assert!(
+            message_signing::verify_message("Hello World", &p2shwsh.to_string(), &signature)
+                .unwrap()
+        );
+        assert!(
+            !message_signing::verify_message("Hello World", &p2wsh.to_string(), &signature)
+                .unwrap()
+        );
+
+        // Legacy P2SH multisig in the full format, with the signatures in the script_sig
+        let p2sh = bitcoin::Address::p2sh(&multisig, Network::Bitcoin).unwrap();
+        let to_spend = message_signing::create_to_spend("Hello World", &p2sh.script_pubkey());
+        let mut to_sign = message_signing::create_to_sign(&to_spend);
+        let sighash = SighashCache::new(&to_sign)
+            .legacy_signature_hash(0, &multisig, EcdsaSighashType::All.to_u32())
+            .unwrap();
+        let mut script_sig = Builder::new().push_int(0);
+        for i in [0, 1] {
+            let signature = sign(Message::from(sighash), &keys[i]);
+            script_sig = script_sig
+                .push_slice(<&bitcoin::script::PushBytes>::try_from(signature.as_slice()).unwrap());
+        }
+        let redeem_script = multisig.to_bytes();
+        to_sign.input[0].script_sig = script_sig
+            .push_slice(<&bitcoin::script::PushBytes>::try_from(redeem_script.as_slice()).unwrap())
+            .into_script();
+        let signature = BASE64.encode(serialize(&to_sign));
+        assert!(
+            message_signing::verify_message("Hello World", &p2sh.to_string(), &signature).unwrap()
+        );
+        assert!(
+            !message_signing::verify_message("tampered", &p2sh.to_string(), &signature).unwrap()
+        );
+
+        // Scripts other than single-key and multisig ones need a script interpreter
+        let anyone_can_spend = ScriptBuf::from_bytes(vec![0x51]);
+        let p2wsh = bitcoin::Address::p2wsh(&anyone_can_spend, Network::Bitcoin);
+        let signature = BASE64.encode(serialize(&Witness::from_slice(&[
+            anyone_can_spend.to_bytes()
+        ])));
+        assert!(matches!(
+            message_signing::verify_message("Hello World", &p2wsh.to_string(), &signature),
+            Err(MessageSigningError::UnsupportedAddressType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bip137_legacy_signing() {
+        let secp = Secp256k1::new();
+        let private_key = PrivateKey::from_wif(BIP322_TEST_WIF).unwrap();
+        let p2pkh_address =
+            bitcoin::Address::p2pkh(private_key.public_key(&secp), Network::Bitcoin).to_string();
+
+        // P2PKH defaults to BIP137
+        let signature =
+            message_signing::sign_message("Hello World", &p2pkh_address, BIP322_TEST_WIF, None)
+                .unwrap();
+        assert_eq!(
+            bitcoin::base64::Engine::decode(
+                &bitcoin::base64::engine::general_purpose::STANDARD,
+                &signature
+            )
+            .unwrap()
+            .len(),
+            65
+        );
+        assert!(
+            message_signing::verify_message("Hello World", &p2pkh_address, &signature).unwrap()
+        );
+        assert!(!message_signing::verify_message(
+            "Hello World",
+            "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+            &signature
+        )
+        .unwrap());
+
+        // BIP137 for a native SegWit address
+        let signature = message_signing::sign_message(
+            "Hello World",
+            BIP322_TEST_ADDRESS,
+            BIP322_TEST_WIF,
+            Some(MessageSignatureFormat::Legacy),
+        )
+        .unwrap();
+        assert!(
+            message_signing::verify_message("Hello World", BIP322_TEST_ADDRESS, &signature)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_message_signing_errors() {
+        // Key does not control the address
+        let result = message_signing::sign_message(
+            "Hello World",
+            "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+            BIP322_TEST_WIF,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(MessageSigningError::InvalidPrivateKey { .. })
+        ));
+
+        // BIP322 simple is not defined for P2PKH
+        let result = message_signing::sign_message(
+            "Hello World",
+            "14vV3aCHBeStb5bkenkNHbe2YAFinYdXgc",
+            BIP322_TEST_WIF,
+            Some(MessageSignatureFormat::Bip322Simple),
+        );
+        assert!(matches!(
+            result,
+            Err(MessageSigningError::UnsupportedAddressType { .. })
+        ));
+
+        // Testnet address with a mainnet key
+        let result = message_signing::sign_message(
+            "Hello World",
+            "tb1q9vza2e8x573nczrlzms0wvx3gsqjx7vaxwd45v",
+            BIP322_TEST_WIF,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(MessageSigningError::InvalidAddress { .. })
+        ));
+
+        let result =
+            message_signing::verify_message("Hello World", BIP322_TEST_ADDRESS, "not base64!");
+        assert!(matches!(
+            result,
+            Err(MessageSigningError::InvalidSignature { .. })
+        ));
+    }
+
+    #[test]
+    fn test_vsize_estimation() {
+        // 1-in 2-out P2WPKH
+        let vsize = vsize::estimate_vsize(
+            &[AddressType::P2WPKH],
+            &[AddressType::P2WPKH, AddressType::P2WPKH],
+        )
+        .unwrap();
+        assert_eq!(vsize, 141);
+
+        // Legacy transactions have no witness discount
+        let weight = vsize::estimate_weight(&[AddressType::P2PKH], &[AddressType::P2PKH]).unwrap();
+        assert_eq!(weight, 4 * (10 + 148 + 34));
+
+        // 1-in 1-out key path Taproot spend
+        let vsize = vsize::estimate_vsize(&[AddressType::P2TR], &[AddressType::P2TR]).unwrap();
+        assert_eq!(vsize, 111);
+
+        assert!(matches!(
+            vsize::estimate_vsize(&[AddressType::Unknown], &[AddressType::P2WPKH]),
+            Err(FeeEstimationError::InvalidTransactionShape { .. })
+        ));
+        assert!(matches!(
+            vsize::estimate_vsize(&[], &[AddressType::P2WPKH]),
+            Err(FeeEstimationError::InvalidTransactionShape { .. })
+        ));
+    }
+
+    #[test]
+    fn test_calculate_fee() {
+        let fee = fees::calculate_fee(
+            &[AddressType::P2WPKH],
+            &[AddressType::P2WPKH, AddressType::P2WPKH],
+            2.5,
+        )
+        .unwrap();
+        assert_eq!(fee.vsize, 141);
+        assert_eq!(fee.fee_sats, 353);
+
+        assert!(matches!(
+            fees::calculate_fee(&[AddressType::P2WPKH], &[AddressType::P2WPKH], -1.0),
+            Err(FeeEstimationError::InvalidFeeRate { .. })
+        ));
+    }
+
+    fn fee_sample(source: &str, fast: f64, normal: f64, slow: f64) -> FeeRateSample {
+        FeeRateSample {
+            source: source.to_string(),
+            fast,
+            normal,
+            slow,
+            minimum: None,
+        }
+    }
+
+    struct MockFeeClient {
+        estimates: Option<HashMap<u16, f64>>,
+        blocks: Option<Vec<fees::MempoolBlock>>,
+    }
+
+    #[async_trait::async_trait]
+    impl fees::FeeEstimatesClient for MockFeeClient {
+        async fn fee_estimates(&self) -> Result<HashMap<u16, f64>, FeeEstimationError> {
+            self.estimates
+                .clone()
+                .ok_or(FeeEstimationError::SourceError {
+                    error_details: "offline".to_string(),
+                })
+        }
+
+        async fn mempool_blocks(&self) -> Result<Vec<fees::MempoolBlock>, FeeEstimationError> {
+            self.blocks.clone().ok_or(FeeEstimationError::SourceError {
+                error_details: "offline".to_string(),
+            })
+        }
+    }
+
+    fn mempool_block(median_fee: f64, min_fee: f64) -> fees::MempoolBlock {
+        fees::MempoolBlock {
+            block_vsize: 997_000.0,
+            n_tx: 3000,
+            median_fee,
+            fee_range: vec![min_fee, median_fee, median_fee * 2.0],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fee_estimator_combines_sources() {
+        let estimator = fees::FeeEstimator::default();
+        let client = MockFeeClient {
+            estimates: Some(HashMap::from([
+                (1, 22.0),
+                (2, 18.0),
+                (3, 14.0),
+                (6, 9.0),
+                (144, 2.0),
+            ])),
+            blocks: Some(vec![
+                mempool_block(20.0, 15.0),
+                mempool_block(16.0, 12.0),
+                mempool_block(12.0, 10.0),
+                mempool_block(5.0, 3.0),
+            ]),
+        };
+
+        let estimates = estimator
+            .estimate(
+                vec![fee_sample(fees::BLOCKTANK_SOURCE, 21.0, 15.0, 8.0)],
+                Some(&client as &dyn fees::FeeEstimatesClient),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(estimates.sources.len(), 3);
+        assert_eq!(estimates.fast, 21.0);
+        assert_eq!(estimates.normal, 14.0);
+        assert_eq!(estimates.slow, 8.0);
+        // Median of Esplora's 144 block target and the last projected block's minimum
+        assert_eq!(estimates.minimum, 2.5);
+        assert_eq!(estimates.confidence, FeeConfidence::High);
+        assert!(!estimates.is_fallback);
+
+        let fees = estimates
+            .fees_for_shape(
+                &[AddressType::P2WPKH],
+                &[AddressType::P2WPKH, AddressType::P2WPKH],
+            )
+            .unwrap();
+        assert_eq!(fees.vsize, 141);
+        assert_eq!(fees.fast_sats, 141 * 21);
+        assert_eq!(fees.minimum_sats, 353);
+    }
+
+    #[test]
+    fn test_sample_from_fee_estimates_between_targets() {
+        // Targets between two estimates use the faster one
+        let sample = fees::sample_from_fee_estimates(&HashMap::from([
+            (1, 30.0),
+            (2, 20.0),
+            (4, 10.0),
+            (25, 4.0),
+            (1008, 1.0),
+        ]))
+        .unwrap();
+        assert_eq!(sample.fast, 30.0);
+        assert_eq!(sample.normal, 20.0);
+        assert_eq!(sample.slow, 10.0);
+        assert_eq!(sample.minimum, Some(4.0));
+
+        // Targets faster than every estimate use the fastest one
+        let sample =
+            fees::sample_from_fee_estimates(&HashMap::from([(2, 20.0), (10, 5.0)])).unwrap();
+        assert_eq!(sample.fast, 20.0);
+        assert_eq!(sample.normal, 20.0);
+        assert_eq!(sample.slow, 20.0);
+        assert_eq!(sample.minimum, Some(5.0));
+
+        assert!(fees::sample_from_fee_estimates(&HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_fee_estimator_sanity_bounds() {
+        let estimator = fees::FeeEstimator::new(1.0, 500.0, 60);
+
+        // Out of bounds samples are discarded, sub-minimum rates are raised, buckets are monotonic
+        let estimates = estimator
+            .combine(
+                vec![
+                    fee_sample("broken", 10_000.0, 5.0, 1.0),
+                    fee_sample("nan", f64::NAN, 5.0, 1.0),
+                    fee_sample("inverted", 4.0, 6.0, 0.5),
+                ],
+                1000,
+            )
+            .unwrap();
+        assert_eq!(estimates.sources, vec!["inverted".to_string()]);
+        assert_eq!(estimates.fast, 4.0);
+        assert_eq!(estimates.normal, 4.0);
+        assert_eq!(estimates.slow, 1.0);
+        assert_eq!(estimates.minimum, 1.0);
+        assert_eq!(estimates.confidence, FeeConfidence::Medium);
+
+        // Disagreeing sources lower the confidence
+        let estimates = estimator
+            .combine(
+                vec![
+                    fee_sample("a", 5.0, 4.0, 3.0),
+                    fee_sample("b", 50.0, 40.0, 30.0),
+                ],
+                1000,
+            )
+            .unwrap();
+        assert_eq!(estimates.confidence, FeeConfidence::Medium);
+    }
+
+    #[tokio::test]
+    async fn test_fee_estimator_cached_fallback() {
+        let estimator = fees::FeeEstimator::new(1.0, 500.0, 60);
+        let offline = MockFeeClient {
+            estimates: None,
+            blocks: None,
+        };
+
+        assert!(matches!(
+            estimator
+                .estimate(vec![], Some(&offline as &dyn fees::FeeEstimatesClient))
+                .await,
+            Err(FeeEstimationError::NoEstimatesAvailable { .. })
+        ));
+
+        let fresh = estimator
+            .combine(vec![fee_sample("a", 10.0, 6.0, 3.0)], 1000)
+            .unwrap();
+
+        let fallback = estimator.combine(vec![], 1030).unwrap();
+        assert!(fallback.is_fallback);
+        assert_eq!(fallback.confidence, FeeConfidence::Low);
+        assert_eq!(fallback.fast, fresh.fast);
+        assert_eq!(fallback.timestamp, 1000);
+
+        // Stale cache is not used
+        assert!(matches!(
+            estimator.combine(vec![], 1061),
+            Err(FeeEstimationError::NoEstimatesAvailable { .. })
+        ));
+    }
+
+    const RECIPIENT_ADDRESS: &str = "bc1q0xcqpzrky6eff2g52qdye53xkk9jxkvrh6yhyw";
+    const RECIPIENT_SCRIPT: &str = "001479b000887626b294a914501a4cd226b58b235983";
+    const CHANGE_ADDRESS: &str = "bc1qa0qwuze2h85zw7nqpsj3ga0z9geyrgwpp9ee7r";
+    const CHANGE_SCRIPT: &str = "0014ebc0ee0b2ab9e8277a600c251475e22a3241a1c1";
+    const WALLET_SCRIPT: &str = "0014417d4be90d35363267b8f2afafc9531111c41ae4";
+    const FUNDING_TXID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+    const ORIGINAL_TXID: &str = "4444444444444444444444444444444444444444444444444444444444444444";
+
+    fn pending_activity(tx_type: PaymentType, address: &str, fee: u64) -> OnchainActivity {
+        OnchainActivity {
+            id: "activity_1".to_string(),
+            tx_type,
+            tx_id: ORIGINAL_TXID.to_string(),
+            value: 50_000,
+            fee,
+            fee_rate: 7,
+            address: address.to_string(),
+            confirmed: false,
+            timestamp: 1234567890,
+            is_boosted: false,
+            boost_tx_ids: vec![],
+            is_transfer: false,
+            does_exist: true,
+            confirm_timestamp: None,
+            channel_id: None,
+            transfer_tx_id: None,
+            created_at: None,
+            updated_at: None,
+            seen_at: None,
+        }
+    }
+
+    /// 1-in 2-out P2WPKH transaction spending 100_000 sats with a 1_000 sat fee (141 vB).
+    fn sent_transaction(sequence: u32) -> TransactionDetails {
+        TransactionDetails {
+            tx_id: ORIGINAL_TXID.to_string(),
+            amount_sats: -51_000,
+            inputs: vec![TxInput {
+                txid: FUNDING_TXID.to_string(),
+                vout: 0,
+                scriptsig: String::new(),
+                witness: vec!["30".repeat(72), "02".repeat(33)],
+                sequence,
+            }],
+            outputs: vec![
+                TxOutput {
+                    scriptpubkey: RECIPIENT_SCRIPT.to_string(),
+                    scriptpubkey_type: "v0_p2wpkh".to_string(),
+                    scriptpubkey_address: Some(RECIPIENT_ADDRESS.to_string()),
+                    value: 50_000,
+                    n: 0,
+                },
+                TxOutput {
+                    scriptpubkey: CHANGE_SCRIPT.to_string(),
+                    scriptpubkey_type: "v0_p2wpkh".to_string(),
+                    scriptpubkey_address: Some(CHANGE_ADDRESS.to_string()),
+                    value: 49_000,
+                    n: 1,
+                },
+            ],
+        }
+    }
+
+    fn wallet_utxo(txid: &str, value: u64, script: &str, confirmed: bool) -> WalletUtxo {
+        WalletUtxo {
+            txid: txid.to_string(),
+            vout: 0,
+            value,
+            script_pubkey: script.to_string(),
+            confirmed,
+        }
+    }
+
+    fn decode_psbt(plan: &FeeBumpPlan) -> bitcoin::Psbt {
+        bitcoin::Psbt::from_str(&plan.psbt).unwrap()
+    }
+
+    #[test]
+    fn test_plan_rbf_reduces_change() {
+        let activity = pending_activity(PaymentType::Sent, RECIPIENT_ADDRESS, 1_000);
+        let utxos = vec![wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true)];
+
+        let plan = fee_bump::plan_rbf(
+            &activity,
+            &sent_transaction(0xfffffffd),
+            &utxos,
+            CHANGE_ADDRESS,
+            20.0,
+        )
+        .unwrap();
+
+        assert_eq!(plan.method, FeeBumpMethod::Rbf);
+        assert_eq!(plan.vsize, 141);
+        assert_eq!(plan.fee_sats, 2_820);
+        assert_eq!(plan.change_sats, Some(47_180));
+        assert!(plan.effective_fee_rate >= 20.0);
+
+        let psbt = decode_psbt(&plan);
+        let tx = &psbt.unsigned_tx;
+        assert_eq!(tx.compute_txid().to_string(), plan.tx_id);
+        assert_eq!(tx.input.len(), 1);
+        assert!(tx.is_explicitly_rbf());
+        // The payment is untouched and the change keeps its position
+        assert_eq!(tx.output[0].value.to_sat(), 50_000);
+        assert_eq!(tx.output[0].script_pubkey.to_hex_string(), RECIPIENT_SCRIPT);
+        assert_eq!(tx.output[1].value.to_sat(), 47_180);
+        assert_eq!(
+            psbt.inputs[0].witness_utxo.as_ref().unwrap().value.to_sat(),
+            100_000
+        );
+    }
+
+    #[test]
+    fn test_plan_rbf_pays_for_its_own_bandwidth() {
+        let activity = pending_activity(PaymentType::Sent, RECIPIENT_ADDRESS, 1_000);
+        let utxos = vec![wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true)];
+
+        // 7.5 sat/vB barely beats the original rate, BIP125 rule 4 sets the fee
+        let plan = fee_bump::plan_rbf(
+            &activity,
+            &sent_transaction(0xfffffffd),
+            &utxos,
+            CHANGE_ADDRESS,
+            7.5,
+        )
+        .unwrap();
+        assert_eq!(plan.fee_sats, 1_000 + 141);
+    }
+
+    #[test]
+    fn test_plan_rbf_adds_confirmed_inputs() {
+        let activity = pending_activity(PaymentType::Sent, RECIPIENT_ADDRESS, 1_000);
+        let extra_txid = "2222222222222222222222222222222222222222222222222222222222222222";
+        let unconfirmed_txid = "3333333333333333333333333333333333333333333333333333333333333333";
+        // The change of the original is replaced with it, so it can never fund the bump
+        let utxos = vec![
+            wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true),
+            wallet_utxo(unconfirmed_txid, 500_000, WALLET_SCRIPT, false),
+            wallet_utxo(ORIGINAL_TXID, 500_000, CHANGE_SCRIPT, true),
+            wallet_utxo(extra_txid, 100_000, WALLET_SCRIPT, true),
+        ];
+
+        let plan = fee_bump::plan_rbf(
+            &activity,
+            &sent_transaction(0xfffffffd),
+            &utxos,
+            CHANGE_ADDRESS,
+            500.0,
+        )
+        .unwrap();
+
+        let tx = decode_psbt(&plan).unsigned_tx;
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.input[1].previous_output.txid.to_string(), extra_txid);
+        assert_eq!(tx.output[0].value.to_sat(), 50_000);
+        assert_eq!(plan.fee_sats + plan.change_sats.unwrap() + 50_000, 200_000);
+        assert!(plan.effective_fee_rate >= 500.0);
+
+        let utxos = vec![wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true)];
+        assert!(matches!(
+            fee_bump::plan_rbf(
+                &activity,
+                &sent_transaction(0xfffffffd),
+                &utxos,
+                CHANGE_ADDRESS,
+                500.0
+            ),
+            Err(FeeBumpError::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_plan_rbf_drops_dust_change() {
+        let activity = pending_activity(PaymentType::Sent, RECIPIENT_ADDRESS, 1_000);
+        let utxos = vec![wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true)];
+
+        // At 400 sat/vB a change output would cost more than it returns
+        let plan = fee_bump::plan_rbf(
+            &activity,
+            &sent_transaction(0xfffffffd),
+            &utxos,
+            CHANGE_ADDRESS,
+            400.0,
+        )
+        .unwrap();
+        assert_eq!(plan.change_sats, None);
+        assert_eq!(plan.fee_sats, 50_000);
+        assert_eq!(decode_psbt(&plan).unsigned_tx.output.len(), 1);
+    }
+
+    #[test]
+    fn test_plan_rbf_rejections() {
+        let activity = pending_activity(PaymentType::Sent, RECIPIENT_ADDRESS, 1_000);
+        let utxos = vec![wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true)];
+        let details = sent_transaction(0xfffffffd);
+
+        assert!(matches!(
+            fee_bump::plan_rbf(
+                &activity,
+                &sent_transaction(0xffffffff),
+                &utxos,
+                CHANGE_ADDRESS,
+                20.0
+            ),
+            Err(FeeBumpError::NotReplaceable { .. })
+        ));
+        assert!(matches!(
+            fee_bump::plan_rbf(&activity, &details, &[], CHANGE_ADDRESS, 20.0),
+            Err(FeeBumpError::MissingUtxo { .. })
+        ));
+        assert!(matches!(
+            fee_bump::plan_rbf(&activity, &details, &utxos, CHANGE_ADDRESS, 5.0),
+            Err(FeeBumpError::InvalidFeeRate { .. })
+        ));
+        assert!(matches!(
+            fee_bump::plan_rbf(&activity, &details, &utxos, "not an address", 20.0),
+            Err(FeeBumpError::InvalidAddress { .. })
+        ));
+
+        let mut confirmed = activity.clone();
+        confirmed.confirmed = true;
+        assert!(matches!(
+            fee_bump::plan_rbf(&confirmed, &details, &utxos, CHANGE_ADDRESS, 20.0),
+            Err(FeeBumpError::AlreadyConfirmed { .. })
+        ));
+
+        let received = pending_activity(PaymentType::Received, CHANGE_ADDRESS, 1_000);
+        assert!(matches!(
+            fee_bump::plan_rbf(&received, &details, &utxos, CHANGE_ADDRESS, 20.0),
+            Err(FeeBumpError::NotReplaceable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_plan_rbf_rejects_boosted_activity() {
+        let utxos = vec![wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true)];
+        let details = sent_transaction(0xfffffffd);
+
+        // Replacing the parent would evict its CPFP child without paying for it
+        let mut boosted = pending_activity(PaymentType::Sent, RECIPIENT_ADDRESS, 1_000);
+        boosted.is_boosted = true;
+        boosted.boost_tx_ids =
+            vec!["5555555555555555555555555555555555555555555555555555555555555555".to_string()];
+        assert!(matches!(
+            fee_bump::plan_rbf(&boosted, &details, &utxos, CHANGE_ADDRESS, 20.0),
+            Err(FeeBumpError::NotReplaceable { .. })
+        ));
+
+        // A CPFP child can still be planned for it
+        let plan = fee_bump::plan_cpfp(&boosted, &details, &utxos, CHANGE_ADDRESS, 20.0).unwrap();
+        assert_eq!(plan.method, FeeBumpMethod::Cpfp);
+    }
+
+    #[test]
+    fn test_plan_cpfp_spends_change() {
+        let activity = pending_activity(PaymentType::Sent, RECIPIENT_ADDRESS, 1_000);
+        let details = sent_transaction(0xffffffff);
+        let utxos = vec![wallet_utxo(FUNDING_TXID, 100_000, WALLET_SCRIPT, true)];
+
+        let plan = fee_bump::plan_cpfp(&activity, &details, &utxos, CHANGE_ADDRESS, 20.0).unwrap();
+
+        assert_eq!(plan.method, FeeBumpMethod::Cpfp);
+        // 1-in 1-out P2WPKH child
+        assert_eq!(plan.vsize, 110);
+        // (141 + 110) * 20 minus the 1_000 sats already paid by the parent
+        assert_eq!(plan.fee_sats, 4_020);
+        assert_eq!(plan.change_sats, Some(49_000 - 4_020));
+        assert!((plan.effective_fee_rate - 20.0).abs() < f64::EPSILON);
+
+        let tx = decode_psbt(&plan).unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output.txid.to_string(), ORIGINAL_TXID);
+        assert_eq!(tx.input[0].previous_output.vout, 1);
+        assert_eq!(tx.output[0].script_pubkey.to_hex_string(), CHANGE_SCRIPT);
+    }
+
+    #[test]
+    fn test_plan_cpfp_received() {
+        // Someone else funded the transaction, so the recorded fee is used
+        let activity = pending_activity(PaymentType::Received, RECIPIENT_ADDRESS, 1_000);
+        let details = sent_transaction(0xffffffff);
+
+        let plan = fee_bump::plan_cpfp(&activity, &details, &[], CHANGE_ADDRESS, 20.0).unwrap();
+        let tx = decode_psbt(&plan).unsigned_tx;
+        assert_eq!(tx.input[0].previous_output.txid.to_string(), ORIGINAL_TXID);
+        assert_eq!(tx.input[0].previous_output.vout, 0);
+        assert_eq!(plan.fee_sats, 4_020);
+        assert_eq!(plan.change_sats, Some(50_000 - 4_020));
+
+        let stranger = pending_activity(
+            PaymentType::Received,
+            "bc1qg975h6gdx5mryeac72h6lj2nzygugxhyuukqvs",
+            1_000,
+        );
+        assert!(matches!(
+            fee_bump::plan_cpfp(&stranger, &details, &[], CHANGE_ADDRESS, 20.0),
+            Err(FeeBumpError::NoSpendableOutput { .. })
+        ));
+        assert!(matches!(
+            fee_bump::plan_cpfp(&activity, &details, &[], CHANGE_ADDRESS, 2.0),
+            Err(FeeBumpError::InvalidFeeRate { .. })
+        ));
+    }
+
+    #[test]
+    fn test_vsize_for_scripts() {
+        let p2wpkh = bitcoin::ScriptBuf::from_hex(CHANGE_SCRIPT).unwrap();
+        assert_eq!(vsize::address_type_for_script(&p2wpkh), AddressType::P2WPKH);
+
+        let op_return = bitcoin::ScriptBuf::new_op_return([0u8; 40]);
+        assert_eq!(
+            vsize::address_type_for_script(&op_return),
+            AddressType::Unknown
+        );
+
+        // Matches the shape based estimate for standard outputs
+        assert_eq!(
+            vsize::estimate_vsize_for_scripts(
+                &[AddressType::P2WPKH],
+                &[p2wpkh.clone(), p2wpkh.clone()]
+            )
+            .unwrap(),
+            141
+        );
+        assert_eq!(
+            vsize::estimate_vsize_for_scripts(&[AddressType::P2WPKH], &[op_return, p2wpkh])
+                .unwrap(),
+            161
+        );
+    }
 }
@@ -126,7 +126,7 @@ impl From<Network> for BitcoinNetwork {
     }
 }
 
-#[derive(uniffi::Enum, Debug, PartialEq)]
+#[derive(uniffi::Enum, Debug, Clone, PartialEq)]
 pub enum AddressType {
     P2PKH,  // Legacy
     P2SH,   // SegWit
@@ -155,3 +155,143 @@ pub struct ValidationResult {
     pub network: NetworkType,
     pub address_type: AddressType,
 }
+
+/// Encoding of a signed message.
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageSignatureFormat {
+    /// BIP137 / "Bitcoin Signed Message" compact recoverable signature
+    Legacy,
+    /// BIP322 simple: the consensus encoded witness stack
+    Bip322Simple,
+    /// BIP322 full: the consensus encoded `to_sign` transaction
+    Bip322Full,
+}
+
+/// Member configuration of a single SLIP-39 group.
+#[derive(uniffi::Record, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Slip39Group {
+    /// Number of member shares required to reconstruct the group secret
+    pub member_threshold: u8,
+    /// Total number of member shares to generate for the group
+    pub member_count: u8,
+}
+
+/// Generated mnemonic shares of a single SLIP-39 group.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Slip39GroupShares {
+    pub group_index: u8,
+    pub member_threshold: u8,
+    /// Space separated SLIP-39 mnemonics, one per member
+    pub shares: Vec<String>,
+}
+
+/// Metadata decoded from a single SLIP-39 share mnemonic.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Slip39ShareInfo {
+    /// Random identifier shared by all shares of the same secret
+    pub identifier: u16,
+    pub extendable: bool,
+    pub iteration_exponent: u8,
+    pub group_index: u8,
+    pub group_threshold: u8,
+    pub group_count: u8,
+    pub member_index: u8,
+    pub member_threshold: u8,
+}
+
+/// How much the combined fee estimate can be trusted.
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeeConfidence {
+    /// Multiple sources agree
+    High,
+    /// A single source, or sources that disagree
+    Medium,
+    /// Served from the cached fallback
+    Low,
+}
+
+/// Fee rates in sat/vB reported by a single source.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeRateSample {
+    pub source: String,
+    /// Next block
+    pub fast: f64,
+    /// Within ~3 blocks
+    pub normal: f64,
+    /// Within ~6 blocks
+    pub slow: f64,
+    /// Within ~1 day, if the source provides it
+    pub minimum: Option<f64>,
+}
+
+/// Combined fee rates in sat/vB.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimates {
+    pub fast: f64,
+    pub normal: f64,
+    pub slow: f64,
+    pub minimum: f64,
+    pub confidence: FeeConfidence,
+    /// Names of the sources that contributed to the estimate
+    pub sources: Vec<String>,
+    /// Whether the estimate is a cached result because all sources failed
+    pub is_fallback: bool,
+    /// Unix timestamp (seconds) of when the estimate was computed
+    pub timestamp: u64,
+}
+
+/// Absolute fee of a transaction at a single fee rate.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionFee {
+    pub vsize: u64,
+    pub fee_rate: f64,
+    pub fee_sats: u64,
+}
+
+/// Absolute fees of a transaction for each fee bucket.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionFees {
+    pub vsize: u64,
+    pub fast_sats: u64,
+    pub normal_sats: u64,
+    pub slow_sats: u64,
+    pub minimum_sats: u64,
+}
+
+/// A wallet-owned transaction output.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletUtxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    /// Script pubkey as hex
+    pub script_pubkey: String,
+    pub confirmed: bool,
+}
+
+/// How a stuck transaction is accelerated.
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeeBumpMethod {
+    /// BIP125 replace-by-fee
+    Rbf,
+    /// Child-pays-for-parent
+    Cpfp,
+}
+
+/// An unsigned transaction that bumps the fee of a pending transaction.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeBumpPlan {
+    pub method: FeeBumpMethod,
+    /// Unsigned PSBT, base64 encoded
+    pub psbt: String,
+    /// Txid of the unsigned transaction, final once signed if every input is native SegWit
+    pub tx_id: String,
+    /// Estimated vsize of the signed transaction
+    pub vsize: u64,
+    /// Absolute fee paid by the new transaction
+    pub fee_sats: u64,
+    /// Fee rate of the replacement (RBF) or of the parent and child package (CPFP), in sat/vB
+    pub effective_fee_rate: f64,
+    /// Value sent back to the wallet, if any
+    pub change_sats: Option<u64>,
+}
@@ -0,0 +1,184 @@
+//! Virtual size estimation for transactions of a given shape.
+//!
+//! Sizes assume low-R DER signatures (71 bytes + sighash byte, rounded up to 72),
+//! compressed public keys, Taproot key path spends and 2-of-3 multisig for P2WSH inputs.
+
+use bitcoin::Script;
+
+use super::errors::FeeEstimationError;
+use super::types::AddressType;
+
+const WITNESS_SCALE_FACTOR: u64 = 4;
+
+/// version (4) + locktime (4)
+const TX_BASE_SIZE: u64 = 8;
+/// SegWit marker and flag bytes, counted as witness data
+const SEGWIT_MARKER_WEIGHT: u64 = 2;
+
+/// outpoint (36) + sequence (4) + script_sig length (1)
+const INPUT_BASE_SIZE: u64 = 41;
+/// value (8)
+const OUTPUT_VALUE_SIZE: u64 = 8;
+/// value (8) + script_pubkey length (1)
+const OUTPUT_BASE_SIZE: u64 = OUTPUT_VALUE_SIZE + 1;
+
+const ECDSA_SIGNATURE_PUSH_SIZE: u64 = 1 + 72;
+const SCHNORR_SIGNATURE_PUSH_SIZE: u64 = 1 + 64;
+const COMPRESSED_PUBKEY_PUSH_SIZE: u64 = 1 + 33;
+/// OP_2 <pk> <pk> <pk> OP_3 OP_CHECKMULTISIG
+const MULTISIG_2_OF_3_SCRIPT_SIZE: u64 = 1 + 3 * 34 + 1 + 1;
+
+fn compact_size_len(n: u64) -> u64 {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x10000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+/// Returns the (non-witness size, witness size) of an input spending `address_type`.
+fn input_size(address_type: &AddressType) -> Result<(u64, u64), FeeEstimationError> {
+    let sizes = match address_type {
+        AddressType::P2PKH => (
+            INPUT_BASE_SIZE + ECDSA_SIGNATURE_PUSH_SIZE + COMPRESSED_PUBKEY_PUSH_SIZE,
+            0,
+        ),
+        // Assumes P2SH-P2WPKH: script_sig pushes the 22 byte witness program
+        AddressType::P2SH => (
+            INPUT_BASE_SIZE + 23,
+            1 + ECDSA_SIGNATURE_PUSH_SIZE + COMPRESSED_PUBKEY_PUSH_SIZE,
+        ),
+        AddressType::P2WPKH => (
+            INPUT_BASE_SIZE,
+            1 + ECDSA_SIGNATURE_PUSH_SIZE + COMPRESSED_PUBKEY_PUSH_SIZE,
+        ),
+        // Assumes 2-of-3 multisig: item count, empty dummy, two signatures, witness script
+        AddressType::P2WSH => (
+            INPUT_BASE_SIZE,
+            1 + 1 + 2 * ECDSA_SIGNATURE_PUSH_SIZE + 1 + MULTISIG_2_OF_3_SCRIPT_SIZE,
+        ),
+        AddressType::P2TR => (INPUT_BASE_SIZE, 1 + SCHNORR_SIGNATURE_PUSH_SIZE),
+        AddressType::Unknown => {
+            return Err(FeeEstimationError::InvalidTransactionShape {
+                error_details: "Cannot estimate the size of an input of unknown type".to_string(),
+            })
+        }
+    };
+    Ok(sizes)
+}
+
+fn output_size(address_type: &AddressType) -> Result<u64, FeeEstimationError> {
+    let script_pubkey_size = match address_type {
+        AddressType::P2PKH => 25,
+        AddressType::P2SH => 23,
+        AddressType::P2WPKH => 22,
+        AddressType::P2WSH | AddressType::P2TR => 34,
+        AddressType::Unknown => {
+            return Err(FeeEstimationError::InvalidTransactionShape {
+                error_details: "Cannot estimate the size of an output of unknown type".to_string(),
+            })
+        }
+    };
+    Ok(OUTPUT_BASE_SIZE + script_pubkey_size)
+}
+
+fn script_output_size(script: &Script) -> u64 {
+    let len = script.len() as u64;
+    OUTPUT_VALUE_SIZE + compact_size_len(len) + len
+}
+
+/// Returns the address type of a script pubkey, `Unknown` for non-standard scripts.
+pub fn address_type_for_script(script: &Script) -> AddressType {
+    if script.is_p2pkh() {
+        AddressType::P2PKH
+    } else if script.is_p2sh() {
+        AddressType::P2SH
+    } else if script.is_p2wpkh() {
+        AddressType::P2WPKH
+    } else if script.is_p2wsh() {
+        AddressType::P2WSH
+    } else if script.is_p2tr() {
+        AddressType::P2TR
+    } else {
+        AddressType::Unknown
+    }
+}
+
+fn weight_for_output_sizes(
+    inputs: &[AddressType],
+    output_sizes: &[u64],
+) -> Result<u64, FeeEstimationError> {
+    if inputs.is_empty() || output_sizes.is_empty() {
+        return Err(FeeEstimationError::InvalidTransactionShape {
+            error_details: "A transaction needs at least one input and one output".to_string(),
+        });
+    }
+
+    let mut base_size = TX_BASE_SIZE
+        + compact_size_len(inputs.len() as u64)
+        + compact_size_len(output_sizes.len() as u64)
+        + output_sizes.iter().sum::<u64>();
+    let mut witness_size = 0;
+    let mut has_witness = false;
+
+    for input in inputs {
+        let (base, witness) = input_size(input)?;
+        base_size += base;
+        if witness > 0 {
+            has_witness = true;
+            witness_size += witness;
+        } else {
+            // Empty witness item count, only serialized in SegWit transactions
+            witness_size += 1;
+        }
+    }
+
+    let mut weight = base_size * WITNESS_SCALE_FACTOR;
+    if has_witness {
+        weight += SEGWIT_MARKER_WEIGHT + witness_size;
+    }
+    Ok(weight)
+}
+
+/// Estimates the weight units of a transaction spending `inputs` into `outputs`.
+pub fn estimate_weight(
+    inputs: &[AddressType],
+    outputs: &[AddressType],
+) -> Result<u64, FeeEstimationError> {
+    let output_sizes = outputs
+        .iter()
+        .map(output_size)
+        .collect::<Result<Vec<_>, _>>()?;
+    weight_for_output_sizes(inputs, &output_sizes)
+}
+
+/// Estimates the weight units of a transaction spending `inputs` into outputs with the
+/// exact `output_scripts`, which may include non-standard scripts such as `OP_RETURN`.
+pub fn estimate_weight_for_scripts<S: AsRef<Script>>(
+    inputs: &[AddressType],
+    output_scripts: &[S],
+) -> Result<u64, FeeEstimationError> {
+    let output_sizes: Vec<u64> = output_scripts
+        .iter()
+        .map(|script| script_output_size(script.as_ref()))
+        .collect();
+    weight_for_output_sizes(inputs, &output_sizes)
+}
+
+/// Estimates the virtual size in vbytes of a transaction spending `inputs` into `outputs`.
+pub fn estimate_vsize(
+    inputs: &[AddressType],
+    outputs: &[AddressType],
+) -> Result<u64, FeeEstimationError> {
+    Ok(estimate_weight(inputs, outputs)?.div_ceil(WITNESS_SCALE_FACTOR))
+}
+
+/// Estimates the virtual size in vbytes of a transaction spending `inputs` into outputs
+/// with the exact `output_scripts`.
+pub fn estimate_vsize_for_scripts<S: AsRef<Script>>(
+    inputs: &[AddressType],
+    output_scripts: &[S],
+) -> Result<u64, FeeEstimationError> {
+    Ok(estimate_weight_for_scripts(inputs, output_scripts)?.div_ceil(WITNESS_SCALE_FACTOR))
+}
@@ -0,0 +1,385 @@
+//! Watch-only accounts imported from Trezor.
+//!
+//! An account is created from the `getPublicKey` response for its path, and derives its
+//! receive and change addresses locally from the xpub. A derived address can be checked on
+//! the device by requesting it with `getAddress`, which fails if the device derives another
+//! address, and by comparing the returned address with `address_matches`.
+
+use crate::modules::trezor::{
+    coin_network, open_trezor_db, parse_fingerprint, AddressResponse, GetAddressParams,
+    PublicKeyResponse, ScriptType, TrezorConnectError, TrezorConnectResult, TrezorDbConnection,
+};
+use bitcoin::bip32::{ChildNumber, Xpub};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, CompressedPublicKey, Network, NetworkKind};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const HARDENED: u32 = 0x8000_0000;
+
+const CREATE_TREZOR_ACCOUNTS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS trezor_accounts (
+        xpub TEXT PRIMARY KEY,
+        coin TEXT NOT NULL,
+        path TEXT NOT NULL,
+        fingerprint INTEGER NOT NULL,
+        master_fingerprint TEXT,
+        script_type TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    )";
+
+fn account_error(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::AccountError { error_details }
+}
+
+/// Serialize a BIP32 path the way Trezor does, like `m/84'/0'/0'/0/1`
+pub fn serialize_path(path: &[u32]) -> String {
+    let mut serialized = "m".to_string();
+    for index in path {
+        if index & HARDENED != 0 {
+            serialized.push_str(&format!("/{}'", index & !HARDENED));
+        } else {
+            serialized.push_str(&format!("/{}", index));
+        }
+    }
+    serialized
+}
+
+/// Script type of the accounts of a BIP44, BIP49, BIP84 or BIP86 path
+pub(crate) fn purpose_script_type(path: &[u32]) -> Option<ScriptType> {
+    match path.first()? {
+        purpose if *purpose == 44 | HARDENED => Some(ScriptType::SpendAddress),
+        purpose if *purpose == 49 | HARDENED => Some(ScriptType::SpendP2SHWitness),
+        purpose if *purpose == 84 | HARDENED => Some(ScriptType::SpendWitness),
+        purpose if *purpose == 86 | HARDENED => Some(ScriptType::SpendTaproot),
+        _ => None,
+    }
+}
+
+/// Master fingerprint from the key origin of an output descriptor, like
+/// `wpkh([73c5da0a/84'/0'/0']xpub...)`
+pub(crate) fn descriptor_master_fingerprint(descriptor: &str) -> Option<String> {
+    let origin = descriptor.split_once('[')?.1;
+    let fingerprint = origin.split(['/', ']']).next()?;
+    parse_fingerprint(fingerprint)
+        .ok()
+        .map(|fingerprint| fingerprint.to_string())
+}
+
+/// Watch-only account of a Trezor, imported from its `getPublicKey` response
+#[derive(Serialize, Deserialize, Debug, Clone, uniffi::Record)]
+pub struct TrezorWatchOnlyAccount {
+    /// Coin of the account, like `btc` or `test`
+    pub coin: String,
+    /// BIP32 path of the account
+    pub path: Vec<u32>,
+    /// Serialized BIP32 path of the account, like `m/84'/0'/0'`
+    pub serialized_path: String,
+    /// Extended public key of the account
+    pub xpub: String,
+    /// Fingerprint of the parent key, as returned by Trezor
+    pub fingerprint: u32,
+    /// Fingerprint of the master key, when Trezor returned the account descriptor
+    pub master_fingerprint: Option<String>,
+    /// Script type of the addresses: `SpendAddress`, `SpendP2SHWitness`, `SpendWitness` or
+    /// `SpendTaproot`
+    pub script_type: ScriptType,
+}
+
+/// Address derived from a watch-only account
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TrezorAccountAddress {
+    pub address: String,
+    /// BIP32 path of the address
+    pub path: Vec<u32>,
+    /// Serialized BIP32 path of the address, like `m/84'/0'/0'/0/1`
+    pub serialized_path: String,
+    pub change: bool,
+    pub index: u32,
+}
+
+impl TrezorWatchOnlyAccount {
+    /// Create an account from a `getPublicKey` response for `coin`. The script type is
+    /// inferred from the purpose of the path when `script_type` is not set.
+    pub fn from_public_key(
+        response: &PublicKeyResponse,
+        coin: &str,
+        script_type: Option<ScriptType>,
+    ) -> TrezorConnectResult<Self> {
+        let network = coin_network(coin)?;
+        let xpub = Xpub::from_str(&response.xpub)
+            .map_err(|e| account_error(format!("Invalid xpub {}: {}", response.xpub, e)))?;
+        if xpub.network != NetworkKind::from(network) {
+            return Err(account_error(format!(
+                "xpub {} is not for coin {}",
+                response.xpub, coin
+            )));
+        }
+        if usize::from(xpub.depth) != response.path.len()
+            || xpub.public_key.to_string() != response.publicKey
+        {
+            return Err(account_error(format!(
+                "xpub {} is not the key of {}",
+                response.xpub, response.serializedPath
+            )));
+        }
+
+        let script_type = match script_type.or_else(|| purpose_script_type(&response.path)) {
+            Some(
+                script_type @ (ScriptType::SpendAddress
+                | ScriptType::SpendP2SHWitness
+                | ScriptType::SpendWitness
+                | ScriptType::SpendTaproot),
+            ) => script_type,
+            Some(script_type) => {
+                return Err(account_error(format!(
+                    "Unsupported account script type {:?}",
+                    script_type
+                )))
+            }
+            None => {
+                return Err(account_error(format!(
+                    "No script type for account {}",
+                    response.serializedPath
+                )))
+            }
+        };
+
+        Ok(Self {
+            coin: coin.to_string(),
+            path: response.path.clone(),
+            serialized_path: serialize_path(&response.path),
+            xpub: response.xpub.clone(),
+            fingerprint: response.fingerprint,
+            master_fingerprint: response
+                .descriptor
+                .as_deref()
+                .and_then(descriptor_master_fingerprint),
+            script_type,
+        })
+    }
+
+    fn network(&self) -> TrezorConnectResult<Network> {
+        coin_network(&self.coin)
+    }
+
+    /// Derive the receive or change address at `index`
+    pub fn derive_address(
+        &self,
+        change: bool,
+        index: u32,
+    ) -> TrezorConnectResult<TrezorAccountAddress> {
+        let secp = Secp256k1::verification_only();
+        let network = self.network()?;
+        let xpub = Xpub::from_str(&self.xpub)
+            .map_err(|e| account_error(format!("Invalid xpub {}: {}", self.xpub, e)))?;
+        let children = [
+            ChildNumber::from_normal_idx(u32::from(change))
+                .map_err(|e| account_error(e.to_string()))?,
+            ChildNumber::from_normal_idx(index)
+                .map_err(|e| account_error(format!("Invalid address index {}: {}", index, e)))?,
+        ];
+        let key = xpub
+            .derive_pub(&secp, &children)
+            .map_err(|e| account_error(e.to_string()))?;
+        let public_key = CompressedPublicKey(key.public_key);
+
+        let address = match self.script_type {
+            ScriptType::SpendAddress => Address::p2pkh(public_key, network),
+            ScriptType::SpendP2SHWitness => Address::p2shwpkh(&public_key, network),
+            ScriptType::SpendWitness => Address::p2wpkh(&public_key, network),
+            ScriptType::SpendTaproot => {
+                Address::p2tr(&secp, key.public_key.x_only_public_key().0, None, network)
+            }
+            _ => {
+                return Err(account_error(format!(
+                    "Unsupported account script type {:?}",
+                    self.script_type
+                )))
+            }
+        };
+
+        let mut path = self.path.clone();
+        path.extend([u32::from(change), index]);
+        Ok(TrezorAccountAddress {
+            address: address.to_string(),
+            serialized_path: serialize_path(&path),
+            path,
+            change,
+            index,
+        })
+    }
+
+    /// Derive `count` receive or change addresses from `start_index`
+    pub fn derive_addresses(
+        &self,
+        change: bool,
+        start_index: u32,
+        count: u32,
+    ) -> TrezorConnectResult<Vec<TrezorAccountAddress>> {
+        (0..count)
+            .map(|offset| {
+                let index = start_index.checked_add(offset).ok_or_else(|| {
+                    account_error(format!(
+                        "Address index {} + {} overflows",
+                        start_index, offset
+                    ))
+                })?;
+                self.derive_address(change, index)
+            })
+            .collect()
+    }
+
+    /// `getAddress` parameters showing the address at `index` on the device. Trezor fails
+    /// the request if it derives another address than the local one.
+    pub fn verify_address_params(
+        &self,
+        change: bool,
+        index: u32,
+    ) -> TrezorConnectResult<GetAddressParams> {
+        let address = self.derive_address(change, index)?;
+        let script_type = match serde_json::to_value(&self.script_type)? {
+            serde_json::Value::String(script_type) => script_type,
+            other => other.to_string(),
+        };
+        Ok(GetAddressParams {
+            path: address.serialized_path,
+            address: Some(address.address),
+            showOnTrezor: Some(true),
+            chunkify: None,
+            useEventListener: None,
+            coin: Some(self.coin.clone()),
+            crossChain: None,
+            multisig: None,
+            scriptType: Some(script_type),
+            unlockPath: None,
+            common: None,
+        })
+    }
+
+    /// Whether a `getAddress` response is the address this account derives at its path
+    pub fn address_matches(&self, response: &AddressResponse) -> TrezorConnectResult<bool> {
+        let (change, index) = match response.path.strip_prefix(self.path.as_slice()) {
+            Some([0, index]) => (false, *index),
+            Some([1, index]) => (true, *index),
+            _ => {
+                return Err(account_error(format!(
+                    "Address {} is not an address of account {}",
+                    response.serializedPath, self.serialized_path
+                )))
+            }
+        };
+        Ok(self.derive_address(change, index)?.address == response.address)
+    }
+}
+
+/// Persistent store of the watch-only accounts imported from Trezor
+#[derive(Debug)]
+pub struct TrezorAccountStore {
+    conn: TrezorDbConnection,
+}
+
+impl TrezorAccountStore {
+    /// Open the store at `db_path`, creating it if needed. Use `:memory:` for a store that is
+    /// not persisted.
+    pub fn new(db_path: &str) -> TrezorConnectResult<Self> {
+        Self::with_connection(open_trezor_db(db_path)?)
+    }
+
+    /// Open the store on a connection shared with the other Trezor stores
+    pub fn with_connection(conn: TrezorDbConnection) -> TrezorConnectResult<Self> {
+        conn.lock()
+            .unwrap()
+            .execute(CREATE_TREZOR_ACCOUNTS_TABLE, [])?;
+        Ok(Self { conn })
+    }
+
+    /// Delete all stored accounts
+    pub fn wipe_all(&self) -> TrezorConnectResult<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM trezor_accounts", [])?;
+        Ok(())
+    }
+
+    /// Store an account, replacing the account with the same xpub
+    pub fn save(&self, account: &TrezorWatchOnlyAccount) -> TrezorConnectResult<()> {
+        let created_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO trezor_accounts
+             (xpub, coin, path, fingerprint, master_fingerprint, script_type, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                account.xpub,
+                account.coin,
+                serde_json::to_string(&account.path)?,
+                account.fingerprint,
+                account.master_fingerprint,
+                serde_json::to_string(&account.script_type)?,
+                created_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Stored accounts, in import order
+    pub fn accounts(&self) -> TrezorConnectResult<Vec<TrezorWatchOnlyAccount>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT xpub, coin, path, fingerprint, master_fingerprint, script_type
+             FROM trezor_accounts ORDER BY created_at, rowid",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, u32>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(
+                |(xpub, coin, path, fingerprint, master_fingerprint, script_type)| -> TrezorConnectResult<_> {
+                    let path: Vec<u32> = serde_json::from_str(&path)?;
+                    Ok(TrezorWatchOnlyAccount {
+                        coin,
+                        serialized_path: serialize_path(&path),
+                        path,
+                        xpub,
+                        fingerprint,
+                        master_fingerprint,
+                        script_type: serde_json::from_str(&script_type)?,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Stored account with the given xpub
+    pub fn account(&self, xpub: &str) -> TrezorConnectResult<TrezorWatchOnlyAccount> {
+        self.accounts()?
+            .into_iter()
+            .find(|account| account.xpub == xpub)
+            .ok_or_else(|| account_error(format!("No Trezor account with xpub {}", xpub)))
+    }
+
+    /// Remove an account. Returns whether it was stored.
+    pub fn remove(&self, xpub: &str) -> TrezorConnectResult<bool> {
+        let removed = self
+            .conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM trezor_accounts WHERE xpub = ?1", params![xpub])?;
+        Ok(removed > 0)
+    }
+}
@@ -0,0 +1,219 @@
+//! Onchain activities of the transactions signed by a Trezor.
+//!
+//! A `signTransaction` response only carries the serialized transaction. Its spent outputs
+//! come from the previous transactions, and the inputs and outputs of the account are found
+//! by deriving its receive and change addresses.
+
+use crate::modules::activity::{
+    OnchainActivity, PaymentType, TransactionDetails, TxInput, TxOutput,
+};
+use crate::modules::trezor::{
+    coin_network, parse_transaction, SignedTransactionResponse, TrezorConnectError,
+    TrezorConnectResult, TrezorWatchOnlyAccount,
+};
+use bitcoin::{Address, Network, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Receive and change addresses of the account derived to find its inputs and outputs
+pub const DEFAULT_ACTIVITY_ADDRESS_COUNT: u32 = 1000;
+
+fn activity_error(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::ActivityError { error_details }
+}
+
+/// Activity and transaction details of a transaction signed by a Trezor
+#[derive(Debug, Clone, Serialize, Deserialize, uniffi::Record)]
+pub struct TrezorSignedActivity {
+    /// Pending send activity, with the transaction id as id
+    pub activity: OnchainActivity,
+    /// Inputs and outputs of the transaction, with the net amount for the account
+    pub details: TransactionDetails,
+}
+
+/// Type of an output script, with the names used by Esplora
+fn script_pubkey_type(script: &Script) -> &'static str {
+    if script.is_p2pk() {
+        "p2pk"
+    } else if script.is_p2pkh() {
+        "p2pkh"
+    } else if script.is_p2sh() {
+        "p2sh"
+    } else if script.is_p2wpkh() {
+        "v0_p2wpkh"
+    } else if script.is_p2wsh() {
+        "v0_p2wsh"
+    } else if script.is_p2tr() {
+        "v1_p2tr"
+    } else if script.is_op_return() {
+        "op_return"
+    } else {
+        "unknown"
+    }
+}
+
+fn script_address(script: &Script, network: Network) -> Option<String> {
+    Address::from_script(script, network)
+        .ok()
+        .map(|address| address.to_string())
+}
+
+/// Output scripts of the first `address_count` receive and change addresses of `account`
+fn account_scripts(
+    account: &TrezorWatchOnlyAccount,
+    network: Network,
+    address_count: u32,
+) -> TrezorConnectResult<HashSet<ScriptBuf>> {
+    let mut scripts = HashSet::new();
+    for change in [false, true] {
+        for address in account.derive_addresses(change, 0, address_count)? {
+            let address = address
+                .address
+                .parse::<Address<_>>()
+                .and_then(|address| address.require_network(network))
+                .map_err(|e| activity_error(format!("Invalid account address: {}", e)))?;
+            scripts.insert(address.script_pubkey());
+        }
+    }
+    Ok(scripts)
+}
+
+/// Build the activity of a transaction signed by the Trezor of `account`, from the response
+/// to `signTransaction` and the transactions it spends, `prev_txs`. The inputs and outputs
+/// of the account are found among its first `address_count` receive and change addresses.
+///
+/// The activity is an unconfirmed send of the outputs paying other wallets, at
+/// `timestamp`. Fails if the transaction spends no output of the account.
+pub fn signed_transaction_activity(
+    response: &SignedTransactionResponse,
+    account: &TrezorWatchOnlyAccount,
+    prev_txs: &[Transaction],
+    address_count: u32,
+    timestamp: u64,
+) -> TrezorConnectResult<TrezorSignedActivity> {
+    let tx = parse_transaction(&response.serializedTx)?;
+    let txid = tx.compute_txid();
+    if let Some(response_txid) = &response.txid {
+        if *response_txid != txid.to_string() {
+            return Err(TrezorConnectError::TransactionMismatch {
+                error_details: format!(
+                    "Signed transaction {} is not the broadcast transaction {}",
+                    txid, response_txid
+                ),
+            });
+        }
+    }
+
+    let network = coin_network(&account.coin)?;
+    let owned = account_scripts(account, network, address_count)?;
+    let known_txs: HashMap<Txid, &Transaction> = prev_txs
+        .iter()
+        .map(|prev_tx| (prev_tx.compute_txid(), prev_tx))
+        .collect();
+    let spent_output = |outpoint: &OutPoint| -> TrezorConnectResult<&TxOut> {
+        known_txs
+            .get(&outpoint.txid)
+            .and_then(|prev_tx| prev_tx.output.get(outpoint.vout as usize))
+            .ok_or_else(|| {
+                activity_error(format!(
+                    "Previous transaction of the input spending {} is missing",
+                    outpoint
+                ))
+            })
+    };
+
+    let mut inputs_total = 0u64;
+    let mut owned_inputs_total = 0u64;
+    for input in &tx.input {
+        let spent = spent_output(&input.previous_output)?;
+        inputs_total = inputs_total.saturating_add(spent.value.to_sat());
+        if owned.contains(&spent.script_pubkey) {
+            owned_inputs_total = owned_inputs_total.saturating_add(spent.value.to_sat());
+        }
+    }
+    if owned_inputs_total == 0 {
+        return Err(activity_error(format!(
+            "Transaction {} spends no output of account {}",
+            txid, account.serialized_path
+        )));
+    }
+
+    let mut outputs_total = 0u64;
+    let mut owned_outputs_total = 0u64;
+    let mut value = 0u64;
+    let mut recipient = None;
+    for output in &tx.output {
+        let amount = output.value.to_sat();
+        outputs_total = outputs_total.saturating_add(amount);
+        if owned.contains(&output.script_pubkey) {
+            owned_outputs_total = owned_outputs_total.saturating_add(amount);
+        } else {
+            value = value.saturating_add(amount);
+            recipient = recipient.or_else(|| script_address(&output.script_pubkey, network));
+        }
+    }
+    let fee = inputs_total.checked_sub(outputs_total).ok_or_else(|| {
+        activity_error(format!(
+            "Transaction {} pays {} sats from {} sats of inputs",
+            txid, outputs_total, inputs_total
+        ))
+    })?;
+    let vsize = tx.vsize() as u64;
+    let address = recipient
+        .or_else(|| {
+            tx.output
+                .iter()
+                .find_map(|output| script_address(&output.script_pubkey, network))
+        })
+        .unwrap_or_default();
+
+    let activity = OnchainActivity {
+        id: txid.to_string(),
+        tx_type: PaymentType::Sent,
+        tx_id: txid.to_string(),
+        value,
+        fee,
+        fee_rate: (fee + vsize / 2) / vsize,
+        address,
+        confirmed: false,
+        timestamp,
+        is_boosted: false,
+        boost_tx_ids: Vec::new(),
+        is_transfer: false,
+        does_exist: true,
+        confirm_timestamp: None,
+        channel_id: None,
+        transfer_tx_id: None,
+        created_at: None,
+        updated_at: None,
+        seen_at: None,
+    };
+    let details = TransactionDetails {
+        tx_id: txid.to_string(),
+        amount_sats: owned_outputs_total as i64 - owned_inputs_total as i64,
+        inputs: tx
+            .input
+            .iter()
+            .map(|input| TxInput {
+                txid: input.previous_output.txid.to_string(),
+                vout: input.previous_output.vout,
+                scriptsig: hex::encode(input.script_sig.as_bytes()),
+                witness: input.witness.iter().map(hex::encode).collect(),
+                sequence: input.sequence.to_consensus_u32(),
+            })
+            .collect(),
+        outputs: tx
+            .output
+            .iter()
+            .enumerate()
+            .map(|(n, output)| TxOutput {
+                scriptpubkey: hex::encode(output.script_pubkey.as_bytes()),
+                scriptpubkey_type: script_pubkey_type(&output.script_pubkey).to_string(),
+                scriptpubkey_address: script_address(&output.script_pubkey, network),
+                value: output.value.to_sat(),
+                n: n as u32,
+            })
+            .collect(),
+    };
+    Ok(TrezorSignedActivity { activity, details })
+}
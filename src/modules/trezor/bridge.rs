@@ -0,0 +1,1156 @@
+//! Trezor Bridge transport.
+//!
+//! Trezor Bridge exposes the USB devices of a desktop to local apps over HTTP: `/enumerate`
+//! lists the devices, `/acquire` opens a session on one, `/call` exchanges a wire message with
+//! it and `/release` closes the session. `TrezorBridgeClient` takes the parameters of the deep
+//! link methods and returns the same `TrezorResponsePayload`, so desktop tooling can use a
+//! device without Suite Lite. Methods needing a blockchain backend, `getAccountInfo` and
+//! `composeTransaction`, are not available.
+
+use crate::modules::trezor::protobuf::request_type::{TX_FINISHED, TX_INPUT, TX_META, TX_OUTPUT};
+use crate::modules::trezor::protobuf::{
+    decode_frame, encode_frame, message_type, ProtoMessage, ProtoWriter,
+};
+use crate::modules::trezor::{
+    coin_network, purpose_script_type, serialize_path, AddressResponse, AmountUnit,
+    FeatureResponse, GetAddressParams, GetOwnershipIdParams, GetOwnershipProofParams,
+    GetPublicKeyParams, HDNodeType, HDNodeTypeOrString, MessageSignatureResponse,
+    MultisigRedeemScriptType, OwnershipIdResponse, OwnershipProofResponse, PublicKeyResponse,
+    RefTransaction, ScriptType, SignMessageParams, SignTransactionParams,
+    SignedTransactionResponse, TrezorConnectError, TrezorConnectResult, TrezorFailureKind,
+    TrezorResponsePayload, TxInputType, TxOutputType, UnlockPath, VerifyMessageParams,
+    VerifyMessageResponse,
+};
+use async_trait::async_trait;
+use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+use bitcoin::base64::Engine;
+use bitcoin::bip32::{DerivationPath, Xpub};
+use bitcoin::{Network, NetworkKind};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Address Trezor Bridge listens on
+pub const DEFAULT_TREZOR_BRIDGE_URL: &str = "http://127.0.0.1:21325";
+
+/// Origin sent to Trezor Bridge, which only answers the origins it trusts
+pub const DEFAULT_TREZOR_BRIDGE_ORIGIN: &str = "http://localhost:8000";
+
+fn bridge_error(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::BridgeError { error_details }
+}
+
+/// Code of a `FailureType` of the device, as Trezor Connect reports it
+fn failure_code(failure_type: u64) -> Option<&'static str> {
+    Some(match failure_type {
+        1 => "Failure_UnexpectedMessage",
+        2 => "Failure_ButtonExpected",
+        3 => "Failure_DataError",
+        4 => "Failure_ActionCancelled",
+        5 => "Failure_PinExpected",
+        6 => "Failure_PinCancelled",
+        7 => "Failure_PinInvalid",
+        8 => "Failure_InvalidSignature",
+        9 => "Failure_ProcessError",
+        10 => "Failure_NotEnoughFunds",
+        11 => "Failure_NotInitialized",
+        12 => "Failure_PinMismatch",
+        13 => "Failure_WipeCodeMismatch",
+        14 => "Failure_InvalidSession",
+        99 => "Failure_FirmwareError",
+        _ => return None,
+    })
+}
+
+/// Error of a `Failure` message of the device, classified like a Connect error
+fn device_failure(failure: &ProtoMessage) -> TrezorConnectError {
+    let failure_type = failure.uint(1).unwrap_or_default();
+    let message = failure.string(2).unwrap_or_default();
+    match failure_code(failure_type) {
+        Some(code) => TrezorConnectError::Failure {
+            kind: TrezorFailureKind::from_code(code),
+            code: Some(code.to_string()),
+            error_details: message,
+        },
+        None => TrezorConnectError::Failure {
+            kind: TrezorFailureKind::Unknown,
+            code: None,
+            error_details: format!("Trezor failure {}: {}", failure_type, message),
+        },
+    }
+}
+
+fn missing(message: &str, field: &str) -> TrezorConnectError {
+    bridge_error(format!("{} from Trezor has no {}", message, field))
+}
+
+/// Device connected to Trezor Bridge
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TrezorBridgeDevice {
+    /// Bridge path of the device, used to acquire it
+    pub path: String,
+    /// Session currently open on the device, if any
+    pub session: Option<String>,
+    /// USB vendor ID
+    pub vendor: Option<u32>,
+    /// USB product ID
+    pub product: Option<u32>,
+    /// Whether this is the debug link of the device
+    #[serde(default)]
+    pub debug: bool,
+}
+
+/// HTTP API of Trezor Bridge, injectable for testing
+#[async_trait]
+pub trait TrezorBridgeTransport: Send + Sync {
+    /// POST `body` to `path` and return the response body. Requests refused by Bridge are
+    /// returned as `BridgeError`.
+    async fn post(&self, path: &str, body: &str) -> TrezorConnectResult<String>;
+}
+
+/// HTTP client of a Trezor Bridge such as `http://127.0.0.1:21325`
+pub struct HttpTrezorBridge {
+    base_url: String,
+    origin: String,
+    client: reqwest::Client,
+}
+
+impl HttpTrezorBridge {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            origin: DEFAULT_TREZOR_BRIDGE_ORIGIN.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Set the `Origin` header sent to Bridge
+    pub fn with_origin(mut self, origin: &str) -> Self {
+        self.origin = origin.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl TrezorBridgeTransport for HttpTrezorBridge {
+    async fn post(&self, path: &str, body: &str) -> TrezorConnectResult<String> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = self
+            .client
+            .post(&url)
+            .header(reqwest::header::ORIGIN, &self.origin)
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| bridge_error(format!("Request to {} failed: {}", url, e)))?;
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| bridge_error(format!("Invalid response from {}: {}", url, e)))?;
+        if !status.is_success() {
+            // Bridge reports errors as `{"error": "..."}`
+            let error = serde_json::from_str::<serde_json::Value>(&text)
+                .ok()
+                .and_then(|value| value.get("error")?.as_str().map(str::to_string))
+                .unwrap_or(text);
+            return Err(bridge_error(format!(
+                "Request to {} failed with {}: {}",
+                url, status, error
+            )));
+        }
+        Ok(text)
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct AcquireResponse {
+    session: String,
+}
+
+/// Connect method to run on a device
+enum BridgeRequest {
+    Features,
+    Address(GetAddressParams),
+    PublicKey(GetPublicKeyParams),
+    SignMessage(SignMessageParams),
+    VerifyMessage(VerifyMessageParams),
+    SignTransaction(SignTransactionParams),
+    OwnershipId(GetOwnershipIdParams),
+    OwnershipProof(GetOwnershipProofParams),
+}
+
+impl BridgeRequest {
+    fn parse(method: &str, params_json: &str) -> TrezorConnectResult<Self> {
+        match method {
+            "getFeatures" => Ok(Self::Features),
+            "getAddress" => Ok(Self::Address(serde_json::from_str(params_json)?)),
+            "getPublicKey" => Ok(Self::PublicKey(serde_json::from_str(params_json)?)),
+            "signMessage" => Ok(Self::SignMessage(serde_json::from_str(params_json)?)),
+            "verifyMessage" => Ok(Self::VerifyMessage(serde_json::from_str(params_json)?)),
+            "signTransaction" => Ok(Self::SignTransaction(serde_json::from_str(params_json)?)),
+            "getOwnershipId" => Ok(Self::OwnershipId(serde_json::from_str(params_json)?)),
+            "getOwnershipProof" => Ok(Self::OwnershipProof(serde_json::from_str(params_json)?)),
+            "getAccountInfo" | "composeTransaction" => Err(bridge_error(format!(
+                "{} needs a blockchain backend and is not available over Bridge",
+                method
+            ))),
+            _ => Err(bridge_error(format!("Unsupported method {}", method))),
+        }
+    }
+}
+
+/// Client of the devices connected to a Trezor Bridge.
+///
+/// Every method acquires the device, runs the request and releases the device again. Buttons
+/// are confirmed and passphrases entered on the device.
+pub struct TrezorBridgeClient {
+    transport: Arc<dyn TrezorBridgeTransport>,
+}
+
+impl TrezorBridgeClient {
+    pub fn new(transport: Arc<dyn TrezorBridgeTransport>) -> Self {
+        Self { transport }
+    }
+
+    /// Client of the Bridge at `bridge_url`, or at `DEFAULT_TREZOR_BRIDGE_URL`
+    pub fn http(bridge_url: Option<&str>) -> Self {
+        Self::new(Arc::new(HttpTrezorBridge::new(
+            bridge_url.unwrap_or(DEFAULT_TREZOR_BRIDGE_URL),
+        )))
+    }
+
+    /// Version of the Bridge
+    pub async fn version(&self) -> TrezorConnectResult<String> {
+        let response = self.transport.post("/", "").await?;
+        Ok(serde_json::from_str::<VersionResponse>(&response)?.version)
+    }
+
+    /// Devices connected to the Bridge
+    pub async fn enumerate(&self) -> TrezorConnectResult<Vec<TrezorBridgeDevice>> {
+        let response = self.transport.post("/enumerate", "").await?;
+        Ok(serde_json::from_str(&response)?)
+    }
+
+    pub async fn get_features(
+        &self,
+        device_path: &str,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::Features).await
+    }
+
+    pub async fn get_address(
+        &self,
+        device_path: &str,
+        params: GetAddressParams,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::Address(params)).await
+    }
+
+    pub async fn get_public_key(
+        &self,
+        device_path: &str,
+        params: GetPublicKeyParams,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::PublicKey(params))
+            .await
+    }
+
+    pub async fn sign_message(
+        &self,
+        device_path: &str,
+        params: SignMessageParams,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::SignMessage(params))
+            .await
+    }
+
+    pub async fn verify_message(
+        &self,
+        device_path: &str,
+        params: VerifyMessageParams,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::VerifyMessage(params))
+            .await
+    }
+
+    /// Sign a transaction. The device streams the previous transactions of the inputs it
+    /// spends, so `refTxs` must contain them unless every input is taproot.
+    pub async fn sign_transaction(
+        &self,
+        device_path: &str,
+        params: SignTransactionParams,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::SignTransaction(params))
+            .await
+    }
+
+    pub async fn get_ownership_id(
+        &self,
+        device_path: &str,
+        params: GetOwnershipIdParams,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::OwnershipId(params))
+            .await
+    }
+
+    /// Get a proof of ownership, confirmed on the device with `userConfirmation`
+    pub async fn get_ownership_proof(
+        &self,
+        device_path: &str,
+        params: GetOwnershipProofParams,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::OwnershipProof(params))
+            .await
+    }
+
+    /// Run a Connect method given by name, with its parameters as JSON
+    pub async fn call_method(
+        &self,
+        device_path: &str,
+        method: &str,
+        params_json: &str,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        self.run(device_path, BridgeRequest::parse(method, params_json)?)
+            .await
+    }
+
+    async fn run(
+        &self,
+        device_path: &str,
+        request: BridgeRequest,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        let response = self
+            .transport
+            .post(&format!("/acquire/{}/null", device_path), "")
+            .await?;
+        let session = serde_json::from_str::<AcquireResponse>(&response)?.session;
+
+        let result = match request {
+            BridgeRequest::Features => self
+                .features(&session)
+                .await
+                .map(TrezorResponsePayload::Features),
+            BridgeRequest::Address(params) => self
+                .address(&session, params)
+                .await
+                .map(TrezorResponsePayload::Address),
+            BridgeRequest::PublicKey(params) => self
+                .public_key(&session, params)
+                .await
+                .map(TrezorResponsePayload::PublicKey),
+            BridgeRequest::SignMessage(params) => self
+                .message_signature(&session, params)
+                .await
+                .map(TrezorResponsePayload::MessageSignature),
+            BridgeRequest::VerifyMessage(params) => self
+                .verified_message(&session, params)
+                .await
+                .map(TrezorResponsePayload::VerifyMessage),
+            BridgeRequest::SignTransaction(params) => self
+                .signed_transaction(&session, params)
+                .await
+                .map(TrezorResponsePayload::SignedTransaction),
+            BridgeRequest::OwnershipId(params) => self
+                .ownership_id(&session, params)
+                .await
+                .map(TrezorResponsePayload::OwnershipId),
+            BridgeRequest::OwnershipProof(params) => self
+                .ownership_proof(&session, params)
+                .await
+                .map(TrezorResponsePayload::OwnershipProof),
+        };
+
+        let released = self
+            .transport
+            .post(&format!("/release/{}", session), "")
+            .await;
+        let payload = result?;
+        released?;
+        Ok(payload)
+    }
+
+    /// Send a message and return the answer of the device
+    async fn exchange(
+        &self,
+        session: &str,
+        message_type: u16,
+        payload: Vec<u8>,
+    ) -> TrezorConnectResult<(u16, ProtoMessage)> {
+        let frame = encode_frame(message_type, &payload)?;
+        let response = self
+            .transport
+            .post(&format!("/call/{}", session), &hex::encode(frame))
+            .await?;
+        let frame = hex::decode(response.trim())
+            .map_err(|e| bridge_error(format!("Invalid response from Bridge: {}", e)))?;
+        let (message_type, payload) = decode_frame(&frame)?;
+        Ok((message_type, ProtoMessage::decode(payload)?))
+    }
+
+    /// Send a message and return the answer of type `expected`, after confirming the button
+    /// and passphrase requests of the device
+    async fn call(
+        &self,
+        session: &str,
+        message_type: u16,
+        payload: ProtoWriter,
+        expected: u16,
+    ) -> TrezorConnectResult<ProtoMessage> {
+        let mut answer = self
+            .exchange(session, message_type, payload.into_bytes())
+            .await?;
+        loop {
+            answer = match answer.0 {
+                message_type::BUTTON_REQUEST => {
+                    self.exchange(session, message_type::BUTTON_ACK, Vec::new())
+                        .await?
+                }
+                message_type::PASSPHRASE_REQUEST => {
+                    // on_device
+                    let ack = ProtoWriter::new().bool(3, true);
+                    self.exchange(session, message_type::PASSPHRASE_ACK, ack.into_bytes())
+                        .await?
+                }
+                message_type::PIN_MATRIX_REQUEST => {
+                    return Err(bridge_error(
+                        "Trezor is locked, unlock it with its PIN first".to_string(),
+                    ))
+                }
+                message_type::FAILURE => return Err(device_failure(&answer.1)),
+                answer_type if answer_type == expected => return Ok(answer.1),
+                answer_type => {
+                    return Err(bridge_error(format!(
+                        "Unexpected message {} from Trezor",
+                        answer_type
+                    )))
+                }
+            };
+        }
+    }
+
+    async fn features(&self, session: &str) -> TrezorConnectResult<FeatureResponse> {
+        let features = self
+            .call(
+                session,
+                message_type::INITIALIZE,
+                ProtoWriter::new(),
+                message_type::FEATURES,
+            )
+            .await?;
+        Ok(FeatureResponse {
+            vendor: features.string(1).unwrap_or_default(),
+            major_version: features.uint32(2).unwrap_or_default(),
+            minor_version: features.uint32(3).unwrap_or_default(),
+            patch_version: features.uint32(4).unwrap_or_default(),
+            device_id: features.string(6).unwrap_or_default(),
+            capabilities: Some(
+                features
+                    .uints(30)
+                    .into_iter()
+                    .map(capability_name)
+                    .collect(),
+            ),
+        })
+    }
+
+    async fn address(
+        &self,
+        session: &str,
+        params: GetAddressParams,
+    ) -> TrezorConnectResult<AddressResponse> {
+        reject_unlock_path(&params.unlockPath)?;
+        let path = parse_path(&params.path)?;
+        let script_type = script_type(params.scriptType.as_deref(), &path)?;
+        let mut request = ProtoWriter::new()
+            .uints(1, &path)
+            .string(2, coin_name(params.coin.as_deref())?)
+            .bool(3, params.showOnTrezor.unwrap_or(true));
+        if let Some(multisig) = &params.multisig {
+            request = request.message(4, encode_multisig(multisig)?);
+        }
+        let request = request
+            .opt_uint(5, script_type.as_ref().map(script_type_code))
+            .opt_bool(7, params.chunkify);
+
+        let answer = self
+            .call(
+                session,
+                message_type::GET_ADDRESS,
+                request,
+                message_type::ADDRESS,
+            )
+            .await?;
+        let address = answer
+            .string(1)
+            .ok_or_else(|| missing("Address", "address"))?;
+        if let Some(expected) = params.address.filter(|expected| *expected != address) {
+            return Err(bridge_error(format!(
+                "Trezor derived address {} instead of {}",
+                address, expected
+            )));
+        }
+        Ok(AddressResponse {
+            address,
+            serializedPath: serialize_path(&path),
+            path,
+        })
+    }
+
+    async fn public_key(
+        &self,
+        session: &str,
+        params: GetPublicKeyParams,
+    ) -> TrezorConnectResult<PublicKeyResponse> {
+        reject_unlock_path(&params.unlockPath)?;
+        let path = parse_path(&params.path)?;
+        let network = coin_network(params.coin.as_deref().unwrap_or("btc"))?;
+        let script_type = script_type(params.scriptType.as_deref(), &path)?;
+        let request = ProtoWriter::new()
+            .uints(1, &path)
+            .opt_string(2, params.ecdsaCurveName.as_deref())
+            .opt_bool(3, params.showOnTrezor)
+            .string(4, coin_name(params.coin.as_deref())?)
+            .opt_uint(5, script_type.as_ref().map(script_type_code))
+            .opt_bool(6, params.ignoreXpubMagic);
+
+        let answer = self
+            .call(
+                session,
+                message_type::GET_PUBLIC_KEY,
+                request,
+                message_type::PUBLIC_KEY,
+            )
+            .await?;
+        let node = answer
+            .message(1)?
+            .ok_or_else(|| missing("PublicKey", "node"))?;
+        let depth = node.uint32(1).unwrap_or_default();
+        let fingerprint = node.uint32(2).unwrap_or_default();
+        let child_num = node.uint32(3).unwrap_or_default();
+        let chain_code = node
+            .bytes(4)
+            .ok_or_else(|| missing("PublicKey", "chain code"))?;
+        let public_key = node
+            .bytes(6)
+            .ok_or_else(|| missing("PublicKey", "public key"))?;
+
+        // The xpub of the device has the SLIP-132 version of the script type, the response
+        // also has it with the standard version like Connect
+        let xpub = standard_xpub(
+            network,
+            depth,
+            fingerprint,
+            child_num,
+            chain_code,
+            public_key,
+        )?;
+        let device_xpub = answer.string(2).filter(|device_xpub| *device_xpub != xpub);
+        let descriptor = answer.string(4).or_else(|| {
+            let root_fingerprint = answer.uint32(3)?;
+            account_descriptor(script_type.as_ref()?, root_fingerprint, &path, &xpub)
+        });
+
+        Ok(PublicKeyResponse {
+            serializedPath: serialize_path(&path),
+            path,
+            xpub,
+            xpubSegwit: device_xpub,
+            chainCode: hex::encode(chain_code),
+            childNum: child_num,
+            publicKey: hex::encode(public_key),
+            fingerprint,
+            depth,
+            descriptor,
+        })
+    }
+
+    async fn message_signature(
+        &self,
+        session: &str,
+        params: SignMessageParams,
+    ) -> TrezorConnectResult<MessageSignatureResponse> {
+        let path = parse_path(&params.path)?;
+        let message = message_bytes(&params.message, params.hex)?;
+        let request = ProtoWriter::new()
+            .uints(1, &path)
+            .bytes(2, &message)
+            .string(3, coin_name(params.coin.as_deref())?)
+            .opt_uint(4, purpose_script_type(&path).as_ref().map(script_type_code))
+            .opt_bool(5, params.no_script_type);
+
+        let answer = self
+            .call(
+                session,
+                message_type::SIGN_MESSAGE,
+                request,
+                message_type::MESSAGE_SIGNATURE,
+            )
+            .await?;
+        Ok(MessageSignatureResponse {
+            address: answer
+                .string(1)
+                .ok_or_else(|| missing("MessageSignature", "address"))?,
+            signature: BASE64.encode(
+                answer
+                    .bytes(2)
+                    .ok_or_else(|| missing("MessageSignature", "signature"))?,
+            ),
+        })
+    }
+
+    async fn verified_message(
+        &self,
+        session: &str,
+        params: VerifyMessageParams,
+    ) -> TrezorConnectResult<VerifyMessageResponse> {
+        let signature = BASE64
+            .decode(&params.signature)
+            .map_err(|e| bridge_error(format!("Invalid signature {}: {}", params.signature, e)))?;
+        let request = ProtoWriter::new()
+            .string(1, &params.address)
+            .bytes(2, &signature)
+            .bytes(3, &message_bytes(&params.message, params.hex)?)
+            .string(4, coin_name(Some(&params.coin))?);
+
+        let answer = self
+            .call(
+                session,
+                message_type::VERIFY_MESSAGE,
+                request,
+                message_type::SUCCESS,
+            )
+            .await?;
+        Ok(VerifyMessageResponse {
+            message: answer
+                .string(1)
+                .unwrap_or_else(|| "Message verified".to_string()),
+        })
+    }
+
+    async fn ownership_id(
+        &self,
+        session: &str,
+        params: GetOwnershipIdParams,
+    ) -> TrezorConnectResult<OwnershipIdResponse> {
+        let path = parse_path(&params.path)?;
+        let script_type = script_type(params.scriptType.as_deref(), &path)?;
+        let mut request = ProtoWriter::new()
+            .uints(1, &path)
+            .string(2, coin_name(params.coin.as_deref())?);
+        if let Some(multisig) = &params.multisig {
+            request = request.message(3, encode_multisig(multisig)?);
+        }
+        let request = request.opt_uint(4, script_type.as_ref().map(script_type_code));
+
+        let answer = self
+            .call(
+                session,
+                message_type::GET_OWNERSHIP_ID,
+                request,
+                message_type::OWNERSHIP_ID,
+            )
+            .await?;
+        Ok(OwnershipIdResponse {
+            ownership_id: hex::encode(
+                answer
+                    .bytes(1)
+                    .ok_or_else(|| missing("OwnershipId", "ownership id"))?,
+            ),
+            serializedPath: serialize_path(&path),
+            path,
+        })
+    }
+
+    async fn ownership_proof(
+        &self,
+        session: &str,
+        params: GetOwnershipProofParams,
+    ) -> TrezorConnectResult<OwnershipProofResponse> {
+        let path = parse_path(&params.path)?;
+        let script_type = script_type(params.scriptType.as_deref(), &path)?;
+        let mut request = ProtoWriter::new()
+            .uints(1, &path)
+            .string(2, coin_name(params.coin.as_deref())?)
+            .opt_uint(3, script_type.as_ref().map(script_type_code));
+        if let Some(multisig) = &params.multisig {
+            request = request.message(4, encode_multisig(multisig)?);
+        }
+        request = request.opt_bool(5, params.userConfirmation);
+        for ownership_id in params.ownershipIds.iter().flatten() {
+            request = request.bytes(6, &decode_hex("ownership id", ownership_id)?);
+        }
+        let commitment_data = params
+            .commitmentData
+            .as_deref()
+            .map(|data| decode_hex("commitment data", data))
+            .transpose()?;
+        let request = request.opt_bytes(7, commitment_data.as_deref());
+
+        let answer = self
+            .call(
+                session,
+                message_type::GET_OWNERSHIP_PROOF,
+                request,
+                message_type::OWNERSHIP_PROOF,
+            )
+            .await?;
+        Ok(OwnershipProofResponse {
+            ownership_proof: hex::encode(
+                answer
+                    .bytes(1)
+                    .ok_or_else(|| missing("OwnershipProof", "ownership proof"))?,
+            ),
+            signature: hex::encode(
+                answer
+                    .bytes(2)
+                    .ok_or_else(|| missing("OwnershipProof", "signature"))?,
+            ),
+            serializedPath: serialize_path(&path),
+            path,
+        })
+    }
+
+    /// Drive the `SignTx` protocol: the device requests the inputs and outputs of the
+    /// transaction and of its previous transactions one at a time, and returns each signature
+    /// and chunk of the signed transaction along with its next request.
+    async fn signed_transaction(
+        &self,
+        session: &str,
+        params: SignTransactionParams,
+    ) -> TrezorConnectResult<SignedTransactionResponse> {
+        if params.push == Some(true) {
+            return Err(bridge_error(
+                "Broadcasting is not available over Bridge".to_string(),
+            ));
+        }
+        if params
+            .paymentRequests
+            .as_ref()
+            .is_some_and(|requests| !requests.is_empty())
+        {
+            return Err(bridge_error(
+                "Payment requests are not available over Bridge".to_string(),
+            ));
+        }
+        reject_unlock_path(&params.unlockPath)?;
+
+        let request = ProtoWriter::new()
+            .uint(1, params.outputs.len() as u64)
+            .uint(2, params.inputs.len() as u64)
+            .string(3, coin_name(Some(&params.coin))?)
+            .opt_uint(4, params.version.map(u64::from))
+            .opt_uint(5, params.locktime.map(u64::from))
+            .opt_uint(11, params.amountUnit.as_ref().map(amount_unit_code))
+            .opt_bool(13, params.serialize)
+            .opt_bool(15, params.chunkify);
+
+        let mut signatures = vec![String::new(); params.inputs.len()];
+        let mut serialized_tx = Vec::new();
+        let mut tx_request = self
+            .call(
+                session,
+                message_type::SIGN_TX,
+                request,
+                message_type::TX_REQUEST,
+            )
+            .await?;
+        loop {
+            if let Some(serialized) = tx_request.message(3)? {
+                if let (Some(index), Some(signature)) = (serialized.uint32(1), serialized.bytes(2))
+                {
+                    let slot = signatures.get_mut(index as usize).ok_or_else(|| {
+                        bridge_error(format!("Trezor signed unknown input {}", index))
+                    })?;
+                    *slot = hex::encode(signature);
+                }
+                serialized_tx.extend_from_slice(serialized.bytes(3).unwrap_or_default());
+            }
+
+            let request_type = tx_request.uint(1).unwrap_or(TX_INPUT);
+            if request_type == TX_FINISHED {
+                break;
+            }
+            let details = tx_request.message(2)?.unwrap_or_default();
+            let index = details.uint32(1).unwrap_or_default() as usize;
+            let tx = match details.bytes(2) {
+                None => current_tx_part(&params, request_type, index)?,
+                Some(tx_hash) => {
+                    ref_tx_part(ref_transaction(&params, tx_hash)?, request_type, index)?
+                }
+            };
+
+            tx_request = self
+                .call(
+                    session,
+                    message_type::TX_ACK,
+                    ProtoWriter::new().message(1, tx),
+                    message_type::TX_REQUEST,
+                )
+                .await?;
+        }
+
+        Ok(SignedTransactionResponse {
+            signatures,
+            serializedTx: hex::encode(serialized_tx),
+            txid: None,
+        })
+    }
+}
+
+fn capability_name(capability: u32) -> String {
+    let name = match capability {
+        1 => "Bitcoin",
+        2 => "Bitcoin_like",
+        3 => "Binance",
+        4 => "Cardano",
+        5 => "Crypto",
+        6 => "EOS",
+        7 => "Ethereum",
+        8 => "Lisk",
+        9 => "Monero",
+        10 => "NEM",
+        11 => "Ripple",
+        12 => "Stellar",
+        13 => "Tezos",
+        14 => "U2F",
+        15 => "Shamir",
+        16 => "ShamirGroups",
+        17 => "PassphraseEntry",
+        _ => return format!("Capability_{}", capability),
+    };
+    format!("Capability_{}", name)
+}
+
+fn reject_unlock_path(unlock_path: &Option<UnlockPath>) -> TrezorConnectResult<()> {
+    match unlock_path {
+        Some(_) => Err(bridge_error(
+            "Unlocking paths is not available over Bridge".to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Trezor coin name of a Connect coin, Bitcoin when not set
+fn coin_name(coin: Option<&str>) -> TrezorConnectResult<&'static str> {
+    match coin.map(coin_network).transpose()? {
+        None | Some(Network::Bitcoin) => Ok("Bitcoin"),
+        Some(Network::Regtest) => Ok("Regtest"),
+        Some(_) => Ok("Testnet"),
+    }
+}
+
+fn parse_path(path: &str) -> TrezorConnectResult<Vec<u32>> {
+    let path = DerivationPath::from_str(path)
+        .map_err(|e| bridge_error(format!("Invalid path {}: {}", path, e)))?;
+    Ok(path.into_iter().map(|index| u32::from(*index)).collect())
+}
+
+/// Script type given by name, or inferred from the purpose of the path
+fn script_type(name: Option<&str>, path: &[u32]) -> TrezorConnectResult<Option<ScriptType>> {
+    match name {
+        Some(name) => Ok(Some(serde_json::from_value(serde_json::Value::String(
+            name.to_string(),
+        ))?)),
+        None => Ok(purpose_script_type(path)),
+    }
+}
+
+/// Wire value of a script type, in `InputScriptType` or `OutputScriptType`
+pub(crate) fn script_type_code(script_type: &ScriptType) -> u64 {
+    match script_type {
+        ScriptType::SpendAddress => 0,
+        ScriptType::SpendMultisig => 1,
+        ScriptType::External => 2,
+        ScriptType::SpendWitness => 3,
+        ScriptType::SpendP2SHWitness => 4,
+        ScriptType::SpendTaproot => 5,
+        ScriptType::PayToAddress => 0,
+        ScriptType::PayToScriptHash => 1,
+        ScriptType::PayToMultisig => 2,
+        ScriptType::PayToOpReturn => 3,
+        ScriptType::PayToWitness => 4,
+        ScriptType::PayToP2SHWitness => 5,
+        ScriptType::PayToTaproot => 6,
+    }
+}
+
+fn amount_unit_code(amount_unit: &AmountUnit) -> u64 {
+    match amount_unit {
+        AmountUnit::Bitcoin => 0,
+        AmountUnit::MilliBitcoin => 1,
+        AmountUnit::MicroBitcoin => 2,
+        AmountUnit::Satoshi => 3,
+    }
+}
+
+fn decode_hex(field: &str, value: &str) -> TrezorConnectResult<Vec<u8>> {
+    hex::decode(value).map_err(|e| bridge_error(format!("Invalid {} {}: {}", field, value, e)))
+}
+
+fn message_bytes(message: &str, is_hex: Option<bool>) -> TrezorConnectResult<Vec<u8>> {
+    if is_hex == Some(true) {
+        decode_hex("message", message)
+    } else {
+        Ok(message.as_bytes().to_vec())
+    }
+}
+
+/// xpub with the standard version bytes of `network`
+fn standard_xpub(
+    network: Network,
+    depth: u32,
+    fingerprint: u32,
+    child_num: u32,
+    chain_code: &[u8],
+    public_key: &[u8],
+) -> TrezorConnectResult<String> {
+    let version: [u8; 4] = match NetworkKind::from(network) {
+        NetworkKind::Main => [0x04, 0x88, 0xb2, 0x1e],
+        NetworkKind::Test => [0x04, 0x35, 0x87, 0xcf],
+    };
+    let depth =
+        u8::try_from(depth).map_err(|_| bridge_error(format!("Invalid depth {}", depth)))?;
+    let mut encoded = Vec::with_capacity(78);
+    encoded.extend_from_slice(&version);
+    encoded.push(depth);
+    encoded.extend_from_slice(&fingerprint.to_be_bytes());
+    encoded.extend_from_slice(&child_num.to_be_bytes());
+    encoded.extend_from_slice(chain_code);
+    encoded.extend_from_slice(public_key);
+    Xpub::decode(&encoded)
+        .map(|xpub| xpub.to_string())
+        .map_err(|e| bridge_error(format!("Invalid public key from Trezor: {}", e)))
+}
+
+/// Output descriptor of the receive and change addresses of an account, like Connect
+/// returns it
+fn account_descriptor(
+    script_type: &ScriptType,
+    root_fingerprint: u32,
+    path: &[u32],
+    xpub: &str,
+) -> Option<String> {
+    let origin = serialize_path(path);
+    let key = format!(
+        "[{:08x}{}]{}/<0;1>/*",
+        root_fingerprint,
+        origin.trim_start_matches('m'),
+        xpub
+    );
+    match script_type {
+        ScriptType::SpendAddress => Some(format!("pkh({})", key)),
+        ScriptType::SpendP2SHWitness => Some(format!("sh(wpkh({}))", key)),
+        ScriptType::SpendWitness => Some(format!("wpkh({})", key)),
+        ScriptType::SpendTaproot => Some(format!("tr({})", key)),
+        _ => None,
+    }
+}
+
+fn encode_hd_node(node: &HDNodeType) -> TrezorConnectResult<ProtoWriter> {
+    Ok(ProtoWriter::new()
+        .uint(1, u64::from(node.depth))
+        .uint(2, u64::from(node.fingerprint))
+        .uint(3, u64::from(node.child_num))
+        .bytes(4, &decode_hex("chain code", &node.chain_code)?)
+        .bytes(6, &decode_hex("public key", &node.public_key)?))
+}
+
+fn encode_node(node: &HDNodeTypeOrString) -> TrezorConnectResult<ProtoWriter> {
+    match node {
+        HDNodeTypeOrString::Node(node) => encode_hd_node(node),
+        HDNodeTypeOrString::String(xpub) => {
+            let xpub = Xpub::from_str(xpub)
+                .map_err(|e| bridge_error(format!("Invalid xpub {}: {}", xpub, e)))?;
+            Ok(ProtoWriter::new()
+                .uint(1, u64::from(xpub.depth))
+                .uint(
+                    2,
+                    u64::from(u32::from_be_bytes(xpub.parent_fingerprint.to_bytes())),
+                )
+                .uint(3, u64::from(u32::from(xpub.child_number)))
+                .bytes(4, xpub.chain_code.as_bytes())
+                .bytes(6, &xpub.public_key.serialize()))
+        }
+    }
+}
+
+fn encode_multisig(multisig: &MultisigRedeemScriptType) -> TrezorConnectResult<ProtoWriter> {
+    let mut writer = ProtoWriter::new();
+    for pubkey in &multisig.pubkeys {
+        let path_node = ProtoWriter::new()
+            .message(1, encode_node(&pubkey.node)?)
+            .uints(2, &pubkey.address_n);
+        writer = writer.message(1, path_node);
+    }
+    for signature in &multisig.signatures {
+        writer = writer.bytes(2, &decode_hex("signature", signature)?);
+    }
+    writer = writer.uint(3, u64::from(multisig.m));
+    for node in multisig.nodes.iter().flatten() {
+        writer = writer.message(4, encode_hd_node(node)?);
+    }
+    Ok(writer.opt_uint(6, multisig.pubkeys_order.map(u64::from)))
+}
+
+fn encode_input(input: &TxInputType) -> TrezorConnectResult<ProtoWriter> {
+    let hex_field = |field: &str, value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|value| decode_hex(field, value))
+            .transpose()
+    };
+    let mut writer = ProtoWriter::new()
+        .uints(1, input.address_n.as_deref().unwrap_or_default())
+        .bytes(2, &decode_hex("prev_hash", &input.prev_hash)?)
+        .uint(3, u64::from(input.prev_index))
+        .opt_bytes(4, hex_field("script_sig", &input.script_sig)?.as_deref())
+        .opt_uint(5, input.sequence.map(u64::from))
+        .opt_uint(6, input.script_type.as_ref().map(script_type_code));
+    if let Some(multisig) = &input.multisig {
+        writer = writer.message(7, encode_multisig(multisig)?);
+    }
+    Ok(writer
+        .uint(8, input.amount)
+        .opt_bytes(13, hex_field("witness", &input.witness)?.as_deref())
+        .opt_bytes(
+            14,
+            hex_field("ownership_proof", &input.ownership_proof)?.as_deref(),
+        )
+        .opt_bytes(
+            15,
+            hex_field("commitment_data", &input.commitment_data)?.as_deref(),
+        )
+        .opt_bytes(16, hex_field("orig_hash", &input.orig_hash)?.as_deref())
+        .opt_uint(17, input.orig_index.map(u64::from))
+        .opt_bytes(
+            19,
+            hex_field("script_pubkey", &input.script_pubkey)?.as_deref(),
+        )
+        .opt_uint(20, input.coinjoin_flags.map(u64::from)))
+}
+
+fn encode_output(output: &TxOutputType) -> TrezorConnectResult<ProtoWriter> {
+    let mut writer = ProtoWriter::new()
+        .opt_string(1, output.address.as_deref())
+        .uints(2, output.address_n.as_deref().unwrap_or_default())
+        .uint(3, output.amount)
+        .uint(4, script_type_code(&output.script_type));
+    if let Some(multisig) = &output.multisig {
+        writer = writer.message(5, encode_multisig(multisig)?);
+    }
+    let op_return_data = output
+        .op_return_data
+        .as_deref()
+        .map(|data| decode_hex("op_return_data", data))
+        .transpose()?;
+    let orig_hash = output
+        .orig_hash
+        .as_deref()
+        .map(|hash| decode_hex("orig_hash", hash))
+        .transpose()?;
+    Ok(writer
+        .opt_bytes(6, op_return_data.as_deref())
+        .opt_bytes(10, orig_hash.as_deref())
+        .opt_uint(11, output.orig_index.map(u64::from))
+        .opt_uint(12, output.payment_req_index.map(u64::from)))
+}
+
+/// Input or output of the transaction being signed, as a `TransactionType`
+fn current_tx_part(
+    params: &SignTransactionParams,
+    request_type: u64,
+    index: usize,
+) -> TrezorConnectResult<ProtoWriter> {
+    let out_of_range = || bridge_error(format!("Trezor requested unknown index {}", index));
+    match request_type {
+        TX_INPUT => {
+            let input = params.inputs.get(index).ok_or_else(out_of_range)?;
+            Ok(ProtoWriter::new().message(2, encode_input(input)?))
+        }
+        TX_OUTPUT => {
+            let output = params.outputs.get(index).ok_or_else(out_of_range)?;
+            Ok(ProtoWriter::new().message(5, encode_output(output)?))
+        }
+        _ => Err(bridge_error(format!(
+            "Unsupported Trezor request {}",
+            request_type
+        ))),
+    }
+}
+
+fn ref_transaction<'a>(
+    params: &'a SignTransactionParams,
+    tx_hash: &[u8],
+) -> TrezorConnectResult<&'a RefTransaction> {
+    let tx_hash = hex::encode(tx_hash);
+    params
+        .refTxs
+        .iter()
+        .flatten()
+        .find(|ref_tx| ref_tx.hash.eq_ignore_ascii_case(&tx_hash))
+        .ok_or_else(|| {
+            bridge_error(format!(
+                "Trezor requested transaction {} missing from refTxs",
+                tx_hash
+            ))
+        })
+}
+
+/// Metadata, input or output of a previous transaction, as a `TransactionType`
+fn ref_tx_part(
+    ref_tx: &RefTransaction,
+    request_type: u64,
+    index: usize,
+) -> TrezorConnectResult<ProtoWriter> {
+    let out_of_range = || {
+        bridge_error(format!(
+            "Trezor requested unknown index {} of transaction {}",
+            index, ref_tx.hash
+        ))
+    };
+    match request_type {
+        TX_META => {
+            let extra_data_len = ref_tx
+                .extra_data
+                .as_deref()
+                .map(|data| decode_hex("extra_data", data).map(|data| data.len() as u64))
+                .transpose()?;
+            Ok(ProtoWriter::new()
+                .opt_uint(1, ref_tx.version.map(u64::from))
+                .opt_uint(4, ref_tx.lock_time.map(u64::from))
+                .uint(6, ref_tx.inputs.len() as u64)
+                .uint(7, ref_tx.bin_outputs.len() as u64)
+                .opt_uint(9, extra_data_len)
+                .opt_uint(10, ref_tx.expiry.map(u64::from))
+                .opt_uint(12, ref_tx.version_group_id.map(u64::from))
+                .opt_uint(13, ref_tx.timestamp.map(u64::from))
+                .opt_uint(14, ref_tx.branch_id.map(u64::from)))
+        }
+        TX_INPUT => {
+            let input = ref_tx.inputs.get(index).ok_or_else(out_of_range)?;
+            let prev_input = ProtoWriter::new()
+                .bytes(2, &decode_hex("prev_hash", &input.prev_hash)?)
+                .uint(3, u64::from(input.prev_index))
+                .bytes(4, &decode_hex("script_sig", &input.script_sig)?)
+                .uint(5, u64::from(input.sequence));
+            Ok(ProtoWriter::new().message(2, prev_input))
+        }
+        TX_OUTPUT => {
+            let output = ref_tx.bin_outputs.get(index).ok_or_else(out_of_range)?;
+            let prev_output = ProtoWriter::new()
+                .uint(1, output.amount)
+                .bytes(2, &decode_hex("script_pubkey", &output.script_pubkey)?);
+            Ok(ProtoWriter::new().message(3, prev_output))
+        }
+        _ => Err(bridge_error(format!(
+            "Unsupported Trezor request {} for transaction {}",
+            request_type, ref_tx.hash
+        ))),
+    }
+}
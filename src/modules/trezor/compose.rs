@@ -0,0 +1,275 @@
+//! Validation of the transactions precomposed by Trezor Connect `composeTransaction`.
+//!
+//! A precomposed transaction is checked against the requested outputs, the account it was
+//! composed for and the fee level it was composed at, before it is signed. Problems are
+//! returned as warnings for the user to review rather than errors, since Connect may have
+//! good reasons, like folding dust change into the fee.
+
+use crate::modules::trezor::{
+    ComposeAccount, ComposeOutput, FeeLevel, PrecomposedOutput, PrecomposedTransaction, ScriptType,
+    TrezorConnectError, TrezorConnectResult,
+};
+use bitcoin::bip32::DerivationPath;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Change below the dust limit that Connect adds to the fee instead of creating an output
+const DUST_LIMIT: u64 = 546;
+
+/// Type of the precomposed transactions that can be signed
+const FINAL_TX_TYPE: &str = "final";
+
+fn compose_error(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::ComposeError { error_details }
+}
+
+fn parse_amount(what: &str, amount: &str) -> TrezorConnectResult<u64> {
+    amount
+        .parse()
+        .map_err(|e| compose_error(format!("Invalid {} amount {}: {}", what, amount, e)))
+}
+
+fn parse_path(path: &str) -> TrezorConnectResult<Vec<u32>> {
+    let path = DerivationPath::from_str(path)
+        .map_err(|e| compose_error(format!("Invalid path {}: {}", path, e)))?;
+    Ok(path.into_iter().map(|index| u32::from(*index)).collect())
+}
+
+/// Problem found in a precomposed transaction, to show before signing it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, uniffi::Enum)]
+pub enum TrezorComposeWarning {
+    /// Transaction that could not be composed, like when the account has not enough funds
+    NotFinal { tx_type: String },
+    /// Input spending an output that is not a UTXO of the account
+    UnknownInput { prev_hash: String, prev_index: u32 },
+    /// Input with another amount or path than the account UTXO it spends
+    InputMismatch { prev_hash: String, prev_index: u32 },
+    /// Requested output, by index, that the transaction does not pay
+    MissingOutput { index: u32 },
+    /// Output paying a requested recipient another amount than requested
+    AmountMismatch {
+        address: Option<String>,
+        requested: u64,
+        amount: u64,
+    },
+    /// Output that was not requested and is not change
+    UnexpectedOutput {
+        address: Option<String>,
+        amount: u64,
+    },
+    /// Change output to a path that is not a change address of the account
+    ForeignChange { path: Vec<u32>, amount: u64 },
+    /// Fee that is not the inputs minus the outputs
+    FeeMismatch { fee: u64, inputs: u64, outputs: u64 },
+    /// Total spent that is not the fee plus the outputs other than change
+    TotalSpentMismatch { total_spent: u64, expected: u64 },
+    /// Fee below the fee level for the size of the transaction
+    FeeTooLow { fee: u64, minimum: u64 },
+    /// Fee above the fee level for the size of the transaction, by more than dust change
+    FeeTooHigh { fee: u64, maximum: u64 },
+}
+
+/// Output of the precomposed transaction, with its parsed amount
+struct ComposedOutput<'a> {
+    output: &'a PrecomposedOutput,
+    amount: u64,
+    matched: bool,
+}
+
+impl ComposedOutput<'_> {
+    fn pays(&self, address: Option<&str>) -> bool {
+        !self.matched
+            && self.output.address_n.is_none()
+            && !matches!(self.output.script_type, ScriptType::PayToOpReturn)
+            && match (self.output.address.as_deref(), address) {
+                (Some(output), Some(address)) => output.eq_ignore_ascii_case(address),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+/// Match a requested payment of `requested` to `address` with an output, preferring one of
+/// the same amount. Returns the index of the output, with a warning if the amount differs.
+fn match_payment(
+    outputs: &[ComposedOutput],
+    address: Option<&str>,
+    requested: Option<u64>,
+) -> Option<(usize, Option<TrezorComposeWarning>)> {
+    let mut paying = outputs
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.pays(address));
+    let Some(requested) = requested else {
+        return paying.next().map(|(index, _)| (index, None));
+    };
+    let paying: Vec<_> = paying.collect();
+    if let Some((index, _)) = paying.iter().find(|(_, output)| output.amount == requested) {
+        return Some((*index, None));
+    }
+    paying.first().map(|(index, output)| {
+        let warning = TrezorComposeWarning::AmountMismatch {
+            address: address.map(str::to_string),
+            requested,
+            amount: output.amount,
+        };
+        (*index, Some(warning))
+    })
+}
+
+/// Check a precomposed transaction against the requested `outputs`, the `account` it was
+/// composed for and the `fee_level` it was composed at, and return the problems found.
+///
+/// Fails only if amounts or paths cannot be parsed.
+pub fn validate_precomposed_transaction(
+    precomposed: &PrecomposedTransaction,
+    outputs: &[ComposeOutput],
+    account: &ComposeAccount,
+    fee_level: &FeeLevel,
+) -> TrezorConnectResult<Vec<TrezorComposeWarning>> {
+    if precomposed.tx_type != FINAL_TX_TYPE {
+        return Ok(vec![TrezorComposeWarning::NotFinal {
+            tx_type: precomposed.tx_type.clone(),
+        }]);
+    }
+    let mut warnings = Vec::new();
+
+    let mut inputs_total = 0u64;
+    for input in precomposed.inputs.iter().flatten() {
+        let amount = parse_amount("input", &input.amount)?;
+        inputs_total = inputs_total.saturating_add(amount);
+        let utxo = account
+            .utxo
+            .iter()
+            .find(|utxo| utxo.txid == input.prev_hash && utxo.vout == input.prev_index);
+        match utxo {
+            None => warnings.push(TrezorComposeWarning::UnknownInput {
+                prev_hash: input.prev_hash.clone(),
+                prev_index: input.prev_index,
+            }),
+            Some(utxo) => {
+                if parse_amount("UTXO", &utxo.amount)? != amount
+                    || parse_path(&utxo.path)? != input.address_n
+                {
+                    warnings.push(TrezorComposeWarning::InputMismatch {
+                        prev_hash: input.prev_hash.clone(),
+                        prev_index: input.prev_index,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut composed = precomposed
+        .outputs
+        .iter()
+        .flatten()
+        .map(|output| {
+            Ok(ComposedOutput {
+                output,
+                amount: parse_amount("output", &output.amount)?,
+                matched: false,
+            })
+        })
+        .collect::<TrezorConnectResult<Vec<_>>>()?;
+    for (index, requested) in outputs.iter().enumerate() {
+        let found = match requested {
+            ComposeOutput::Regular { amount, address } => match_payment(
+                &composed,
+                Some(address),
+                Some(parse_amount("requested", amount)?),
+            ),
+            ComposeOutput::SendMax { address } => match_payment(&composed, Some(address), None),
+            ComposeOutput::PaymentNoAddress { amount } => {
+                match_payment(&composed, None, Some(parse_amount("requested", amount)?))
+            }
+            ComposeOutput::SendMaxNoAddress => match_payment(&composed, None, None),
+            ComposeOutput::OpReturn { .. } => composed
+                .iter()
+                .position(|output| {
+                    !output.matched
+                        && matches!(output.output.script_type, ScriptType::PayToOpReturn)
+                })
+                .map(|index| (index, None)),
+        };
+        match found {
+            Some((output, warning)) => {
+                composed[output].matched = true;
+                warnings.extend(warning);
+            }
+            None => warnings.push(TrezorComposeWarning::MissingOutput {
+                index: index as u32,
+            }),
+        }
+    }
+
+    let account_path = parse_path(&account.path)?;
+    for output in composed.iter().filter(|output| !output.matched) {
+        match &output.output.address_n {
+            Some(path) => {
+                let is_change = matches!(path.strip_prefix(account_path.as_slice()), Some([1, _]));
+                if !is_change {
+                    warnings.push(TrezorComposeWarning::ForeignChange {
+                        path: path.clone(),
+                        amount: output.amount,
+                    });
+                }
+            }
+            None => warnings.push(TrezorComposeWarning::UnexpectedOutput {
+                address: output.output.address.clone(),
+                amount: output.amount,
+            }),
+        }
+    }
+
+    let outputs_total = composed
+        .iter()
+        .fold(0u64, |total, output| total.saturating_add(output.amount));
+    let payments_total = composed
+        .iter()
+        .filter(|output| output.output.address_n.is_none())
+        .fold(0u64, |total, output| total.saturating_add(output.amount));
+    let fee = match &precomposed.fee {
+        Some(fee) => parse_amount("fee", fee)?,
+        None => inputs_total.saturating_sub(outputs_total),
+    };
+    if inputs_total.checked_sub(outputs_total) != Some(fee) {
+        warnings.push(TrezorComposeWarning::FeeMismatch {
+            fee,
+            inputs: inputs_total,
+            outputs: outputs_total,
+        });
+    }
+    if let Some(total_spent) = &precomposed.total_spent {
+        let total_spent = parse_amount("total spent", total_spent)?;
+        let expected = payments_total.saturating_add(fee);
+        if total_spent != expected {
+            warnings.push(TrezorComposeWarning::TotalSpentMismatch {
+                total_spent,
+                expected,
+            });
+        }
+    }
+
+    if let Some(bytes) = precomposed.bytes {
+        let fee_per_unit: f64 = fee_level.fee_per_unit.parse().map_err(|e| {
+            compose_error(format!(
+                "Invalid fee per unit {}: {}",
+                fee_level.fee_per_unit, e
+            ))
+        })?;
+        let level_fee = fee_per_unit * f64::from(bytes);
+        let base_fee = u64::from(fee_level.base_fee.unwrap_or_default());
+        let minimum = (level_fee.floor() as u64).saturating_add(base_fee);
+        let maximum = (level_fee.ceil() as u64)
+            .saturating_add(base_fee)
+            .saturating_add(DUST_LIMIT);
+        if fee < minimum {
+            warnings.push(TrezorComposeWarning::FeeTooLow { fee, minimum });
+        } else if fee > maximum {
+            warnings.push(TrezorComposeWarning::FeeTooHigh { fee, maximum });
+        }
+    }
+
+    Ok(warnings)
+}
@@ -1,3 +1,4 @@
+use crate::modules::activity::ActivityError;
 use thiserror::Error;
 
 /// Error types for Trezor Connect operations
@@ -22,6 +23,103 @@ pub enum TrezorConnectError {
 
     #[error("Unable to create client: {error_details}")]
     ClientError { error_details: String },
+
+    #[error("Database error: {error_details}")]
+    /// Error reading or writing the request registry
+    DatabaseError { error_details: String },
+
+    #[error("Unknown request: {error_details}")]
+    /// Callback for a request that was never made, or was cancelled
+    UnknownRequest { error_details: String },
+
+    #[error("Duplicate callback: {error_details}")]
+    /// Callback for a request that was already answered
+    DuplicateCallback { error_details: String },
+
+    #[error("Request expired: {error_details}")]
+    /// Callback arriving after its request expired
+    RequestExpired { error_details: String },
+
+    #[error("PSBT error: {error_details}")]
+    /// PSBT that cannot be converted to signing parameters, or signatures that cannot be
+    /// merged into it
+    PsbtError { error_details: String },
+
+    #[error("Transaction mismatch: {error_details}")]
+    /// Signed transaction that does not spend the inputs or pay the outputs of its PSBT
+    TransactionMismatch { error_details: String },
+
+    #[error("Account error: {error_details}")]
+    /// Invalid watch-only account, or address that is not one of the account
+    AccountError { error_details: String },
+
+    #[error("Bridge error: {error_details}")]
+    /// Trezor Bridge unreachable or refusing a request
+    BridgeError { error_details: String },
+
+    #[error("Multisig error: {error_details}")]
+    /// Invalid multisig wallet, or PSBT of the wallet that cannot be finalized
+    MultisigError { error_details: String },
+
+    #[error("Compose error: {error_details}")]
+    /// Compose request or precomposed transaction with amounts or paths that cannot be parsed
+    ComposeError { error_details: String },
+
+    #[error("Activity error: {error_details}")]
+    /// Signed transaction that cannot be recorded in the activity database
+    ActivityError { error_details: String },
+
+    #[error("Ownership error: {error_details}")]
+    /// Proof of ownership, or taproot message, that cannot be decoded or verified
+    OwnershipError { error_details: String },
+
+    #[error("Trezor failure: {error_details}")]
+    /// Request that Trezor Connect answered with an error, classified by its code
+    Failure {
+        kind: TrezorFailureKind,
+        code: Option<String>,
+        error_details: String,
+    },
+}
+
+/// Kind of error a Trezor Connect request failed with, to pick how to recover from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum TrezorFailureKind {
+    /// The user cancelled the request, on the device or by closing the Connect popup
+    UserCancelled,
+    /// No device is connected, or it was disconnected during the request
+    DeviceDisconnected,
+    /// The passphrase entered does not open the wallet the request was made for
+    WrongPassphrase,
+    /// The firmware of the device does not support the request and must be updated
+    FirmwareTooOld,
+    /// The request is not allowed for the device or Connect configuration
+    MethodNotAllowed,
+    /// The request parameters were rejected by Connect or the device
+    InvalidParams,
+    /// Any other error, see its code and details
+    Unknown,
+}
+
+impl TrezorFailureKind {
+    /// Classify the `code` of a Trezor Connect error payload, like `Method_Cancel`
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "Method_Cancel"
+            | "Method_Interrupted"
+            | "Method_PermissionsNotGranted"
+            | "Failure_ActionCancelled"
+            | "Failure_PinCancelled" => Self::UserCancelled,
+            "Device_Disconnected" | "Device_NotFound" | "Transport_Missing" => {
+                Self::DeviceDisconnected
+            }
+            "Device_InvalidState" => Self::WrongPassphrase,
+            "Device_FwException" | "Device_MissingCapability" => Self::FirmwareTooOld,
+            "Method_NotAllowed" => Self::MethodNotAllowed,
+            "Method_InvalidParameter" | "Failure_DataError" => Self::InvalidParams,
+            _ => Self::Unknown,
+        }
+    }
 }
 
 impl From<serde_json::Error> for TrezorConnectError {
@@ -39,3 +137,19 @@ impl From<url::ParseError> for TrezorConnectError {
         }
     }
 }
+
+impl From<ActivityError> for TrezorConnectError {
+    fn from(error: ActivityError) -> Self {
+        Self::ActivityError {
+            error_details: error.to_string(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for TrezorConnectError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::DatabaseError {
+            error_details: error.to_string(),
+        }
+    }
+}
@@ -0,0 +1,999 @@
+//! In-process Trezor Bridge with a software device, for tests.
+//!
+//! `FakeTrezorBridge` answers the Bridge API for a single device holding the keys of a seed.
+//! Sessions are acquired and released like with Bridge, and the device speaks the wire
+//! messages used by `TrezorBridgeClient`: it derives addresses and public keys, signs and
+//! verifies messages, gives SLIP-19 ownership ids and proofs, and signs transactions after
+//! requesting their inputs, outputs and the previous transaction of every input, including
+//! P2WSH multisig inputs given with their `multisig` field. Displaying an address, signing a
+//! message, a proof confirmed by the user and the outputs of a transaction wait for a button
+//! confirmation.
+
+use crate::modules::trezor::protobuf::request_type::{TX_FINISHED, TX_INPUT, TX_META, TX_OUTPUT};
+use crate::modules::trezor::protobuf::{
+    decode_frame, encode_frame, message_type, ProtoMessage, ProtoWriter,
+};
+use crate::modules::trezor::{
+    multisig_witness_script, ownership_proof_body, ownership_proof_sighash, TrezorBridgeTransport,
+    TrezorConnectError, TrezorConnectResult,
+};
+use async_trait::async_trait;
+use bitcoin::bip32::{ChainCode, ChildNumber, DerivationPath, Fingerprint, Xpriv, Xpub};
+use bitcoin::consensus::encode::{deserialize, serialize, VarInt};
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, sha256d, sha512, Hash, HashEngine};
+use bitcoin::key::{Keypair, TapTweak};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bitcoin::secp256k1::{All, Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    absolute, ecdsa, taproot, Address, Amount, CompressedPublicKey, Network, NetworkKind, OutPoint,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, XOnlyPublicKey,
+};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Bridge path of the device of a `FakeTrezorBridge`
+pub const FAKE_TREZOR_DEVICE_PATH: &str = "1";
+
+const FAKE_BRIDGE_VERSION: &str = "2.0.33";
+
+/// `InputScriptType` values
+const SPEND_ADDRESS: u64 = 0;
+const EXTERNAL: u64 = 2;
+const SPEND_WITNESS: u64 = 3;
+const SPEND_P2SH_WITNESS: u64 = 4;
+const SPEND_TAPROOT: u64 = 5;
+
+/// `OutputScriptType` values
+const PAY_TO_ADDRESS: u64 = 0;
+const PAY_TO_OP_RETURN: u64 = 3;
+const PAY_TO_WITNESS: u64 = 4;
+const PAY_TO_P2SH_WITNESS: u64 = 5;
+const PAY_TO_TAPROOT: u64 = 6;
+
+/// `FailureType` values
+const FAILURE_UNEXPECTED_MESSAGE: u64 = 1;
+const FAILURE_DATA_ERROR: u64 = 3;
+const FAILURE_INVALID_SIGNATURE: u64 = 8;
+
+/// Message type and protobuf encoding of a message
+type Reply = (u16, Vec<u8>);
+
+/// Failure code and message
+type Failure = (u64, String);
+
+fn data_error(message: impl ToString) -> Failure {
+    (FAILURE_DATA_ERROR, message.to_string())
+}
+
+fn refused(error_details: &str) -> TrezorConnectError {
+    TrezorConnectError::BridgeError {
+        error_details: error_details.to_string(),
+    }
+}
+
+/// Next message a transaction being signed waits for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Awaiting {
+    Input(usize),
+    Output(usize),
+    PrevMeta(usize),
+    PrevInput(usize, usize),
+    PrevOutput(usize, usize),
+    Finish,
+}
+
+/// Previous transaction being streamed
+struct PrevTx {
+    version: i32,
+    lock_time: u32,
+    inputs_count: usize,
+    outputs_count: usize,
+    inputs: Vec<TxIn>,
+    outputs: Vec<TxOut>,
+}
+
+/// Transaction being signed
+struct Signing {
+    network: Network,
+    version: i32,
+    lock_time: u32,
+    inputs_count: usize,
+    outputs_count: usize,
+    inputs: Vec<ProtoMessage>,
+    outputs: Vec<ProtoMessage>,
+    prev: Option<PrevTx>,
+    prev_txs: Vec<Transaction>,
+    awaiting: Awaiting,
+    /// `TxRequest`s returning the signatures and the signed transaction
+    finish: VecDeque<Reply>,
+}
+
+impl Signing {
+    fn prev_hash(&self, input: usize) -> Vec<u8> {
+        self.inputs[input].bytes(2).unwrap_or_default().to_vec()
+    }
+
+    fn request(&self) -> Reply {
+        match self.awaiting {
+            Awaiting::Input(index) => tx_request(TX_INPUT, Some(index), None, None),
+            Awaiting::Output(index) => tx_request(TX_OUTPUT, Some(index), None, None),
+            Awaiting::PrevMeta(input) => {
+                tx_request(TX_META, None, Some(&self.prev_hash(input)), None)
+            }
+            Awaiting::PrevInput(input, index) => {
+                tx_request(TX_INPUT, Some(index), Some(&self.prev_hash(input)), None)
+            }
+            Awaiting::PrevOutput(input, index) => {
+                tx_request(TX_OUTPUT, Some(index), Some(&self.prev_hash(input)), None)
+            }
+            Awaiting::Finish => tx_request(TX_FINISHED, None, None, None),
+        }
+    }
+}
+
+fn tx_request(
+    request_type: u64,
+    index: Option<usize>,
+    tx_hash: Option<&[u8]>,
+    serialized: Option<ProtoWriter>,
+) -> Reply {
+    let details = ProtoWriter::new()
+        .opt_uint(1, index.map(|index| index as u64))
+        .opt_bytes(2, tx_hash);
+    let mut request = ProtoWriter::new().uint(1, request_type).message(2, details);
+    if let Some(serialized) = serialized {
+        request = request.message(3, serialized);
+    }
+    (message_type::TX_REQUEST, request.into_bytes())
+}
+
+#[derive(Default)]
+struct FakeState {
+    session: Option<String>,
+    sessions_opened: u32,
+    /// Answer sent once the pending button request is confirmed
+    after_button: Option<Reply>,
+    signing: Option<Signing>,
+    messages: Vec<u16>,
+}
+
+/// Trezor Bridge with one software device, implementing `TrezorBridgeTransport` in process
+pub struct FakeTrezorBridge {
+    master: Xpriv,
+    /// SLIP-21 key of the SLIP-19 ownership ids
+    ownership_key: [u8; 32],
+    secp: Secp256k1<All>,
+    state: Mutex<FakeState>,
+}
+
+impl FakeTrezorBridge {
+    /// Bridge with a device holding the keys of `seed`
+    pub fn new(seed: &[u8]) -> TrezorConnectResult<Self> {
+        let master =
+            Xpriv::new_master(NetworkKind::Test, seed).map_err(|e| TrezorConnectError::Other {
+                error_details: format!("Invalid seed: {}", e),
+            })?;
+        Ok(Self {
+            master,
+            ownership_key: slip21_key(seed, &[b"SLIP-0019", b"Ownership identification key"]),
+            secp: Secp256k1::new(),
+            state: Mutex::new(FakeState::default()),
+        })
+    }
+
+    /// Session open on the device, if any
+    pub fn session(&self) -> Option<String> {
+        self.state.lock().unwrap().session.clone()
+    }
+
+    /// Types of the messages the device received, in order
+    pub fn messages(&self) -> Vec<u16> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    fn handle(&self, state: &mut FakeState, message_type: u16, message: &ProtoMessage) -> Reply {
+        let result = match message_type {
+            message_type::INITIALIZE | message_type::GET_FEATURES => Ok(self.features()),
+            message_type::BUTTON_ACK => state.after_button.take().ok_or_else(|| {
+                (
+                    FAILURE_UNEXPECTED_MESSAGE,
+                    "No button request pending".to_string(),
+                )
+            }),
+            message_type::GET_ADDRESS => self.address(message).map(|reply| {
+                let show_display = message.bool(3).unwrap_or_default();
+                confirm(state, reply, show_display)
+            }),
+            message_type::GET_PUBLIC_KEY => self.public_key(message),
+            message_type::SIGN_MESSAGE => self
+                .message_signature(message)
+                .map(|reply| confirm(state, reply, true)),
+            message_type::VERIFY_MESSAGE => self.verify_message(message),
+            message_type::SIGN_TX => start_signing(state, message),
+            message_type::GET_OWNERSHIP_ID => self.ownership_id(message),
+            message_type::GET_OWNERSHIP_PROOF => self.ownership_proof(message).map(|reply| {
+                let user_confirmation = message.bool(5).unwrap_or_default();
+                confirm(state, reply, user_confirmation)
+            }),
+            message_type::TX_ACK => self.continue_signing(state, message),
+            _ => Err((
+                FAILURE_UNEXPECTED_MESSAGE,
+                format!("Unexpected message {}", message_type),
+            )),
+        };
+        result.unwrap_or_else(|(code, error)| {
+            state.signing = None;
+            state.after_button = None;
+            let failure = ProtoWriter::new().uint(1, code).string(2, &error);
+            (message_type::FAILURE, failure.into_bytes())
+        })
+    }
+
+    fn features(&self) -> Reply {
+        let features = ProtoWriter::new()
+            .string(1, "trezor.io")
+            .uint(2, 2)
+            .uint(3, 8)
+            .uint(4, 1)
+            .string(6, "FAKE0123456789ABCDEF0123")
+            .uints(30, &[1, 17]);
+        (message_type::FEATURES, features.into_bytes())
+    }
+
+    fn key(&self, path: &[u32]) -> Result<(SecretKey, PublicKey), Failure> {
+        let path: DerivationPath = path.iter().map(|index| ChildNumber::from(*index)).collect();
+        let xpriv = self
+            .master
+            .derive_priv(&self.secp, &path)
+            .map_err(data_error)?;
+        Ok((xpriv.private_key, xpriv.private_key.public_key(&self.secp)))
+    }
+
+    fn script_pubkey(&self, path: &[u32], script_type: u64) -> Result<ScriptBuf, Failure> {
+        let (_, public_key) = self.key(path)?;
+        let public_key = CompressedPublicKey(public_key);
+        match script_type {
+            SPEND_ADDRESS => Ok(ScriptBuf::new_p2pkh(&public_key.pubkey_hash())),
+            SPEND_WITNESS => Ok(ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash())),
+            SPEND_P2SH_WITNESS => Ok(ScriptBuf::new_p2sh(
+                &ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()).script_hash(),
+            )),
+            SPEND_TAPROOT => Ok(ScriptBuf::new_p2tr(
+                &self.secp,
+                XOnlyPublicKey::from(public_key.0),
+                None,
+            )),
+            _ => Err(data_error(format!(
+                "Unsupported script type {}",
+                script_type
+            ))),
+        }
+    }
+
+    /// Witness script of a `multisig` field, the position of the key at `path` among its
+    /// keys and the signatures of the keys, empty when missing
+    fn multisig(
+        &self,
+        multisig: &ProtoMessage,
+        path: &[u32],
+    ) -> Result<(ScriptBuf, usize, Vec<Vec<u8>>), Failure> {
+        let mut keys = Vec::new();
+        for pubkey in multisig.messages(1).map_err(data_error)? {
+            let node = pubkey
+                .message(1)
+                .map_err(data_error)?
+                .ok_or_else(|| data_error("Multisig key without node"))?;
+            let chain_code: [u8; 32] = node
+                .bytes(4)
+                .unwrap_or_default()
+                .try_into()
+                .map_err(data_error)?;
+            let xpub = Xpub {
+                network: NetworkKind::Test,
+                depth: node.uint(1).unwrap_or_default() as u8,
+                parent_fingerprint: Fingerprint::from(
+                    node.uint32(2).unwrap_or_default().to_be_bytes(),
+                ),
+                child_number: ChildNumber::from(node.uint32(3).unwrap_or_default()),
+                public_key: PublicKey::from_slice(node.bytes(6).unwrap_or_default())
+                    .map_err(data_error)?,
+                chain_code: ChainCode::from(chain_code),
+            };
+            let children: DerivationPath =
+                pubkey.uints(2).into_iter().map(ChildNumber::from).collect();
+            let key = xpub.derive_pub(&self.secp, &children).map_err(data_error)?;
+            keys.push(key.public_key);
+        }
+        let mut signatures: Vec<Vec<u8>> = multisig
+            .repeated_bytes(2)
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .collect();
+        signatures.resize(keys.len(), Vec::new());
+        let mut keys: Vec<_> = keys.into_iter().zip(signatures).collect();
+        if multisig.uint(6) == Some(1) {
+            keys.sort_by_key(|(key, _)| key.serialize());
+        }
+        let (keys, signatures): (Vec<PublicKey>, Vec<Vec<u8>>) = keys.into_iter().unzip();
+
+        let (_, own_key) = self.key(path)?;
+        let position = keys
+            .iter()
+            .position(|key| *key == own_key)
+            .ok_or_else(|| data_error("Multisig is not of the wallet"))?;
+        let threshold = multisig.uint32(3).unwrap_or_default();
+        Ok((
+            multisig_witness_script(threshold, &keys),
+            position,
+            signatures,
+        ))
+    }
+
+    /// Script of an address, input or output, P2WSH when it has a `multisig` field
+    fn wallet_script_pubkey(
+        &self,
+        path: &[u32],
+        script_type: u64,
+        multisig: Option<&ProtoMessage>,
+    ) -> Result<ScriptBuf, Failure> {
+        match multisig {
+            Some(multisig) => {
+                let (witness_script, ..) = self.multisig(multisig, path)?;
+                Ok(ScriptBuf::new_p2wsh(&witness_script.wscript_hash()))
+            }
+            None => self.script_pubkey(path, script_type),
+        }
+    }
+
+    fn address(&self, message: &ProtoMessage) -> Result<Reply, Failure> {
+        let network = network(message.string(2))?;
+        let script_pubkey = self.wallet_script_pubkey(
+            &message.uints(1),
+            message.uint(5).unwrap_or(SPEND_ADDRESS),
+            message.message(4).map_err(data_error)?.as_ref(),
+        )?;
+        let address = Address::from_script(&script_pubkey, network).map_err(data_error)?;
+        let reply = ProtoWriter::new().string(1, &address.to_string());
+        Ok((message_type::ADDRESS, reply.into_bytes()))
+    }
+
+    fn public_key(&self, message: &ProtoMessage) -> Result<Reply, Failure> {
+        let network = network(message.string(4))?;
+        let path: DerivationPath = message
+            .uints(1)
+            .into_iter()
+            .map(ChildNumber::from)
+            .collect();
+        let xpriv = self
+            .master
+            .derive_priv(&self.secp, &path)
+            .map_err(data_error)?;
+        let mut xpub = Xpub::from_priv(&self.secp, &xpriv);
+        xpub.network = NetworkKind::from(network);
+
+        let node = ProtoWriter::new()
+            .uint(1, u64::from(xpub.depth))
+            .uint(
+                2,
+                u64::from(u32::from_be_bytes(xpub.parent_fingerprint.to_bytes())),
+            )
+            .uint(3, u64::from(u32::from(xpub.child_number)))
+            .bytes(4, xpub.chain_code.as_bytes())
+            .bytes(6, &xpub.public_key.serialize());
+        let root_fingerprint = u32::from_be_bytes(self.master.fingerprint(&self.secp).to_bytes());
+        let reply = ProtoWriter::new()
+            .message(1, node)
+            .string(2, &xpub.to_string())
+            .uint(3, u64::from(root_fingerprint));
+        Ok((message_type::PUBLIC_KEY, reply.into_bytes()))
+    }
+
+    fn message_signature(&self, message: &ProtoMessage) -> Result<Reply, Failure> {
+        let network = network(message.string(3))?;
+        let path = message.uints(1);
+        let script_type = if message.bool(5).unwrap_or_default() {
+            SPEND_ADDRESS
+        } else {
+            message.uint(4).unwrap_or(SPEND_ADDRESS)
+        };
+        // Header of the signature, before adding the recovery ID
+        let header = match script_type {
+            SPEND_ADDRESS => 31,
+            SPEND_P2SH_WITNESS => 35,
+            SPEND_WITNESS => 39,
+            _ => return Err(data_error("Unsupported script type for message signing")),
+        };
+
+        let (secret_key, _) = self.key(&path)?;
+        let digest = signed_message_hash(message.bytes(2).unwrap_or_default());
+        let (recovery_id, signature) = self
+            .secp
+            .sign_ecdsa_recoverable(&Message::from_digest(digest.to_byte_array()), &secret_key)
+            .serialize_compact();
+        let mut encoded = vec![header + recovery_id.to_i32() as u8];
+        encoded.extend_from_slice(&signature);
+
+        let script_pubkey = self.script_pubkey(&path, script_type)?;
+        let address = Address::from_script(&script_pubkey, network).map_err(data_error)?;
+        let reply = ProtoWriter::new()
+            .string(1, &address.to_string())
+            .bytes(2, &encoded);
+        Ok((message_type::MESSAGE_SIGNATURE, reply.into_bytes()))
+    }
+
+    fn verify_message(&self, message: &ProtoMessage) -> Result<Reply, Failure> {
+        let network = network(message.string(4))?;
+        let invalid = || (FAILURE_INVALID_SIGNATURE, "Invalid signature".to_string());
+        let signature = message.bytes(2).unwrap_or_default();
+        let header = *signature.first().ok_or_else(invalid)?;
+        if signature.len() != 65 || !(31..=42).contains(&header) {
+            return Err(invalid());
+        }
+
+        let recovery_id =
+            RecoveryId::from_i32(i32::from((header - 27) & 3)).map_err(|_| invalid())?;
+        let recoverable = RecoverableSignature::from_compact(&signature[1..], recovery_id)
+            .map_err(|_| invalid())?;
+        let digest = signed_message_hash(message.bytes(3).unwrap_or_default());
+        let public_key = self
+            .secp
+            .recover_ecdsa(&Message::from_digest(digest.to_byte_array()), &recoverable)
+            .map_err(|_| invalid())?;
+        let public_key = CompressedPublicKey(public_key);
+        let script_pubkey = match header {
+            31..=34 => ScriptBuf::new_p2pkh(&public_key.pubkey_hash()),
+            35..=38 => ScriptBuf::new_p2sh(
+                &ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()).script_hash(),
+            ),
+            _ => ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()),
+        };
+
+        let address = Address::from_str(&message.string(1).unwrap_or_default())
+            .map_err(data_error)?
+            .require_network(network)
+            .map_err(data_error)?;
+        if address.script_pubkey() != script_pubkey {
+            return Err(invalid());
+        }
+        let reply = ProtoWriter::new().string(1, "Message verified");
+        Ok((message_type::SUCCESS, reply.into_bytes()))
+    }
+
+    fn ownership_id_of(&self, script_pubkey: &ScriptBuf) -> [u8; 32] {
+        let mut engine = HmacEngine::<sha256::Hash>::new(&self.ownership_key);
+        engine.input(script_pubkey.as_bytes());
+        Hmac::from_engine(engine).to_byte_array()
+    }
+
+    fn ownership_id(&self, message: &ProtoMessage) -> Result<Reply, Failure> {
+        network(message.string(2))?;
+        if message.bytes(3).is_some() {
+            return Err(data_error("Multisig ownership ids are not supported"));
+        }
+        let script_pubkey =
+            self.script_pubkey(&message.uints(1), message.uint(4).unwrap_or(SPEND_ADDRESS))?;
+        let reply = ProtoWriter::new().bytes(1, &self.ownership_id_of(&script_pubkey));
+        Ok((message_type::OWNERSHIP_ID, reply.into_bytes()))
+    }
+
+    /// SLIP-19 proof for a P2WPKH or taproot script, signed like the firmware does
+    fn ownership_proof(&self, message: &ProtoMessage) -> Result<Reply, Failure> {
+        network(message.string(2))?;
+        if message.bytes(4).is_some() {
+            return Err(data_error("Multisig ownership proofs are not supported"));
+        }
+        let path = message.uints(1);
+        let script_type = message.uint(3).unwrap_or(SPEND_WITNESS);
+        if script_type != SPEND_WITNESS && script_type != SPEND_TAPROOT {
+            return Err(data_error("Invalid script type"));
+        }
+        let script_pubkey = self.script_pubkey(&path, script_type)?;
+
+        // The ownership id of the device comes last
+        let ownership_id = self.ownership_id_of(&script_pubkey);
+        let mut ownership_ids = message
+            .repeated_bytes(6)
+            .into_iter()
+            .map(<[u8; 32]>::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| data_error("Invalid ownership identifier"))?;
+        match ownership_ids.last() {
+            None => ownership_ids.push(ownership_id),
+            Some(last) if *last == ownership_id => {}
+            Some(_) => return Err(data_error("Invalid ownership identifier")),
+        }
+
+        let user_confirmed = message.bool(5).unwrap_or_default();
+        let mut proof = ownership_proof_body(user_confirmed, &ownership_ids);
+        let sighash =
+            ownership_proof_sighash(&proof, &script_pubkey, message.bytes(7).unwrap_or_default());
+        let sighash = Message::from_digest(sighash.to_byte_array());
+        let (secret_key, public_key) = self.key(&path)?;
+        let (signature, witness) = if script_type == SPEND_TAPROOT {
+            let keypair = Keypair::from_secret_key(&self.secp, &secret_key)
+                .tap_tweak(&self.secp, None)
+                .to_keypair();
+            let signature = self.secp.sign_schnorr_no_aux_rand(&sighash, &keypair);
+            let witness = Witness::p2tr_key_spend(&taproot::Signature {
+                signature,
+                sighash_type: TapSighashType::Default,
+            });
+            (signature.serialize().to_vec(), witness)
+        } else {
+            let signature = self.secp.sign_ecdsa(&sighash, &secret_key);
+            let witness = Witness::p2wpkh(&ecdsa::Signature::sighash_all(signature), &public_key);
+            (signature.serialize_der().to_vec(), witness)
+        };
+        proof.extend(serialize(&ScriptBuf::new()));
+        proof.extend(serialize(&witness));
+
+        let reply = ProtoWriter::new().bytes(1, &proof).bytes(2, &signature);
+        Ok((message_type::OWNERSHIP_PROOF, reply.into_bytes()))
+    }
+
+    fn continue_signing(
+        &self,
+        state: &mut FakeState,
+        ack: &ProtoMessage,
+    ) -> Result<Reply, Failure> {
+        let unexpected = || (FAILURE_UNEXPECTED_MESSAGE, "Not signing".to_string());
+        let signing = state.signing.as_mut().ok_or_else(unexpected)?;
+        let tx = ack.message(1).map_err(data_error)?.unwrap_or_default();
+        let part = |field: u32| {
+            tx.message(field)
+                .map_err(data_error)?
+                .ok_or_else(|| data_error("Missing transaction part"))
+        };
+
+        match signing.awaiting {
+            Awaiting::Input(index) => {
+                signing.inputs.push(part(2)?);
+                signing.awaiting = if index + 1 < signing.inputs_count {
+                    Awaiting::Input(index + 1)
+                } else {
+                    Awaiting::Output(0)
+                };
+            }
+            Awaiting::Output(index) => {
+                signing.outputs.push(part(5)?);
+                if index + 1 < signing.outputs_count {
+                    signing.awaiting = Awaiting::Output(index + 1);
+                } else {
+                    // The user confirms the outputs before the inputs are checked
+                    signing.awaiting = Awaiting::PrevMeta(0);
+                    let reply = signing.request();
+                    return Ok(confirm(state, reply, true));
+                }
+            }
+            Awaiting::PrevMeta(input) => {
+                let prev = PrevTx {
+                    version: tx.uint32(1).unwrap_or(1) as i32,
+                    lock_time: tx.uint32(4).unwrap_or_default(),
+                    inputs_count: tx.uint32(6).unwrap_or_default() as usize,
+                    outputs_count: tx.uint32(7).unwrap_or_default() as usize,
+                    inputs: Vec::new(),
+                    outputs: Vec::new(),
+                };
+                if prev.outputs_count == 0 {
+                    return Err(data_error("Previous transaction has no outputs"));
+                }
+                signing.awaiting = if prev.inputs_count > 0 {
+                    Awaiting::PrevInput(input, 0)
+                } else {
+                    Awaiting::PrevOutput(input, 0)
+                };
+                signing.prev = Some(prev);
+            }
+            Awaiting::PrevInput(input, index) => {
+                let prev_input = part(2)?;
+                let prev = signing.prev.as_mut().ok_or_else(unexpected)?;
+                prev.inputs.push(TxIn {
+                    previous_output: OutPoint {
+                        txid: txid(prev_input.bytes(2).unwrap_or_default())?,
+                        vout: prev_input.uint32(3).unwrap_or_default(),
+                    },
+                    script_sig: ScriptBuf::from_bytes(
+                        prev_input.bytes(4).unwrap_or_default().to_vec(),
+                    ),
+                    sequence: Sequence(prev_input.uint32(5).unwrap_or(u32::MAX)),
+                    witness: Witness::new(),
+                });
+                signing.awaiting = if index + 1 < prev.inputs_count {
+                    Awaiting::PrevInput(input, index + 1)
+                } else {
+                    Awaiting::PrevOutput(input, 0)
+                };
+            }
+            Awaiting::PrevOutput(input, index) => {
+                let prev_output = part(3)?;
+                let prev = signing.prev.as_mut().ok_or_else(unexpected)?;
+                prev.outputs.push(TxOut {
+                    value: Amount::from_sat(prev_output.uint(1).unwrap_or_default()),
+                    script_pubkey: ScriptBuf::from_bytes(
+                        prev_output.bytes(2).unwrap_or_default().to_vec(),
+                    ),
+                });
+                if index + 1 < prev.outputs_count {
+                    signing.awaiting = Awaiting::PrevOutput(input, index + 1);
+                } else {
+                    let prev = signing.prev.take().ok_or_else(unexpected)?;
+                    signing.prev_txs.push(check_prev_tx(signing, input, prev)?);
+                    if input + 1 < signing.inputs_count {
+                        signing.awaiting = Awaiting::PrevMeta(input + 1);
+                    } else {
+                        signing.finish = self.sign(signing)?;
+                        signing.awaiting = Awaiting::Finish;
+                    }
+                }
+            }
+            Awaiting::Finish => {}
+        }
+
+        if signing.awaiting != Awaiting::Finish {
+            return Ok(signing.request());
+        }
+        let reply = signing.finish.pop_front().ok_or_else(unexpected)?;
+        if signing.finish.is_empty() {
+            state.signing = None;
+        }
+        Ok(reply)
+    }
+
+    fn output(&self, output: &ProtoMessage, network: Network) -> Result<TxOut, Failure> {
+        let script_type = output.uint(4).unwrap_or(PAY_TO_ADDRESS);
+        let script_pubkey = if script_type == PAY_TO_OP_RETURN {
+            let data = PushBytesBuf::try_from(output.bytes(6).unwrap_or_default().to_vec())
+                .map_err(data_error)?;
+            ScriptBuf::new_op_return(data)
+        } else if let Some(address) = output.string(1) {
+            Address::from_str(&address)
+                .map_err(data_error)?
+                .require_network(network)
+                .map_err(data_error)?
+                .script_pubkey()
+        } else {
+            let input_script_type = match script_type {
+                PAY_TO_ADDRESS => SPEND_ADDRESS,
+                PAY_TO_WITNESS => SPEND_WITNESS,
+                PAY_TO_P2SH_WITNESS => SPEND_P2SH_WITNESS,
+                PAY_TO_TAPROOT => SPEND_TAPROOT,
+                _ => {
+                    return Err(data_error(format!(
+                        "Unsupported script type {}",
+                        script_type
+                    )))
+                }
+            };
+            self.wallet_script_pubkey(
+                &output.uints(2),
+                input_script_type,
+                output.message(5).map_err(data_error)?.as_ref(),
+            )?
+        };
+        Ok(TxOut {
+            value: Amount::from_sat(output.uint(3).unwrap_or_default()),
+            script_pubkey,
+        })
+    }
+
+    /// Sign the inputs of the wallet and return the `TxRequest`s with the signatures
+    fn sign(&self, signing: &Signing) -> Result<VecDeque<Reply>, Failure> {
+        let mut tx = Transaction {
+            version: Version(signing.version),
+            lock_time: absolute::LockTime::from_consensus(signing.lock_time),
+            input: Vec::with_capacity(signing.inputs.len()),
+            output: Vec::with_capacity(signing.outputs.len()),
+        };
+        let mut prevouts = Vec::with_capacity(signing.inputs.len());
+        for (input, prev_tx) in signing.inputs.iter().zip(&signing.prev_txs) {
+            let vout = input.uint32(3).unwrap_or_default();
+            tx.input.push(TxIn {
+                previous_output: OutPoint {
+                    txid: prev_tx.compute_txid(),
+                    vout,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(input.uint32(5).unwrap_or(u32::MAX)),
+                witness: Witness::new(),
+            });
+            let prevout = prev_tx
+                .output
+                .get(vout as usize)
+                .ok_or_else(|| data_error(format!("Previous output {} not found", vout)))?;
+            if input.uint(8) != Some(prevout.value.to_sat()) {
+                return Err(data_error(
+                    "Input amount does not match its previous output",
+                ));
+            }
+            prevouts.push(prevout.clone());
+        }
+        for output in &signing.outputs {
+            tx.output.push(self.output(output, signing.network)?);
+        }
+
+        let mut signatures = Vec::with_capacity(tx.input.len());
+        let mut spends = Vec::with_capacity(tx.input.len());
+        let mut cache = SighashCache::new(&tx);
+        for (index, input) in signing.inputs.iter().enumerate() {
+            let script_type = input.uint(6).unwrap_or(SPEND_ADDRESS);
+            if script_type == EXTERNAL {
+                let witness = match input.bytes(13) {
+                    Some(witness) => deserialize::<Witness>(witness).map_err(data_error)?,
+                    None => Witness::new(),
+                };
+                let script_sig = ScriptBuf::from_bytes(input.bytes(4).unwrap_or_default().to_vec());
+                signatures.push(None);
+                spends.push((script_sig, witness));
+                continue;
+            }
+
+            let path = input.uints(1);
+            let multisig = input.message(7).map_err(data_error)?;
+            if prevouts[index].script_pubkey
+                != self.wallet_script_pubkey(&path, script_type, multisig.as_ref())?
+            {
+                return Err(data_error(format!("Input {} is not of the wallet", index)));
+            }
+            let (secret_key, public_key) = self.key(&path)?;
+            let compressed = CompressedPublicKey(public_key);
+            match script_type {
+                SPEND_WITNESS if multisig.is_some() => {
+                    let (witness_script, position, mut cosigner_signatures) =
+                        self.multisig(multisig.as_ref().unwrap(), &path)?;
+                    let sighash = cache
+                        .p2wsh_signature_hash(
+                            index,
+                            &witness_script,
+                            prevouts[index].value,
+                            EcdsaSighashType::All,
+                        )
+                        .map_err(data_error)?;
+                    let signature = self
+                        .secp
+                        .sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &secret_key);
+                    cosigner_signatures[position] = signature.serialize_der().to_vec();
+
+                    let mut witness = Witness::new();
+                    witness.push([]);
+                    for mut signature in cosigner_signatures.into_iter().filter(|s| !s.is_empty()) {
+                        signature.push(EcdsaSighashType::All.to_u32() as u8);
+                        witness.push(signature);
+                    }
+                    witness.push(witness_script.as_bytes());
+                    signatures.push(Some(signature.serialize_der().to_vec()));
+                    spends.push((ScriptBuf::new(), witness));
+                }
+                SPEND_TAPROOT => {
+                    let sighash = cache
+                        .taproot_key_spend_signature_hash(
+                            index,
+                            &Prevouts::All(&prevouts),
+                            TapSighashType::Default,
+                        )
+                        .map_err(data_error)?;
+                    let keypair = Keypair::from_secret_key(&self.secp, &secret_key)
+                        .tap_tweak(&self.secp, None)
+                        .to_keypair();
+                    let signature = self.secp.sign_schnorr_no_aux_rand(
+                        &Message::from_digest(sighash.to_byte_array()),
+                        &keypair,
+                    );
+                    signatures.push(Some(signature.serialize().to_vec()));
+                    spends.push((
+                        ScriptBuf::new(),
+                        Witness::p2tr_key_spend(&taproot::Signature {
+                            signature,
+                            sighash_type: TapSighashType::Default,
+                        }),
+                    ));
+                }
+                SPEND_WITNESS | SPEND_P2SH_WITNESS => {
+                    let witness_script = ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash());
+                    let sighash = cache
+                        .p2wpkh_signature_hash(
+                            index,
+                            &witness_script,
+                            prevouts[index].value,
+                            EcdsaSighashType::All,
+                        )
+                        .map_err(data_error)?;
+                    let signature = self
+                        .secp
+                        .sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &secret_key);
+                    let script_sig = if script_type == SPEND_P2SH_WITNESS {
+                        let redeem_script = PushBytesBuf::try_from(witness_script.into_bytes())
+                            .map_err(data_error)?;
+                        Builder::new().push_slice(redeem_script).into_script()
+                    } else {
+                        ScriptBuf::new()
+                    };
+                    signatures.push(Some(signature.serialize_der().to_vec()));
+                    spends.push((
+                        script_sig,
+                        Witness::p2wpkh(&ecdsa::Signature::sighash_all(signature), &public_key),
+                    ));
+                }
+                _ => {
+                    let sighash = cache
+                        .legacy_signature_hash(
+                            index,
+                            &prevouts[index].script_pubkey,
+                            EcdsaSighashType::All.to_u32(),
+                        )
+                        .map_err(data_error)?;
+                    let signature = self
+                        .secp
+                        .sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &secret_key);
+                    let script_sig = Builder::new()
+                        .push_slice(ecdsa::Signature::sighash_all(signature).serialize())
+                        .push_key(&bitcoin::PublicKey::new(public_key))
+                        .into_script();
+                    signatures.push(Some(signature.serialize_der().to_vec()));
+                    spends.push((script_sig, Witness::new()));
+                }
+            }
+        }
+
+        for (input, (script_sig, witness)) in tx.input.iter_mut().zip(spends) {
+            input.script_sig = script_sig;
+            input.witness = witness;
+        }
+        let last = signatures.len() - 1;
+        let replies = signatures
+            .into_iter()
+            .enumerate()
+            .map(|(index, signature)| {
+                let mut serialized = ProtoWriter::new();
+                if let Some(signature) = signature {
+                    serialized = serialized.uint(1, index as u64).bytes(2, &signature);
+                }
+                if index == last {
+                    serialized = serialized.bytes(3, &serialize(&tx));
+                    tx_request(TX_FINISHED, None, None, Some(serialized))
+                } else {
+                    tx_request(TX_INPUT, Some(index + 1), None, Some(serialized))
+                }
+            })
+            .collect();
+        Ok(replies)
+    }
+}
+
+fn confirm(state: &mut FakeState, reply: Reply, needs_button: bool) -> Reply {
+    if !needs_button {
+        return reply;
+    }
+    state.after_button = Some(reply);
+    (message_type::BUTTON_REQUEST, Vec::new())
+}
+
+fn network(coin_name: Option<String>) -> Result<Network, Failure> {
+    match coin_name.as_deref().unwrap_or("Bitcoin") {
+        "Bitcoin" => Ok(Network::Bitcoin),
+        "Testnet" => Ok(Network::Testnet),
+        "Regtest" => Ok(Network::Regtest),
+        coin_name => Err(data_error(format!("Unsupported coin {}", coin_name))),
+    }
+}
+
+/// Txid from its bytes in display order, as Trezor messages carry it
+fn txid(hash: &[u8]) -> Result<Txid, Failure> {
+    Txid::from_str(&hex::encode(hash)).map_err(data_error)
+}
+
+/// SLIP-21 symmetric key of `seed` at the path of `labels`
+fn slip21_key(seed: &[u8], labels: &[&[u8]]) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha512::Hash>::new(b"Symmetric key seed");
+    engine.input(seed);
+    let mut node = Hmac::from_engine(engine).to_byte_array();
+    for label in labels {
+        let mut engine = HmacEngine::<sha512::Hash>::new(&node[..32]);
+        engine.input(&[0]);
+        engine.input(label);
+        node = Hmac::from_engine(engine).to_byte_array();
+    }
+    node[32..].try_into().expect("SLIP-21 nodes are 64 bytes")
+}
+
+fn signed_message_hash(message: &[u8]) -> sha256d::Hash {
+    let mut data = b"\x18Bitcoin Signed Message:\n".to_vec();
+    data.extend(serialize(&VarInt::from(message.len())));
+    data.extend_from_slice(message);
+    sha256d::Hash::hash(&data)
+}
+
+fn start_signing(state: &mut FakeState, message: &ProtoMessage) -> Result<Reply, Failure> {
+    let inputs_count = message.uint32(2).unwrap_or_default() as usize;
+    let outputs_count = message.uint32(1).unwrap_or_default() as usize;
+    if inputs_count == 0 || outputs_count == 0 {
+        return Err(data_error("Transaction has no inputs or no outputs"));
+    }
+    let signing = Signing {
+        network: network(message.string(3))?,
+        version: message.uint32(4).unwrap_or(1) as i32,
+        lock_time: message.uint32(5).unwrap_or_default(),
+        inputs_count,
+        outputs_count,
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        prev: None,
+        prev_txs: Vec::new(),
+        awaiting: Awaiting::Input(0),
+        finish: VecDeque::new(),
+    };
+    let reply = signing.request();
+    state.signing = Some(signing);
+    Ok(reply)
+}
+
+/// Previous transaction of an input, once its hash and the amount of the input are checked
+fn check_prev_tx(signing: &Signing, input: usize, prev: PrevTx) -> Result<Transaction, Failure> {
+    let tx = Transaction {
+        version: Version(prev.version),
+        lock_time: absolute::LockTime::from_consensus(prev.lock_time),
+        input: prev.inputs,
+        output: prev.outputs,
+    };
+    if tx.compute_txid() != txid(&signing.prev_hash(input))? {
+        return Err(data_error("Encountered invalid prevhash"));
+    }
+    Ok(tx)
+}
+
+#[async_trait]
+impl TrezorBridgeTransport for FakeTrezorBridge {
+    async fn post(&self, path: &str, body: &str) -> TrezorConnectResult<String> {
+        let mut state = self.state.lock().unwrap();
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match segments.as_slice() {
+            [""] => Ok(json!({ "version": FAKE_BRIDGE_VERSION }).to_string()),
+            ["enumerate"] => Ok(json!([{
+                "path": FAKE_TREZOR_DEVICE_PATH,
+                "session": state.session,
+                "vendor": 4617,
+                "product": 21441,
+                "debug": false,
+            }])
+            .to_string()),
+            ["acquire", device_path, previous] => {
+                if *device_path != FAKE_TREZOR_DEVICE_PATH {
+                    return Err(refused("device not found"));
+                }
+                if state.session.as_deref().unwrap_or("null") != *previous {
+                    return Err(refused("wrong previous session"));
+                }
+                state.sessions_opened += 1;
+                let session = state.sessions_opened.to_string();
+                state.session = Some(session.clone());
+                state.after_button = None;
+                state.signing = None;
+                Ok(json!({ "session": session }).to_string())
+            }
+            ["release", session] => {
+                if state.session.as_deref() != Some(*session) {
+                    return Err(refused("session not found"));
+                }
+                state.session = None;
+                Ok("{}".to_string())
+            }
+            ["call", session] => {
+                if state.session.as_deref() != Some(*session) {
+                    return Err(refused("wrong session"));
+                }
+                let frame = hex::decode(body).map_err(|e| refused(&e.to_string()))?;
+                let (message_type, payload) = decode_frame(&frame)?;
+                let message = ProtoMessage::decode(payload)?;
+                state.messages.push(message_type);
+                let (reply_type, reply) = self.handle(&mut state, message_type, &message);
+                Ok(hex::encode(encode_frame(reply_type, &reply)?))
+            }
+            _ => Err(refused("not found")),
+        }
+    }
+}
@@ -3,16 +3,19 @@
 //! A Rust library for generating Trezor Connect deep links according to the
 //! Trezor Suite Lite deep linking specification.
 
-use crate::modules::trezor::types::{Empty, TrezorEnvironment};
+use crate::modules::trezor::types::{Empty, TrezorEndpointSettings, TrezorEnvironment};
 use crate::modules::trezor::{
     AccountInfoResponse, AddressResponse, ComposeTransactionParams, ComposeTransactionResponse,
-    DeepLinkResult, FeatureResponse, GetAccountInfoParams, GetAddressParams, GetPublicKeyParams,
-    MessageSignatureResponse, PublicKeyResponse, SignMessageParams, SignTransactionParams,
+    DeepLinkResult, FeatureResponse, GetAccountInfoParams, GetAddressParams, GetOwnershipIdParams,
+    GetOwnershipProofParams, GetPublicKeyParams, MessageSignatureResponse, OwnershipIdResponse,
+    OwnershipProofResponse, PublicKeyResponse, SignMessageParams, SignTransactionParams,
     SignedTransactionResponse, TrezorConnectClient, TrezorConnectError, TrezorConnectResult,
-    TrezorResponse, TrezorResponsePayload, VerifyMessageParams, VerifyMessageResponse,
+    TrezorFailureKind, TrezorRequestRegistry, TrezorResponse, TrezorResponsePayload,
+    VerifyMessageParams, VerifyMessageResponse,
 };
 use serde::Serialize;
 use serde_json;
+use std::sync::Arc;
 use url::Url;
 use uuid::Uuid;
 
@@ -50,9 +53,23 @@ impl TrezorConnectClient {
         Ok(Self {
             environment,
             callback_base,
+            registry: None,
+            endpoints: TrezorEndpointSettings::default(),
         })
     }
 
+    /// Use the deep link endpoints of `endpoints` instead of the built-in ones
+    pub fn with_endpoints(mut self, endpoints: TrezorEndpointSettings) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Record every request in `registry`, so callbacks can be matched to their request
+    pub fn with_registry(mut self, registry: Arc<TrezorRequestRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
     /// Generate a deep link for a Trezor Connect method with a specific request ID
     fn generate_deep_link<T: Serialize>(
         &self,
@@ -63,8 +80,8 @@ impl TrezorConnectClient {
         // Serialize params to JSON
         let params_json = serde_json::to_string(&params)?;
 
-        // Get base URL directly without re-parsing
-        let mut url = self.environment.base_url()?;
+        // Get the base URL of the environment
+        let mut url = self.endpoints.base_url(self.environment)?;
 
         // Generate a UUID if request_id is None
         let id = request_id
@@ -81,6 +98,10 @@ impl TrezorConnectClient {
             .append_pair("params", &params_json)
             .append_pair("callback", callback_url.as_ref());
 
+        if let Some(registry) = &self.registry {
+            registry.record(&id, method, &params_json)?;
+        }
+
         Ok(DeepLinkResult {
             url: url.to_string(),
             request_id: id,
@@ -102,7 +123,6 @@ impl TrezorConnectClient {
     }
 
     /// Get public key for the specified path
-    #[allow(dead_code)] // Exposed for future FFI use
     pub fn get_public_key(
         &self,
         params: GetPublicKeyParams,
@@ -183,12 +203,37 @@ impl TrezorConnectClient {
     ) -> TrezorConnectResult<DeepLinkResult> {
         self.generate_deep_link("signTransaction", params, request_id)
     }
+
+    /// Get the SLIP-19 ownership id of the script pubkey of the given BIP32 path
+    ///
+    /// The ownership id identifies the scripts of the wallet without revealing its keys.
+    pub fn get_ownership_id(
+        &self,
+        params: GetOwnershipIdParams,
+        request_id: Option<String>,
+    ) -> TrezorConnectResult<DeepLinkResult> {
+        self.generate_deep_link("getOwnershipId", params, request_id)
+    }
+
+    /// Get a SLIP-19 proof of ownership of the script pubkey of the given BIP32 path
+    ///
+    /// The proof is signed with the key of the path and commits to the ownership ids and the
+    /// commitment data. With `userConfirmation`, the user confirms it on the Trezor device.
+    pub fn get_ownership_proof(
+        &self,
+        params: GetOwnershipProofParams,
+        request_id: Option<String>,
+    ) -> TrezorConnectResult<DeepLinkResult> {
+        self.generate_deep_link("getOwnershipProof", params, request_id)
+    }
 }
 
 /// Handle a callback URL from Trezor
 ///
 /// This method should be called when your application receives a callback from Trezor,
-/// typically from your app's deep link handler.
+/// typically from your app's deep link handler. The payload is deserialized according to the
+/// `method` query parameter; use `TrezorRequestRegistry::handle_callback` to match the
+/// callback to the request it answers instead.
 ///
 /// # Arguments
 /// * `callback_url` - The callback URL that was opened by Trezor
@@ -198,29 +243,42 @@ impl TrezorConnectClient {
 pub fn handle_deep_link<S: AsRef<str>>(
     callback_url: S,
 ) -> TrezorConnectResult<TrezorResponsePayload> {
+    let callback = parse_callback(callback_url.as_ref())?;
+    let payload = callback.response.into_payload()?;
+    parse_payload(callback.method.as_deref().unwrap_or_default(), payload)
+}
+
+/// Query parameters of a callback URL from Trezor
+pub(crate) struct TrezorCallback {
+    /// ID of the request the callback answers
+    pub id: String,
+    /// Method named by the callback, if any
+    pub method: Option<String>,
+    pub response: TrezorResponse,
+}
+
+/// Parse a callback URL from Trezor into its request ID, method and response
+pub(crate) fn parse_callback(callback_url: &str) -> TrezorConnectResult<TrezorCallback> {
     // Parse the URL
-    let parsed_url =
-        Url::parse(callback_url.as_ref()).map_err(|e| TrezorConnectError::UrlError {
-            error_details: format!("Failed to parse callback URL: {}", e),
-        })?;
+    let parsed_url = Url::parse(callback_url).map_err(|e| TrezorConnectError::UrlError {
+        error_details: format!("Failed to parse callback URL: {}", e),
+    })?;
+    let query_param = |name: &str| {
+        parsed_url
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
 
     // Extract the request ID from the query parameters
-    let _id = parsed_url
-        .query_pairs()
-        .find(|(key, _)| key == "id")
-        .map(|(_, value)| value.to_string())
-        .ok_or_else(|| TrezorConnectError::Other {
-            error_details: "Missing 'id' parameter in callback URL".to_string(),
-        })?;
+    let id = query_param("id").ok_or_else(|| TrezorConnectError::Other {
+        error_details: "Missing 'id' parameter in callback URL".to_string(),
+    })?;
 
     // Extract the response parameter
-    let response_param = parsed_url
-        .query_pairs()
-        .find(|(key, _)| key == "response")
-        .map(|(_, value)| value.to_string())
-        .ok_or_else(|| TrezorConnectError::Other {
-            error_details: "Missing 'response' parameter in callback URL".to_string(),
-        })?;
+    let response_param = query_param("response").ok_or_else(|| TrezorConnectError::Other {
+        error_details: "Missing 'response' parameter in callback URL".to_string(),
+    })?;
 
     // Parse the response JSON
     let response: TrezorResponse =
@@ -228,86 +286,123 @@ pub fn handle_deep_link<S: AsRef<str>>(
             error_details: format!("Failed to parse response JSON: {}", e),
         })?;
 
-    // Check if there was an error
-    if !response.success {
-        return Err(TrezorConnectError::Other {
-            error_details: response
-                .error
-                .unwrap_or_else(|| "Unknown error from Trezor".to_string()),
-        });
-    }
+    Ok(TrezorCallback {
+        id,
+        method: query_param("method"),
+        response,
+    })
+}
 
-    // Get the payload or return an error
-    let payload = response.payload.ok_or_else(|| TrezorConnectError::Other {
-        error_details: "Success response but no payload".to_string(),
-    })?;
+impl TrezorResponse {
+    /// Returns the payload of a successful response, or the error Trezor reported
+    pub(crate) fn into_payload(self) -> TrezorConnectResult<serde_json::Value> {
+        // Check if there was an error, reported by Connect as `{ error, code }` in the payload
+        if !self.success {
+            let field = |name: &str| {
+                self.payload
+                    .as_ref()
+                    .and_then(|payload| payload.get(name))
+                    .and_then(|value| value.as_str())
+                    .map(str::to_string)
+            };
+            let code = field("code");
+            return Err(TrezorConnectError::Failure {
+                kind: code
+                    .as_deref()
+                    .map_or(TrezorFailureKind::Unknown, TrezorFailureKind::from_code),
+                error_details: field("error")
+                    .or(self.error)
+                    .unwrap_or_else(|| "Unknown error from Trezor".to_string()),
+                code,
+            });
+        }
 
-    // Extract the method from the URL parameters to help with deserialization
-    let method = parsed_url
-        .query_pairs()
-        .find(|(key, _)| key == "method")
-        .map(|(_, value)| value.to_string());
+        // Get the payload or return an error
+        self.payload.ok_or_else(|| TrezorConnectError::Other {
+            error_details: "Success response but no payload".to_string(),
+        })
+    }
+}
 
+/// Deserialize the payload of a response to `method` into the matching type
+pub(crate) fn parse_payload(
+    method: &str,
+    payload: serde_json::Value,
+) -> TrezorConnectResult<TrezorResponsePayload> {
     // Try to deserialize into the appropriate type based on the method
-    match method.as_deref() {
-        Some("getFeatures") => {
+    match method {
+        "getFeatures" => {
             let features: FeatureResponse =
                 serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse Features response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::Features(features))
         }
-        Some("getAddress") => {
+        "getAddress" => {
             let address: AddressResponse =
                 serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse Address response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::Address(address))
         }
-        Some("getPublicKey") => {
+        "getPublicKey" => {
             let public_key: PublicKeyResponse =
                 serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse PublicKey response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::PublicKey(public_key))
         }
-        Some("getAccountInfo") => {
+        "getAccountInfo" => {
             let account_info: AccountInfoResponse =
                 serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse AccountInfo response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::AccountInfo(account_info))
         }
-        Some("composeTransaction") => {
+        "composeTransaction" => {
             let compose_tx: ComposeTransactionResponse =
                 serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse ComposeTransaction response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::ComposeTransaction(compose_tx))
         }
-        Some("verifyMessage") => {
+        "verifyMessage" => {
             let verify_message: VerifyMessageResponse =
                 serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse VerifyMessage response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::VerifyMessage(verify_message))
         }
-        Some("signMessage") => {
+        "signMessage" => {
             let message_signature: MessageSignatureResponse = serde_json::from_value(payload)
                 .map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse MessageSignature response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::MessageSignature(message_signature))
         }
-        Some("signTransaction") => {
+        "signTransaction" => {
             let signed_tx: SignedTransactionResponse =
                 serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
                     error_details: format!("Failed to parse SignedTransaction response: {}", e),
                 })?;
             Ok(TrezorResponsePayload::SignedTransaction(signed_tx))
         }
+        "getOwnershipId" => {
+            let ownership_id: OwnershipIdResponse =
+                serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
+                    error_details: format!("Failed to parse OwnershipId response: {}", e),
+                })?;
+            Ok(TrezorResponsePayload::OwnershipId(ownership_id))
+        }
+        "getOwnershipProof" => {
+            let ownership_proof: OwnershipProofResponse =
+                serde_json::from_value(payload).map_err(|e| TrezorConnectError::SerdeError {
+                    error_details: format!("Failed to parse OwnershipProof response: {}", e),
+                })?;
+            Ok(TrezorResponsePayload::OwnershipProof(ownership_proof))
+        }
         _ => Err(TrezorConnectError::Other {
-            error_details: format!("Unknown or unsupported method: {:?}", method).to_string(),
+            error_details: format!("Unknown or unsupported method: {:?}", method),
         }),
     }
 }
@@ -1,9 +1,34 @@
+mod account;
+mod activity;
+mod bridge;
+mod compose;
 mod errors;
+#[cfg(test)]
+mod fake_bridge;
 mod implementation;
+mod multisig;
+mod ownership;
+mod protobuf;
+mod psbt;
+mod registry;
+mod settings;
+mod storage;
 #[cfg(test)]
 mod tests;
 mod types;
 
+pub use account::*;
+pub use activity::*;
+pub use bridge::*;
+pub use compose::*;
 pub use errors::*;
+#[cfg(test)]
+pub use fake_bridge::*;
 pub use implementation::*;
+pub use multisig::*;
+pub use ownership::*;
+pub use psbt::*;
+pub use registry::*;
+pub use settings::*;
+pub use storage::*;
 pub use types::*;
@@ -0,0 +1,688 @@
+//! Multisig wallets coordinated across Trezor devices.
+//!
+//! A wallet is the `wsh(sortedmulti(m, ...))` of the account xpubs of its cosigners,
+//! registered from their `getPublicKey` responses or from a descriptor, and derives its
+//! addresses locally. Each device signs the PSBT with the `multisig` field of the wallet
+//! inputs and change outputs, built from the cosigner xpubs and the signatures collected so
+//! far. Once the signatures of enough devices are merged, the inputs are finalized and the
+//! transaction extracted.
+
+use crate::modules::trezor::{
+    coin_network, descriptor_master_fingerprint, open_trezor_db, parse_fingerprint, serialize_path,
+    sign_transaction_params, spent_output, HDNodePathType, HDNodeTypeOrString,
+    MultisigRedeemScriptType, PublicKeyResponse, SignTransactionParams, TrezorConnectError,
+    TrezorConnectResult, TrezorDbConnection,
+};
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource, Xpub};
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::OP_CHECKMULTISIG;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use bitcoin::sighash::SighashCache;
+use bitcoin::{ecdsa, Address, NetworkKind, Psbt, Script, ScriptBuf, Transaction, Witness};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Most keys of a multisig Trezor signs for
+const MAX_COSIGNERS: usize = 15;
+
+/// `pubkeys_order` of the keys of `sortedmulti`
+const PUBKEYS_ORDER_LEXICOGRAPHIC: u8 = 1;
+
+const CREATE_TREZOR_MULTISIG_WALLETS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS trezor_multisig_wallets (
+        name TEXT PRIMARY KEY,
+        coin TEXT NOT NULL,
+        threshold INTEGER NOT NULL,
+        cosigners TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    )";
+
+/// Characters of descriptors, in the order of the BIP380 checksum
+const DESCRIPTOR_INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+const DESCRIPTOR_CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn multisig_error(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::MultisigError { error_details }
+}
+
+fn descriptor_polymod(c: u64, value: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ value;
+    for (bit, generator) in [
+        0xf5_dee5_1989,
+        0xa9_fdca_3312,
+        0x1b_ab10_e32d,
+        0x37_06b1_677a,
+        0x64_4d62_6ffd,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if c0 & (1 << bit) != 0 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+/// BIP380 checksum of a descriptor, `None` if it has characters descriptors cannot have
+pub(crate) fn descriptor_checksum(descriptor: &str) -> Option<String> {
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = DESCRIPTOR_INPUT_CHARSET.find(ch)? as u64;
+        c = descriptor_polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = descriptor_polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = descriptor_polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = descriptor_polymod(c, 0);
+    }
+    c ^= 1;
+    Some(
+        (0..8)
+            .map(|i| DESCRIPTOR_CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+            .collect(),
+    )
+}
+
+/// `OP_CHECKMULTISIG` script of `threshold` of `keys`, in the given order
+pub(crate) fn multisig_witness_script(threshold: u32, keys: &[PublicKey]) -> ScriptBuf {
+    let builder = keys.iter().fold(
+        Builder::new().push_int(i64::from(threshold)),
+        |builder, key| builder.push_slice(key.serialize()),
+    );
+    builder
+        .push_int(keys.len() as i64)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script()
+}
+
+fn parse_xpub(xpub: &str) -> TrezorConnectResult<Xpub> {
+    Xpub::from_str(xpub).map_err(|e| multisig_error(format!("Invalid xpub {}: {}", xpub, e)))
+}
+
+/// Cosigner of a multisig wallet: the account xpub of one device and its origin
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TrezorMultisigCosigner {
+    /// Extended public key of the multisig account of the cosigner
+    pub xpub: String,
+    /// Fingerprint of the master key of the cosigner, like `73c5da0a`
+    pub master_fingerprint: String,
+    /// BIP32 path of the account, usually the BIP48 `m/48'/0'/0'/2'`
+    pub path: Vec<u32>,
+    /// Serialized BIP32 path of the account
+    pub serialized_path: String,
+}
+
+impl TrezorMultisigCosigner {
+    /// Create a cosigner from the `getPublicKey` response of its device. The master
+    /// fingerprint is taken from the descriptor of the response when not set.
+    pub fn from_public_key(
+        response: &PublicKeyResponse,
+        master_fingerprint: Option<String>,
+    ) -> TrezorConnectResult<Self> {
+        let master_fingerprint = master_fingerprint
+            .or_else(|| {
+                response
+                    .descriptor
+                    .as_deref()
+                    .and_then(descriptor_master_fingerprint)
+            })
+            .ok_or_else(|| {
+                multisig_error(format!(
+                    "No master fingerprint for cosigner {}",
+                    response.serializedPath
+                ))
+            })?;
+        Self::new(&response.xpub, &master_fingerprint, response.path.clone())
+    }
+
+    /// Parse the key expression of a cosigner in a descriptor, like
+    /// `[73c5da0a/48'/0'/0'/2']xpub.../<0;1>/*`
+    pub fn from_key_expression(key: &str) -> TrezorConnectResult<Self> {
+        let invalid = || multisig_error(format!("Invalid cosigner key {}", key));
+        let (origin, xpub) = key
+            .trim()
+            .strip_prefix('[')
+            .and_then(|key| key.split_once(']'))
+            .ok_or_else(invalid)?;
+        let xpub = ["/<0;1>/*", "/0/*"]
+            .iter()
+            .find_map(|suffix| xpub.strip_suffix(suffix))
+            .unwrap_or(xpub);
+        let (master_fingerprint, path) = match origin.split_once('/') {
+            Some((master_fingerprint, path)) => (
+                master_fingerprint,
+                DerivationPath::from_str(&format!("m/{}", path)).map_err(|_| invalid())?,
+            ),
+            None => (origin, DerivationPath::master()),
+        };
+        let path = path.into_iter().map(|child| u32::from(*child)).collect();
+        Self::new(xpub, master_fingerprint, path)
+    }
+
+    fn new(xpub: &str, master_fingerprint: &str, path: Vec<u32>) -> TrezorConnectResult<Self> {
+        if usize::from(parse_xpub(xpub)?.depth) != path.len() {
+            return Err(multisig_error(format!(
+                "xpub {} is not the key of {}",
+                xpub,
+                serialize_path(&path)
+            )));
+        }
+        Ok(Self {
+            xpub: xpub.to_string(),
+            master_fingerprint: parse_fingerprint(master_fingerprint)?.to_string(),
+            serialized_path: serialize_path(&path),
+            path,
+        })
+    }
+
+    /// Key expression of the cosigner in the descriptor of the wallet
+    pub fn key_expression(&self) -> String {
+        format!(
+            "[{}{}]{}/<0;1>/*",
+            self.master_fingerprint,
+            self.serialized_path.trim_start_matches('m'),
+            self.xpub
+        )
+    }
+
+    /// Change and index of the address a BIP32 derivation of the cosigner is for
+    fn address_of(&self, (fingerprint, path): &KeySource) -> Option<(bool, u32)> {
+        if fingerprint.to_string() != self.master_fingerprint {
+            return None;
+        }
+        let path: Vec<u32> = path.into_iter().map(|child| u32::from(*child)).collect();
+        match path.strip_prefix(self.path.as_slice())? {
+            [0, index] => Some((false, *index)),
+            [1, index] => Some((true, *index)),
+            _ => None,
+        }
+    }
+}
+
+/// Multisig wallet of Trezor cosigners, spending `wsh(sortedmulti(threshold, ...))` outputs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TrezorMultisigWallet {
+    /// Name the wallet is stored under
+    pub name: String,
+    /// Coin of the wallet, like `btc` or `test`
+    pub coin: String,
+    /// Number of signatures needed to spend
+    pub threshold: u32,
+    pub cosigners: Vec<TrezorMultisigCosigner>,
+}
+
+/// Address derived from a multisig wallet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TrezorMultisigAddress {
+    pub address: String,
+    /// Hex encoded witness script of the address
+    pub witness_script: String,
+    pub change: bool,
+    pub index: u32,
+}
+
+impl TrezorMultisigWallet {
+    /// Create a `threshold` of `cosigners` wallet for `coin`
+    pub fn new(
+        name: &str,
+        coin: &str,
+        threshold: u32,
+        cosigners: Vec<TrezorMultisigCosigner>,
+    ) -> TrezorConnectResult<Self> {
+        let network = coin_network(coin)?;
+        if cosigners.is_empty() || cosigners.len() > MAX_COSIGNERS {
+            return Err(multisig_error(format!(
+                "A multisig wallet has 1 to {} cosigners, not {}",
+                MAX_COSIGNERS,
+                cosigners.len()
+            )));
+        }
+        if threshold == 0 || threshold as usize > cosigners.len() {
+            return Err(multisig_error(format!(
+                "Threshold {} is not between 1 and the {} cosigners",
+                threshold,
+                cosigners.len()
+            )));
+        }
+        for (index, cosigner) in cosigners.iter().enumerate() {
+            if parse_xpub(&cosigner.xpub)?.network != NetworkKind::from(network) {
+                return Err(multisig_error(format!(
+                    "xpub {} is not for coin {}",
+                    cosigner.xpub, coin
+                )));
+            }
+            if cosigners[..index].iter().any(|c| c.xpub == cosigner.xpub) {
+                return Err(multisig_error(format!(
+                    "xpub {} is registered twice",
+                    cosigner.xpub
+                )));
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            coin: coin.to_string(),
+            threshold,
+            cosigners,
+        })
+    }
+
+    /// Create a wallet from a `wsh(sortedmulti(...))` descriptor. Its checksum, if any,
+    /// must be valid.
+    pub fn from_descriptor(name: &str, coin: &str, descriptor: &str) -> TrezorConnectResult<Self> {
+        let descriptor = descriptor.trim();
+        let body = match descriptor.split_once('#') {
+            Some((body, checksum)) => {
+                if descriptor_checksum(body).as_deref() != Some(checksum) {
+                    return Err(multisig_error(format!(
+                        "Invalid checksum of descriptor {}",
+                        descriptor
+                    )));
+                }
+                body
+            }
+            None => descriptor,
+        };
+        let args = body
+            .strip_prefix("wsh(sortedmulti(")
+            .and_then(|body| body.strip_suffix("))"))
+            .ok_or_else(|| {
+                multisig_error(format!(
+                    "Descriptor {} is not a wsh(sortedmulti(...))",
+                    descriptor
+                ))
+            })?;
+        let mut args = args.split(',');
+        let threshold = args
+            .next()
+            .and_then(|threshold| threshold.trim().parse().ok())
+            .ok_or_else(|| {
+                multisig_error(format!("Invalid threshold in descriptor {}", descriptor))
+            })?;
+        let cosigners = args
+            .map(TrezorMultisigCosigner::from_key_expression)
+            .collect::<TrezorConnectResult<Vec<_>>>()?;
+        Self::new(name, coin, threshold, cosigners)
+    }
+
+    /// Descriptor of the receive and change addresses, with its checksum
+    pub fn descriptor(&self) -> String {
+        let keys: Vec<String> = self
+            .cosigners
+            .iter()
+            .map(TrezorMultisigCosigner::key_expression)
+            .collect();
+        let descriptor = format!("wsh(sortedmulti({},{}))", self.threshold, keys.join(","));
+        match descriptor_checksum(&descriptor) {
+            Some(checksum) => format!("{}#{}", descriptor, checksum),
+            None => descriptor,
+        }
+    }
+
+    /// Keys of the cosigners at the receive or change address `index`, with their cosigner
+    /// xpub, in `sortedmulti` order
+    fn sorted_keys(
+        &self,
+        change: bool,
+        index: u32,
+    ) -> TrezorConnectResult<Vec<(PublicKey, &TrezorMultisigCosigner)>> {
+        let secp = Secp256k1::verification_only();
+        let children = [
+            ChildNumber::from_normal_idx(u32::from(change))
+                .map_err(|e| multisig_error(e.to_string()))?,
+            ChildNumber::from_normal_idx(index)
+                .map_err(|e| multisig_error(format!("Invalid address index {}: {}", index, e)))?,
+        ];
+        let mut keys = self
+            .cosigners
+            .iter()
+            .map(|cosigner| {
+                let key = parse_xpub(&cosigner.xpub)?
+                    .derive_pub(&secp, &children)
+                    .map_err(|e| multisig_error(e.to_string()))?;
+                Ok((key.public_key, cosigner))
+            })
+            .collect::<TrezorConnectResult<Vec<_>>>()?;
+        keys.sort_by_key(|(key, _)| key.serialize());
+        Ok(keys)
+    }
+
+    fn witness_script(&self, change: bool, index: u32) -> TrezorConnectResult<ScriptBuf> {
+        let keys: Vec<PublicKey> = self
+            .sorted_keys(change, index)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        Ok(multisig_witness_script(self.threshold, &keys))
+    }
+
+    /// Derive the receive or change address at `index`
+    pub fn derive_address(
+        &self,
+        change: bool,
+        index: u32,
+    ) -> TrezorConnectResult<TrezorMultisigAddress> {
+        let witness_script = self.witness_script(change, index)?;
+        let address = Address::p2wsh(&witness_script, coin_network(&self.coin)?);
+        Ok(TrezorMultisigAddress {
+            address: address.to_string(),
+            witness_script: witness_script.to_hex_string(),
+            change,
+            index,
+        })
+    }
+
+    /// Derive `count` receive or change addresses from `start_index`
+    pub fn derive_addresses(
+        &self,
+        change: bool,
+        start_index: u32,
+        count: u32,
+    ) -> TrezorConnectResult<Vec<TrezorMultisigAddress>> {
+        (0..count)
+            .map(|offset| {
+                let index = start_index.checked_add(offset).ok_or_else(|| {
+                    multisig_error(format!(
+                        "Address index {} + {} overflows",
+                        start_index, offset
+                    ))
+                })?;
+                self.derive_address(change, index)
+            })
+            .collect()
+    }
+
+    /// Change and index of the address of the wallet paying to `script_pubkey`, found from
+    /// the BIP32 derivations of its input or output
+    fn script_address(
+        &self,
+        script_pubkey: &Script,
+        bip32_derivation: &BTreeMap<PublicKey, KeySource>,
+    ) -> TrezorConnectResult<Option<(bool, u32)>> {
+        if !script_pubkey.is_p2wsh() {
+            return Ok(None);
+        }
+        let Some((change, index)) = bip32_derivation.values().find_map(|source| {
+            self.cosigners
+                .iter()
+                .find_map(|cosigner| cosigner.address_of(source))
+        }) else {
+            return Ok(None);
+        };
+        let witness_script = self.witness_script(change, index)?;
+        Ok(
+            (ScriptBuf::new_p2wsh(&witness_script.wscript_hash()) == *script_pubkey)
+                .then_some((change, index)),
+        )
+    }
+
+    /// `multisig` field of the input or output of the wallet paying to `script_pubkey`, with
+    /// the signatures of `partial_sigs`. `None` when it is not of the wallet.
+    pub(crate) fn script_multisig(
+        &self,
+        script_pubkey: &Script,
+        bip32_derivation: &BTreeMap<PublicKey, KeySource>,
+        partial_sigs: &BTreeMap<bitcoin::PublicKey, ecdsa::Signature>,
+    ) -> TrezorConnectResult<Option<MultisigRedeemScriptType>> {
+        let Some((change, index)) = self.script_address(script_pubkey, bip32_derivation)? else {
+            return Ok(None);
+        };
+        let keys = self.sorted_keys(change, index)?;
+        Ok(Some(MultisigRedeemScriptType {
+            pubkeys: keys
+                .iter()
+                .map(|(_, cosigner)| HDNodePathType {
+                    node: HDNodeTypeOrString::String(cosigner.xpub.clone()),
+                    address_n: vec![u32::from(change), index],
+                })
+                .collect(),
+            signatures: keys
+                .iter()
+                .map(|(key, _)| {
+                    partial_sigs
+                        .get(&bitcoin::PublicKey::new(*key))
+                        .map(|signature| hex::encode(signature.signature.serialize_der()))
+                        .unwrap_or_default()
+                })
+                .collect(),
+            m: self.threshold,
+            nodes: None,
+            pubkeys_order: Some(PUBKEYS_ORDER_LEXICOGRAPHIC),
+        }))
+    }
+
+    /// Convert a PSBT spending from the wallet to `signTransaction` parameters for the
+    /// cosigner with `master_fingerprint`.
+    ///
+    /// Inputs and change outputs of the wallet get their `multisig` field, with the
+    /// signatures of the other cosigners already in the PSBT. Other inputs and outputs are
+    /// converted like `psbt_to_sign_transaction_params` does.
+    pub fn sign_transaction_params(
+        &self,
+        psbt: &Psbt,
+        prev_txs: &[Transaction],
+        master_fingerprint: Fingerprint,
+    ) -> TrezorConnectResult<SignTransactionParams> {
+        if !self
+            .cosigners
+            .iter()
+            .any(|cosigner| cosigner.master_fingerprint == master_fingerprint.to_string())
+        {
+            return Err(multisig_error(format!(
+                "{} is not a cosigner of wallet {}",
+                master_fingerprint, self.name
+            )));
+        }
+        sign_transaction_params(
+            psbt,
+            prev_txs,
+            &self.coin,
+            Some(master_fingerprint),
+            Some(self),
+        )
+    }
+
+    /// Combine the PSBTs signed by the cosigners and finalize it into the signed
+    /// transaction.
+    ///
+    /// Every input must be already finalized or be of the wallet with at least `threshold`
+    /// valid partial signatures. Extra signatures are dropped.
+    pub fn finalize_psbt(&self, psbts: &[Psbt]) -> TrezorConnectResult<Transaction> {
+        let (first, others) = psbts
+            .split_first()
+            .ok_or_else(|| multisig_error("No PSBT to finalize".to_string()))?;
+        let mut psbt = first.clone();
+        for other in others {
+            psbt.combine(other.clone())
+                .map_err(|e| multisig_error(format!("Cannot combine PSBTs: {}", e)))?;
+        }
+
+        let secp = Secp256k1::verification_only();
+        let mut witnesses = Vec::new();
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            if input.final_script_witness.is_some() || input.final_script_sig.is_some() {
+                continue;
+            }
+            let spent = spent_output(&psbt, index, &HashMap::new())?;
+            let (change, address_index) = self
+                .script_address(&spent.script_pubkey, &input.bip32_derivation)?
+                .ok_or_else(|| {
+                    multisig_error(format!(
+                        "Input {} is not finalized and not of wallet {}",
+                        index, self.name
+                    ))
+                })?;
+            let keys = self.sorted_keys(change, address_index)?;
+            let witness_script = self.witness_script(change, address_index)?;
+
+            let mut signatures = Vec::new();
+            for (key, _) in &keys {
+                let Some(signature) = input.partial_sigs.get(&bitcoin::PublicKey::new(*key)) else {
+                    continue;
+                };
+                let sighash = cache
+                    .p2wsh_signature_hash(
+                        index,
+                        &witness_script,
+                        spent.value,
+                        signature.sighash_type,
+                    )
+                    .map_err(|e| multisig_error(e.to_string()))?;
+                secp.verify_ecdsa(
+                    &Message::from_digest(sighash.to_byte_array()),
+                    &signature.signature,
+                    key,
+                )
+                .map_err(|e| {
+                    multisig_error(format!(
+                        "Invalid signature of {} for input {}: {}",
+                        key, index, e
+                    ))
+                })?;
+                signatures.push(signature.to_vec());
+            }
+            if signatures.len() < self.threshold as usize {
+                return Err(multisig_error(format!(
+                    "Input {} has {} of the {} signatures needed",
+                    index,
+                    signatures.len(),
+                    self.threshold
+                )));
+            }
+
+            let mut witness = Witness::new();
+            witness.push([]);
+            for signature in signatures.iter().take(self.threshold as usize) {
+                witness.push(signature);
+            }
+            witness.push(witness_script.as_bytes());
+            witnesses.push((index, witness));
+        }
+
+        for (index, witness) in witnesses {
+            let input = &mut psbt.inputs[index];
+            input.final_script_witness = Some(witness);
+            input.partial_sigs.clear();
+            input.bip32_derivation.clear();
+            input.witness_script = None;
+            input.sighash_type = None;
+        }
+        Ok(psbt.extract_tx_unchecked_fee_rate())
+    }
+}
+
+/// Persistent store of the multisig wallets of Trezor cosigners
+#[derive(Debug)]
+pub struct TrezorMultisigStore {
+    conn: TrezorDbConnection,
+}
+
+impl TrezorMultisigStore {
+    /// Open the store at `db_path`, creating it if needed. Use `:memory:` for a store that is
+    /// not persisted.
+    pub fn new(db_path: &str) -> TrezorConnectResult<Self> {
+        Self::with_connection(open_trezor_db(db_path)?)
+    }
+
+    /// Open the store on a connection shared with the other Trezor stores
+    pub fn with_connection(conn: TrezorDbConnection) -> TrezorConnectResult<Self> {
+        conn.lock()
+            .unwrap()
+            .execute(CREATE_TREZOR_MULTISIG_WALLETS_TABLE, [])?;
+        Ok(Self { conn })
+    }
+
+    /// Delete all stored wallets
+    pub fn wipe_all(&self) -> TrezorConnectResult<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM trezor_multisig_wallets", [])?;
+        Ok(())
+    }
+
+    /// Store a wallet, replacing the wallet with the same name
+    pub fn save(&self, wallet: &TrezorMultisigWallet) -> TrezorConnectResult<()> {
+        let created_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO trezor_multisig_wallets
+             (name, coin, threshold, cosigners, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                wallet.name,
+                wallet.coin,
+                wallet.threshold,
+                serde_json::to_string(&wallet.cosigners)?,
+                created_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Stored wallets, in registration order
+    pub fn wallets(&self) -> TrezorConnectResult<Vec<TrezorMultisigWallet>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT name, coin, threshold, cosigners
+             FROM trezor_multisig_wallets ORDER BY created_at, rowid",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(name, coin, threshold, cosigners)| {
+                Ok(TrezorMultisigWallet {
+                    name,
+                    coin,
+                    threshold,
+                    cosigners: serde_json::from_str(&cosigners)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Stored wallet with the given name
+    pub fn wallet(&self, name: &str) -> TrezorConnectResult<TrezorMultisigWallet> {
+        self.wallets()?
+            .into_iter()
+            .find(|wallet| wallet.name == name)
+            .ok_or_else(|| multisig_error(format!("No multisig wallet named {}", name)))
+    }
+
+    /// Remove a wallet. Returns whether it was stored.
+    pub fn remove(&self, name: &str) -> TrezorConnectResult<bool> {
+        let removed = self.conn.lock().unwrap().execute(
+            "DELETE FROM trezor_multisig_wallets WHERE name = ?1",
+            params![name],
+        )?;
+        Ok(removed > 0)
+    }
+}
@@ -0,0 +1,295 @@
+//! SLIP-19 proofs of ownership, as returned by Trezor Connect `getOwnershipProof`.
+//!
+//! A proof is `versionMagic || flags || ownershipIds || scriptSig || witness`. The signature
+//! in its script sig or witness is over `SHA256(versionMagic || flags || ownershipIds ||
+//! scriptPubKey || commitmentData)`, so it proves control of the key of the script pubkey
+//! and commits to arbitrary data, like a payjoin proposal or a message. Proofs of P2WPKH,
+//! P2SH-P2WPKH and taproot key path scripts can be verified.
+
+use crate::modules::trezor::{
+    purpose_script_type, GetOwnershipProofParams, ScriptType, TrezorConnectError,
+    TrezorConnectResult,
+};
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::bip32::DerivationPath;
+use bitcoin::consensus::encode::{serialize, Decodable, VarInt};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::script::Instruction;
+use bitcoin::secp256k1::{Message, Secp256k1, XOnlyPublicKey};
+use bitcoin::{ecdsa, taproot, Address, CompressedPublicKey, Script, ScriptBuf, Witness};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// `SL\x00\x19`, first bytes of every proof
+const VERSION_MAGIC: [u8; 4] = *b"SL\x00\x19";
+
+/// Flag of the proofs confirmed by the user on the device
+const FLAG_USER_CONFIRMED: u8 = 0x01;
+
+/// Length of an ownership id
+const OWNERSHIP_ID_LEN: usize = 32;
+
+fn ownership_error(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::OwnershipError { error_details }
+}
+
+/// Decoded SLIP-19 proof of ownership
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, uniffi::Record)]
+pub struct TrezorOwnershipProof {
+    /// Whether the user confirmed the proof on the device
+    pub user_confirmed: bool,
+    /// Ownership ids the proof commits to, in hex, the one of the signer last
+    pub ownership_ids: Vec<String>,
+    /// Script sig of the proof, in hex
+    pub script_sig: String,
+    /// Witness items of the proof, in hex
+    pub witness: Vec<String>,
+}
+
+/// Proof split into its signed body and its signature
+struct DecodedProof {
+    body: Vec<u8>,
+    user_confirmed: bool,
+    ownership_ids: Vec<[u8; OWNERSHIP_ID_LEN]>,
+    script_sig: ScriptBuf,
+    witness: Witness,
+}
+
+/// Body of a proof: the version, flags and ownership ids
+#[cfg(test)]
+pub(crate) fn ownership_proof_body(
+    user_confirmed: bool,
+    ownership_ids: &[[u8; OWNERSHIP_ID_LEN]],
+) -> Vec<u8> {
+    let mut body = VERSION_MAGIC.to_vec();
+    body.push(if user_confirmed {
+        FLAG_USER_CONFIRMED
+    } else {
+        0
+    });
+    body.extend(serialize(&VarInt::from(ownership_ids.len())));
+    for ownership_id in ownership_ids {
+        body.extend_from_slice(ownership_id);
+    }
+    body
+}
+
+/// Hash signed by a proof with `body`, for `script_pubkey` and `commitment_data`
+pub(crate) fn ownership_proof_sighash(
+    body: &[u8],
+    script_pubkey: &Script,
+    commitment_data: &[u8],
+) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(body);
+    engine.input(&serialize(script_pubkey));
+    engine.input(&serialize(&commitment_data.to_vec()));
+    sha256::Hash::from_engine(engine)
+}
+
+fn decode_proof(proof: &str) -> TrezorConnectResult<DecodedProof> {
+    let bytes = hex::decode(proof)
+        .map_err(|e| ownership_error(format!("Invalid ownership proof hex: {}", e)))?;
+    let invalid = |e: bitcoin::consensus::encode::Error| {
+        ownership_error(format!("Invalid ownership proof: {}", e))
+    };
+    if bytes.len() < VERSION_MAGIC.len() + 1 || bytes[..VERSION_MAGIC.len()] != VERSION_MAGIC {
+        return Err(ownership_error("Not a SLIP-19 ownership proof".to_string()));
+    }
+    let flags = bytes[VERSION_MAGIC.len()];
+    if flags & !FLAG_USER_CONFIRMED != 0 {
+        return Err(ownership_error(format!(
+            "Unknown ownership proof flags {:#04x}",
+            flags
+        )));
+    }
+
+    let mut reader = &bytes[VERSION_MAGIC.len() + 1..];
+    let count = VarInt::consensus_decode(&mut reader).map_err(invalid)?.0;
+    let mut ownership_ids = Vec::new();
+    for _ in 0..count {
+        let (ownership_id, rest) = reader
+            .split_first_chunk::<OWNERSHIP_ID_LEN>()
+            .ok_or_else(|| ownership_error("Truncated ownership ids".to_string()))?;
+        ownership_ids.push(*ownership_id);
+        reader = rest;
+    }
+    let body = bytes[..bytes.len() - reader.len()].to_vec();
+    let script_sig = ScriptBuf::consensus_decode(&mut reader).map_err(invalid)?;
+    let witness = Witness::consensus_decode(&mut reader).map_err(invalid)?;
+    if !reader.is_empty() {
+        return Err(ownership_error(
+            "Ownership proof has trailing data".to_string(),
+        ));
+    }
+
+    Ok(DecodedProof {
+        body,
+        user_confirmed: flags & FLAG_USER_CONFIRMED != 0,
+        ownership_ids,
+        script_sig,
+        witness,
+    })
+}
+
+/// Decode a hex encoded SLIP-19 proof of ownership, without verifying it
+pub fn parse_ownership_proof(proof: &str) -> TrezorConnectResult<TrezorOwnershipProof> {
+    let proof = decode_proof(proof)?;
+    Ok(TrezorOwnershipProof {
+        user_confirmed: proof.user_confirmed,
+        ownership_ids: proof.ownership_ids.iter().map(hex::encode).collect(),
+        script_sig: hex::encode(proof.script_sig.as_bytes()),
+        witness: proof.witness.iter().map(hex::encode).collect(),
+    })
+}
+
+/// Check the P2WPKH witness of a proof signing `sighash` for the key hash of `program`
+fn verify_p2wpkh_witness(witness: &Witness, program: &Script, sighash: &Message) -> bool {
+    let (Some(signature), Some(public_key), 2) = (witness.nth(0), witness.nth(1), witness.len())
+    else {
+        return false;
+    };
+    let (Ok(signature), Ok(public_key)) = (
+        ecdsa::Signature::from_slice(signature),
+        CompressedPublicKey::from_slice(public_key),
+    ) else {
+        return false;
+    };
+    ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()) == *program
+        && Secp256k1::verification_only()
+            .verify_ecdsa(sighash, &signature.signature, &public_key.0)
+            .is_ok()
+}
+
+/// Verify a hex encoded SLIP-19 proof of ownership of `address`, committing to
+/// `commitment_data`.
+///
+/// Returns `Ok(false)` when the proof is well formed but not signed by the owner of
+/// `address` for `commitment_data`, and an error when the proof or address cannot be
+/// decoded or the address is not P2WPKH, P2SH-P2WPKH or taproot.
+pub fn verify_ownership_proof(
+    proof: &str,
+    address: &str,
+    commitment_data: &[u8],
+) -> TrezorConnectResult<bool> {
+    let script_pubkey = Address::<NetworkUnchecked>::from_str(address)
+        .map_err(|e| ownership_error(format!("Invalid address {}: {}", address, e)))?
+        .assume_checked()
+        .script_pubkey();
+    let proof = decode_proof(proof)?;
+    let sighash = Message::from_digest(
+        ownership_proof_sighash(&proof.body, &script_pubkey, commitment_data).to_byte_array(),
+    );
+
+    if script_pubkey.is_p2wpkh() {
+        Ok(proof.script_sig.is_empty()
+            && verify_p2wpkh_witness(&proof.witness, &script_pubkey, &sighash))
+    } else if script_pubkey.is_p2sh() {
+        // The script sig pushes the P2WPKH program the script hash commits to
+        let program = match proof
+            .script_sig
+            .instructions()
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(instructions) => match instructions.as_slice() {
+                [Instruction::PushBytes(program)] => {
+                    ScriptBuf::from_bytes(program.as_bytes().to_vec())
+                }
+                _ => return Ok(false),
+            },
+            Err(_) => return Ok(false),
+        };
+        Ok(program.is_p2wpkh()
+            && ScriptBuf::new_p2sh(&program.script_hash()) == script_pubkey
+            && verify_p2wpkh_witness(&proof.witness, &program, &sighash))
+    } else if script_pubkey.is_p2tr() {
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])
+            .map_err(|e| ownership_error(format!("Invalid taproot output key: {}", e)))?;
+        let signature = match (proof.witness.nth(0), proof.witness.len()) {
+            (Some(signature), 1) => taproot::Signature::from_slice(signature),
+            _ => return Ok(false),
+        };
+        Ok(proof.script_sig.is_empty()
+            && signature.is_ok_and(|signature| {
+                Secp256k1::verification_only()
+                    .verify_schnorr(&signature.signature, &sighash, &output_key)
+                    .is_ok()
+            }))
+    } else {
+        Err(ownership_error(format!(
+            "Ownership proofs of {} cannot be verified, only P2WPKH, P2SH-P2WPKH and taproot",
+            address
+        )))
+    }
+}
+
+/// `getOwnershipProof` parameters signing `message` with the taproot key of `path`, like
+/// BIP322 does for taproot addresses: the proof commits to the message, and the user
+/// confirms it on the device.
+///
+/// Trezor signs messages with the legacy format only, so this is the way to sign with a
+/// taproot address. The proof is not a BIP322 signature, check it with
+/// `verify_taproot_message`.
+pub fn taproot_message_proof_params(
+    path: &str,
+    message: &str,
+    coin: Option<String>,
+    is_hex: Option<bool>,
+) -> TrezorConnectResult<GetOwnershipProofParams> {
+    let parsed = DerivationPath::from_str(path)
+        .map_err(|e| ownership_error(format!("Invalid path {}: {}", path, e)))?;
+    let address_n: Vec<u32> = parsed.into_iter().map(|index| u32::from(*index)).collect();
+    if !matches!(
+        purpose_script_type(&address_n),
+        Some(ScriptType::SpendTaproot)
+    ) {
+        return Err(ownership_error(format!(
+            "{} is not a BIP86 taproot path",
+            path
+        )));
+    }
+    let message = message_bytes(message, is_hex)?;
+
+    Ok(GetOwnershipProofParams {
+        path: path.to_string(),
+        coin,
+        multisig: None,
+        scriptType: Some("SPENDTAPROOT".to_string()),
+        userConfirmation: Some(true),
+        ownershipIds: None,
+        commitmentData: Some(hex::encode(message)),
+        preauthorized: None,
+        common: None,
+    })
+}
+
+fn message_bytes(message: &str, is_hex: Option<bool>) -> TrezorConnectResult<Vec<u8>> {
+    if is_hex == Some(true) {
+        hex::decode(message).map_err(|e| ownership_error(format!("Invalid message hex: {}", e)))
+    } else {
+        Ok(message.as_bytes().to_vec())
+    }
+}
+
+/// Verify a message signed with `taproot_message_proof_params`: `proof` must be a proof of
+/// ownership of the taproot `address`, confirmed by the user and committing to `message`.
+pub fn verify_taproot_message(
+    address: &str,
+    message: &str,
+    proof: &str,
+    is_hex: Option<bool>,
+) -> TrezorConnectResult<bool> {
+    let is_taproot = Address::<NetworkUnchecked>::from_str(address)
+        .map_err(|e| ownership_error(format!("Invalid address {}: {}", address, e)))?
+        .assume_checked()
+        .script_pubkey()
+        .is_p2tr();
+    if !is_taproot {
+        return Err(ownership_error(format!(
+            "{} is not a taproot address",
+            address
+        )));
+    }
+    let message = message_bytes(message, is_hex)?;
+    Ok(decode_proof(proof)?.user_confirmed && verify_ownership_proof(proof, address, &message)?)
+}
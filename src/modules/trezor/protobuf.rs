@@ -0,0 +1,304 @@
+//! Minimal protobuf codec of the Trezor wire messages used over Bridge.
+//!
+//! Only the fields of the messages sent and read by `TrezorBridgeClient` are covered. A
+//! message on the wire is its 2 byte type and 4 byte length, big endian, then its protobuf
+//! encoding.
+
+use crate::modules::trezor::{TrezorConnectError, TrezorConnectResult};
+
+/// Trezor message type ids, from `messages.proto`
+pub(crate) mod message_type {
+    pub const INITIALIZE: u16 = 0;
+    pub const SUCCESS: u16 = 2;
+    pub const FAILURE: u16 = 3;
+    pub const GET_PUBLIC_KEY: u16 = 11;
+    pub const PUBLIC_KEY: u16 = 12;
+    pub const SIGN_TX: u16 = 15;
+    pub const FEATURES: u16 = 17;
+    pub const PIN_MATRIX_REQUEST: u16 = 18;
+    pub const TX_REQUEST: u16 = 21;
+    pub const TX_ACK: u16 = 22;
+    pub const BUTTON_REQUEST: u16 = 26;
+    pub const BUTTON_ACK: u16 = 27;
+    pub const GET_ADDRESS: u16 = 29;
+    pub const ADDRESS: u16 = 30;
+    pub const SIGN_MESSAGE: u16 = 38;
+    pub const VERIFY_MESSAGE: u16 = 39;
+    pub const MESSAGE_SIGNATURE: u16 = 40;
+    pub const PASSPHRASE_REQUEST: u16 = 41;
+    pub const PASSPHRASE_ACK: u16 = 42;
+    pub const GET_OWNERSHIP_ID: u16 = 43;
+    pub const OWNERSHIP_ID: u16 = 44;
+    pub const GET_OWNERSHIP_PROOF: u16 = 49;
+    pub const OWNERSHIP_PROOF: u16 = 50;
+    #[cfg(test)]
+    pub const GET_FEATURES: u16 = 55;
+}
+
+/// `TxRequest` request types
+pub(crate) mod request_type {
+    pub const TX_INPUT: u64 = 0;
+    pub const TX_OUTPUT: u64 = 1;
+    pub const TX_META: u64 = 2;
+    pub const TX_FINISHED: u64 = 3;
+}
+
+fn decode_error(error_details: &str) -> TrezorConnectError {
+    TrezorConnectError::SerdeError {
+        error_details: format!("Invalid Trezor message: {}", error_details),
+    }
+}
+
+/// Protobuf message under construction
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ProtoWriter {
+    buf: Vec<u8>,
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+impl ProtoWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        put_varint(
+            &mut self.buf,
+            (u64::from(field) << 3) | u64::from(wire_type),
+        );
+    }
+
+    pub fn uint(mut self, field: u32, value: u64) -> Self {
+        self.key(field, 0);
+        put_varint(&mut self.buf, value);
+        self
+    }
+
+    pub fn opt_uint(self, field: u32, value: Option<u64>) -> Self {
+        match value {
+            Some(value) => self.uint(field, value),
+            None => self,
+        }
+    }
+
+    pub fn bool(self, field: u32, value: bool) -> Self {
+        self.uint(field, u64::from(value))
+    }
+
+    pub fn opt_bool(self, field: u32, value: Option<bool>) -> Self {
+        self.opt_uint(field, value.map(u64::from))
+    }
+
+    /// Repeated `uint32`, not packed as in the proto2 Trezor messages
+    pub fn uints(self, field: u32, values: &[u32]) -> Self {
+        values
+            .iter()
+            .fold(self, |writer, value| writer.uint(field, u64::from(*value)))
+    }
+
+    pub fn bytes(mut self, field: u32, value: &[u8]) -> Self {
+        self.key(field, 2);
+        put_varint(&mut self.buf, value.len() as u64);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn opt_bytes(self, field: u32, value: Option<&[u8]>) -> Self {
+        match value {
+            Some(value) => self.bytes(field, value),
+            None => self,
+        }
+    }
+
+    pub fn string(self, field: u32, value: &str) -> Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    pub fn opt_string(self, field: u32, value: Option<&str>) -> Self {
+        self.opt_bytes(field, value.map(str::as_bytes))
+    }
+
+    pub fn message(self, field: u32, value: ProtoWriter) -> Self {
+        self.bytes(field, &value.buf)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ProtoValue {
+    Varint(u64),
+    Bytes(Vec<u8>),
+}
+
+/// Decoded protobuf message, with accessors by field number
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ProtoMessage {
+    fields: Vec<(u32, ProtoValue)>,
+}
+
+fn get_varint(bytes: &[u8], pos: &mut usize) -> TrezorConnectResult<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes
+            .get(*pos)
+            .ok_or_else(|| decode_error("truncated varint"))?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(decode_error("varint too long"))
+}
+
+impl ProtoMessage {
+    pub fn decode(bytes: &[u8]) -> TrezorConnectResult<Self> {
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let key = get_varint(bytes, &mut pos)?;
+            let field = u32::try_from(key >> 3).map_err(|_| decode_error("field too large"))?;
+            let value = match key & 7 {
+                0 => ProtoValue::Varint(get_varint(bytes, &mut pos)?),
+                1 | 5 => {
+                    // Fixed size fields are not used by Trezor messages, skip them
+                    pos += if key & 7 == 1 { 8 } else { 4 };
+                    continue;
+                }
+                2 => {
+                    let len = usize::try_from(get_varint(bytes, &mut pos)?)
+                        .map_err(|_| decode_error("length too large"))?;
+                    let end = pos
+                        .checked_add(len)
+                        .filter(|end| *end <= bytes.len())
+                        .ok_or_else(|| decode_error("truncated field"))?;
+                    let value = bytes[pos..end].to_vec();
+                    pos = end;
+                    ProtoValue::Bytes(value)
+                }
+                wire_type => {
+                    return Err(decode_error(&format!(
+                        "unsupported wire type {}",
+                        wire_type
+                    )))
+                }
+            };
+            fields.push((field, value));
+        }
+        if pos > bytes.len() {
+            return Err(decode_error("truncated field"));
+        }
+        Ok(Self { fields })
+    }
+
+    pub fn uint(&self, field: u32) -> Option<u64> {
+        self.fields
+            .iter()
+            .rev()
+            .find_map(|(number, value)| match value {
+                ProtoValue::Varint(value) if *number == field => Some(*value),
+                _ => None,
+            })
+    }
+
+    pub fn uint32(&self, field: u32) -> Option<u32> {
+        self.uint(field).and_then(|value| u32::try_from(value).ok())
+    }
+
+    #[cfg(test)]
+    pub fn bool(&self, field: u32) -> Option<bool> {
+        self.uint(field).map(|value| value != 0)
+    }
+
+    pub fn uints(&self, field: u32) -> Vec<u32> {
+        let mut values = Vec::new();
+        for (number, value) in &self.fields {
+            if *number != field {
+                continue;
+            }
+            match value {
+                ProtoValue::Varint(value) => values.extend(u32::try_from(*value).ok()),
+                // Packed encoding
+                ProtoValue::Bytes(bytes) => {
+                    let mut pos = 0;
+                    while let Ok(value) = get_varint(bytes, &mut pos) {
+                        values.extend(u32::try_from(value).ok());
+                    }
+                }
+            }
+        }
+        values
+    }
+
+    pub fn bytes(&self, field: u32) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .rev()
+            .find_map(|(number, value)| match value {
+                ProtoValue::Bytes(value) if *number == field => Some(value.as_slice()),
+                _ => None,
+            })
+    }
+
+    /// Values of a repeated bytes field, in order
+    #[cfg(test)]
+    pub fn repeated_bytes(&self, field: u32) -> Vec<&[u8]> {
+        self.fields
+            .iter()
+            .filter_map(|(number, value)| match value {
+                ProtoValue::Bytes(value) if *number == field => Some(value.as_slice()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn string(&self, field: u32) -> Option<String> {
+        self.bytes(field)
+            .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    pub fn message(&self, field: u32) -> TrezorConnectResult<Option<ProtoMessage>> {
+        self.bytes(field).map(ProtoMessage::decode).transpose()
+    }
+
+    #[cfg(test)]
+    pub fn messages(&self, field: u32) -> TrezorConnectResult<Vec<ProtoMessage>> {
+        self.repeated_bytes(field)
+            .into_iter()
+            .map(ProtoMessage::decode)
+            .collect()
+    }
+}
+
+/// Frame a message for the wire: its type, its length and its protobuf encoding
+pub(crate) fn encode_frame(message_type: u16, payload: &[u8]) -> TrezorConnectResult<Vec<u8>> {
+    let len = u32::try_from(payload.len()).map_err(|_| decode_error("message too large"))?;
+    let mut frame = Vec::with_capacity(6 + payload.len());
+    frame.extend_from_slice(&message_type.to_be_bytes());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+    Ok(frame)
+}
+
+/// Split a wire message into its type and protobuf encoding
+pub(crate) fn decode_frame(frame: &[u8]) -> TrezorConnectResult<(u16, &[u8])> {
+    if frame.len() < 6 {
+        return Err(decode_error("truncated header"));
+    }
+    let message_type = u16::from_be_bytes([frame[0], frame[1]]);
+    let len = u32::from_be_bytes([frame[2], frame[3], frame[4], frame[5]]) as usize;
+    let payload = frame
+        .get(6..6 + len)
+        .ok_or_else(|| decode_error("truncated payload"))?;
+    Ok((message_type, payload))
+}
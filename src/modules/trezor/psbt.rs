@@ -0,0 +1,563 @@
+//! Conversion between PSBTs and Trezor Connect `signTransaction` calls.
+//!
+//! Inputs and outputs with a BIP32 derivation of the wallet are signed or shown as change by
+//! the device, using the derivation path as `address_n`. Other inputs are passed as external
+//! inputs and other outputs as addresses. Once signed, the signatures of the response are
+//! merged back into the PSBT as partial signatures, after checking that the serialized
+//! transaction is the one of the PSBT.
+
+use crate::modules::trezor::{
+    RefTransaction, RefTxInput, RefTxOutput, ScriptType, SignTransactionParams,
+    SignedTransactionResponse, TrezorConnectError, TrezorConnectResult, TrezorMultisigWallet,
+    TxInputType, TxOutputType,
+};
+use bitcoin::bip32::{DerivationPath, Fingerprint, KeySource};
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::secp256k1::{self, PublicKey, XOnlyPublicKey};
+use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+use bitcoin::taproot::TapLeafHash;
+use bitcoin::{ecdsa, psbt, taproot, Address, Network, Psbt, Script, Transaction, TxOut, Txid};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+fn psbt_error(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::PsbtError { error_details }
+}
+
+fn mismatch(error_details: String) -> TrezorConnectError {
+    TrezorConnectError::TransactionMismatch { error_details }
+}
+
+/// Parse a base64 PSBT
+pub fn parse_psbt(psbt: &str) -> TrezorConnectResult<Psbt> {
+    Psbt::from_str(psbt).map_err(|e| psbt_error(format!("Invalid PSBT: {}", e)))
+}
+
+/// Parse a hex encoded transaction
+pub fn parse_transaction(tx_hex: &str) -> TrezorConnectResult<Transaction> {
+    deserialize_hex(tx_hex).map_err(|e| psbt_error(format!("Invalid transaction: {}", e)))
+}
+
+/// Parse a hex master key fingerprint, like `73c5da0a`
+pub fn parse_fingerprint(fingerprint: &str) -> TrezorConnectResult<Fingerprint> {
+    Fingerprint::from_str(fingerprint)
+        .map_err(|e| psbt_error(format!("Invalid fingerprint {}: {}", fingerprint, e)))
+}
+
+/// Network of the addresses of a Trezor coin
+pub(crate) fn coin_network(coin: &str) -> TrezorConnectResult<Network> {
+    match coin.to_lowercase().as_str() {
+        "btc" | "bitcoin" => Ok(Network::Bitcoin),
+        "test" | "testnet" => Ok(Network::Testnet),
+        "regtest" => Ok(Network::Regtest),
+        _ => Err(psbt_error(format!("Unsupported coin {}", coin))),
+    }
+}
+
+/// Key of the wallet an input or output is derived from
+enum WalletKey {
+    Ecdsa(PublicKey, DerivationPath),
+    Taproot(DerivationPath),
+}
+
+impl WalletKey {
+    fn address_n(&self) -> Vec<u32> {
+        let path = match self {
+            WalletKey::Ecdsa(_, path) | WalletKey::Taproot(path) => path,
+        };
+        path.into_iter().map(|child| u32::from(*child)).collect()
+    }
+}
+
+/// Returns the only derivation of the wallet among `derivations`. Without a fingerprint,
+/// every derivation is one of the wallet.
+fn wallet_derivation<'a, K: Copy + 'a>(
+    derivations: impl Iterator<Item = (&'a K, &'a KeySource)>,
+    fingerprint: Option<Fingerprint>,
+    what: &str,
+) -> TrezorConnectResult<Option<(K, DerivationPath)>> {
+    let mut matching = derivations
+        .filter(|(_, (key_fingerprint, _))| fingerprint.is_none_or(|f| f == *key_fingerprint));
+    let first = matching.next();
+    if matching.next().is_some() {
+        return Err(psbt_error(format!(
+            "{} has several BIP32 derivations of the wallet",
+            what
+        )));
+    }
+    Ok(first.map(|(key, (_, path))| (*key, path.clone())))
+}
+
+/// Wallet key of an input or output: its taproot key path derivation if it has one, its
+/// BIP32 derivation otherwise
+fn wallet_key(
+    bip32_derivation: &BTreeMap<PublicKey, KeySource>,
+    tap_key_origins: &BTreeMap<XOnlyPublicKey, (Vec<TapLeafHash>, KeySource)>,
+    fingerprint: Option<Fingerprint>,
+    what: &str,
+) -> TrezorConnectResult<Option<WalletKey>> {
+    let key_path = tap_key_origins
+        .iter()
+        .filter(|(_, (leaf_hashes, _))| leaf_hashes.is_empty())
+        .map(|(key, (_, source))| (key, source));
+    if let Some((_, path)) = wallet_derivation(key_path, fingerprint, what)? {
+        return Ok(Some(WalletKey::Taproot(path)));
+    }
+    Ok(
+        wallet_derivation(bip32_derivation.iter(), fingerprint, what)?
+            .map(|(key, path)| WalletKey::Ecdsa(key, path)),
+    )
+}
+
+fn input_wallet_key(
+    input: &psbt::Input,
+    index: usize,
+    fingerprint: Option<Fingerprint>,
+) -> TrezorConnectResult<Option<WalletKey>> {
+    wallet_key(
+        &input.bip32_derivation,
+        &input.tap_key_origins,
+        fingerprint,
+        &format!("Input {}", index),
+    )
+}
+
+/// Output spent by an input, from the PSBT or the previous transactions
+pub(crate) fn spent_output(
+    psbt: &Psbt,
+    index: usize,
+    known_txs: &HashMap<Txid, &Transaction>,
+) -> TrezorConnectResult<TxOut> {
+    let outpoint = psbt.unsigned_tx.input[index].previous_output;
+    let input = &psbt.inputs[index];
+    if let Some(witness_utxo) = &input.witness_utxo {
+        return Ok(witness_utxo.clone());
+    }
+
+    let prev_tx = input
+        .non_witness_utxo
+        .as_ref()
+        .filter(|tx| tx.compute_txid() == outpoint.txid)
+        .or_else(|| known_txs.get(&outpoint.txid).copied())
+        .ok_or_else(|| {
+            psbt_error(format!(
+                "Input {} has no UTXO and its previous transaction {} is missing",
+                index, outpoint.txid
+            ))
+        })?;
+    prev_tx
+        .output
+        .get(outpoint.vout as usize)
+        .cloned()
+        .ok_or_else(|| {
+            psbt_error(format!(
+                "Input {} spends a missing output {}",
+                index, outpoint
+            ))
+        })
+}
+
+fn input_script_type(
+    spent: &TxOut,
+    input: &psbt::Input,
+    index: usize,
+) -> TrezorConnectResult<ScriptType> {
+    let script = &spent.script_pubkey;
+    if script.is_p2pkh() {
+        Ok(ScriptType::SpendAddress)
+    } else if script.is_p2wpkh() {
+        Ok(ScriptType::SpendWitness)
+    } else if script.is_p2sh() && input.redeem_script.as_ref().is_some_and(|s| s.is_p2wpkh()) {
+        Ok(ScriptType::SpendP2SHWitness)
+    } else if script.is_p2tr() {
+        Ok(ScriptType::SpendTaproot)
+    } else {
+        Err(psbt_error(format!(
+            "Input {} spends an unsupported script {}",
+            index, script
+        )))
+    }
+}
+
+/// Script type of a change output, `None` when the device cannot derive its script
+fn change_script_type(output: &TxOut, psbt_output: &psbt::Output) -> Option<ScriptType> {
+    let script = &output.script_pubkey;
+    if script.is_p2pkh() {
+        Some(ScriptType::PayToAddress)
+    } else if script.is_p2wpkh() {
+        Some(ScriptType::PayToWitness)
+    } else if script.is_p2sh()
+        && psbt_output
+            .redeem_script
+            .as_ref()
+            .is_some_and(|s| s.is_p2wpkh())
+    {
+        Some(ScriptType::PayToP2SHWitness)
+    } else if script.is_p2tr() {
+        Some(ScriptType::PayToTaproot)
+    } else {
+        None
+    }
+}
+
+fn ref_transaction(tx: &Transaction) -> RefTransaction {
+    RefTransaction {
+        hash: tx.compute_txid().to_string(),
+        version: Some(tx.version.0 as u32),
+        inputs: tx
+            .input
+            .iter()
+            .map(|input| RefTxInput {
+                prev_hash: input.previous_output.txid.to_string(),
+                prev_index: input.previous_output.vout,
+                script_sig: input.script_sig.to_hex_string(),
+                sequence: input.sequence.0,
+            })
+            .collect(),
+        bin_outputs: tx
+            .output
+            .iter()
+            .map(|output| RefTxOutput {
+                amount: output.value.to_sat(),
+                script_pubkey: output.script_pubkey.to_hex_string(),
+            })
+            .collect(),
+        lock_time: Some(tx.lock_time.to_consensus_u32()),
+        expiry: None,
+        version_group_id: None,
+        overwintered: None,
+        timestamp: None,
+        branch_id: None,
+        extra_data: None,
+    }
+}
+
+/// Convert a PSBT to `signTransaction` parameters for `coin`.
+///
+/// Inputs and outputs with a BIP32 derivation from `master_fingerprint` are the wallet's:
+/// inputs are signed and outputs are change. Without a fingerprint, every derivation is
+/// the wallet's. Amounts come from the UTXOs of the PSBT or from `prev_txs`, which are
+/// also sent as reference transactions when they cover every input. Otherwise Trezor
+/// Connect looks the reference transactions up itself.
+pub fn psbt_to_sign_transaction_params(
+    psbt: &Psbt,
+    prev_txs: &[Transaction],
+    coin: &str,
+    master_fingerprint: Option<Fingerprint>,
+) -> TrezorConnectResult<SignTransactionParams> {
+    sign_transaction_params(psbt, prev_txs, coin, master_fingerprint, None)
+}
+
+/// Conversion of `psbt_to_sign_transaction_params`, where the wallet inputs and change
+/// outputs of `multisig` also get their `multisig` field
+pub(crate) fn sign_transaction_params(
+    psbt: &Psbt,
+    prev_txs: &[Transaction],
+    coin: &str,
+    master_fingerprint: Option<Fingerprint>,
+    multisig: Option<&TrezorMultisigWallet>,
+) -> TrezorConnectResult<SignTransactionParams> {
+    let network = coin_network(coin)?;
+    let mut known_txs: HashMap<Txid, &Transaction> =
+        prev_txs.iter().map(|tx| (tx.compute_txid(), tx)).collect();
+    for tx in psbt
+        .inputs
+        .iter()
+        .filter_map(|i| i.non_witness_utxo.as_ref())
+    {
+        known_txs.entry(tx.compute_txid()).or_insert(tx);
+    }
+
+    let mut inputs = Vec::with_capacity(psbt.inputs.len());
+    for (index, (txin, input)) in psbt.unsigned_tx.input.iter().zip(&psbt.inputs).enumerate() {
+        let spent = spent_output(psbt, index, &known_txs)?;
+        let wallet_key = input_wallet_key(input, index, master_fingerprint)?;
+        let input_multisig = match (&wallet_key, multisig) {
+            (Some(_), Some(wallet)) => wallet.script_multisig(
+                &spent.script_pubkey,
+                &input.bip32_derivation,
+                &input.partial_sigs,
+            )?,
+            _ => None,
+        };
+        let (address_n, script_type, script_pubkey) = match &wallet_key {
+            // Trezor signs P2WSH multisig inputs as witness inputs with a `multisig` field
+            Some(key) if input_multisig.is_some() => {
+                (Some(key.address_n()), ScriptType::SpendWitness, None)
+            }
+            Some(key) => (
+                Some(key.address_n()),
+                input_script_type(&spent, input, index)?,
+                None,
+            ),
+            None => (
+                None,
+                ScriptType::External,
+                Some(spent.script_pubkey.to_hex_string()),
+            ),
+        };
+        let external = wallet_key.is_none();
+
+        inputs.push(TxInputType {
+            prev_hash: txin.previous_output.txid.to_string(),
+            prev_index: txin.previous_output.vout,
+            amount: spent.value.to_sat(),
+            sequence: Some(txin.sequence.0),
+            address_n,
+            script_type: Some(script_type),
+            multisig: input_multisig,
+            script_pubkey,
+            script_sig: input
+                .final_script_sig
+                .as_ref()
+                .filter(|_| external)
+                .map(|script| script.to_hex_string()),
+            witness: input
+                .final_script_witness
+                .as_ref()
+                .filter(|_| external)
+                .map(serialize_hex),
+            ownership_proof: None,
+            commitment_data: None,
+            orig_hash: None,
+            orig_index: None,
+            coinjoin_flags: None,
+        });
+    }
+
+    let mut outputs = Vec::with_capacity(psbt.outputs.len());
+    for (index, (txout, output)) in psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .zip(&psbt.outputs)
+        .enumerate()
+    {
+        let wallet_key = wallet_key(
+            &output.bip32_derivation,
+            &output.tap_key_origins,
+            master_fingerprint,
+            &format!("Output {}", index),
+        )?;
+        let change_multisig = match (&wallet_key, multisig) {
+            (Some(_), Some(wallet)) => wallet.script_multisig(
+                &txout.script_pubkey,
+                &output.bip32_derivation,
+                &BTreeMap::new(),
+            )?,
+            _ => None,
+        };
+        let change = wallet_key.and_then(|key| {
+            let script_type = match change_multisig {
+                Some(_) => ScriptType::PayToWitness,
+                None => change_script_type(txout, output)?,
+            };
+            Some((key.address_n(), script_type))
+        });
+
+        let output = match change {
+            Some((address_n, script_type)) => TxOutputType {
+                address: None,
+                address_n: Some(address_n),
+                amount: txout.value.to_sat(),
+                script_type,
+                multisig: change_multisig,
+                op_return_data: None,
+                orig_hash: None,
+                orig_index: None,
+                payment_req_index: None,
+            },
+            None if txout.script_pubkey.is_op_return() => TxOutputType {
+                address: None,
+                address_n: None,
+                amount: txout.value.to_sat(),
+                script_type: ScriptType::PayToOpReturn,
+                multisig: None,
+                op_return_data: Some(hex::encode(op_return_data(&txout.script_pubkey))),
+                orig_hash: None,
+                orig_index: None,
+                payment_req_index: None,
+            },
+            None => {
+                let address = Address::from_script(&txout.script_pubkey, network)
+                    .map_err(|e| psbt_error(format!("Output {} has no address: {}", index, e)))?;
+                TxOutputType {
+                    address: Some(address.to_string()),
+                    address_n: None,
+                    amount: txout.value.to_sat(),
+                    script_type: ScriptType::PayToAddress,
+                    multisig: None,
+                    op_return_data: None,
+                    orig_hash: None,
+                    orig_index: None,
+                    payment_req_index: None,
+                }
+            }
+        };
+        outputs.push(output);
+    }
+
+    let ref_txs = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|txin| known_txs.get(&txin.previous_output.txid).copied())
+        .collect::<Option<Vec<_>>>()
+        .map(|txs| {
+            let mut ref_txs: Vec<RefTransaction> = Vec::new();
+            for tx in txs {
+                let ref_tx = ref_transaction(tx);
+                if !ref_txs.iter().any(|known| known.hash == ref_tx.hash) {
+                    ref_txs.push(ref_tx);
+                }
+            }
+            ref_txs
+        });
+
+    Ok(SignTransactionParams {
+        coin: coin.to_string(),
+        inputs,
+        outputs,
+        refTxs: ref_txs,
+        paymentRequests: None,
+        locktime: Some(psbt.unsigned_tx.lock_time.to_consensus_u32()),
+        version: Some(psbt.unsigned_tx.version.0 as u32),
+        expiry: None,
+        versionGroupId: None,
+        overwintered: None,
+        timestamp: None,
+        branchId: None,
+        push: None,
+        amountUnit: None,
+        unlockPath: None,
+        serialize: None,
+        chunkify: None,
+        common: None,
+    })
+}
+
+fn op_return_data(script: &Script) -> Vec<u8> {
+    script
+        .instructions()
+        .filter_map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes().to_vec()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+/// Check that a signed transaction spends exactly the inputs and pays exactly the outputs
+/// of the unsigned one
+fn check_signed_transaction(
+    unsigned: &Transaction,
+    signed: &Transaction,
+) -> TrezorConnectResult<()> {
+    if signed.version != unsigned.version || signed.lock_time != unsigned.lock_time {
+        return Err(mismatch(
+            "Signed transaction has a different version or lock time".to_string(),
+        ));
+    }
+    if signed.input.len() != unsigned.input.len() {
+        return Err(mismatch(format!(
+            "Signed transaction has {} inputs instead of {}",
+            signed.input.len(),
+            unsigned.input.len()
+        )));
+    }
+    for (index, (signed_in, unsigned_in)) in signed.input.iter().zip(&unsigned.input).enumerate() {
+        if signed_in.previous_output != unsigned_in.previous_output
+            || signed_in.sequence != unsigned_in.sequence
+        {
+            return Err(mismatch(format!(
+                "Signed transaction input {} spends {} instead of {}",
+                index, signed_in.previous_output, unsigned_in.previous_output
+            )));
+        }
+    }
+    if signed.output != unsigned.output {
+        return Err(mismatch(
+            "Signed transaction has different outputs".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Signature returned by Trezor for an input of the wallet
+enum InputSignature {
+    Ecdsa(bitcoin::PublicKey, ecdsa::Signature),
+    Taproot(taproot::Signature),
+}
+
+/// Merge the signatures of a `signTransaction` response into the PSBT it was converted from.
+///
+/// The serialized transaction must spend exactly the inputs and pay exactly the outputs of
+/// the PSBT. Every input of the wallet, as chosen by `master_fingerprint` for the conversion,
+/// gets its signature as a partial signature, or as the key path signature for taproot.
+pub fn merge_signed_transaction(
+    psbt: &mut Psbt,
+    response: &SignedTransactionResponse,
+    master_fingerprint: Option<Fingerprint>,
+) -> TrezorConnectResult<()> {
+    let signed = parse_transaction(&response.serializedTx)?;
+    check_signed_transaction(&psbt.unsigned_tx, &signed)?;
+    if let Some(txid) = &response.txid {
+        if *txid != signed.compute_txid().to_string() {
+            return Err(mismatch(format!(
+                "Broadcast transaction {} is not the signed one",
+                txid
+            )));
+        }
+    }
+    if response.signatures.len() != psbt.inputs.len() {
+        return Err(mismatch(format!(
+            "Response has {} signatures for {} inputs",
+            response.signatures.len(),
+            psbt.inputs.len()
+        )));
+    }
+
+    let mut signatures = Vec::new();
+    for (index, (input, signature)) in psbt.inputs.iter().zip(&response.signatures).enumerate() {
+        let Some(key) = input_wallet_key(input, index, master_fingerprint)? else {
+            continue;
+        };
+        if signature.is_empty() {
+            return Err(psbt_error(format!(
+                "Trezor returned no signature for input {}",
+                index
+            )));
+        }
+        let invalid =
+            |e: String| psbt_error(format!("Invalid signature of input {}: {}", index, e));
+        let bytes = hex::decode(signature).map_err(|e| invalid(e.to_string()))?;
+        let signature = match key {
+            WalletKey::Ecdsa(public_key, _) => InputSignature::Ecdsa(
+                bitcoin::PublicKey::new(public_key),
+                ecdsa::Signature {
+                    signature: secp256k1::ecdsa::Signature::from_der(&bytes)
+                        .map_err(|e| invalid(e.to_string()))?,
+                    sighash_type: EcdsaSighashType::All,
+                },
+            ),
+            WalletKey::Taproot(_) => InputSignature::Taproot(taproot::Signature {
+                signature: secp256k1::schnorr::Signature::from_slice(&bytes)
+                    .map_err(|e| invalid(e.to_string()))?,
+                sighash_type: TapSighashType::Default,
+            }),
+        };
+        signatures.push((index, signature));
+    }
+
+    for (index, signature) in signatures {
+        let input = &mut psbt.inputs[index];
+        match signature {
+            InputSignature::Ecdsa(public_key, signature) => {
+                input.partial_sigs.insert(public_key, signature);
+            }
+            InputSignature::Taproot(signature) => input.tap_key_sig = Some(signature),
+        }
+    }
+    Ok(())
+}
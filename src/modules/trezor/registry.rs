@@ -0,0 +1,219 @@
+//! Registry of outstanding Trezor Connect requests.
+//!
+//! Every deep link generated by a `TrezorConnectClient` with a registry is recorded here, so
+//! its callback can be matched to the request it answers and deserialized by the method that
+//! was requested. Callbacks for unknown, cancelled, already answered or expired requests are
+//! rejected.
+
+use crate::modules::trezor::implementation::{parse_callback, parse_payload};
+use crate::modules::trezor::{
+    open_trezor_db, TrezorConnectError, TrezorConnectResult, TrezorDbConnection,
+    TrezorPendingRequest, TrezorResponsePayload,
+};
+use bitcoin::hashes::{sha256, Hash};
+use rusqlite::{params, OptionalExtension};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Time a request waits for its callback by default
+pub const DEFAULT_TREZOR_REQUEST_TTL_SECS: u64 = 600;
+
+/// Time answered, cancelled and expired requests are kept to reject late callbacks
+const REQUEST_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+
+const CREATE_TREZOR_REQUESTS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS trezor_requests (
+        request_id TEXT PRIMARY KEY,
+        method TEXT NOT NULL,
+        params_digest TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL,
+        state TEXT NOT NULL CHECK (state IN ('pending', 'answered', 'cancelled'))
+    )";
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Persistent registry of the Trezor Connect requests waiting for their callback
+#[derive(Debug)]
+pub struct TrezorRequestRegistry {
+    conn: TrezorDbConnection,
+    request_ttl_secs: u64,
+}
+
+impl TrezorRequestRegistry {
+    /// Open the registry at `db_path`, creating it if needed. Use `:memory:` for a registry
+    /// that is not persisted.
+    pub fn new(db_path: &str) -> TrezorConnectResult<Self> {
+        Self::with_connection(open_trezor_db(db_path)?)
+    }
+
+    /// Open the registry on a connection shared with the other Trezor stores
+    pub fn with_connection(conn: TrezorDbConnection) -> TrezorConnectResult<Self> {
+        conn.lock()
+            .unwrap()
+            .execute(CREATE_TREZOR_REQUESTS_TABLE, [])?;
+        Ok(Self {
+            conn,
+            request_ttl_secs: DEFAULT_TREZOR_REQUEST_TTL_SECS,
+        })
+    }
+
+    /// Set the time new requests wait for their callback
+    pub fn with_request_ttl(mut self, request_ttl_secs: u64) -> Self {
+        self.request_ttl_secs = request_ttl_secs;
+        self
+    }
+
+    /// Record a request. An ID can only be reused once its previous request is no longer
+    /// pending.
+    pub(crate) fn record(
+        &self,
+        request_id: &str,
+        method: &str,
+        params_json: &str,
+    ) -> TrezorConnectResult<()> {
+        let now = now();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM trezor_requests WHERE expires_at < ?1",
+            params![now.saturating_sub(REQUEST_RETENTION_SECS)],
+        )?;
+
+        let pending = conn
+            .query_row(
+                "SELECT 1 FROM trezor_requests
+                 WHERE request_id = ?1 AND state = 'pending' AND expires_at > ?2",
+                params![request_id, now],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if pending.is_some() {
+            return Err(TrezorConnectError::Other {
+                error_details: format!("Request {} is already pending", request_id),
+            });
+        }
+
+        let params_digest = sha256::Hash::hash(params_json.as_bytes()).to_string();
+        conn.execute(
+            "INSERT OR REPLACE INTO trezor_requests
+             (request_id, method, params_digest, created_at, expires_at, state)
+             VALUES (?1, ?2, ?3, ?4, ?5, 'pending')",
+            params![
+                request_id,
+                method,
+                params_digest,
+                now,
+                now.saturating_add(self.request_ttl_secs)
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Requests waiting for their callback, oldest first
+    pub fn pending_requests(&self) -> TrezorConnectResult<Vec<TrezorPendingRequest>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT request_id, method, params_digest, created_at, expires_at
+             FROM trezor_requests WHERE state = 'pending' AND expires_at > ?1
+             ORDER BY created_at, rowid",
+        )?;
+        let requests = stmt
+            .query_map(params![now()], |row| {
+                Ok(TrezorPendingRequest {
+                    request_id: row.get(0)?,
+                    method: row.get(1)?,
+                    params_digest: row.get(2)?,
+                    created_at: row.get(3)?,
+                    expires_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(requests)
+    }
+
+    /// Cancel a pending request, so its callback is rejected. Returns whether it was pending.
+    pub fn cancel(&self, request_id: &str) -> TrezorConnectResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE trezor_requests SET state = 'cancelled'
+             WHERE request_id = ?1 AND state = 'pending' AND expires_at > ?2",
+            params![request_id, now()],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Delete all recorded requests. Callbacks of requests recorded before are rejected.
+    pub fn wipe_all(&self) -> TrezorConnectResult<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM trezor_requests", [])?;
+        Ok(())
+    }
+
+    /// Handle a callback URL from Trezor for a recorded request.
+    ///
+    /// The request is marked as answered, also when Trezor reports an error, and the payload
+    /// is deserialized according to the recorded method.
+    pub fn handle_callback(
+        &self,
+        callback_url: &str,
+    ) -> TrezorConnectResult<TrezorResponsePayload> {
+        let callback = parse_callback(callback_url)?;
+        let method = self.answer(&callback.id)?;
+        if let Some(callback_method) = callback.method.filter(|m| *m != method) {
+            return Err(TrezorConnectError::Other {
+                error_details: format!(
+                    "Callback for {} request {} names method {}",
+                    method, callback.id, callback_method
+                ),
+            });
+        }
+
+        parse_payload(&method, callback.response.into_payload()?)
+    }
+
+    /// Mark a pending request as answered and return its method
+    fn answer(&self, request_id: &str) -> TrezorConnectResult<String> {
+        let conn = self.conn.lock().unwrap();
+        let request: Option<(String, u64, String)> = conn
+            .query_row(
+                "SELECT method, expires_at, state FROM trezor_requests WHERE request_id = ?1",
+                params![request_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+
+        match request {
+            None => Err(TrezorConnectError::UnknownRequest {
+                error_details: format!("No request with ID {}", request_id),
+            }),
+            Some((_, _, state)) if state == "cancelled" => {
+                Err(TrezorConnectError::UnknownRequest {
+                    error_details: format!("Request {} was cancelled", request_id),
+                })
+            }
+            Some((_, _, state)) if state == "answered" => {
+                Err(TrezorConnectError::DuplicateCallback {
+                    error_details: format!("Request {} was already answered", request_id),
+                })
+            }
+            Some((_, expires_at, _)) if expires_at <= now() => {
+                Err(TrezorConnectError::RequestExpired {
+                    error_details: format!("Request {} expired", request_id),
+                })
+            }
+            Some((method, _, _)) => {
+                conn.execute(
+                    "UPDATE trezor_requests SET state = 'answered' WHERE request_id = ?1",
+                    params![request_id],
+                )?;
+                Ok(method)
+            }
+        }
+    }
+}
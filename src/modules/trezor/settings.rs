@@ -0,0 +1,149 @@
+//! Configurable Trezor Connect deep link endpoints.
+//!
+//! Each environment can be pointed at another deep link base URL, e.g. a self-hosted Suite
+//! Lite build for QA. Production has no built-in URL, so it only works once its endpoint is
+//! configured. The settings are persisted in `trezor.db`.
+
+use crate::modules::trezor::{
+    open_trezor_db, TrezorConnectError, TrezorConnectResult, TrezorDbConnection,
+    TrezorEndpointSettings, TrezorEnvironment,
+};
+use rusqlite::{params, OptionalExtension};
+use url::Url;
+
+/// Scheme of the Trezor Suite Lite app deep links
+const SUITE_LITE_SCHEME: &str = "trezorsuitelite";
+
+const ENDPOINTS_KEY: &str = "endpoints";
+
+const CREATE_TREZOR_SETTINGS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS trezor_settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    )";
+
+impl Default for TrezorEndpointSettings {
+    /// Built-in endpoints, defaulting to the local Suite Lite app
+    fn default() -> Self {
+        Self {
+            default_environment: TrezorEnvironment::Local,
+            production_url: None,
+            development_url: None,
+            local_url: None,
+        }
+    }
+}
+
+fn parse_endpoint(environment: TrezorEnvironment, url: &str) -> TrezorConnectResult<Url> {
+    let invalid = |reason: String| TrezorConnectError::EnvironmentError {
+        error_details: format!("Invalid {:?} endpoint {}: {}", environment, url, reason),
+    };
+    let parsed = Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+    if parsed.fragment().is_some() || parsed.query().is_some() {
+        return Err(invalid("it has a query or fragment".to_string()));
+    }
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err(invalid("it has credentials".to_string()));
+    }
+    if environment == TrezorEnvironment::Production
+        && parsed.scheme() != "https"
+        && parsed.scheme() != SUITE_LITE_SCHEME
+    {
+        return Err(invalid(format!(
+            "production endpoints must use https or {}",
+            SUITE_LITE_SCHEME
+        )));
+    }
+    Ok(parsed)
+}
+
+impl TrezorEndpointSettings {
+    fn custom_url(&self, environment: TrezorEnvironment) -> Option<&str> {
+        match environment {
+            TrezorEnvironment::Production => self.production_url.as_deref(),
+            TrezorEnvironment::Development => self.development_url.as_deref(),
+            TrezorEnvironment::Local => self.local_url.as_deref(),
+        }
+    }
+
+    /// Check every configured endpoint
+    pub fn validate(&self) -> TrezorConnectResult<()> {
+        for environment in [
+            TrezorEnvironment::Production,
+            TrezorEnvironment::Development,
+            TrezorEnvironment::Local,
+        ] {
+            if let Some(url) = self.custom_url(environment) {
+                parse_endpoint(environment, url)?;
+            }
+        }
+        self.base_url(self.default_environment).map(|_| ())
+    }
+
+    /// Deep link base URL of `environment`, the configured one or the built-in one
+    pub fn base_url(&self, environment: TrezorEnvironment) -> TrezorConnectResult<Url> {
+        match self.custom_url(environment) {
+            Some(url) => parse_endpoint(environment, url),
+            None => environment.base_url(),
+        }
+    }
+}
+
+/// Persistent Trezor Connect settings
+#[derive(Debug)]
+pub struct TrezorSettingsStore {
+    conn: TrezorDbConnection,
+}
+
+impl TrezorSettingsStore {
+    /// Open the store at `db_path`, creating it if needed. Use `:memory:` for a store that is
+    /// not persisted.
+    pub fn new(db_path: &str) -> TrezorConnectResult<Self> {
+        Self::with_connection(open_trezor_db(db_path)?)
+    }
+
+    /// Open the store on a connection shared with the other Trezor stores
+    pub fn with_connection(conn: TrezorDbConnection) -> TrezorConnectResult<Self> {
+        conn.lock()
+            .unwrap()
+            .execute(CREATE_TREZOR_SETTINGS_TABLE, [])?;
+        Ok(Self { conn })
+    }
+
+    /// Delete all stored settings
+    pub fn wipe_all(&self) -> TrezorConnectResult<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM trezor_settings", [])?;
+        Ok(())
+    }
+
+    /// Stored endpoint settings, the built-in ones if none were saved
+    pub fn endpoints(&self) -> TrezorConnectResult<TrezorEndpointSettings> {
+        let value: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM trezor_settings WHERE key = ?1",
+                params![ENDPOINTS_KEY],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(TrezorEndpointSettings::default()),
+        }
+    }
+
+    /// Validate and store endpoint settings
+    pub fn save_endpoints(&self, endpoints: &TrezorEndpointSettings) -> TrezorConnectResult<()> {
+        endpoints.validate()?;
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO trezor_settings (key, value) VALUES (?1, ?2)",
+            params![ENDPOINTS_KEY, serde_json::to_string(endpoints)?],
+        )?;
+        Ok(())
+    }
+}
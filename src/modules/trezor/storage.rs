@@ -0,0 +1,32 @@
+//! SQLite database of the Trezor module.
+//!
+//! The request registry and the account, settings and multisig stores keep their tables in
+//! `trezor.db`. They share a single connection, so their writes never contend for the file
+//! lock.
+
+use crate::modules::trezor::{TrezorConnectError, TrezorConnectResult};
+use rusqlite::Connection;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Connection to the Trezor database, shared by the registry and the stores
+pub type TrezorDbConnection = Arc<Mutex<Connection>>;
+
+/// Time a statement waits for a lock held by another connection to the same file
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Open the Trezor database at `db_path`, creating it if needed. Use `:memory:` for a
+/// database that is not persisted.
+pub fn open_trezor_db(db_path: &str) -> TrezorConnectResult<TrezorDbConnection> {
+    if let Some(dir_path) = std::path::Path::new(db_path).parent() {
+        if !dir_path.as_os_str().is_empty() && !dir_path.exists() {
+            std::fs::create_dir_all(dir_path).map_err(|e| TrezorConnectError::DatabaseError {
+                error_details: format!("Failed to create directory: {}", e),
+            })?;
+        }
+    }
+
+    let conn = Connection::open(db_path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(Arc::new(Mutex::new(conn)))
+}
@@ -1,8 +1,9 @@
 // Trezor Connect API uses camelCase field names - must match external API exactly
 #![allow(non_snake_case)]
 
-use crate::modules::trezor::TrezorConnectError;
+use crate::modules::trezor::{TrezorConnectError, TrezorRequestRegistry};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use url::Url;
 
 /// Result type for deep link generation, including the URL and the ID used
@@ -15,7 +16,7 @@ pub struct DeepLinkResult {
 }
 
 /// Environment options for Trezor deep linking
-#[derive(Debug, Clone, Copy, uniffi::Enum)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
 pub enum TrezorEnvironment {
     /// Production environment (currently unavailable according to docs)
     Production,
@@ -25,6 +26,21 @@ pub enum TrezorEnvironment {
     Local,
 }
 
+/// Deep link endpoints of the Trezor Connect environments, persisted in `trezor.db`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct TrezorEndpointSettings {
+    /// Environment used when a call does not name one
+    pub default_environment: TrezorEnvironment,
+    /// Deep link base URL of the production environment, which has no built-in URL. Must
+    /// be `https` or the Suite Lite app scheme.
+    pub production_url: Option<String>,
+    /// Deep link base URL replacing the built-in development one, e.g. a self-hosted Suite
+    /// Lite build
+    pub development_url: Option<String>,
+    /// Deep link base URL replacing the built-in local one
+    pub local_url: Option<String>,
+}
+
 /// Empty struct that serializes to {}
 #[derive(Serialize)]
 pub(crate) struct Empty;
@@ -36,6 +52,25 @@ pub struct TrezorConnectClient {
     pub(crate) environment: TrezorEnvironment,
     /// Base callback URL for Trezor to return results to (without the ID)
     pub(crate) callback_base: Url,
+    /// Registry the requests are recorded in, to match their callbacks
+    pub(crate) registry: Option<Arc<TrezorRequestRegistry>>,
+    /// Deep link endpoints of the environments
+    pub(crate) endpoints: TrezorEndpointSettings,
+}
+
+/// Trezor Connect request that is waiting for its callback
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct TrezorPendingRequest {
+    /// ID of the request, as in the callback URL
+    pub request_id: String,
+    /// Trezor Connect method, e.g. `signTransaction`
+    pub method: String,
+    /// Hex SHA256 of the JSON parameters of the request
+    pub params_digest: String,
+    /// Unix timestamp in seconds
+    pub created_at: u64,
+    /// Unix timestamp in seconds after which the callback is rejected
+    pub expires_at: u64,
 }
 
 /// Common parameters for all Trezor Connect methods
@@ -91,7 +126,6 @@ pub struct HDNodeType {
 #[derive(Serialize, Deserialize, Debug, Clone, uniffi::Record)]
 pub struct HDNodePathType {
     /// Node data (can be String or HDNodeType)
-    #[serde(flatten)]
     pub node: HDNodeTypeOrString,
     /// BIP32 derivation path
     pub address_n: Vec<u32>,
@@ -613,6 +647,59 @@ pub struct SignMessageParams {
     pub common: Option<CommonParams>,
 }
 
+/// Parameters for getOwnershipId method
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetOwnershipIdParams {
+    /// BIP-32 path as string or array of numbers
+    pub path: String,
+    /// Coin name/type (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coin: Option<String>,
+    /// Multisig information (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultisigRedeemScriptType>,
+    /// Script type (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scriptType: Option<String>,
+    /// Use a preauthorized CoinJoin session (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preauthorized: Option<bool>,
+    /// Additional common parameters
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub common: Option<CommonParams>,
+}
+
+/// Parameters for getOwnershipProof method
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetOwnershipProofParams {
+    /// BIP-32 path as string or array of numbers
+    pub path: String,
+    /// Coin name/type (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coin: Option<String>,
+    /// Multisig information (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultisigRedeemScriptType>,
+    /// Script type (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scriptType: Option<String>,
+    /// Ask the user to confirm the proof, and flag it as confirmed (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userConfirmation: Option<bool>,
+    /// Ownership ids of the other cosigners, then of the device, in hex (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ownershipIds: Option<Vec<String>>,
+    /// Data the proof commits to, in hex (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commitmentData: Option<String>,
+    /// Use a preauthorized CoinJoin session (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preauthorized: Option<bool>,
+    /// Additional common parameters
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub common: Option<CommonParams>,
+}
+
 /// Parameters for signTransaction method
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SignTransactionParams {
@@ -718,7 +805,6 @@ pub struct GetAddressParams {
 }
 
 /// Parameters for getPublicKey method
-#[allow(dead_code)] // Used in implementation, will be exposed via FFI
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GetPublicKeyParams {
     /// BIP-32 path as string
@@ -916,6 +1002,12 @@ pub enum TrezorResponsePayload {
 
     /// Response from signTransaction method
     SignedTransaction(SignedTransactionResponse),
+
+    /// Response from getOwnershipId method
+    OwnershipId(OwnershipIdResponse),
+
+    /// Response from getOwnershipProof method
+    OwnershipProof(OwnershipProofResponse),
 }
 
 /// Feature response containing device capabilities and information
@@ -999,6 +1091,26 @@ pub struct SignedTransactionResponse {
     pub txid: Option<String>,
 }
 
+/// Ownership id response
+#[derive(Debug, Clone, Deserialize, Serialize, uniffi::Record)]
+pub struct OwnershipIdResponse {
+    /// SLIP-19 ownership id of the script pubkey, in hex
+    pub ownership_id: String,
+    pub path: Vec<u32>,
+    pub serializedPath: String,
+}
+
+/// Ownership proof response
+#[derive(Debug, Clone, Deserialize, Serialize, uniffi::Record)]
+pub struct OwnershipProofResponse {
+    /// SLIP-19 proof of ownership, in hex
+    pub ownership_proof: String,
+    /// Signature of the proof, in hex
+    pub signature: String,
+    pub path: Vec<u32>,
+    pub serializedPath: String,
+}
+
 /// Common response wrapper for all Trezor responses
 #[derive(Debug, Clone, Deserialize)]
 pub struct TrezorResponse {